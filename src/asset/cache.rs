@@ -0,0 +1,137 @@
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use asset::{load_source, write_asset};
+use asset::format::{AssetFile, hash_bytes};
+use error::LoadError;
+
+// Baked files kept in a directory, next to nothing else. A baked file is used as long as the hash
+// of its source file matches, otherwise it is baked again. Only the source file itself is
+// hashed: a changed MTL file or glTF buffer is not noticed.
+pub struct AssetCache {
+    directory: PathBuf
+}
+
+impl AssetCache {
+    pub fn new<P: AsRef<Path>>(directory: P) -> AssetCache {
+        AssetCache {
+            directory: directory.as_ref().to_path_buf()
+        }
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    // Sources with the same name in different directories get different baked files
+    pub fn baked_path<P: AsRef<Path>>(&self, source: P) -> PathBuf {
+        let source = source.as_ref();
+        let name = source.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        let location = hash_bytes(source.to_string_lossy().as_bytes());
+        self.directory.join(format!("{}-{:016x}.ogla", name, location))
+    }
+
+    // The baked version of `source`, baked first if it is missing, outdated or damaged
+    pub fn load<P: AsRef<Path>>(&self, source: P) -> Result<AssetFile, LoadError> {
+        let source = source.as_ref();
+        let hash = hash_file(source)?;
+
+        if let Ok(file) = AssetFile::open(self.baked_path(source)) {
+            if file.source_hash() == hash {
+                return Ok(file);
+            }
+        }
+        self.bake_with_hash(source, hash)
+    }
+
+    pub fn is_up_to_date<P: AsRef<Path>>(&self, source: P) -> Result<bool, LoadError> {
+        let source = source.as_ref();
+        let hash = hash_file(source)?;
+        Ok(AssetFile::open(self.baked_path(source)).map(|file| file.source_hash() == hash).unwrap_or(false))
+    }
+
+    // Bake `source` even if it is up to date
+    pub fn bake<P: AsRef<Path>>(&self, source: P) -> Result<AssetFile, LoadError> {
+        let source = source.as_ref();
+        let hash = hash_file(source)?;
+        self.bake_with_hash(source, hash)
+    }
+
+    fn bake_with_hash(&self, source: &Path, hash: u64) -> Result<AssetFile, LoadError> {
+        let mut data = Vec::new();
+        write_asset(&load_source(source)?, hash, &mut data)?;
+
+        // Written under another name first, so that an interrupted bake leaves no broken file
+        fs::create_dir_all(&self.directory)?;
+        let path = self.baked_path(source);
+        let temporary = path.with_extension("ogla.tmp");
+        fs::write(&temporary, &data)?;
+        fs::rename(&temporary, &path)?;
+
+        AssetFile::from_bytes(&data)
+    }
+}
+
+fn hash_file(path: &Path) -> Result<u64, LoadError> {
+    let mut data = Vec::new();
+    File::open(path)?.read_to_end(&mut data)?;
+    Ok(hash_bytes(&data))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use asset::{Asset, AssetMaterial};
+    use mesh::Mesh;
+    use super::*;
+
+    const TRIANGLE: &str = "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n";
+    const QUAD: &str = "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 1 1 0\nf 1 2 3\nf 2 4 3\n";
+
+    #[test]
+    fn baked_files_follow_their_source() {
+        let directory = env::temp_dir().join(format!("ogldev-asset-cache-{}", process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let source = directory.join("model.obj");
+        let cache = AssetCache::new(directory.join("baked"));
+
+        fs::write(&source, TRIANGLE).unwrap();
+        assert!(!cache.is_up_to_date(&source).unwrap());
+        let file = cache.load(&source).unwrap();
+        assert_eq!(file.index_count(), 3);
+        assert_eq!(file.source_hash(), hash_bytes(TRIANGLE.as_bytes()));
+        assert!(cache.is_up_to_date(&source).unwrap());
+
+        // The baked file is used as it is while the source does not change, so a marked one
+        // comes back
+        let mut marked = Asset {
+            mesh: Mesh::new(),
+            materials: vec![AssetMaterial::new("marked")]
+        };
+        marked.mesh.positions = vec![[0.0, 0.0, 0.0]; 3];
+        marked.mesh.indices = vec![0, 1, 2];
+        let mut data = Vec::new();
+        write_asset(&marked, hash_bytes(TRIANGLE.as_bytes()), &mut data).unwrap();
+        fs::write(cache.baked_path(&source), &data).unwrap();
+        assert_eq!(cache.load(&source).unwrap().materials()[0].name, "marked");
+
+        // A new source is baked again
+        fs::write(&source, QUAD).unwrap();
+        assert!(!cache.is_up_to_date(&source).unwrap());
+        let file = cache.load(&source).unwrap();
+        assert_eq!(file.index_count(), 6);
+        assert!(file.materials().is_empty());
+        assert!(cache.is_up_to_date(&source).unwrap());
+
+        // So is a damaged baked file
+        fs::write(cache.baked_path(&source), &data[..data.len() / 2]).unwrap();
+        assert_eq!(cache.load(&source).unwrap().index_count(), 6);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
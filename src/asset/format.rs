@@ -0,0 +1,703 @@
+// The baked file, little-endian everywhere:
+//
+//   header   magic "OGLDEVA\0", version (u32), number of sections (u32), hash of the source (u64)
+//   table    per section: tag ([u8; 4]), 0 (u32), offset (u64), length (u64), checksum (u64)
+//   sections each one starts on a multiple of 16 bytes
+//
+// LAYT  stride and vertex count (u32, u32), attributes (u32 count, then for each one its offset
+//       and size in floats (u32, u32) and its name)
+// VERT  the interleaved vertices (f32)
+// INDX  size of an index in bytes and index count (u32, u32), then the indices (u16 or u32)
+// SUBM  submeshes (u32 count, then name, start, count and material (u32, u32::MAX for none))
+// MATL  materials (u32 count, then the fields of `AssetMaterial` in order)
+//
+// Strings are a byte length (u32) followed by UTF-8, missing paths are empty strings. The
+// checksums are FNV-1a hashes of the section bytes.
+//
+// On little-endian machines, the vertices and the indices are uploaded straight from the bytes
+// of the file.
+
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::slice;
+
+use glium::backend::Facade;
+use glium::index::{IndexBuffer, PrimitiveType};
+
+use asset::{Asset, AssetMaterial};
+use error::LoadError;
+use mesh::{GpuIndices, GpuMesh, IndexFormat, Mesh, SubMesh, UploadError, VertexLayout,
+    upload_interleaved};
+use mesh::{POSITION_ATTRIBUTE, NORMAL_ATTRIBUTE, TEX_COORDS_ATTRIBUTE, COLOR_ATTRIBUTE,
+    TANGENT_ATTRIBUTE};
+
+pub const FORMAT_VERSION: u32 = 1;
+
+const MAGIC: &[u8; 8] = b"OGLDEVA\0";
+const HEADER_SIZE: usize = 24;
+const SECTION_ENTRY_SIZE: usize = 32;
+const SECTION_ALIGNMENT: usize = 16;
+
+const LAYOUT_TAG: &[u8; 4] = b"LAYT";
+const VERTICES_TAG: &[u8; 4] = b"VERT";
+const INDICES_TAG: &[u8; 4] = b"INDX";
+const SUBMESHES_TAG: &[u8; 4] = b"SUBM";
+const MATERIALS_TAG: &[u8; 4] = b"MATL";
+
+// The attributes a baked file may have, with their number of floats
+const ATTRIBUTES: &[(&str, usize)] = &[
+    (POSITION_ATTRIBUTE, 3),
+    (NORMAL_ATTRIBUTE, 3),
+    (TEX_COORDS_ATTRIBUTE, 2),
+    (COLOR_ATTRIBUTE, 4),
+    (TANGENT_ATTRIBUTE, 4)
+];
+
+// 64 bits FNV-1a: not cryptographic, but quick and good enough to notice a changed file
+pub fn hash_bytes(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+pub fn save_asset<P: AsRef<Path>>(asset: &Asset, source_hash: u64, path: P) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_asset(asset, source_hash, &mut writer)?;
+    writer.flush()
+}
+
+pub fn write_asset<W: Write>(asset: &Asset, source_hash: u64, writer: &mut W) -> io::Result<()> {
+    let mesh = &asset.mesh;
+    if let Err(message) = mesh.validate() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+    }
+
+    let layout = mesh.vertex_layout();
+    let mut layout_section = Vec::new();
+    put_u32(&mut layout_section, layout.stride as u32);
+    put_u32(&mut layout_section, mesh.vertex_count() as u32);
+    put_u32(&mut layout_section, layout.attributes.len() as u32);
+    for &(name, offset, components) in layout.attributes.iter() {
+        put_u32(&mut layout_section, offset as u32);
+        put_u32(&mut layout_section, components as u32);
+        put_str(&mut layout_section, name);
+    }
+
+    let mut vertices_section = Vec::new();
+    for value in mesh.interleave() {
+        put_f32(&mut vertices_section, value);
+    }
+
+    let index_size = match mesh.index_format() {
+        IndexFormat::U16 => 2,
+        IndexFormat::U32 => 4
+    };
+    let mut indices_section = Vec::new();
+    put_u32(&mut indices_section, index_size);
+    put_u32(&mut indices_section, mesh.indices.len() as u32);
+    for &index in mesh.indices.iter() {
+        if index_size == 2 {
+            indices_section.extend_from_slice(&(index as u16).to_le_bytes());
+        } else {
+            put_u32(&mut indices_section, index);
+        }
+    }
+
+    let parts = mesh.parts();
+    let mut submeshes_section = Vec::new();
+    put_u32(&mut submeshes_section, parts.len() as u32);
+    for part in parts.iter() {
+        put_str(&mut submeshes_section, &part.name);
+        put_u32(&mut submeshes_section, part.start as u32);
+        put_u32(&mut submeshes_section, part.count as u32);
+        put_u32(&mut submeshes_section, part.material.map_or(u32::MAX, |material| material as u32));
+    }
+
+    let mut materials_section = Vec::new();
+    put_u32(&mut materials_section, asset.materials.len() as u32);
+    for material in asset.materials.iter() {
+        put_material(&mut materials_section, material);
+    }
+
+    let sections = [
+        (LAYOUT_TAG, layout_section),
+        (VERTICES_TAG, vertices_section),
+        (INDICES_TAG, indices_section),
+        (SUBMESHES_TAG, submeshes_section),
+        (MATERIALS_TAG, materials_section)
+    ];
+
+    let mut header = Vec::new();
+    header.extend_from_slice(MAGIC);
+    put_u32(&mut header, FORMAT_VERSION);
+    put_u32(&mut header, sections.len() as u32);
+    header.extend_from_slice(&source_hash.to_le_bytes());
+
+    let mut offset = align(HEADER_SIZE + sections.len() * SECTION_ENTRY_SIZE);
+    for &(tag, ref data) in sections.iter() {
+        header.extend_from_slice(tag);
+        put_u32(&mut header, 0);
+        header.extend_from_slice(&(offset as u64).to_le_bytes());
+        header.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        header.extend_from_slice(&hash_bytes(data).to_le_bytes());
+        offset = align(offset + data.len());
+    }
+
+    writer.write_all(&header)?;
+    let mut written = header.len();
+    for (_, data) in sections.iter() {
+        let padding = align(written) - written;
+        writer.write_all(&[0; SECTION_ALIGNMENT][..padding])?;
+        writer.write_all(data)?;
+        written += padding + data.len();
+    }
+
+    Ok(())
+}
+
+fn align(offset: usize) -> usize {
+    offset.div_ceil(SECTION_ALIGNMENT) * SECTION_ALIGNMENT
+}
+
+fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_f32(out: &mut Vec<u8>, value: f32) {
+    out.extend_from_slice(&value.to_bits().to_le_bytes());
+}
+
+fn put_str(out: &mut Vec<u8>, value: &str) {
+    put_u32(out, value.len() as u32);
+    out.extend_from_slice(value.as_bytes());
+}
+
+fn put_path(out: &mut Vec<u8>, path: &Option<PathBuf>) {
+    put_str(out, &path.as_ref().map(|path| path.to_string_lossy().into_owned()).unwrap_or_default());
+}
+
+fn put_material(out: &mut Vec<u8>, material: &AssetMaterial) {
+    put_str(out, &material.name);
+    let values = material.base_color.iter()
+        .chain(material.specular.iter())
+        .chain(Some(&material.shininess))
+        .chain(Some(&material.metallic))
+        .chain(Some(&material.roughness))
+        .chain(material.emissive.iter());
+    for &value in values {
+        put_f32(out, value);
+    }
+    put_path(out, &material.base_color_map);
+    put_path(out, &material.specular_map);
+    put_path(out, &material.normal_map);
+    put_path(out, &material.metallic_roughness_map);
+}
+
+// A baked file in memory. The sections are checked when the file is read, the vertices and the
+// indices stay as bytes until they are uploaded or turned back into a `Mesh`.
+pub struct AssetFile {
+    // u64 words keep the sections aligned for the casts to f32, u16 and u32
+    storage: Vec<u64>,
+    length: usize,
+    source_hash: u64,
+    layout: VertexLayout,
+    vertex_count: usize,
+    vertices: (usize, usize),
+    index_size: usize,
+    indices: (usize, usize),
+    submeshes: Vec<SubMesh>,
+    materials: Vec<AssetMaterial>
+}
+
+impl AssetFile {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<AssetFile, LoadError> {
+        AssetFile::read(File::open(path)?)
+    }
+
+    pub fn read<R: Read>(mut reader: R) -> Result<AssetFile, LoadError> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        AssetFile::from_bytes(&data)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<AssetFile, LoadError> {
+        let mut storage = vec![0u64; data.len().div_ceil(8)];
+        unsafe {
+            slice::from_raw_parts_mut(storage.as_mut_ptr() as *mut u8, data.len()).copy_from_slice(data);
+        }
+
+        let mut file = AssetFile {
+            storage: storage,
+            length: data.len(),
+            source_hash: 0,
+            layout: VertexLayout {
+                attributes: Vec::new(),
+                stride: 0
+            },
+            vertex_count: 0,
+            vertices: (0, 0),
+            index_size: 4,
+            indices: (0, 0),
+            submeshes: Vec::new(),
+            materials: Vec::new()
+        };
+        file.parse()?;
+        Ok(file)
+    }
+
+    fn bytes(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.storage.as_ptr() as *const u8, self.length) }
+    }
+
+    // The hash of the source file this one was baked from
+    pub fn source_hash(&self) -> u64 {
+        self.source_hash
+    }
+
+    pub fn layout(&self) -> &VertexLayout {
+        &self.layout
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.vertex_count
+    }
+
+    pub fn index_count(&self) -> usize {
+        self.indices.1 / self.index_size
+    }
+
+    pub fn submeshes(&self) -> &[SubMesh] {
+        &self.submeshes
+    }
+
+    pub fn materials(&self) -> &[AssetMaterial] {
+        &self.materials
+    }
+
+    // The interleaved vertices, borrowed from the file on little-endian machines
+    pub fn vertex_data(&self) -> Cow<'_, [f32]> {
+        let bytes = &self.bytes()[self.vertices.0..self.vertices.0 + self.vertices.1];
+        if cfg!(target_endian = "little") {
+            // Sections start on a multiple of 16 bytes of 8-bytes aligned storage
+            Cow::Borrowed(unsafe { slice::from_raw_parts(bytes.as_ptr() as *const f32, bytes.len() / 4) })
+        } else {
+            Cow::Owned(bytes.chunks(4).map(|value| f32::from_bits(read_u32(value, 0))).collect())
+        }
+    }
+
+    pub fn indices(&self) -> Vec<u32> {
+        let bytes = &self.bytes()[self.indices.0..self.indices.0 + self.indices.1];
+        if self.index_size == 2 {
+            bytes.chunks(2).map(|index| u32::from(u16::from_le_bytes([index[0], index[1]]))).collect()
+        } else {
+            bytes.chunks(4).map(|index| read_u32(index, 0)).collect()
+        }
+    }
+
+    pub fn upload<F: Facade>(&self, facade: &F) -> Result<GpuMesh, UploadError> {
+        let vertices = upload_interleaved(facade, &self.layout, &self.vertex_data())?;
+
+        let bytes = &self.bytes()[self.indices.0..self.indices.0 + self.indices.1];
+        let indices = match (self.index_size, cfg!(target_endian = "little")) {
+            (2, true) => {
+                let indices = unsafe { slice::from_raw_parts(bytes.as_ptr() as *const u16, bytes.len() / 2) };
+                GpuIndices::U16(IndexBuffer::new(facade, PrimitiveType::TrianglesList, indices)?)
+            },
+            (4, true) => {
+                let indices = unsafe { slice::from_raw_parts(bytes.as_ptr() as *const u32, bytes.len() / 4) };
+                GpuIndices::U32(IndexBuffer::new(facade, PrimitiveType::TrianglesList, indices)?)
+            },
+            _ => GpuIndices::U32(IndexBuffer::new(facade, PrimitiveType::TrianglesList, &self.indices())?)
+        };
+
+        GpuMesh::new(vertices, indices, self.submeshes.clone())
+    }
+
+    // Turn the file back into separate attribute arrays
+    pub fn to_asset(&self) -> Asset {
+        let mut mesh = Mesh::new();
+        let data = self.vertex_data();
+
+        for vertex in data.chunks(self.layout.stride) {
+            for &(name, offset, components) in self.layout.attributes.iter() {
+                let value = &vertex[offset..offset + components];
+                match name {
+                    POSITION_ATTRIBUTE => mesh.positions.push([value[0], value[1], value[2]]),
+                    NORMAL_ATTRIBUTE => mesh.normals.push([value[0], value[1], value[2]]),
+                    TEX_COORDS_ATTRIBUTE => mesh.tex_coords.push([value[0], value[1]]),
+                    COLOR_ATTRIBUTE => mesh.colors.push([value[0], value[1], value[2], value[3]]),
+                    _ => mesh.tangents.push([value[0], value[1], value[2], value[3]])
+                }
+            }
+        }
+
+        mesh.indices = self.indices();
+        // A single part covering everything is what a mesh without submeshes bakes to
+        if self.submeshes.len() != 1 || !self.submeshes[0].name.is_empty() || self.submeshes[0].material.is_some() {
+            mesh.submeshes = self.submeshes.clone();
+        }
+
+        Asset {
+            mesh: mesh,
+            materials: self.materials.clone()
+        }
+    }
+
+    fn parse(&mut self) -> Result<(), LoadError> {
+        let (source_hash, sections) = {
+            let data = self.bytes();
+            if data.len() < HEADER_SIZE || &data[..8] != MAGIC {
+                return Err(LoadError::parse("not a baked asset"));
+            }
+            let version = read_u32(data, 8);
+            if version != FORMAT_VERSION {
+                return Err(LoadError::parse(format!("baked asset version {}, expected {}",
+                    version, FORMAT_VERSION)));
+            }
+
+            let count = read_u32(data, 12) as usize;
+            if count.checked_mul(SECTION_ENTRY_SIZE).is_none_or(|size| HEADER_SIZE + size > data.len()) {
+                return Err(LoadError::parse("truncated section table"));
+            }
+
+            let mut sections = Vec::new();
+            for i in 0..count {
+                let entry = HEADER_SIZE + i * SECTION_ENTRY_SIZE;
+                let tag = &data[entry..entry + 4];
+                let offset = read_u64(data, entry + 8);
+                let length = read_u64(data, entry + 16);
+                if !offset.is_multiple_of(SECTION_ALIGNMENT as u64) || offset.checked_add(length).is_none_or(|end| end > data.len() as u64) {
+                    return Err(LoadError::parse(format!("section {} out of the file",
+                        String::from_utf8_lossy(tag))));
+                }
+
+                let (offset, length) = (offset as usize, length as usize);
+                if hash_bytes(&data[offset..offset + length]) != read_u64(data, entry + 24) {
+                    return Err(LoadError::parse(format!("bad checksum for section {}",
+                        String::from_utf8_lossy(tag))));
+                }
+                sections.push(([tag[0], tag[1], tag[2], tag[3]], offset, length));
+            }
+
+            (read_u64(data, 16), sections)
+        };
+        self.source_hash = source_hash;
+
+        let find = |tag: &[u8; 4]| {
+            sections.iter().find(|section| &section.0 == tag)
+                .map(|&(_, offset, length)| (offset, length))
+                .ok_or_else(|| LoadError::parse(format!("missing section {}", String::from_utf8_lossy(tag))))
+        };
+
+        let layout = find(LAYOUT_TAG)?;
+        let (layout, vertex_count) = parse_layout(&self.bytes()[layout.0..layout.0 + layout.1])?;
+        let vertices = find(VERTICES_TAG)?;
+        if vertices.1 != vertex_count * layout.stride * mem::size_of::<f32>() {
+            return Err(LoadError::parse(format!("{} bytes of vertices for {} vertices of {} floats",
+                vertices.1, vertex_count, layout.stride)));
+        }
+
+        let indices = find(INDICES_TAG)?;
+        let (index_size, indices) = {
+            let mut reader = Reader::new(&self.bytes()[indices.0..indices.0 + indices.1]);
+            let index_size = reader.u32()? as usize;
+            let count = reader.u32()? as usize;
+            if (index_size != 2 && index_size != 4) || !count.is_multiple_of(3) || Some(reader.remaining()) != count.checked_mul(index_size) {
+                return Err(LoadError::parse("malformed indices"));
+            }
+            (index_size, (indices.0 + 8, count * index_size))
+        };
+
+        let submeshes = find(SUBMESHES_TAG)?;
+        let submeshes = parse_submeshes(&self.bytes()[submeshes.0..submeshes.0 + submeshes.1])?;
+        let materials = find(MATERIALS_TAG)?;
+        let materials = parse_materials(&self.bytes()[materials.0..materials.0 + materials.1])?;
+
+        self.layout = layout;
+        self.vertex_count = vertex_count;
+        self.vertices = vertices;
+        self.index_size = index_size;
+        self.indices = indices;
+        self.submeshes = submeshes;
+        self.materials = materials;
+
+        // The GPU must not read past the vertices
+        let index_count = self.index_count();
+        if let Some(index) = self.indices().into_iter().find(|&index| index as usize >= vertex_count) {
+            return Err(LoadError::parse(format!("index {} out of {} vertices", index, vertex_count)));
+        }
+        if let Some(submesh) = self.submeshes.iter().find(|submesh| submesh.start + submesh.count > index_count) {
+            return Err(LoadError::parse(format!("submesh '{}' out of {} indices", submesh.name, index_count)));
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_layout(data: &[u8]) -> Result<(VertexLayout, usize), LoadError> {
+    let mut reader = Reader::new(data);
+    let stride = reader.u32()? as usize;
+    let vertex_count = reader.u32()? as usize;
+    let count = reader.u32()?;
+
+    let mut layout = VertexLayout {
+        attributes: Vec::new(),
+        stride: 0
+    };
+    for _ in 0..count {
+        let offset = reader.u32()? as usize;
+        let components = reader.u32()? as usize;
+        let name = reader.string()?;
+
+        let known = ATTRIBUTES.iter().find(|attribute| attribute.0 == name && attribute.1 == components);
+        let name = match known {
+            Some(&(name, _)) if offset == layout.stride => name,
+            _ => return Err(LoadError::parse(format!("unexpected attribute '{}' of {} floats at {}",
+                name, components, offset)))
+        };
+        if layout.attributes.iter().any(|attribute| attribute.0 == name) {
+            return Err(LoadError::parse(format!("attribute '{}' twice", name)));
+        }
+        layout.push(name, components);
+    }
+
+    if layout.attributes.first().map(|attribute| attribute.0) != Some(POSITION_ATTRIBUTE) || layout.stride != stride {
+        return Err(LoadError::parse("the layout must start with the positions and match the stride"));
+    }
+    Ok((layout, vertex_count))
+}
+
+fn parse_submeshes(data: &[u8]) -> Result<Vec<SubMesh>, LoadError> {
+    let mut reader = Reader::new(data);
+    let count = reader.u32()?;
+    let mut submeshes = Vec::new();
+    for _ in 0..count {
+        let name = reader.string()?;
+        let start = reader.u32()? as usize;
+        let count = reader.u32()? as usize;
+        let material = reader.u32()?;
+        submeshes.push(SubMesh {
+            name: name,
+            start: start,
+            count: count,
+            material: if material == u32::MAX { None } else { Some(material as usize) }
+        });
+    }
+    Ok(submeshes)
+}
+
+fn parse_materials(data: &[u8]) -> Result<Vec<AssetMaterial>, LoadError> {
+    let mut reader = Reader::new(data);
+    let count = reader.u32()?;
+    let mut materials = Vec::new();
+    for _ in 0..count {
+        let mut material = AssetMaterial::new(&reader.string()?);
+        for value in material.base_color.iter_mut().chain(material.specular.iter_mut()) {
+            *value = reader.f32()?;
+        }
+        material.shininess = reader.f32()?;
+        material.metallic = reader.f32()?;
+        material.roughness = reader.f32()?;
+        for value in material.emissive.iter_mut() {
+            *value = reader.f32()?;
+        }
+        material.base_color_map = reader.path()?;
+        material.specular_map = reader.path()?;
+        material.normal_map = reader.path()?;
+        material.metallic_roughness_map = reader.path()?;
+        materials.push(material);
+    }
+    Ok(materials)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from(read_u32(data, offset)) | u64::from(read_u32(data, offset + 4)) << 32
+}
+
+// Reads the values of a section in order, failing at its end
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Reader<'a> {
+        Reader {
+            data: data,
+            position: 0
+        }
+    }
+
+    fn remaining(&self) -> usize {
+        self.data.len() - self.position
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], LoadError> {
+        if length > self.remaining() {
+            return Err(LoadError::parse("truncated section"));
+        }
+        let bytes = &self.data[self.position..self.position + length];
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, LoadError> {
+        self.bytes(4).map(|bytes| read_u32(bytes, 0))
+    }
+
+    fn f32(&mut self) -> Result<f32, LoadError> {
+        self.u32().map(f32::from_bits)
+    }
+
+    fn string(&mut self) -> Result<String, LoadError> {
+        let length = self.u32()? as usize;
+        let bytes = self.bytes(length)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| LoadError::parse("string is not UTF-8"))
+    }
+
+    fn path(&mut self) -> Result<Option<PathBuf>, LoadError> {
+        let path = self.string()?;
+        Ok(if path.is_empty() { None } else { Some(PathBuf::from(path)) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use asset::{Asset, AssetMaterial};
+    use mesh::{IndexFormat, Mesh, SubMesh};
+    use super::*;
+
+    // A strip of `count` quads in two submeshes, with two materials
+    fn strip(count: u32) -> Asset {
+        let mut mesh = Mesh::new();
+        for i in 0..count + 1 {
+            for &y in &[0.0, 1.0] {
+                mesh.positions.push([i as f32, y, 0.0]);
+                mesh.normals.push([0.0, 0.0, -1.0]);
+                mesh.tex_coords.push([i as f32 / count as f32, y]);
+            }
+        }
+        for i in 0..count {
+            let a = 2 * i;
+            mesh.indices.extend_from_slice(&[a, a + 1, a + 3, a, a + 3, a + 2]);
+        }
+        let half = (count / 2 * 6) as usize;
+        mesh.submeshes = vec![
+            SubMesh { name: "left".to_string(), start: 0, count: half, material: Some(1) },
+            SubMesh { name: "right".to_string(), start: half, count: mesh.indices.len() - half, material: None }
+        ];
+
+        let mut shiny = AssetMaterial::new("shiny");
+        shiny.base_color = [0.5, 0.25, 1.0, 0.75];
+        shiny.shininess = 32.0;
+        shiny.normal_map = Some(PathBuf::from("textures/normal.png"));
+        Asset {
+            mesh: mesh,
+            materials: vec![AssetMaterial::new("plain"), shiny]
+        }
+    }
+
+    fn bake(asset: &Asset) -> Vec<u8> {
+        let mut data = Vec::new();
+        write_asset(asset, 0x1234_5678_9abc_def0, &mut data).unwrap();
+        data
+    }
+
+    // Change a section and fix its checksum, to get past the checks of the table
+    fn patch<F: FnOnce(&mut [u8])>(data: &mut [u8], tag: &[u8; 4], change: F) {
+        let count = read_u32(data, 12) as usize;
+        let entry = (0..count).map(|i| HEADER_SIZE + i * SECTION_ENTRY_SIZE)
+            .find(|&entry| &data[entry..entry + 4] == tag)
+            .unwrap();
+        let offset = read_u64(data, entry + 8) as usize;
+        let length = read_u64(data, entry + 16) as usize;
+        change(&mut data[offset..offset + length]);
+        let checksum = hash_bytes(&data[offset..offset + length]);
+        data[entry + 24..entry + 32].copy_from_slice(&checksum.to_le_bytes());
+    }
+
+    fn error(data: &[u8]) -> String {
+        match AssetFile::from_bytes(data) {
+            Ok(_) => panic!("a broken file was read"),
+            Err(err) => err.to_string()
+        }
+    }
+
+    fn assert_round_trip(asset: &Asset, index_format: IndexFormat) {
+        assert_eq!(asset.mesh.index_format(), index_format);
+        let file = AssetFile::from_bytes(&bake(asset)).unwrap();
+        assert_eq!(file.source_hash(), 0x1234_5678_9abc_def0);
+        assert_eq!(file.vertex_count(), asset.mesh.vertex_count());
+        assert_eq!(file.index_count(), asset.mesh.indices.len());
+
+        let read = file.to_asset();
+        assert_eq!(read.mesh.positions, asset.mesh.positions);
+        assert_eq!(read.mesh.normals, asset.mesh.normals);
+        assert_eq!(read.mesh.tex_coords, asset.mesh.tex_coords);
+        assert!(read.mesh.colors.is_empty() && read.mesh.tangents.is_empty());
+        assert_eq!(read.mesh.indices, asset.mesh.indices);
+        assert_eq!(read.mesh.submeshes, asset.mesh.submeshes);
+        assert_eq!(read.materials, asset.materials);
+    }
+
+    #[test]
+    fn small_meshes_round_trip() {
+        assert_round_trip(&strip(8), IndexFormat::U16);
+    }
+
+    #[test]
+    fn large_meshes_round_trip() {
+        assert_round_trip(&strip(40000), IndexFormat::U32);
+    }
+
+    #[test]
+    fn a_single_part_bakes_to_no_submeshes() {
+        let mut asset = strip(8);
+        asset.mesh.submeshes.clear();
+        let read = AssetFile::from_bytes(&bake(&asset)).unwrap().to_asset();
+        assert!(read.mesh.submeshes.is_empty());
+        assert_eq!(read.mesh.indices, asset.mesh.indices);
+    }
+
+    #[test]
+    fn damaged_files_are_rejected() {
+        let data = bake(&strip(8));
+
+        let mut flipped = data.clone();
+        let last = flipped.len() - 1;
+        flipped[last] ^= 1;
+        assert!(error(&flipped).contains("bad checksum for section MATL"), "{}", error(&flipped));
+
+        let mut version = data.clone();
+        version[8..12].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert!(error(&version).contains("baked asset version"), "{}", error(&version));
+
+        assert!(error(&data[..data.len() - 1]).contains("out of the file"), "{}", error(&data[..data.len() - 1]));
+        assert!(error(&data[..HEADER_SIZE + 10]).contains("truncated section table"));
+        assert!(error(&data[..4]).contains("not a baked asset"));
+    }
+
+    #[test]
+    fn out_of_range_indices_and_submeshes_are_rejected() {
+        let data = bake(&strip(8));
+
+        let mut index = data.clone();
+        // The first index, after the index size and count
+        patch(&mut index, INDICES_TAG, |section| section[8..10].copy_from_slice(&18u16.to_le_bytes()));
+        assert!(error(&index).contains("index 18 out of 18 vertices"), "{}", error(&index));
+
+        let mut submesh = data.clone();
+        // The count of the first submesh, after the number of submeshes and the name "left"
+        patch(&mut submesh, SUBMESHES_TAG, |section| section[16..20].copy_from_slice(&100u32.to_le_bytes()));
+        assert!(error(&submesh).contains("submesh 'left' out of 48 indices"), "{}", error(&submesh));
+    }
+}
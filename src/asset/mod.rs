@@ -0,0 +1,173 @@
+// Meshes and materials baked into one binary file, which loads much faster than the source
+// formats: the vertices are stored exactly as the GPU wants them.
+//
+// `load_source()` reads any model the crate knows, `save_asset()` bakes it, `AssetFile` reads it
+// back and `AssetCache` does both, rebuilding the baked file whenever its source changes. The
+// `ogldev-bake` binary bakes files ahead of time.
+
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+use cgmath::{Matrix4, SquareMatrix};
+
+use error::LoadError;
+use gltf::{self, GltfDocument, ImageSource, PbrMaterial, TextureRef};
+use mesh::Mesh;
+use mesh::obj::{self, ObjMaterial};
+use mesh::ply;
+use mesh::stl;
+
+pub use self::cache::AssetCache;
+pub use self::format::{AssetFile, FORMAT_VERSION, hash_bytes, save_asset, write_asset};
+
+mod cache;
+mod format;
+
+// The extensions `load_source()` understands
+pub const SOURCE_EXTENSIONS: &[&str] = &["obj", "gltf", "glb", "ply", "stl"];
+
+// The material model common to every source format, somewhere between Phong and
+// metallic-roughness so that both kinds of shaders find what they need
+#[derive(Clone, Debug, PartialEq)]
+pub struct AssetMaterial {
+    pub name: String,
+    pub base_color: [f32; 4],
+    pub specular: [f32; 3],
+    pub shininess: f32,
+    pub metallic: f32,
+    pub roughness: f32,
+    pub emissive: [f32; 3],
+    pub base_color_map: Option<PathBuf>,
+    pub specular_map: Option<PathBuf>,
+    pub normal_map: Option<PathBuf>,
+    pub metallic_roughness_map: Option<PathBuf>
+}
+
+impl AssetMaterial {
+    pub fn new(name: &str) -> AssetMaterial {
+        AssetMaterial {
+            name: name.to_string(),
+            base_color: [1.0, 1.0, 1.0, 1.0],
+            specular: [0.0, 0.0, 0.0],
+            shininess: 0.0,
+            metallic: 0.0,
+            roughness: 1.0,
+            emissive: [0.0, 0.0, 0.0],
+            base_color_map: None,
+            specular_map: None,
+            normal_map: None,
+            metallic_roughness_map: None
+        }
+    }
+
+    // The roughness comes from the usual Blinn-Phong equivalence: shininess = 2 / r^2 - 2
+    pub fn from_obj(material: &ObjMaterial) -> AssetMaterial {
+        let diffuse = material.diffuse;
+        AssetMaterial {
+            base_color: [diffuse[0], diffuse[1], diffuse[2], material.dissolve],
+            specular: material.specular,
+            shininess: material.shininess,
+            roughness: (2.0 / (material.shininess.max(0.0) + 2.0)).sqrt(),
+            base_color_map: material.diffuse_map.clone(),
+            specular_map: material.specular_map.clone(),
+            normal_map: material.normal_map.clone(),
+            ..AssetMaterial::new(&material.name)
+        }
+    }
+
+    // Only the textures read from files are kept, embedded images have no path to refer to
+    pub fn from_gltf(material: &PbrMaterial, document: &GltfDocument) -> AssetMaterial {
+        let texture_path = |texture: &Option<TextureRef>| {
+            let image = texture.and_then(|texture| document.textures[texture.texture].source);
+            match image.map(|image| &document.images[image].source) {
+                Some(ImageSource::Path(path)) => Some(path.clone()),
+                _ => None
+            }
+        };
+
+        // Dielectrics reflect about 4% of the light, metals reflect their color
+        let color = material.base_color_factor;
+        let specular = |channel: usize| 0.04 + (color[channel] - 0.04) * material.metallic_factor;
+        let roughness = material.roughness_factor.max(0.01);
+
+        AssetMaterial {
+            base_color: color,
+            specular: [specular(0), specular(1), specular(2)],
+            shininess: 2.0 / (roughness * roughness) - 2.0,
+            metallic: material.metallic_factor,
+            roughness: material.roughness_factor,
+            emissive: material.emissive_factor,
+            base_color_map: texture_path(&material.base_color_texture),
+            normal_map: texture_path(&material.normal_texture),
+            metallic_roughness_map: texture_path(&material.metallic_roughness_texture),
+            ..AssetMaterial::new(&material.name)
+        }
+    }
+}
+
+// A mesh with the materials its submeshes refer to
+#[derive(Clone, Debug)]
+pub struct Asset {
+    pub mesh: Mesh,
+    pub materials: Vec<AssetMaterial>
+}
+
+// Load a model of any supported format, chosen by the extension of the file. glTF scenes are
+// flattened into one mesh: every mesh instance of the default scene is moved to world space.
+pub fn load_source<P: AsRef<Path>>(path: P) -> Result<Asset, LoadError> {
+    let path = path.as_ref();
+    let extension = path.extension().and_then(OsStr::to_str).unwrap_or("").to_lowercase();
+
+    match extension.as_str() {
+        "obj" => {
+            let model = obj::load_obj(path)?;
+            Ok(Asset {
+                mesh: model.mesh,
+                materials: model.materials.iter().map(AssetMaterial::from_obj).collect()
+            })
+        },
+        "gltf" | "glb" => Ok(flatten_gltf(&gltf::load_gltf(path)?)),
+        "ply" => Ok(Asset {
+            mesh: ply::load_ply(path)?,
+            materials: Vec::new()
+        }),
+        "stl" => Ok(Asset {
+            mesh: stl::load_stl(path)?,
+            materials: Vec::new()
+        }),
+        _ => Err(LoadError::parse(format!("unknown model format '{}'", path.display())))
+    }
+}
+
+fn flatten_gltf(document: &GltfDocument) -> Asset {
+    // Without any scene, every mesh is drawn once where it is
+    let instances: Vec<(usize, Matrix4<f32>)> = match document.default_scene {
+        Some(scene) => document.mesh_instances(scene),
+        None if !document.scenes.is_empty() => document.mesh_instances(0),
+        None => (0..document.meshes.len()).map(|mesh| (mesh, Matrix4::identity())).collect()
+    };
+    let from_scene = !document.scenes.is_empty();
+
+    let mut mesh = Mesh::new();
+    for (index, matrix) in instances {
+        let source = if from_scene { document.nodes[index].mesh.unwrap() } else { index };
+        let mut instance = document.meshes[source].clone();
+        instance.transform(matrix);
+
+        // Keep the submeshes of the instance instead of the single one `append()` adds
+        let start = mesh.indices.len();
+        mesh.append(&instance, "", None);
+        mesh.submeshes.pop();
+        for mut part in instance.parts() {
+            part.start += start;
+            mesh.submeshes.push(part);
+        }
+    }
+
+    Asset {
+        mesh: mesh,
+        materials: document.materials.iter()
+            .map(|material| AssetMaterial::from_gltf(material, document))
+            .collect()
+    }
+}
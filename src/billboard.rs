@@ -0,0 +1,445 @@
+// Billboards: quads which always face the camera, for foliage, markers, particles...
+//
+// A few large billboards can be drawn as a quad mesh with the world matrix of
+// `spherical_billboard()` or `cylindrical_billboard()`. Many small ones go into a
+// `BillboardBatch`, where each is a single point expanded into a quad by a geometry shader.
+//
+// Spherical billboards turn in every direction, like the screen. Cylindrical billboards only
+// turn around an axis, so trees stay upright when the camera looks down on them. Both follow the
+// direction of the camera rather than the direction to each billboard, so the CPU and the GPU
+// paths agree and the billboards of a batch never cut through each other.
+
+use std::cmp::Ordering;
+
+use cgmath::{InnerSpace, Matrix4, Vector3};
+use glium::{Blend, BlendingFunction, Depth, DepthTest, DrawError, DrawParameters, LinearBlendingFactor, Program,
+    ProgramCreationError, Surface, VertexBuffer};
+use glium::backend::Facade;
+use glium::index::{NoIndices, PrimitiveType};
+use glium::vertex::BufferCreationError;
+
+use camera::Camera;
+use mesh::Mesh;
+use pipeline::Pipeline;
+use texture::SampledTexture;
+
+const VERTEX_SHADER: &str = r#"
+    #version 330
+
+    in vec3 position;
+    in vec2 size;
+    in vec4 color;
+
+    out vec2 size0;
+    out vec4 color0;
+
+    void main() {
+        gl_Position = vec4(position, 1.0);
+        size0 = size;
+        color0 = color;
+    }
+"#;
+
+// One point in, one quad out as a strip: bottom left, top left, bottom right, top right. Points
+// with no width are dropped. Other vertex shaders can feed it with `size0` and `color0`.
+pub const BILLBOARD_GEOMETRY_SHADER: &str = r#"
+    #version 330
+
+    layout (points) in;
+    layout (triangle_strip, max_vertices = 4) out;
+
+    in vec2 size0[];
+    in vec4 color0[];
+
+    uniform mat4 gVP;
+    uniform vec3 gRight;
+    uniform vec3 gUp;
+
+    out vec2 texCoord0;
+    out vec4 color1;
+
+    void main() {
+        if (size0[0].x <= 0.0) {
+            return;
+        }
+
+        vec3 center = gl_in[0].gl_Position.xyz;
+        vec3 right = gRight * size0[0].x * 0.5;
+        vec3 up = gUp * size0[0].y * 0.5;
+
+        vec2 corners[4] = vec2[](vec2(-1.0, -1.0), vec2(-1.0, 1.0), vec2(1.0, -1.0), vec2(1.0, 1.0));
+        for (int i = 0; i < 4; i++) {
+            vec2 corner = corners[i];
+            gl_Position = gVP * vec4(center + right * corner.x + up * corner.y, 1.0);
+            texCoord0 = corner * 0.5 + 0.5;
+            color1 = color0[0];
+            EmitVertex();
+        }
+        EndPrimitive();
+    }
+"#;
+
+pub const BILLBOARD_FRAGMENT_SHADER: &str = r#"
+    #version 330
+
+    in vec2 texCoord0;
+    in vec4 color1;
+
+    out vec4 fragColor;
+
+    uniform sampler2D gTexture;
+    uniform float gAlphaCutoff;
+
+    void main() {
+        fragColor = texture(gTexture, texCoord0) * color1;
+        if (fragColor.a <= gAlphaCutoff) {
+            discard;
+        }
+    }
+"#;
+
+#[derive(Debug)]
+pub enum BillboardError {
+    Buffer(BufferCreationError),
+    Program(ProgramCreationError),
+    Draw(DrawError)
+}
+
+impl From<BufferCreationError> for BillboardError {
+    fn from(err: BufferCreationError) -> BillboardError {
+        BillboardError::Buffer(err)
+    }
+}
+
+impl From<ProgramCreationError> for BillboardError {
+    fn from(err: ProgramCreationError) -> BillboardError {
+        BillboardError::Program(err)
+    }
+}
+
+impl From<DrawError> for BillboardError {
+    fn from(err: DrawError) -> BillboardError {
+        BillboardError::Draw(err)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BillboardMode {
+    Spherical,
+    // Around this axis, usually +Y
+    Cylindrical(Vector3<f32>)
+}
+
+// The directions of the right and the top of the billboards seen by `camera`
+pub fn billboard_axes(mode: BillboardMode, camera: &Camera) -> (Vector3<f32>, Vector3<f32>) {
+    // Same basis as `init_camera_transform`
+    let forward = camera.get_target().normalize();
+    let right = camera.get_up().cross(forward).normalize();
+
+    match mode {
+        BillboardMode::Spherical => (right, forward.cross(right)),
+        BillboardMode::Cylindrical(axis) => {
+            let up = axis.normalize();
+            // Looking along the axis, any side will do, the one facing the top of the screen
+            let flat = forward - up * up.dot(forward);
+            let flat = if flat.magnitude2() > 1e-6 { flat } else { forward.cross(right) };
+            (up.cross(flat.normalize()), up)
+        }
+    }
+}
+
+// The world matrix putting `quad()` at `position`, `width` x `height` wide and facing the camera
+pub fn billboard_transform(mode: BillboardMode, position: Vector3<f32>, width: f32, height: f32,
+        camera: &Camera) -> Matrix4<f32> {
+    let (right, up) = billboard_axes(mode, camera);
+    let forward = right.cross(up);
+    Matrix4::from_cols((right * width).extend(0.0), (up * height).extend(0.0), forward.extend(0.0),
+        position.extend(1.0))
+}
+
+pub fn spherical_billboard(position: Vector3<f32>, width: f32, height: f32, camera: &Camera) -> Matrix4<f32> {
+    billboard_transform(BillboardMode::Spherical, position, width, height, camera)
+}
+
+pub fn cylindrical_billboard(position: Vector3<f32>, axis: Vector3<f32>, width: f32, height: f32,
+        camera: &Camera) -> Matrix4<f32> {
+    billboard_transform(BillboardMode::Cylindrical(axis), position, width, height, camera)
+}
+
+// A unit square on the XY plane, centered at the origin and facing -Z, which is toward the camera
+// once transformed by `billboard_transform()`. U goes along +X and V along +Y.
+pub fn quad() -> Mesh {
+    let mut mesh = Mesh::new();
+    for &(x, y) in &[(-0.5, -0.5), (0.5, -0.5), (-0.5, 0.5), (0.5, 0.5)] {
+        mesh.positions.push([x, y, 0.0]);
+        mesh.normals.push([0.0, 0.0, -1.0]);
+        mesh.tex_coords.push([x + 0.5, y + 0.5]);
+        // The bitangent is -normal.cross(tangent), i.e. +Y
+        mesh.tangents.push([1.0, 0.0, 0.0, -1.0]);
+    }
+    mesh.indices.extend_from_slice(&[0, 2, 1, 1, 2, 3]);
+    mesh
+}
+
+// A billboard of a batch, also the vertex of the geometry shader
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Billboard {
+    pub position: [f32; 3],
+    // Width and height
+    pub size: [f32; 2],
+    // Multiplies the texture
+    pub color: [f32; 4]
+}
+
+implement_vertex!(Billboard, position, size, color);
+
+impl Billboard {
+    pub fn new(position: Vector3<f32>, width: f32, height: f32) -> Billboard {
+        Billboard {
+            position: position.into(),
+            size: [width, height],
+            color: [1.0, 1.0, 1.0, 1.0]
+        }
+    }
+}
+
+// Sort the billboards from the farthest to the closest along the direction the camera looks,
+// the order alpha blending needs
+pub fn sort_back_to_front(billboards: &mut [Billboard], camera: &Camera) {
+    let pos = camera.get_pos();
+    let forward = camera.get_target().normalize();
+    let depth = |billboard: &Billboard| {
+        let p = billboard.position;
+        (Vector3::new(p[0], p[1], p[2]) - pos).dot(forward)
+    };
+    billboards.sort_by(|a, b| depth(b).partial_cmp(&depth(a)).unwrap_or(Ordering::Equal));
+}
+
+// Adds the colors weighted by their alpha, for glowing things like fire and sparks. Unlike alpha
+// blending, the order does not matter.
+pub fn additive_blending() -> Blend {
+    Blend {
+        color: BlendingFunction::Addition {
+            source: LinearBlendingFactor::SourceAlpha,
+            destination: LinearBlendingFactor::One
+        },
+        alpha: BlendingFunction::Addition {
+            source: LinearBlendingFactor::Zero,
+            destination: LinearBlendingFactor::One
+        },
+        constant_value: (0.0, 0.0, 0.0, 0.0)
+    }
+}
+
+// The program expanding points into quads
+pub struct BillboardRenderer {
+    program: Program,
+    // The fragments at or below this alpha are discarded, so that the cut-out parts of a texture do
+    // not hide what is behind them
+    pub alpha_cutoff: f32,
+    // Alpha blending by default
+    pub blend: Blend
+}
+
+impl BillboardRenderer {
+    pub fn new<F: Facade>(facade: &F) -> Result<BillboardRenderer, BillboardError> {
+        Ok(BillboardRenderer {
+            program: Program::from_source(facade, VERTEX_SHADER, BILLBOARD_FRAGMENT_SHADER,
+                Some(BILLBOARD_GEOMETRY_SHADER))?,
+            alpha_cutoff: 0.01,
+            blend: Blend::alpha_blending()
+        })
+    }
+
+    // The billboards test the depth but do not write it
+    pub fn draw_parameters(&self) -> DrawParameters<'static> {
+        DrawParameters {
+            depth: Depth {
+                test: DepthTest::IfLess,
+                write: false,
+                .. Default::default()
+            },
+            blend: self.blend,
+            .. Default::default()
+        }
+    }
+
+    // Draw the billboards in the order of the buffer, blended over what is already drawn
+    pub fn draw<S: Surface>(&self, surface: &mut S, billboards: &VertexBuffer<Billboard>, pipeline: &mut Pipeline,
+            mode: BillboardMode, camera: &Camera, texture: SampledTexture<'_>) -> Result<(), DrawError> {
+        let (right, up) = billboard_axes(mode, camera);
+        let view_projection: [[f32; 4]; 4] = (pipeline.get_project_trans() * pipeline.get_view_trans()).into();
+        let right: [f32; 3] = right.into();
+        let up: [f32; 3] = up.into();
+        let uniforms = uniform! {
+            gVP: view_projection,
+            gRight: right,
+            gUp: up,
+            gTexture: texture,
+            gAlphaCutoff: self.alpha_cutoff
+        };
+        surface.draw(billboards, NoIndices(PrimitiveType::Points), &self.program, &uniforms, &self.draw_parameters())
+    }
+}
+
+// Billboards gathered on the CPU, sorted and uploaded when they are drawn. The vertex buffer is
+// kept while the number of billboards does not change.
+#[derive(Default)]
+pub struct BillboardBatch {
+    billboards: Vec<Billboard>,
+    buffer: Option<VertexBuffer<Billboard>>
+}
+
+impl BillboardBatch {
+    pub fn new() -> BillboardBatch {
+        BillboardBatch::default()
+    }
+
+    pub fn push(&mut self, billboard: Billboard) {
+        self.billboards.push(billboard);
+    }
+
+    pub fn clear(&mut self) {
+        self.billboards.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.billboards.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.billboards.is_empty()
+    }
+
+    pub fn billboards(&self) -> &[Billboard] {
+        &self.billboards
+    }
+
+    pub fn billboards_mut(&mut self) -> &mut [Billboard] {
+        &mut self.billboards
+    }
+
+    // Sort back to front for `camera`, upload and draw
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw<F: Facade, S: Surface>(&mut self, facade: &F, surface: &mut S, renderer: &BillboardRenderer,
+            pipeline: &mut Pipeline, mode: BillboardMode, camera: &Camera, texture: SampledTexture<'_>)
+            -> Result<(), BillboardError> {
+        if self.billboards.is_empty() {
+            return Ok(());
+        }
+
+        sort_back_to_front(&mut self.billboards, camera);
+        let reuse = self.buffer.as_ref().is_some_and(|buffer| buffer.len() == self.billboards.len());
+        if reuse {
+            self.buffer.as_ref().unwrap().write(&self.billboards);
+        } else {
+            self.buffer = Some(VertexBuffer::dynamic(facade, &self.billboards)?);
+        }
+
+        let buffer = self.buffer.as_ref().unwrap();
+        renderer.draw(surface, buffer, pipeline, mode, camera, texture)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Vector3, Vector4};
+
+    use camera::Camera;
+    use super::*;
+
+    fn cameras() -> Vec<Camera> {
+        vec![
+            Camera::new(800, 600, Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 1.0),
+                Vector3::new(0.0, 1.0, 0.0)),
+            Camera::new(800, 600, Vector3::new(3.0, 5.0, -2.0), Vector3::new(-0.5, -0.7, 1.0),
+                Vector3::new(0.0, 1.0, 0.0)),
+            Camera::new(800, 600, Vector3::new(-4.0, 1.0, 6.0), Vector3::new(1.0, 0.2, -0.3),
+                Vector3::new(0.1, 1.0, 0.2))
+        ]
+    }
+
+    fn assert_close(a: Vector3<f32>, b: Vector3<f32>) {
+        assert!((a - b).magnitude() < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn spherical_axes_face_the_camera() {
+        for camera in cameras() {
+            let (right, up) = billboard_axes(BillboardMode::Spherical, &camera);
+            let forward = camera.get_target().normalize();
+            assert!((right.magnitude() - 1.0).abs() < 1e-4 && (up.magnitude() - 1.0).abs() < 1e-4);
+            assert!(right.dot(up).abs() < 1e-4);
+            assert!(right.dot(forward).abs() < 1e-4 && up.dot(forward).abs() < 1e-4);
+            // The top of the billboard is on the side of the top of the screen
+            assert!(up.dot(camera.get_up()) > 0.0);
+        }
+    }
+
+    #[test]
+    fn cylindrical_up_is_the_axis() {
+        let axis = Vector3::new(0.0, 2.0, 0.0);
+        for camera in cameras() {
+            let (right, up) = billboard_axes(BillboardMode::Cylindrical(axis), &camera);
+            assert_close(up, axis.normalize());
+            assert!((right.magnitude() - 1.0).abs() < 1e-4);
+            assert!(right.dot(up).abs() < 1e-4);
+            assert!(right.dot(camera.get_target()).abs() < 1e-4);
+        }
+
+        // Looking down the axis
+        let camera = Camera::new(800, 600, Vector3::new(0.0, 10.0, 0.0), Vector3::new(0.0, -1.0, 0.0),
+            Vector3::new(0.0, 0.0, 1.0));
+        let (right, up) = billboard_axes(BillboardMode::Cylindrical(axis), &camera);
+        assert_close(up, Vector3::new(0.0, 1.0, 0.0));
+        assert!(right.x.is_finite() && right.y.is_finite() && right.z.is_finite(), "{:?}", right);
+        assert!((right.magnitude() - 1.0).abs() < 1e-4);
+        assert!(right.dot(up).abs() < 1e-4);
+    }
+
+    #[test]
+    fn quads_face_the_camera() {
+        let quad = quad();
+        for camera in cameras() {
+            let position = camera.get_pos() + camera.get_target().normalize() * 5.0 + Vector3::new(0.5, 0.2, 0.0);
+            for &mode in &[BillboardMode::Spherical, BillboardMode::Cylindrical(Vector3::new(0.0, 1.0, 0.0))] {
+                let world = billboard_transform(mode, position, 2.0, 3.0, &camera);
+                let to_camera = camera.get_pos() - position;
+                for (&p, &n) in quad.positions.iter().zip(&quad.normals) {
+                    let normal = (world * Vector4::new(n[0], n[1], n[2], 0.0)).truncate();
+                    assert!(normal.dot(to_camera) > 0.0, "{:?} {:?}", mode, normal);
+
+                    // The corners are half the size away from the center
+                    let corner = (world * Vector4::new(p[0], p[1], p[2], 1.0)).truncate() - position;
+                    let (right, up) = billboard_axes(mode, &camera);
+                    assert!((corner.dot(right).abs() - 1.0).abs() < 1e-4, "{:?}", corner);
+                    assert!((corner.dot(up).abs() - 1.5).abs() < 1e-4, "{:?}", corner);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sorted_far_to_near() {
+        for camera in cameras() {
+            let forward = camera.get_target().normalize();
+            let mut billboards: Vec<Billboard> = [3.0, 10.0, -2.0, 7.5, 0.5, 20.0].iter().enumerate()
+                .map(|(i, &depth)| {
+                    let side = Vector3::new(i as f32, -(i as f32), 0.5);
+                    let side = side - forward * side.dot(forward);
+                    Billboard::new(camera.get_pos() + forward * depth + side, 1.0, 1.0)
+                })
+                .collect();
+            sort_back_to_front(&mut billboards, &camera);
+
+            let depths: Vec<f32> = billboards.iter()
+                .map(|billboard| (Vector3::from(billboard.position) - camera.get_pos()).dot(forward))
+                .collect();
+            for pair in depths.windows(2) {
+                assert!(pair[0] >= pair[1], "{:?}", depths);
+            }
+            assert!((depths[0] - 20.0).abs() < 1e-3 && (depths[5] + 2.0).abs() < 1e-3, "{:?}", depths);
+        }
+    }
+}
@@ -0,0 +1,65 @@
+// Bake models ahead of time into the cache directory `AssetCache` reads them from:
+//
+// > cargo run --bin ogldev-bake -- [-o directory] [--force] model.obj scene.gltf ...
+//
+// Up to date files are skipped unless `--force` is given.
+
+extern crate ogldev;
+
+use std::env;
+use std::process;
+
+use ogldev::asset::{AssetCache, SOURCE_EXTENSIONS};
+
+const DEFAULT_DIRECTORY: &str = "baked";
+
+fn usage() -> ! {
+    eprintln!("usage: ogldev-bake [-o directory] [--force] file...");
+    eprintln!("supported formats: {}", SOURCE_EXTENSIONS.join(", "));
+    process::exit(2);
+}
+
+fn main() {
+    let mut directory = DEFAULT_DIRECTORY.to_string();
+    let mut force = false;
+    let mut sources = Vec::new();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => directory = args.next().unwrap_or_else(|| usage()),
+            "--force" => force = true,
+            "-h" | "--help" => usage(),
+            _ => sources.push(arg)
+        }
+    }
+    if sources.is_empty() {
+        usage();
+    }
+
+    let cache = AssetCache::new(&directory);
+    let mut failed = false;
+    for source in sources.iter() {
+        let up_to_date = !force && cache.is_up_to_date(source).unwrap_or(false);
+        if up_to_date {
+            println!("{}: up to date", source);
+            continue;
+        }
+
+        match cache.bake(source) {
+            Ok(file) => {
+                println!("{} -> {} ({} vertices, {} triangles, {} materials)", source,
+                    cache.baked_path(source).display(), file.vertex_count(), file.index_count() / 3,
+                    file.materials().len());
+            },
+            Err(err) => {
+                eprintln!("{}: {}", source, err);
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        process::exit(1);
+    }
+}
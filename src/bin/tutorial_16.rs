@@ -1,18 +1,15 @@
 #[macro_use]
 extern crate glium;
 extern crate ogldev;
-extern crate image;
-
-use std::path::Path;
 
 use glium::{DisplayBuild, Surface, VertexBuffer, Program, DrawParameters};
 use glium::glutin::{Event, WindowBuilder, VirtualKeyCode};
 use glium::index::{IndexBuffer, PrimitiveType};
 use glium::backend::glutin_backend::GlutinFacade;
-use glium::texture::{RawImage2d, Texture2d};
 use glium::draw_parameters::BackfaceCullingMode;
 
 use ogldev::{Camera, Pipeline};
+use ogldev::texture::{ColorSpace, SampledTexture, TextureManager};
 
 const WINDOW_WIDTH: u32 = 1280;
 const WINDOW_HEIGHT: u32 = 1024;
@@ -86,7 +83,7 @@ fn create_shaders(display: &GlutinFacade) -> Program {
 
 fn render_scene(display: &GlutinFacade, vertex_buffer: &VertexBuffer<Vertex>,
         index_buffer: &IndexBuffer<u32>, program: &Program, camera: &mut Camera, scale: f32,
-        texture: &Texture2d, params: &DrawParameters) {
+        texture: SampledTexture, params: &DrawParameters) {
 
     // Notify the camera
     camera.on_render();
@@ -140,11 +137,9 @@ fn main() {
         .. Default::default()
     };
 
-    // Load a texture
-    let image = image::open(&Path::new("content/test.png")).unwrap().to_rgba();
-    let image_dim = image.dimensions();
-    let image = RawImage2d::from_raw_rgba_reversed(image.into_raw(), image_dim);
-    let texture = Texture2d::new(&display, image).unwrap();
+    // Load a texture. It holds colors, so it is stored in sRGB.
+    let mut textures = TextureManager::new();
+    let texture = textures.load(&display, "content/test.png", ColorSpace::Srgb).unwrap();
 
     // Main loop
     let mut scale: f32 = 0.0;
@@ -155,7 +150,8 @@ fn main() {
         scale += 0.01;
 
         // Render
-        render_scene(&display, &vertex_buffer, &index_buffer, &program, &mut camera, scale, &texture, &params);
+        render_scene(&display, &vertex_buffer, &index_buffer, &program, &mut camera, scale,
+            textures.sampled(texture), &params);
 
         // Handle events
         for event in display.poll_events() {
@@ -0,0 +1,221 @@
+#[macro_use]
+extern crate glium;
+extern crate cgmath;
+extern crate ogldev;
+
+use glium::{DisplayBuild, Surface, VertexBuffer, Program, DrawParameters};
+use glium::glutin::{ElementState, Event, WindowBuilder, VirtualKeyCode};
+use glium::index::{IndexBuffer, PrimitiveType};
+use glium::backend::glutin_backend::GlutinFacade;
+use glium::draw_parameters::BackfaceCullingMode;
+use cgmath::Vector3;
+
+use ogldev::{Camera, Pipeline};
+use ogldev::lighting::{self, DirectionalLight, LightUniforms};
+use ogldev::mesh::normals::{self, NormalWeighting};
+use ogldev::texture::{ColorSpace, TextureHandle, TextureManager};
+
+const WINDOW_WIDTH: u32 = 1280;
+const WINDOW_HEIGHT: u32 = 1024;
+
+// Represent a 3D vertex
+#[derive(Copy, Clone)]
+struct Vertex {
+    position: [f32; 3],
+    tex_coords: [f32; 2],
+    normal: [f32; 3]
+}
+
+// Let glium implement Vertex for us
+implement_vertex!(Vertex, position, tex_coords, normal);
+
+const INDICES: [u32; 12] = [
+    0, 3, 1,
+    1, 3, 2,
+    2, 3, 0,
+    0, 1, 2
+];
+
+fn create_vertex_buffer(display: &GlutinFacade) -> VertexBuffer<Vertex> {
+    let positions = [
+        [-1.0, -1.0, 0.5773],
+        [0.0, -1.0, -1.15475],
+        [1.0, -1.0, 0.5773],
+        [0.0, 1.0, 0.0]
+    ];
+    let tex_coords = [[0.0, 0.0], [0.5, 0.0], [1.0, 0.0], [0.5, 1.0]];
+
+    // The vertices are shared by the faces, so every normal is the average of the normals of the
+    // faces around it
+    let vertex_normals = normals::smooth_normals(&positions, &INDICES, NormalWeighting::Area);
+
+    let vertices: Vec<Vertex> = (0..positions.len()).map(|i| Vertex {
+        position: positions[i],
+        tex_coords: tex_coords[i],
+        normal: vertex_normals[i]
+    }).collect();
+    let vertex_buffer = VertexBuffer::new(display, &vertices).unwrap();
+    vertex_buffer
+}
+
+fn create_index_buffer(display: &GlutinFacade) -> IndexBuffer<u32> {
+    let index_buffer = IndexBuffer::new(display, PrimitiveType::TrianglesList, &INDICES).unwrap();
+    index_buffer
+}
+
+fn create_shaders(display: &GlutinFacade) -> Program {
+    let vertex_shader_src = r#"
+        #version 330
+
+        layout (location = 0) in vec3 position;
+        layout (location = 1) in vec2 tex_coords;
+        layout (location = 2) in vec3 normal;
+
+        uniform mat4 gWVP;
+        uniform mat4 gWorld;
+
+        out vec2 texCoord0;
+        out vec3 normal0;
+
+        void main() {
+            gl_Position = gWVP * vec4(position, 1.0);
+            texCoord0 = tex_coords;
+            // The light is in world space. The world matrix has no scaling, so it can transform
+            // the normals too.
+            normal0 = (gWorld * vec4(normal, 0.0)).xyz;
+        }
+    "#;
+
+    let fragment_shader_src = format!(r#"
+        #version 330
+
+        {}
+
+        in vec2 texCoord0;
+        in vec3 normal0;
+
+        out vec4 fragColor;
+
+        uniform sampler2D gSampler;
+
+        void main() {{
+            fragColor = texture2D(gSampler, texCoord0.xy) * vec4(CalcDirectionalLight(normal0), 1.0);
+        }}
+    "#, lighting::DIRECTIONAL_LIGHT_GLSL);
+
+    Program::from_source(display,
+        vertex_shader_src, &fragment_shader_src, None).unwrap()
+}
+
+fn render_scene(display: &GlutinFacade, vertex_buffer: &VertexBuffer<Vertex>,
+        index_buffer: &IndexBuffer<u32>, program: &Program, camera: &mut Camera, scale: f32,
+        textures: &TextureManager, texture: TextureHandle, light: &DirectionalLight, params: &DrawParameters) {
+
+    // Notify the camera
+    camera.on_render();
+
+    // Create a Pipeline
+    let mut pipeline = Pipeline::new();
+    pipeline.rotate(0.0, scale, 0.0);
+    pipeline.world_pos(0.0, 0.0, 3.0);
+    pipeline.set_camera(camera.get_pos(), camera.get_target(), camera.get_up());
+    pipeline.set_perspective_proj(60.0, WINDOW_WIDTH as f32, WINDOW_HEIGHT as f32, 1.0, 100.0);
+
+    // Set the uniform matrices
+    let wvp: [[f32; 4]; 4] = pipeline.get_wvp_trans().into();
+    let world: [[f32; 4]; 4] = pipeline.get_world_trans().into();
+    let uniform = LightUniforms::new(uniform!{ gWVP: wvp, gWorld: world, gSampler: textures.sampled(texture) })
+        .with_directional("gDirectionalLight", light);
+
+    // Drawing
+    let mut frame = display.draw();
+    frame.clear_color(0.0, 0.0, 0.0, 0.0);
+    frame.draw(vertex_buffer, index_buffer, program,
+        &uniform, params).unwrap();
+    frame.finish().unwrap();
+}
+
+fn main() {
+    // Set up and create a window
+    let display = WindowBuilder::new()
+        .with_dimensions(WINDOW_WIDTH, WINDOW_HEIGHT)
+        .with_srgb(Some(true))
+        .with_title("Tutorial 18")
+        .build_glium()
+        .unwrap();
+
+    // Create a vertex buffer and indices
+    let vertex_buffer = create_vertex_buffer(&display);
+    let index_buffer = create_index_buffer(&display);
+
+    // Create a shader program
+    let program = create_shaders(&display);
+
+    // Create a camera
+    let mut camera = Camera::default(WINDOW_WIDTH, WINDOW_HEIGHT);
+
+    // Setup culling backface
+    // NOTE: Here is a little bit different from the original tutorial. The tutorial says that you
+    // need to specify the front face is drawn clockwisely or counterclockwisely. However, in glium
+    // , to cull back faces, you have to specify the way of identifying BACK FACES. That's why I
+    // give `BackfaceCullingMode::CullCounterClockwise` here, instead of
+    // `BackfaceCullingMode::CullClockwise`. You can try the effect of both parameters.
+    let params = DrawParameters {
+        backface_culling: BackfaceCullingMode::CullCounterClockwise,
+        .. Default::default()
+    };
+
+    // Load a texture. It holds colors, so it is stored in sRGB.
+    let mut textures = TextureManager::new();
+    let texture = textures.load(&display, "content/test.png", ColorSpace::Srgb).unwrap();
+
+    // Create a light. 'A' and 'S' change its ambient intensity, 'Z' and 'X' its diffuse intensity.
+    let mut light = DirectionalLight {
+        ambient_intensity: 0.0,
+        diffuse_intensity: 0.75,
+        direction: Vector3::new(1.0, 0.0, 0.0),
+        .. DirectionalLight::default()
+    };
+
+    // Main loop
+    let mut scale: f32 = 0.0;
+    loop {
+        // Change the scale
+        // (I use a smaller factor than the one used in the original source code
+        // since the original factor is too large in my case)
+        scale += 0.01;
+
+        // Render
+        render_scene(&display, &vertex_buffer, &index_buffer, &program, &mut camera, scale, &textures, texture,
+            &light, &params);
+
+        // Handle events
+        for event in display.poll_events() {
+            match event {
+                Event::Closed => return,
+                Event::KeyboardInput(_, _, Some(VirtualKeyCode::Q)) => {
+                    std::process::exit(0);
+                },
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VirtualKeyCode::A)) => {
+                    light.ambient_intensity += 0.05;
+                },
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VirtualKeyCode::S)) => {
+                    light.ambient_intensity = (light.ambient_intensity - 0.05).max(0.0);
+                },
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VirtualKeyCode::Z)) => {
+                    light.diffuse_intensity += 0.05;
+                },
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VirtualKeyCode::X)) => {
+                    light.diffuse_intensity = (light.diffuse_intensity - 0.05).max(0.0);
+                },
+                Event::KeyboardInput(_, _, Some(key)) => {
+                    camera.on_key_board(key);
+                },
+                Event::MouseMoved(x, y) => {
+                    camera.on_mouse(x, y);
+                },
+                _ => ()
+            }
+        }
+    }
+}
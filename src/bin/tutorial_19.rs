@@ -0,0 +1,208 @@
+#[macro_use]
+extern crate glium;
+extern crate cgmath;
+extern crate ogldev;
+
+use glium::{DisplayBuild, Surface, Program, DrawParameters, Depth, DepthTest};
+use glium::glutin::{ElementState, Event, WindowBuilder, VirtualKeyCode};
+use glium::backend::glutin_backend::GlutinFacade;
+use glium::draw_parameters::BackfaceCullingMode;
+use cgmath::Vector3;
+
+use ogldev::{Camera, Pipeline};
+use ogldev::lighting::{self, DirectionalLight, LightUniforms, Material, SpecularModel};
+use ogldev::mesh::GpuMesh;
+use ogldev::mesh::primitives;
+use ogldev::texture::{ColorSpace, TextureManager};
+
+const WINDOW_WIDTH: u32 = 1280;
+const WINDOW_HEIGHT: u32 = 1024;
+
+fn create_shaders(display: &GlutinFacade) -> Program {
+    let vertex_shader_src = r#"
+        #version 330
+
+        layout (location = 0) in vec3 position;
+        layout (location = 1) in vec3 normal;
+        layout (location = 2) in vec2 tex_coords;
+
+        uniform mat4 gWVP;
+        uniform mat4 gWorld;
+        uniform mat3 gNormal;
+
+        out vec2 texCoord0;
+        out vec3 normal0;
+        out vec3 worldPos0;
+
+        void main() {
+            gl_Position = gWVP * vec4(position, 1.0);
+            texCoord0 = tex_coords;
+            normal0 = gNormal * normal;
+            worldPos0 = (gWorld * vec4(position, 1.0)).xyz;
+        }
+    "#;
+
+    // The highlight is added after the texture, so it keeps the color of the light
+    let fragment_shader_src = format!(r#"
+        #version 330
+
+        {}
+        {}
+        {}
+
+        in vec2 texCoord0;
+        in vec3 normal0;
+        in vec3 worldPos0;
+
+        out vec4 fragColor;
+
+        void main() {{
+            vec3 albedo = MaterialDiffuse(texCoord0);
+            vec3 color = albedo * CalcDirectionalLight(normal0) +
+                CalcDirectionalSpecular(normal0, worldPos0, texCoord0);
+            fragColor = vec4(color, 1.0);
+        }}
+    "#, lighting::DIRECTIONAL_LIGHT_GLSL, lighting::MATERIAL_GLSL, lighting::DIRECTIONAL_SPECULAR_GLSL);
+
+    Program::from_source(display,
+        vertex_shader_src, &fragment_shader_src, None).unwrap()
+}
+
+fn render_object(frame: &mut glium::Frame, mesh: &GpuMesh, program: &Program, pipeline: &mut Pipeline,
+        camera: &Camera, textures: &TextureManager, light: &DirectionalLight, material: &Material,
+        params: &DrawParameters) {
+    // The world and normal matrices are used for the lighting, which is done in world space
+    let wvp: [[f32; 4]; 4] = pipeline.get_wvp_trans().into();
+    let world: [[f32; 4]; 4] = pipeline.get_world_trans().into();
+    let normal: [[f32; 3]; 3] = pipeline.get_normal_trans().into();
+    let uniform = LightUniforms::new(uniform!{ gWVP: wvp, gWorld: world, gNormal: normal })
+        .with_directional("gDirectionalLight", light)
+        .with_material("gMaterial", material, textures)
+        .with_eye(camera);
+
+    mesh.draw(frame, program, &uniform, params).unwrap();
+}
+
+fn render_scene(display: &GlutinFacade, pyramid: &GpuMesh, floor: &GpuMesh, program: &Program,
+        camera: &mut Camera, scale: f32, textures: &TextureManager, light: &DirectionalLight,
+        material: &Material, params: &DrawParameters) {
+
+    // Notify the camera
+    camera.on_render();
+
+    // Create a Pipeline
+    let mut pipeline = Pipeline::new();
+    pipeline.set_camera(camera.get_pos(), camera.get_target(), camera.get_up());
+    pipeline.set_perspective_proj(60.0, WINDOW_WIDTH as f32, WINDOW_HEIGHT as f32, 1.0, 100.0);
+
+    // Drawing
+    let mut frame = display.draw();
+    frame.clear_color_and_depth((0.0, 0.0, 0.0, 0.0), 1.0);
+
+    pipeline.rotate(0.0, scale, 0.0);
+    pipeline.world_pos(0.0, 0.0, 3.0);
+    render_object(&mut frame, pyramid, program, &mut pipeline, camera, textures, light, material, params);
+
+    pipeline.rotate(0.0, 0.0, 0.0);
+    pipeline.world_pos(0.0, -1.0, 3.0);
+    render_object(&mut frame, floor, program, &mut pipeline, camera, textures, light, material, params);
+
+    frame.finish().unwrap();
+}
+
+fn main() {
+    // Set up and create a window
+    let display = WindowBuilder::new()
+        .with_dimensions(WINDOW_WIDTH, WINDOW_HEIGHT)
+        .with_srgb(Some(true))
+        .with_depth_buffer(24)
+        .with_title("Tutorial 19")
+        .build_glium()
+        .unwrap();
+
+    // Create the meshes and a shader program
+    let pyramid = primitives::pyramid().upload(&display).unwrap();
+    let floor = primitives::plane(6.0, 6.0, 1, 1).upload(&display).unwrap();
+    let program = create_shaders(&display);
+
+    // Create a camera
+    let mut camera = Camera::default(WINDOW_WIDTH, WINDOW_HEIGHT);
+
+    let params = DrawParameters {
+        depth: Depth {
+            test: DepthTest::IfLess,
+            write: true,
+            .. Default::default()
+        },
+        backface_culling: BackfaceCullingMode::CullCounterClockwise,
+        .. Default::default()
+    };
+
+    // Load the texture into the material. 'C' and 'V' change the shininess, 'B' switches between
+    // Phong and Blinn-Phong.
+    let mut textures = TextureManager::new();
+    let texture = textures.load(&display, "content/test.png", ColorSpace::Srgb).unwrap();
+    let mut material = Material {
+        diffuse_map: Some(texture),
+        .. Material::new(1.0, 32.0)
+    };
+
+    // Create a light. 'A' and 'S' change its ambient intensity, 'Z' and 'X' its diffuse intensity.
+    let mut light = DirectionalLight {
+        ambient_intensity: 0.1,
+        diffuse_intensity: 0.75,
+        direction: Vector3::new(1.0, -1.0, 1.0),
+        .. DirectionalLight::default()
+    };
+
+    // Main loop
+    let mut scale: f32 = 0.0;
+    loop {
+        scale += 0.01;
+
+        // Render
+        render_scene(&display, &pyramid, &floor, &program, &mut camera, scale, &textures, &light, &material,
+            &params);
+
+        // Handle events
+        for event in display.poll_events() {
+            match event {
+                Event::Closed => return,
+                Event::KeyboardInput(_, _, Some(VirtualKeyCode::Q)) => {
+                    std::process::exit(0);
+                },
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VirtualKeyCode::A)) => {
+                    light.ambient_intensity += 0.05;
+                },
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VirtualKeyCode::S)) => {
+                    light.ambient_intensity = (light.ambient_intensity - 0.05).max(0.0);
+                },
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VirtualKeyCode::Z)) => {
+                    light.diffuse_intensity += 0.05;
+                },
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VirtualKeyCode::X)) => {
+                    light.diffuse_intensity = (light.diffuse_intensity - 0.05).max(0.0);
+                },
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VirtualKeyCode::C)) => {
+                    material.shininess *= 2.0;
+                },
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VirtualKeyCode::V)) => {
+                    material.shininess = (material.shininess / 2.0).max(1.0);
+                },
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VirtualKeyCode::B)) => {
+                    material.model = match material.model {
+                        SpecularModel::Phong => SpecularModel::BlinnPhong,
+                        SpecularModel::BlinnPhong => SpecularModel::Phong
+                    };
+                },
+                Event::KeyboardInput(_, _, Some(key)) => {
+                    camera.on_key_board(key);
+                },
+                Event::MouseMoved(x, y) => {
+                    camera.on_mouse(x, y);
+                },
+                _ => ()
+            }
+        }
+    }
+}
@@ -0,0 +1,207 @@
+#[macro_use]
+extern crate glium;
+extern crate cgmath;
+extern crate ogldev;
+
+use glium::{DisplayBuild, Surface, Program, DrawParameters, Depth, DepthTest};
+use glium::glutin::{Event, WindowBuilder, VirtualKeyCode};
+use glium::backend::glutin_backend::GlutinFacade;
+use glium::draw_parameters::BackfaceCullingMode;
+use cgmath::Vector3;
+
+use ogldev::{Camera, Pipeline};
+use ogldev::lighting::{self, Attenuation, DirectionalLight, LightUniforms, Material, PointLight};
+use ogldev::mesh::GpuMesh;
+use ogldev::mesh::primitives;
+use ogldev::texture::{ColorSpace, TextureManager};
+
+const WINDOW_WIDTH: u32 = 1280;
+const WINDOW_HEIGHT: u32 = 1024;
+
+// The floor is made of TILES x TILES tiles, each lit by its own lights
+const TILES: i32 = 4;
+const TILE_SIZE: f32 = 5.0;
+const LIGHT_COUNT: usize = 12;
+
+fn create_shaders(display: &GlutinFacade) -> Program {
+    let vertex_shader_src = r#"
+        #version 330
+
+        layout (location = 0) in vec3 position;
+        layout (location = 1) in vec3 normal;
+        layout (location = 2) in vec2 tex_coords;
+
+        uniform mat4 gWVP;
+        uniform mat4 gWorld;
+        uniform mat3 gNormal;
+
+        out vec2 texCoord0;
+        out vec3 normal0;
+        out vec3 worldPos0;
+
+        void main() {
+            gl_Position = gWVP * vec4(position, 1.0);
+            texCoord0 = tex_coords;
+            normal0 = gNormal * normal;
+            worldPos0 = (gWorld * vec4(position, 1.0)).xyz;
+        }
+    "#;
+
+    let fragment_shader_src = format!(r#"
+        #version 330
+
+        {}
+        {}
+        {}
+
+        in vec2 texCoord0;
+        in vec3 normal0;
+        in vec3 worldPos0;
+
+        out vec4 fragColor;
+
+        void main() {{
+            vec3 albedo = MaterialDiffuse(texCoord0);
+            vec3 color = albedo * CalcDirectionalLight(normal0) +
+                CalcLocalLights(albedo, normal0, worldPos0, texCoord0);
+            fragColor = vec4(color, 1.0);
+        }}
+    "#, lighting::DIRECTIONAL_LIGHT_GLSL, lighting::MATERIAL_GLSL, lighting::local_lights_glsl());
+
+    Program::from_source(display,
+        vertex_shader_src, &fragment_shader_src, None).unwrap()
+}
+
+// Lights of different colors going around the middle of the floor, some of them far enough to
+// leave tiles out
+fn create_lights(time: f32) -> Vec<PointLight> {
+    let colors = [
+        Vector3::new(1.0, 0.3, 0.3),
+        Vector3::new(0.3, 1.0, 0.3),
+        Vector3::new(0.3, 0.3, 1.0),
+        Vector3::new(1.0, 1.0, 0.3)
+    ];
+
+    (0..LIGHT_COUNT).map(|i| {
+        let radius = 2.0 + 3.0 * (i % 3) as f32;
+        let angle = time * (0.5 + 0.1 * i as f32) + i as f32 * std::f32::consts::PI * 2.0 / LIGHT_COUNT as f32;
+        PointLight::new(colors[i % colors.len()], 0.5,
+            Vector3::new(radius * angle.cos(), 0.5, 10.0 + radius * angle.sin()),
+            Attenuation { constant: 1.0, linear: 0.0, exp: 1.0 })
+    }).collect()
+}
+
+fn render_scene(display: &GlutinFacade, tile: &GpuMesh, tile_bounds: (Vector3<f32>, f32), program: &Program,
+        camera: &mut Camera, time: f32, textures: &TextureManager, material: &Material, params: &DrawParameters) {
+
+    // Notify the camera
+    camera.on_render();
+
+    // Create a Pipeline
+    let mut pipeline = Pipeline::new();
+    pipeline.set_camera(camera.get_pos(), camera.get_target(), camera.get_up());
+    pipeline.set_perspective_proj(60.0, WINDOW_WIDTH as f32, WINDOW_HEIGHT as f32, 1.0, 100.0);
+
+    // A dim light everywhere, the point lights do the rest
+    let light = DirectionalLight {
+        ambient_intensity: 0.05,
+        diffuse_intensity: 0.05,
+        .. DirectionalLight::default()
+    };
+    let point_lights = create_lights(time);
+
+    // Drawing
+    let mut frame = display.draw();
+    frame.clear_color_and_depth((0.0, 0.0, 0.0, 0.0), 1.0);
+
+    for x in 0..TILES {
+        for z in 0..TILES {
+            let position = Vector3::new((x as f32 - (TILES - 1) as f32 / 2.0) * TILE_SIZE, 0.0,
+                10.0 + (z as f32 - (TILES - 1) as f32 / 2.0) * TILE_SIZE);
+            pipeline.world_pos(position.x, position.y, position.z);
+
+            // Only the lights reaching this tile
+            let (center, radius) = tile_bounds;
+            let lights = lighting::cull_point_lights(&point_lights, position + center, radius);
+
+            let wvp: [[f32; 4]; 4] = pipeline.get_wvp_trans().into();
+            let world: [[f32; 4]; 4] = pipeline.get_world_trans().into();
+            let normal: [[f32; 3]; 3] = pipeline.get_normal_trans().into();
+            let uniform = LightUniforms::new(uniform!{ gWVP: wvp, gWorld: world, gNormal: normal })
+                .with_directional("gDirectionalLight", &light)
+                .with_point_lights(&lights)
+                .with_spot_lights(&[])
+                .with_material("gMaterial", material, textures)
+                .with_eye(camera);
+
+            tile.draw(&mut frame, program, &uniform, params).unwrap();
+        }
+    }
+
+    frame.finish().unwrap();
+}
+
+fn main() {
+    // Set up and create a window
+    let display = WindowBuilder::new()
+        .with_dimensions(WINDOW_WIDTH, WINDOW_HEIGHT)
+        .with_srgb(Some(true))
+        .with_depth_buffer(24)
+        .with_title("Tutorial 20")
+        .build_glium()
+        .unwrap();
+
+    // Create a tile of the floor. The lights are culled per tile, with its bounding sphere.
+    let tile_mesh = primitives::plane(TILE_SIZE, TILE_SIZE, 1, 1);
+    let tile_bounds = tile_mesh.bounding_sphere();
+    let tile = tile_mesh.upload(&display).unwrap();
+    let program = create_shaders(&display);
+
+    // Create a camera above the floor, looking down at it
+    let mut camera = Camera::new(WINDOW_WIDTH, WINDOW_HEIGHT, Vector3::new(0.0, 4.0, -2.0),
+        Vector3::new(0.0, -0.5, 1.0), Vector3::new(0.0, 1.0, 0.0));
+
+    let params = DrawParameters {
+        depth: Depth {
+            test: DepthTest::IfLess,
+            write: true,
+            .. Default::default()
+        },
+        backface_culling: BackfaceCullingMode::CullCounterClockwise,
+        .. Default::default()
+    };
+
+    // Load the texture into the material
+    let mut textures = TextureManager::new();
+    let texture = textures.load(&display, "content/test.png", ColorSpace::Srgb).unwrap();
+    let material = Material {
+        diffuse_map: Some(texture),
+        .. Material::new(0.5, 32.0)
+    };
+
+    // Main loop
+    let mut time: f32 = 0.0;
+    loop {
+        time += 0.01;
+
+        // Render
+        render_scene(&display, &tile, tile_bounds, &program, &mut camera, time, &textures, &material, &params);
+
+        // Handle events
+        for event in display.poll_events() {
+            match event {
+                Event::Closed => return,
+                Event::KeyboardInput(_, _, Some(VirtualKeyCode::Q)) => {
+                    std::process::exit(0);
+                },
+                Event::KeyboardInput(_, _, Some(key)) => {
+                    camera.on_key_board(key);
+                },
+                Event::MouseMoved(x, y) => {
+                    camera.on_mouse(x, y);
+                },
+                _ => ()
+            }
+        }
+    }
+}
@@ -0,0 +1,258 @@
+#[macro_use]
+extern crate glium;
+extern crate cgmath;
+extern crate ogldev;
+
+use glium::{DisplayBuild, Surface, Program, DrawParameters, Depth, DepthTest};
+use glium::glutin::{ElementState, Event, WindowBuilder, VirtualKeyCode};
+use glium::backend::glutin_backend::GlutinFacade;
+use glium::draw_parameters::BackfaceCullingMode;
+use cgmath::{Matrix4, Vector3};
+
+use ogldev::{Camera, PersProjInfo, Pipeline};
+use ogldev::lighting::{self, Attenuation, DirectionalLight, LightUniforms, Material, PointLight, SpotLight};
+use ogldev::mesh::GpuMesh;
+use ogldev::mesh::primitives;
+use ogldev::shadow::{self, ShadowMap, ShadowSettings};
+use ogldev::shadow::frustum;
+use ogldev::texture::{ColorSpace, TextureManager};
+
+const WINDOW_WIDTH: u32 = 1280;
+const WINDOW_HEIGHT: u32 = 1024;
+
+// The shadows of the sun reach that far from the camera
+const SHADOW_DISTANCE: f32 = 30.0;
+
+fn create_shaders(display: &GlutinFacade) -> Program {
+    let vertex_shader_src = r#"
+        #version 330
+
+        layout (location = 0) in vec3 position;
+        layout (location = 1) in vec3 normal;
+        layout (location = 2) in vec2 tex_coords;
+
+        uniform mat4 gWVP;
+        uniform mat4 gWorld;
+        uniform mat3 gNormal;
+
+        out vec2 texCoord0;
+        out vec3 normal0;
+        out vec3 worldPos0;
+
+        void main() {
+            gl_Position = gWVP * vec4(position, 1.0);
+            texCoord0 = tex_coords;
+            normal0 = gNormal * normal;
+            worldPos0 = (gWorld * vec4(position, 1.0)).xyz;
+        }
+    "#;
+
+    // Either the sun or the spot light is on, and the shadow map is the one of that light. The
+    // ambient light stays in the shadows.
+    let fragment_shader_src = format!(r#"
+        #version 330
+
+        {}
+        {}
+        {}
+        {}
+        {}
+
+        in vec2 texCoord0;
+        in vec3 normal0;
+        in vec3 worldPos0;
+
+        out vec4 fragColor;
+
+        void main() {{
+            vec3 normal = normalize(normal0);
+            vec3 albedo = MaterialDiffuse(texCoord0);
+
+            vec3 ambient = albedo * gDirectionalLight.Color * gDirectionalLight.AmbientIntensity;
+            vec3 lit = albedo * CalcDirectionalLight(normal) - ambient +
+                CalcDirectionalSpecular(normal, worldPos0, texCoord0) +
+                CalcLocalLights(albedo, normal, worldPos0, texCoord0);
+
+            vec3 toLight = gNumSpotLights > 0 ?
+                normalize(gSpotLights[0].Base.Position - worldPos0) : -gDirectionalLight.Direction;
+            float shadow = CalcShadowFactor(worldPos0, dot(normal, toLight));
+
+            fragColor = vec4(ambient + shadow * lit, 1.0);
+        }}
+    "#, lighting::DIRECTIONAL_LIGHT_GLSL, lighting::MATERIAL_GLSL, lighting::DIRECTIONAL_SPECULAR_GLSL,
+        lighting::local_lights_glsl(), shadow::SHADOW_GLSL);
+
+    Program::from_source(display,
+        vertex_shader_src, &fragment_shader_src, None).unwrap()
+}
+
+struct Object<'a> {
+    mesh: &'a GpuMesh,
+    pipeline: Pipeline
+}
+
+impl<'a> Object<'a> {
+    fn new(mesh: &'a GpuMesh, position: Vector3<f32>, rotate_y: f32) -> Object<'a> {
+        let mut pipeline = Pipeline::new();
+        pipeline.world_pos(position.x, position.y, position.z);
+        pipeline.rotate(0.0, rotate_y, 0.0);
+        Object {
+            mesh: mesh,
+            pipeline: pipeline
+        }
+    }
+}
+
+fn render_scene(display: &GlutinFacade, objects: &mut [Object], program: &Program, camera: &mut Camera,
+        shadow_map: &mut ShadowMap, use_spot: bool, textures: &TextureManager, material: &Material,
+        params: &DrawParameters) {
+
+    // Notify the camera
+    camera.on_render();
+
+    let proj = PersProjInfo {
+        fov: 60.0,
+        width: WINDOW_WIDTH as f32,
+        height: WINDOW_HEIGHT as f32,
+        z_near: 1.0,
+        z_far: 100.0
+    };
+
+    // The lights, and the pipeline of the one casting the shadows: an orthographic one around
+    // what the camera sees for the sun, a perspective one for the spot light
+    let mut sun = DirectionalLight {
+        ambient_intensity: 0.15,
+        diffuse_intensity: 0.85,
+        direction: Vector3::new(1.0, -1.5, 0.8),
+        .. DirectionalLight::default()
+    };
+    let mut spot_lights = Vec::new();
+    let mut light_pipeline = if use_spot {
+        sun.diffuse_intensity = 0.0;
+        let spot = SpotLight::new(
+            PointLight::new(Vector3::new(1.0, 0.9, 0.8), 1.0, Vector3::new(-4.0, 8.0, 4.0),
+                Attenuation { constant: 1.0, linear: 0.05, exp: 0.0 }),
+            Vector3::new(1.0, -2.0, 1.0), 35.0);
+        spot_lights.push(spot);
+        frustum::spot_light_pipeline(&spot, 1.0, 50.0)
+    } else {
+        frustum::fit_directional_light(sun.direction, camera, &proj, SHADOW_DISTANCE, 20.0)
+    };
+
+    // First pass: the depth from the light
+    let world_matrices: Vec<Matrix4<f32>> = objects.iter_mut().map(|object| object.pipeline.get_world_trans()).collect();
+    let casters: Vec<(&GpuMesh, Matrix4<f32>)> = objects.iter().map(|object| object.mesh).zip(world_matrices).collect();
+    shadow_map.render(display, &mut light_pipeline, &casters).unwrap();
+
+    // Second pass: the scene from the camera
+    let mut frame = display.draw();
+    frame.clear_color_and_depth((0.0, 0.0, 0.0, 0.0), 1.0);
+
+    for object in objects.iter_mut() {
+        let pipeline = &mut object.pipeline;
+        pipeline.set_camera(camera.get_pos(), camera.get_target(), camera.get_up());
+        pipeline.set_perspective_proj(proj.fov, proj.width, proj.height, proj.z_near, proj.z_far);
+
+        let wvp: [[f32; 4]; 4] = pipeline.get_wvp_trans().into();
+        let world: [[f32; 4]; 4] = pipeline.get_world_trans().into();
+        let normal: [[f32; 3]; 3] = pipeline.get_normal_trans().into();
+        let uniform = LightUniforms::new(uniform!{ gWVP: wvp, gWorld: world, gNormal: normal })
+            .with_directional("gDirectionalLight", &sun)
+            .with_point_lights(&[])
+            .with_spot_lights(&spot_lights)
+            .with_material("gMaterial", material, textures)
+            .with_shadow_map(shadow_map)
+            .with_eye(camera);
+
+        object.mesh.draw(&mut frame, program, &uniform, params).unwrap();
+    }
+
+    frame.finish().unwrap();
+}
+
+fn main() {
+    // Set up and create a window
+    let display = WindowBuilder::new()
+        .with_dimensions(WINDOW_WIDTH, WINDOW_HEIGHT)
+        .with_srgb(Some(true))
+        .with_depth_buffer(24)
+        .with_title("Tutorial 24")
+        .build_glium()
+        .unwrap();
+
+    // Create the meshes, the shader program and the shadow map
+    let floor = primitives::plane(40.0, 40.0, 1, 1).upload(&display).unwrap();
+    let pyramid = primitives::pyramid().upload(&display).unwrap();
+    let cube = primitives::cube(2.0, 1).upload(&display).unwrap();
+    let sphere = primitives::uv_sphere(1.0, 32, 16).upload(&display).unwrap();
+    let program = create_shaders(&display);
+    let mut shadow_map = ShadowMap::new(&display, ShadowSettings::default()).unwrap();
+
+    // Place the objects on the floor
+    let mut objects = vec![
+        Object::new(&floor, Vector3::new(0.0, 0.0, 10.0), 0.0),
+        Object::new(&pyramid, Vector3::new(0.0, 1.0, 8.0), 0.0),
+        Object::new(&cube, Vector3::new(-4.0, 1.0, 12.0), 30.0),
+        Object::new(&cube, Vector3::new(5.0, 1.0, 16.0), -15.0),
+        Object::new(&sphere, Vector3::new(3.0, 1.5, 9.0), 0.0)
+    ];
+
+    // Create a camera above the floor
+    let mut camera = Camera::new(WINDOW_WIDTH, WINDOW_HEIGHT, Vector3::new(0.0, 5.0, -4.0),
+        Vector3::new(0.0, -0.4, 1.0), Vector3::new(0.0, 1.0, 0.0));
+
+    let params = DrawParameters {
+        depth: Depth {
+            test: DepthTest::IfLess,
+            write: true,
+            .. Default::default()
+        },
+        backface_culling: BackfaceCullingMode::CullCounterClockwise,
+        .. Default::default()
+    };
+
+    // Load the texture into the material
+    let mut textures = TextureManager::new();
+    let texture = textures.load(&display, "content/test.png", ColorSpace::Srgb).unwrap();
+    let material = Material {
+        diffuse_map: Some(texture),
+        .. Material::new(0.3, 32.0)
+    };
+
+    // 'L' switches between the sun and the spot light, 'P' changes the size of the PCF kernel
+    let mut use_spot = false;
+
+    // Main loop
+    let mut angle: f32 = 0.0;
+    loop {
+        angle += 0.5;
+        objects[1].pipeline.rotate(0.0, angle, 0.0);
+
+        // Render
+        render_scene(&display, &mut objects, &program, &mut camera, &mut shadow_map, use_spot, &textures,
+            &material, &params);
+
+        // Handle events
+        for event in display.poll_events() {
+            match event {
+                Event::Closed => return,
+                Event::KeyboardInput(_, _, Some(VirtualKeyCode::Q)) => {
+                    std::process::exit(0);
+                },
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VirtualKeyCode::L)) => {
+                    use_spot = !use_spot;
+                },
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VirtualKeyCode::P)) => {
+                    shadow_map.settings.pcf_radius = (shadow_map.settings.pcf_radius + 1) % 4;
+                },
+                Event::KeyboardInput(_, _, Some(key)) => {
+                    camera.on_key_board(key);
+                },
+                Event::MouseMoved(x, y) => {
+                    camera.on_mouse(x, y);
+                },
+                _ => ()
+            }
+        }
+    }
+}
@@ -0,0 +1,186 @@
+#[macro_use]
+extern crate glium;
+extern crate ogldev;
+
+use std::env;
+
+use glium::{DisplayBuild, Surface, Program, DrawParameters, Depth, DepthTest};
+use glium::glutin::{Event, WindowBuilder, VirtualKeyCode};
+use glium::backend::Facade;
+use glium::backend::glutin_backend::GlutinFacade;
+use glium::draw_parameters::BackfaceCullingMode;
+
+use ogldev::{Camera, Pipeline, Skybox};
+use ogldev::mesh::GpuMesh;
+use ogldev::mesh::primitives;
+use ogldev::texture::{ColorSpace, TextureHandle, TextureManager};
+use ogldev::texture::cubemap;
+
+const WINDOW_WIDTH: u32 = 1280;
+const WINDOW_HEIGHT: u32 = 1024;
+
+fn create_shaders(display: &GlutinFacade) -> Program {
+    let vertex_shader_src = r#"
+        #version 330
+
+        layout (location = 0) in vec3 position;
+        layout (location = 1) in vec2 tex_coords;
+
+        uniform mat4 gWVP;
+
+        out vec2 texCoord0;
+
+        void main() {
+            gl_Position = gWVP * vec4(position, 1.0);
+            texCoord0 = tex_coords;
+        }
+    "#;
+
+    let fragment_shader_src = r#"
+        #version 330
+
+        in vec2 texCoord0;
+
+        out vec4 fragColor;
+
+        uniform sampler2D gSampler;
+
+        void main() {
+            fragColor = texture(gSampler, texCoord0.xy);
+        }
+    "#;
+
+    Program::from_source(display,
+        vertex_shader_src, fragment_shader_src, None).unwrap()
+}
+
+// A sky fading from blue to white at the horizon over a dark ground, used when no image is given
+fn create_default_sky<F: Facade>(display: &F, textures: &mut TextureManager) -> TextureHandle {
+    let (width, height) = (256, 128);
+    let mut pixels = Vec::with_capacity(width * height * 3);
+    for y in 0..height {
+        // From 1 at the top to -1 at the bottom
+        let elevation = 1.0 - 2.0 * (y as f32 + 0.5) / height as f32;
+        let color = if elevation >= 0.0 {
+            let t = elevation.sqrt();
+            [0.9 - 0.7 * t, 0.9 - 0.5 * t, 1.0 - 0.2 * t]
+        } else {
+            [0.15, 0.12, 0.1]
+        };
+        for _ in 0..width {
+            pixels.extend_from_slice(&color);
+        }
+    }
+
+    let size = 128;
+    let faces = cubemap::equirectangular_to_faces(&pixels, width as u32, height as u32, size).unwrap().into_iter()
+        .map(|face| cubemap::rgb_float_face(face, size))
+        .collect();
+    let texture = cubemap::upload_cubemap(display, faces, ColorSpace::Linear).unwrap();
+    textures.insert(texture)
+}
+
+// One argument is a panorama (an .hdr file or any image), six are the faces +X, -X, +Y, -Y, +Z
+// and -Z
+fn load_sky(display: &GlutinFacade, textures: &mut TextureManager) -> TextureHandle {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.len() {
+        0 => create_default_sky(display, textures),
+        1 => textures.load_panorama(display, &args[0], 0).unwrap(),
+        6 => {
+            let faces = [&args[0], &args[1], &args[2], &args[3], &args[4], &args[5]];
+            textures.load_cubemap(display, &faces, ColorSpace::Srgb).unwrap()
+        },
+        _ => {
+            println!("Usage: tutorial_25 [panorama | +X -X +Y -Y +Z -Z]");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn render_scene(display: &GlutinFacade, cube: &GpuMesh, program: &Program, skybox: &Skybox,
+        camera: &mut Camera, scale: f32, textures: &TextureManager, texture: TextureHandle,
+        sky: TextureHandle, params: &DrawParameters) {
+
+    // Notify the camera
+    camera.on_render();
+
+    // Create a Pipeline
+    let mut pipeline = Pipeline::new();
+    pipeline.rotate(0.0, scale, 0.0);
+    pipeline.world_pos(0.0, 0.0, 3.0);
+    pipeline.set_camera(camera.get_pos(), camera.get_target(), camera.get_up());
+    pipeline.set_perspective_proj(60.0, WINDOW_WIDTH as f32, WINDOW_HEIGHT as f32, 1.0, 100.0);
+
+    // Set the uniform matrix
+    let wvp: [[f32; 4]; 4] = pipeline.get_wvp_trans().into();
+    let uniform = uniform!{ gWVP: wvp, gSampler: textures.sampled(texture) };
+
+    // Drawing: first the objects, then the sky in the pixels they leave
+    let mut frame = display.draw();
+    frame.clear_color_and_depth((0.0, 0.0, 0.0, 0.0), 1.0);
+    cube.draw(&mut frame, program, &uniform, params).unwrap();
+    skybox.draw(&mut frame, &mut pipeline, textures.sampled(sky)).unwrap();
+    frame.finish().unwrap();
+}
+
+fn main() {
+    // Set up and create a window
+    let display = WindowBuilder::new()
+        .with_dimensions(WINDOW_WIDTH, WINDOW_HEIGHT)
+        .with_srgb(Some(true))
+        .with_depth_buffer(24)
+        .with_title("Tutorial 25")
+        .build_glium()
+        .unwrap();
+
+    // Create a cube and a shader program
+    let cube = primitives::cube(1.0, 1).upload(&display).unwrap();
+    let program = create_shaders(&display);
+
+    // Create a camera
+    let mut camera = Camera::default(WINDOW_WIDTH, WINDOW_HEIGHT);
+
+    let params = DrawParameters {
+        depth: Depth {
+            test: DepthTest::IfLess,
+            write: true,
+            .. Default::default()
+        },
+        backface_culling: BackfaceCullingMode::CullCounterClockwise,
+        .. Default::default()
+    };
+
+    // Load the textures and create the skybox
+    let mut textures = TextureManager::new();
+    let texture = textures.load(&display, "content/test.png", ColorSpace::Srgb).unwrap();
+    let sky = load_sky(&display, &mut textures);
+    let skybox = Skybox::new(&display).unwrap();
+
+    // Main loop
+    let mut scale: f32 = 0.0;
+    loop {
+        scale += 0.01;
+
+        // Render
+        render_scene(&display, &cube, &program, &skybox, &mut camera, scale, &textures, texture, sky,
+            &params);
+
+        // Handle events
+        for event in display.poll_events() {
+            match event {
+                Event::Closed => return,
+                Event::KeyboardInput(_, _, Some(VirtualKeyCode::Q)) => {
+                    std::process::exit(0);
+                },
+                Event::KeyboardInput(_, _, Some(key)) => {
+                    camera.on_key_board(key);
+                },
+                Event::MouseMoved(x, y) => {
+                    camera.on_mouse(x, y);
+                },
+                _ => ()
+            }
+        }
+    }
+}
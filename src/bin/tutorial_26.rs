@@ -0,0 +1,261 @@
+#[macro_use]
+extern crate glium;
+extern crate cgmath;
+extern crate ogldev;
+
+use glium::{DisplayBuild, Surface, Program, DrawParameters, Depth, DepthTest};
+use glium::glutin::{ElementState, Event, WindowBuilder, VirtualKeyCode};
+use glium::backend::glutin_backend::GlutinFacade;
+use glium::draw_parameters::BackfaceCullingMode;
+use cgmath::Vector3;
+
+use ogldev::{Camera, Pipeline};
+use ogldev::lighting::{self, DirectionalLight, LightUniforms, Material, NormalMapConvention};
+use ogldev::mesh::GpuMesh;
+use ogldev::mesh::primitives;
+use ogldev::texture::{ColorSpace, TextureManager};
+
+const WINDOW_WIDTH: u32 = 1280;
+const WINDOW_HEIGHT: u32 = 1024;
+
+const BUMP_MAP_SIZE: u32 = 256;
+
+fn create_shaders(display: &GlutinFacade) -> Program {
+    // The tangent is a direction, like the normal, but it stays in the surface so the world matrix
+    // moves it correctly
+    let vertex_shader_src = r#"
+        #version 330
+
+        layout (location = 0) in vec3 position;
+        layout (location = 1) in vec3 normal;
+        layout (location = 2) in vec2 tex_coords;
+        layout (location = 3) in vec4 tangent;
+
+        uniform mat4 gWVP;
+        uniform mat4 gWorld;
+        uniform mat3 gNormal;
+
+        out vec2 texCoord0;
+        out vec3 normal0;
+        out vec4 tangent0;
+        out vec3 worldPos0;
+
+        void main() {
+            gl_Position = gWVP * vec4(position, 1.0);
+            texCoord0 = tex_coords;
+            normal0 = gNormal * normal;
+            tangent0 = vec4(mat3(gWorld) * tangent.xyz, tangent.w);
+            worldPos0 = (gWorld * vec4(position, 1.0)).xyz;
+        }
+    "#;
+
+    let fragment_shader_src = format!(r#"
+        #version 330
+
+        {}
+        {}
+        {}
+
+        in vec2 texCoord0;
+        in vec3 normal0;
+        in vec4 tangent0;
+        in vec3 worldPos0;
+
+        out vec4 fragColor;
+
+        void main() {{
+            vec3 normal = MaterialNormal(normal0, tangent0, texCoord0);
+            vec3 albedo = MaterialDiffuse(texCoord0);
+            vec3 color = albedo * CalcDirectionalLight(normal) +
+                CalcDirectionalSpecular(normal, worldPos0, texCoord0);
+            fragColor = vec4(color, 1.0);
+        }}
+    "#, lighting::DIRECTIONAL_LIGHT_GLSL, lighting::MATERIAL_GLSL, lighting::DIRECTIONAL_SPECULAR_GLSL);
+
+    Program::from_source(display,
+        vertex_shader_src, &fragment_shader_src, None).unwrap()
+}
+
+// The heights of a brick wall, 4 rows of 2 bricks with every other row shifted by half a brick.
+// The mortar is at 0 and the bricks are beveled up to 1.
+fn brick_heights(size: u32) -> Vec<f32> {
+    let rows = 4.0;
+    let columns = 2.0;
+    let mortar = 0.04;
+    let bevel = 0.06;
+
+    let mut heights = Vec::with_capacity((size * size) as usize);
+    for y in 0..size {
+        for x in 0..size {
+            let v = y as f32 / size as f32 * rows;
+            let shift = if v.floor() as i32 % 2 == 1 { 0.5 } else { 0.0 };
+            let u = (x as f32 / size as f32 * columns + shift) % 1.0;
+            let v = v.fract();
+
+            // The distance to the closest edge of the brick, in fractions of its height
+            let edge = (u.min(1.0 - u) * rows / columns).min(v.min(1.0 - v));
+            heights.push(((edge - mortar) / bevel).clamp(0.0, 1.0));
+        }
+    }
+    heights
+}
+
+fn render_object(frame: &mut glium::Frame, mesh: &GpuMesh, program: &Program, pipeline: &mut Pipeline,
+        camera: &Camera, textures: &TextureManager, light: &DirectionalLight, material: &Material,
+        params: &DrawParameters) {
+    let wvp: [[f32; 4]; 4] = pipeline.get_wvp_trans().into();
+    let world: [[f32; 4]; 4] = pipeline.get_world_trans().into();
+    let normal: [[f32; 3]; 3] = pipeline.get_normal_trans().into();
+    let uniform = LightUniforms::new(uniform!{ gWVP: wvp, gWorld: world, gNormal: normal })
+        .with_directional("gDirectionalLight", light)
+        .with_material("gMaterial", material, textures)
+        .with_eye(camera);
+
+    mesh.draw(frame, program, &uniform, params).unwrap();
+}
+
+fn render_scene(display: &GlutinFacade, cube: &GpuMesh, sphere: &GpuMesh, floor: &GpuMesh, program: &Program,
+        camera: &mut Camera, scale: f32, textures: &TextureManager, light: &DirectionalLight,
+        material: &Material, params: &DrawParameters) {
+
+    // Notify the camera
+    camera.on_render();
+
+    // Create a Pipeline
+    let mut pipeline = Pipeline::new();
+    pipeline.set_camera(camera.get_pos(), camera.get_target(), camera.get_up());
+    pipeline.set_perspective_proj(60.0, WINDOW_WIDTH as f32, WINDOW_HEIGHT as f32, 1.0, 100.0);
+
+    // Drawing
+    let mut frame = display.draw();
+    frame.clear_color_and_depth((0.0, 0.0, 0.0, 0.0), 1.0);
+
+    pipeline.rotate(0.0, scale, 0.0);
+    pipeline.world_pos(-1.5, 0.0, 5.0);
+    render_object(&mut frame, cube, program, &mut pipeline, camera, textures, light, material, params);
+
+    pipeline.world_pos(1.5, 0.0, 5.0);
+    render_object(&mut frame, sphere, program, &mut pipeline, camera, textures, light, material, params);
+
+    pipeline.rotate(0.0, 0.0, 0.0);
+    pipeline.world_pos(0.0, -1.0, 5.0);
+    render_object(&mut frame, floor, program, &mut pipeline, camera, textures, light, material, params);
+
+    frame.finish().unwrap();
+}
+
+fn main() {
+    // Set up and create a window
+    let display = WindowBuilder::new()
+        .with_dimensions(WINDOW_WIDTH, WINDOW_HEIGHT)
+        .with_srgb(Some(true))
+        .with_depth_buffer(24)
+        .with_title("Tutorial 26")
+        .build_glium()
+        .unwrap();
+
+    // Create the meshes, which all come with their tangents, and a shader program
+    let cube = primitives::cube(2.0, 1).upload(&display).unwrap();
+    let sphere = primitives::uv_sphere(1.0, 48, 24).upload(&display).unwrap();
+    let floor = primitives::plane(8.0, 8.0, 1, 1).upload(&display).unwrap();
+    let program = create_shaders(&display);
+
+    // Create a camera
+    let mut camera = Camera::default(WINDOW_WIDTH, WINDOW_HEIGHT);
+
+    let params = DrawParameters {
+        depth: Depth {
+            test: DepthTest::IfLess,
+            write: true,
+            .. Default::default()
+        },
+        backface_culling: BackfaceCullingMode::CullCounterClockwise,
+        .. Default::default()
+    };
+
+    // The normal maps hold directions, not colors, so they are linear. Both conventions are
+    // generated from the same bricks: read with the right setting, they look the same, and read
+    // with the wrong one, the light seems to come from below.
+    let mut textures = TextureManager::new();
+    let diffuse = textures.load(&display, "content/test.png", ColorSpace::Srgb).unwrap();
+    let heights = brick_heights(BUMP_MAP_SIZE);
+    let bump_map = |convention| lighting::normal_map_from_heights(&heights, BUMP_MAP_SIZE, BUMP_MAP_SIZE, 8.0,
+        convention);
+    let opengl_map = textures.insert_image(&display, bump_map(NormalMapConvention::OpenGl), ColorSpace::Linear)
+        .unwrap();
+    let directx_map = textures.insert_image(&display, bump_map(NormalMapConvention::DirectX), ColorSpace::Linear)
+        .unwrap();
+
+    // 'N' switches the normal map on and off, 'G' switches between the OpenGL map and the DirectX
+    // map, and 'H' reads the current map with the other convention
+    let mut normal_mapping = true;
+    let mut map_convention = NormalMapConvention::OpenGl;
+    let mut read_convention = NormalMapConvention::OpenGl;
+    let base_material = Material {
+        diffuse_map: Some(diffuse),
+        .. Material::new(0.5, 32.0)
+    };
+
+    // The light turns around the objects to show the bumps
+    let mut light = DirectionalLight {
+        ambient_intensity: 0.1,
+        diffuse_intensity: 0.8,
+        .. DirectionalLight::default()
+    };
+
+    // Main loop
+    let mut scale: f32 = 0.0;
+    loop {
+        scale += 0.002;
+        let angle = scale * 3.0;
+        light.direction = Vector3::new(angle.cos(), -0.5, angle.sin());
+
+        let map = match map_convention {
+            NormalMapConvention::OpenGl => opengl_map,
+            NormalMapConvention::DirectX => directx_map
+        };
+        let material = if normal_mapping {
+            base_material.with_normal_map(map, read_convention)
+        } else {
+            base_material
+        };
+
+        // Render
+        render_scene(&display, &cube, &sphere, &floor, &program, &mut camera, scale, &textures, &light,
+            &material, &params);
+
+        // Handle events
+        for event in display.poll_events() {
+            match event {
+                Event::Closed => return,
+                Event::KeyboardInput(_, _, Some(VirtualKeyCode::Q)) => {
+                    std::process::exit(0);
+                },
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VirtualKeyCode::N)) => {
+                    normal_mapping = !normal_mapping;
+                },
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VirtualKeyCode::G)) => {
+                    map_convention = other_convention(map_convention);
+                    read_convention = other_convention(read_convention);
+                },
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VirtualKeyCode::H)) => {
+                    read_convention = other_convention(read_convention);
+                },
+                Event::KeyboardInput(_, _, Some(key)) => {
+                    camera.on_key_board(key);
+                },
+                Event::MouseMoved(x, y) => {
+                    camera.on_mouse(x, y);
+                },
+                _ => ()
+            }
+        }
+    }
+}
+
+fn other_convention(convention: NormalMapConvention) -> NormalMapConvention {
+    match convention {
+        NormalMapConvention::OpenGl => NormalMapConvention::DirectX,
+        NormalMapConvention::DirectX => NormalMapConvention::OpenGl
+    }
+}
@@ -0,0 +1,213 @@
+#[macro_use]
+extern crate glium;
+extern crate cgmath;
+extern crate image;
+extern crate ogldev;
+
+use glium::{DisplayBuild, Surface, Program, DrawParameters, Depth, DepthTest};
+use glium::glutin::{ElementState, Event, WindowBuilder, VirtualKeyCode};
+use glium::backend::glutin_backend::GlutinFacade;
+use cgmath::Vector3;
+use image::{Rgba, RgbaImage};
+
+use ogldev::{Camera, Pipeline};
+use ogldev::billboard::{self, Billboard, BillboardBatch, BillboardMode, BillboardRenderer};
+use ogldev::mesh::GpuMesh;
+use ogldev::mesh::primitives;
+use ogldev::texture::{ColorSpace, TextureHandle, TextureManager};
+
+const WINDOW_WIDTH: u32 = 1280;
+const WINDOW_HEIGHT: u32 = 1024;
+
+const TREE_ROWS: u32 = 50;
+
+fn create_shaders(display: &GlutinFacade) -> Program {
+    let vertex_shader_src = r#"
+        #version 330
+
+        layout (location = 0) in vec3 position;
+        layout (location = 1) in vec2 tex_coords;
+
+        uniform mat4 gWVP;
+
+        out vec2 texCoord0;
+
+        void main() {
+            gl_Position = gWVP * vec4(position, 1.0);
+            texCoord0 = tex_coords;
+        }
+    "#;
+
+    // The cut-out parts of the marker are discarded
+    let fragment_shader_src = r#"
+        #version 330
+
+        in vec2 texCoord0;
+
+        out vec4 fragColor;
+
+        uniform sampler2D gSampler;
+
+        void main() {
+            fragColor = texture(gSampler, texCoord0.xy);
+            if (fragColor.a < 0.5) {
+                discard;
+            }
+        }
+    "#;
+
+    Program::from_source(display,
+        vertex_shader_src, fragment_shader_src, None).unwrap()
+}
+
+// A tree seen from the side: a trunk under a round crown, with soft edges for the blending
+fn create_tree_image(size: u32) -> RgbaImage {
+    RgbaImage::from_fn(size, size, |x, y| {
+        let u = (x as f32 + 0.5) / size as f32 - 0.5;
+        let v = 1.0 - (y as f32 + 0.5) / size as f32;
+
+        let crown = 0.3 - (u * u + (v - 0.6) * (v - 0.6)).sqrt();
+        let trunk = (0.04 - u.abs()).min(0.45 - v);
+        let coverage = (crown.max(trunk) * size as f32 * 0.25 + 0.5).clamp(0.0, 1.0);
+
+        let color = if crown > trunk { [40, 110 + (v * 80.0) as u8, 40] } else { [90, 60, 30] };
+        Rgba { data: [color[0], color[1], color[2], (coverage * 255.0) as u8] }
+    })
+}
+
+// A red ring, drawn with the CPU billboard matrix
+fn create_marker_image(size: u32) -> RgbaImage {
+    RgbaImage::from_fn(size, size, |x, y| {
+        let u = (x as f32 + 0.5) / size as f32 - 0.5;
+        let v = (y as f32 + 0.5) / size as f32 - 0.5;
+        let distance = (u * u + v * v).sqrt();
+        let alpha = if distance > 0.3 && distance < 0.45 { 255 } else { 0 };
+        Rgba { data: [220, 30, 30, alpha] }
+    })
+}
+
+// Trees on a jittered grid, with a simple generator so that the forest is the same every time
+fn plant_trees(batch: &mut BillboardBatch) {
+    let mut seed: u32 = 12345;
+    let mut random = || {
+        seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        (seed >> 8) as f32 / (1 << 24) as f32
+    };
+
+    for row in 0..TREE_ROWS {
+        for column in 0..TREE_ROWS {
+            let x = (column as f32 - TREE_ROWS as f32 / 2.0 + random()) * 2.0;
+            let z = (row as f32 + random()) * 2.0 + 3.0;
+            let height = 1.5 + random();
+            let mut tree = Billboard::new(Vector3::new(x, -1.0 + height / 2.0, z), height, height);
+            let shade = 0.8 + random() * 0.2;
+            tree.color = [shade, shade, shade, 1.0];
+            batch.push(tree);
+        }
+    }
+}
+
+fn render_scene(display: &GlutinFacade, floor: &GpuMesh, marker: &GpuMesh, program: &Program,
+        renderer: &BillboardRenderer, trees: &mut BillboardBatch, camera: &mut Camera, mode: BillboardMode,
+        textures: &TextureManager, ground: TextureHandle, tree: TextureHandle, ring: TextureHandle) {
+
+    // Notify the camera
+    camera.on_render();
+
+    // Create a Pipeline
+    let mut pipeline = Pipeline::new();
+    pipeline.set_camera(camera.get_pos(), camera.get_target(), camera.get_up());
+    pipeline.set_perspective_proj(60.0, WINDOW_WIDTH as f32, WINDOW_HEIGHT as f32, 1.0, 200.0);
+
+    // Drawing
+    let mut frame = display.draw();
+    frame.clear_color_and_depth((0.5, 0.7, 0.9, 0.0), 1.0);
+
+    let params = DrawParameters {
+        depth: Depth {
+            test: DepthTest::IfLess,
+            write: true,
+            .. Default::default()
+        },
+        .. Default::default()
+    };
+
+    // The opaque objects first
+    pipeline.world_pos(0.0, -1.0, 50.0);
+    let wvp: [[f32; 4]; 4] = pipeline.get_wvp_trans().into();
+    let uniforms = uniform! { gWVP: wvp, gSampler: textures.sampled(ground) };
+    floor.draw(&mut frame, program, &uniforms, &params).unwrap();
+
+    // One billboard placed by its world matrix, floating above the first trees
+    let view_projection = pipeline.get_project_trans() * pipeline.get_view_trans();
+    let world = billboard::billboard_transform(mode, Vector3::new(0.0, 3.0, 8.0), 2.0, 2.0, camera);
+    let wvp: [[f32; 4]; 4] = (view_projection * world).into();
+    let uniforms = uniform! { gWVP: wvp, gSampler: textures.sampled(ring) };
+    marker.draw(&mut frame, program, &uniforms, &params).unwrap();
+
+    // Then the blended trees, sorted by the batch
+    trees.draw(display, &mut frame, renderer, &mut pipeline, mode, camera, textures.sampled(tree)).unwrap();
+
+    frame.finish().unwrap();
+}
+
+fn main() {
+    // Set up and create a window
+    let display = WindowBuilder::new()
+        .with_dimensions(WINDOW_WIDTH, WINDOW_HEIGHT)
+        .with_srgb(Some(true))
+        .with_depth_buffer(24)
+        .with_title("Tutorial 27")
+        .build_glium()
+        .unwrap();
+
+    // Create the meshes and the shader programs
+    let floor = primitives::plane(120.0, 120.0, 1, 1).upload(&display).unwrap();
+    let marker = billboard::quad().upload(&display).unwrap();
+    let program = create_shaders(&display);
+    let renderer = BillboardRenderer::new(&display).unwrap();
+
+    // Create a camera
+    let mut camera = Camera::default(WINDOW_WIDTH, WINDOW_HEIGHT);
+
+    let mut textures = TextureManager::new();
+    let ground = textures.load(&display, "content/test.png", ColorSpace::Srgb).unwrap();
+    let tree = textures.insert_image(&display, create_tree_image(128), ColorSpace::Srgb).unwrap();
+    let ring = textures.insert_image(&display, create_marker_image(128), ColorSpace::Srgb).unwrap();
+
+    let mut trees = BillboardBatch::new();
+    plant_trees(&mut trees);
+
+    // 'M' switches between spherical billboards and cylindrical ones around +Y
+    let mut mode = BillboardMode::Cylindrical(Vector3::new(0.0, 1.0, 0.0));
+
+    // Main loop
+    loop {
+        // Render
+        render_scene(&display, &floor, &marker, &program, &renderer, &mut trees, &mut camera, mode, &textures,
+            ground, tree, ring);
+
+        // Handle events
+        for event in display.poll_events() {
+            match event {
+                Event::Closed => return,
+                Event::KeyboardInput(_, _, Some(VirtualKeyCode::Q)) => {
+                    std::process::exit(0);
+                },
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VirtualKeyCode::M)) => {
+                    mode = match mode {
+                        BillboardMode::Spherical => BillboardMode::Cylindrical(Vector3::new(0.0, 1.0, 0.0)),
+                        BillboardMode::Cylindrical(_) => BillboardMode::Spherical
+                    };
+                },
+                Event::KeyboardInput(_, _, Some(key)) => {
+                    camera.on_key_board(key);
+                },
+                Event::MouseMoved(x, y) => {
+                    camera.on_mouse(x, y);
+                },
+                _ => ()
+            }
+        }
+    }
+}
@@ -0,0 +1,255 @@
+#[macro_use]
+extern crate glium;
+extern crate cgmath;
+extern crate image;
+extern crate ogldev;
+
+use std::time::Instant;
+
+use glium::{DisplayBuild, Surface, Program, DrawParameters, Depth, DepthTest};
+use glium::glutin::{ElementState, Event, WindowBuilder, VirtualKeyCode};
+use glium::backend::glutin_backend::GlutinFacade;
+use cgmath::Vector3;
+use image::{Rgba, RgbaImage};
+
+use ogldev::{Camera, Pipeline};
+use ogldev::billboard::{self, BillboardRenderer};
+use ogldev::mesh::GpuMesh;
+use ogldev::mesh::primitives;
+use ogldev::particles::{Emitter, ParticleSettings, ParticleSystem};
+use ogldev::texture::{ColorSpace, TextureHandle, TextureManager};
+
+const WINDOW_WIDTH: u32 = 1280;
+const WINDOW_HEIGHT: u32 = 1024;
+
+fn create_shaders(display: &GlutinFacade) -> Program {
+    let vertex_shader_src = r#"
+        #version 330
+
+        layout (location = 0) in vec3 position;
+        layout (location = 1) in vec2 tex_coords;
+
+        uniform mat4 gWVP;
+
+        out vec2 texCoord0;
+
+        void main() {
+            gl_Position = gWVP * vec4(position, 1.0);
+            texCoord0 = tex_coords;
+        }
+    "#;
+
+    let fragment_shader_src = r#"
+        #version 330
+
+        in vec2 texCoord0;
+
+        out vec4 fragColor;
+
+        uniform sampler2D gSampler;
+
+        void main() {
+            fragColor = texture(gSampler, texCoord0.xy);
+        }
+    "#;
+
+    Program::from_source(display,
+        vertex_shader_src, fragment_shader_src, None).unwrap()
+}
+
+// A white disc fading out toward its edge, tinted by the colors of the particles
+fn create_particle_image(size: u32) -> RgbaImage {
+    RgbaImage::from_fn(size, size, |x, y| {
+        let u = (x as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+        let v = (y as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+        let falloff = (1.0 - (u * u + v * v).sqrt()).max(0.0);
+        Rgba { data: [255, 255, 255, (falloff * falloff * 255.0) as u8] }
+    })
+}
+
+// Flames rising from a narrow cone and turning red as they shrink
+fn fire_settings() -> ParticleSettings {
+    ParticleSettings {
+        emitter: Emitter::Cone { direction: Vector3::new(0.0, 1.0, 0.0), angle: 15.0 },
+        position: Vector3::new(-3.0, -1.0, 8.0),
+        rate: 400.0,
+        min_lifetime: 0.6,
+        max_lifetime: 1.2,
+        min_speed: 1.0,
+        max_speed: 2.0,
+        gravity: Vector3::new(0.0, 1.0, 0.0),
+        start_color: [1.0, 0.8, 0.3, 0.8],
+        end_color: [0.8, 0.1, 0.0, 0.0],
+        start_size: 0.6,
+        end_size: 0.2
+    }
+}
+
+// Smoke puffs spreading and thinning out as they slowly rise
+fn smoke_settings() -> ParticleSettings {
+    ParticleSettings {
+        emitter: Emitter::Sphere { radius: 0.3 },
+        position: Vector3::new(0.0, -0.5, 8.0),
+        rate: 40.0,
+        min_lifetime: 3.0,
+        max_lifetime: 5.0,
+        min_speed: 0.1,
+        max_speed: 0.3,
+        gravity: Vector3::new(0.0, 0.3, 0.0),
+        start_color: [0.5, 0.5, 0.5, 0.6],
+        end_color: [0.3, 0.3, 0.3, 0.0],
+        start_size: 0.5,
+        end_size: 2.0
+    }
+}
+
+// Sparks shooting out in every direction and falling back
+fn spark_settings() -> ParticleSettings {
+    ParticleSettings {
+        emitter: Emitter::Point,
+        position: Vector3::new(3.0, 1.0, 8.0),
+        rate: 300.0,
+        min_lifetime: 1.0,
+        max_lifetime: 2.0,
+        min_speed: 2.0,
+        max_speed: 4.0,
+        gravity: Vector3::new(0.0, -9.81, 0.0),
+        start_color: [1.0, 0.9, 0.5, 1.0],
+        end_color: [1.0, 0.4, 0.1, 0.0],
+        start_size: 0.08,
+        end_size: 0.04
+    }
+}
+
+// The fire and the sparks on the GPU if possible, the smoke always on the CPU because it needs to
+// be sorted
+fn create_systems(display: &GlutinFacade, gpu: bool) -> (ParticleSystem, ParticleSystem) {
+    if gpu {
+        (ParticleSystem::new(display, fire_settings(), 1).unwrap(),
+            ParticleSystem::new(display, spark_settings(), 2).unwrap())
+    } else {
+        (ParticleSystem::cpu(fire_settings(), 1), ParticleSystem::cpu(spark_settings(), 2))
+    }
+}
+
+fn render_scene(display: &GlutinFacade, floor: &GpuMesh, program: &Program, camera: &mut Camera,
+        systems: &mut [&mut ParticleSystem], glowing: &BillboardRenderer, blended: &BillboardRenderer,
+        textures: &TextureManager, ground: TextureHandle, particle: TextureHandle) {
+
+    // Notify the camera
+    camera.on_render();
+
+    // Create a Pipeline
+    let mut pipeline = Pipeline::new();
+    pipeline.set_camera(camera.get_pos(), camera.get_target(), camera.get_up());
+    pipeline.set_perspective_proj(60.0, WINDOW_WIDTH as f32, WINDOW_HEIGHT as f32, 1.0, 100.0);
+
+    // Drawing
+    let mut frame = display.draw();
+    frame.clear_color_and_depth((0.0, 0.0, 0.0, 0.0), 1.0);
+
+    let params = DrawParameters {
+        depth: Depth {
+            test: DepthTest::IfLess,
+            write: true,
+            .. Default::default()
+        },
+        .. Default::default()
+    };
+
+    pipeline.world_pos(0.0, -1.0, 8.0);
+    let wvp: [[f32; 4]; 4] = pipeline.get_wvp_trans().into();
+    let uniforms = uniform! { gWVP: wvp, gSampler: textures.sampled(ground) };
+    floor.draw(&mut frame, program, &uniforms, &params).unwrap();
+
+    // The smoke first, then the glowing particles added on top of it
+    let (smoke, glowing_systems) = systems.split_first_mut().unwrap();
+    smoke.draw(display, &mut frame, blended, &mut pipeline, camera, textures.sampled(particle)).unwrap();
+    for system in glowing_systems {
+        system.draw(display, &mut frame, glowing, &mut pipeline, camera, textures.sampled(particle)).unwrap();
+    }
+
+    frame.finish().unwrap();
+}
+
+fn main() {
+    // Set up and create a window
+    let display = WindowBuilder::new()
+        .with_dimensions(WINDOW_WIDTH, WINDOW_HEIGHT)
+        .with_srgb(Some(true))
+        .with_depth_buffer(24)
+        .with_title("Tutorial 28")
+        .build_glium()
+        .unwrap();
+
+    // Create the floor, the shader programs and the billboard renderers
+    let floor = primitives::plane(20.0, 20.0, 1, 1).upload(&display).unwrap();
+    let program = create_shaders(&display);
+    let blended = BillboardRenderer::new(&display).unwrap();
+    let mut glowing = BillboardRenderer::new(&display).unwrap();
+    glowing.blend = billboard::additive_blending();
+
+    // Create a camera
+    let mut camera = Camera::default(WINDOW_WIDTH, WINDOW_HEIGHT);
+
+    let mut textures = TextureManager::new();
+    let ground = textures.load(&display, "content/test.png", ColorSpace::Srgb).unwrap();
+    let particle = textures.insert_image(&display, create_particle_image(64), ColorSpace::Linear).unwrap();
+
+    // 'G' switches the fire and the sparks between the GPU and the CPU, 'E' stops and restarts
+    // the emitters
+    let mut gpu = true;
+    let mut emitting = true;
+    let mut smoke = ParticleSystem::cpu(smoke_settings(), 3);
+    let (mut fire, mut sparks) = create_systems(&display, gpu);
+    println!("Simulating on the {}", if fire.is_gpu() { "GPU" } else { "CPU" });
+
+    // Main loop
+    let mut last_frame = Instant::now();
+    loop {
+        // Move the particles by the time of the last frame, at most a tenth of a second
+        let now = Instant::now();
+        let elapsed = now - last_frame;
+        let dt = (elapsed.as_secs() as f32 + elapsed.subsec_nanos() as f32 * 1e-9).min(0.1);
+        last_frame = now;
+        for system in &mut [&mut smoke, &mut fire, &mut sparks] {
+            system.update(&display, dt).unwrap();
+        }
+
+        // Render
+        render_scene(&display, &floor, &program, &mut camera, &mut [&mut smoke, &mut fire, &mut sparks], &glowing,
+            &blended, &textures, ground, particle);
+
+        // Handle events
+        for event in display.poll_events() {
+            match event {
+                Event::Closed => return,
+                Event::KeyboardInput(_, _, Some(VirtualKeyCode::Q)) => {
+                    std::process::exit(0);
+                },
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VirtualKeyCode::G)) => {
+                    gpu = !gpu;
+                    let systems = create_systems(&display, gpu);
+                    fire = systems.0;
+                    sparks = systems.1;
+                    fire.set_emitting(emitting);
+                    sparks.set_emitting(emitting);
+                    println!("Simulating on the {}", if fire.is_gpu() { "GPU" } else { "CPU" });
+                },
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VirtualKeyCode::E)) => {
+                    emitting = !emitting;
+                    for system in &mut [&mut smoke, &mut fire, &mut sparks] {
+                        system.set_emitting(emitting);
+                    }
+                },
+                Event::KeyboardInput(_, _, Some(key)) => {
+                    camera.on_key_board(key);
+                },
+                Event::MouseMoved(x, y) => {
+                    camera.on_mouse(x, y);
+                },
+                _ => ()
+            }
+        }
+    }
+}
@@ -0,0 +1,227 @@
+#[macro_use]
+extern crate glium;
+extern crate cgmath;
+extern crate ogldev;
+
+use glium::{DisplayBuild, Surface, Program, DrawParameters, Depth, DepthTest};
+use glium::glutin::{ElementState, Event, MouseButton, WindowBuilder, VirtualKeyCode};
+use glium::backend::glutin_backend::GlutinFacade;
+use glium::draw_parameters::BackfaceCullingMode;
+use cgmath::Matrix4;
+
+use ogldev::{Camera, Pipeline};
+use ogldev::mesh::GpuMesh;
+use ogldev::mesh::primitives;
+use ogldev::picking::{PickId, PickingTexture};
+use ogldev::texture::{ColorSpace, TextureHandle, TextureManager};
+
+const WINDOW_WIDTH: u32 = 1280;
+const WINDOW_HEIGHT: u32 = 1024;
+
+const PYRAMID_COUNT: usize = 3;
+
+fn create_shaders(display: &GlutinFacade) -> Program {
+    let vertex_shader_src = r#"
+        #version 330
+
+        layout (location = 0) in vec3 position;
+        layout (location = 1) in vec2 tex_coords;
+
+        uniform mat4 gWVP;
+
+        out vec2 texCoord0;
+
+        void main() {
+            gl_Position = gWVP * vec4(position, 1.0);
+            texCoord0 = tex_coords;
+        }
+    "#;
+
+    let fragment_shader_src = r#"
+        #version 330
+
+        in vec2 texCoord0;
+
+        out vec4 fragColor;
+
+        uniform sampler2D gSampler;
+
+        void main() {
+            fragColor = texture(gSampler, texCoord0.xy);
+        }
+    "#;
+
+    Program::from_source(display,
+        vertex_shader_src, fragment_shader_src, None).unwrap()
+}
+
+// Draws only the triangle `gPrimitive`, in red, over the object drawn before
+fn create_highlight_shaders(display: &GlutinFacade) -> Program {
+    let vertex_shader_src = r#"
+        #version 330
+
+        in vec3 position;
+
+        uniform mat4 gWVP;
+
+        void main() {
+            gl_Position = gWVP * vec4(position, 1.0);
+        }
+    "#;
+
+    let fragment_shader_src = r#"
+        #version 330
+
+        uniform uint gPrimitive;
+
+        out vec4 fragColor;
+
+        void main() {
+            if (uint(gl_PrimitiveID) != gPrimitive) {
+                discard;
+            }
+            fragColor = vec4(1.0, 0.0, 0.0, 1.0);
+        }
+    "#;
+
+    Program::from_source(display,
+        vertex_shader_src, fragment_shader_src, None).unwrap()
+}
+
+// The world matrices of the pyramids, side by side and turning
+fn pyramid_worlds(scale: f32) -> Vec<Matrix4<f32>> {
+    (0..PYRAMID_COUNT).map(|i| {
+        let mut pipeline = Pipeline::new();
+        pipeline.rotate(0.0, scale * (i as f32 + 1.0), 0.0);
+        pipeline.world_pos((i as f32 - 1.0) * 3.0, 0.0, 6.0);
+        pipeline.get_world_trans()
+    }).collect()
+}
+
+fn render_scene(display: &GlutinFacade, pyramid: &GpuMesh, program: &Program, highlight: &Program,
+        picking: &mut PickingTexture, camera: &mut Camera, scale: f32, textures: &TextureManager,
+        texture: TextureHandle, click: bool, picked: &mut Option<PickId>) {
+
+    // Notify the camera
+    camera.on_render();
+
+    // Create a Pipeline
+    let mut pipeline = Pipeline::new();
+    pipeline.set_camera(camera.get_pos(), camera.get_target(), camera.get_up());
+    pipeline.set_perspective_proj(60.0, WINDOW_WIDTH as f32, WINDOW_HEIGHT as f32, 1.0, 100.0);
+    let view_projection = pipeline.get_project_trans() * pipeline.get_view_trans();
+
+    let params = DrawParameters {
+        depth: Depth {
+            test: DepthTest::IfLess,
+            write: true,
+            .. Default::default()
+        },
+        backface_culling: BackfaceCullingMode::CullCounterClockwise,
+        .. Default::default()
+    };
+
+    // On a click, render the IDs and read the one under the mouse
+    let worlds = pyramid_worlds(scale);
+    if click {
+        let objects: Vec<(&GpuMesh, Matrix4<f32>)> = worlds.iter().map(|&world| (pyramid, world)).collect();
+        picking.render(display, view_projection, &objects, &params).unwrap();
+        let (x, y) = camera.get_mouse_pos();
+        *picked = picking.pick(x, y);
+        match *picked {
+            Some(id) => println!("Picked the triangle {} of the pyramid {}", id.primitive, id.object),
+            None => println!("Picked nothing")
+        }
+    }
+
+    // Drawing
+    let mut frame = display.draw();
+    frame.clear_color_and_depth((0.0, 0.0, 0.0, 0.0), 1.0);
+
+    for world in &worlds {
+        let wvp: [[f32; 4]; 4] = (view_projection * world).into();
+        let uniforms = uniform! { gWVP: wvp, gSampler: textures.sampled(texture) };
+        pyramid.draw(&mut frame, program, &uniforms, &params).unwrap();
+    }
+
+    // The picked triangle is drawn again at the same depth
+    if let Some(id) = *picked {
+        let wvp: [[f32; 4]; 4] = (view_projection * worlds[id.object]).into();
+        let uniforms = uniform! { gWVP: wvp, gPrimitive: id.primitive };
+        let params = DrawParameters {
+            depth: Depth {
+                test: DepthTest::IfLessOrEqual,
+                .. Default::default()
+            },
+            .. params.clone()
+        };
+        pyramid.draw(&mut frame, highlight, &uniforms, &params).unwrap();
+    }
+
+    frame.finish().unwrap();
+}
+
+fn main() {
+    // Set up and create a window
+    let display = WindowBuilder::new()
+        .with_dimensions(WINDOW_WIDTH, WINDOW_HEIGHT)
+        .with_srgb(Some(true))
+        .with_depth_buffer(24)
+        .with_title("Tutorial 29")
+        .build_glium()
+        .unwrap();
+
+    // Create the mesh, the shader programs and the picking texture, as large as the window
+    let pyramid = primitives::pyramid().upload(&display).unwrap();
+    let program = create_shaders(&display);
+    let highlight = create_highlight_shaders(&display);
+    let mut picking = PickingTexture::new(&display, WINDOW_WIDTH, WINDOW_HEIGHT).unwrap();
+
+    // Create a camera
+    let mut camera = Camera::default(WINDOW_WIDTH, WINDOW_HEIGHT);
+
+    let mut textures = TextureManager::new();
+    let texture = textures.load(&display, "content/test.png", ColorSpace::Srgb).unwrap();
+
+    // A left click picks the triangle under the mouse, 'P' stops the pyramids so that they are
+    // easier to hit
+    let mut picked = None;
+    let mut click = false;
+    let mut turning = true;
+
+    // Main loop
+    let mut scale: f32 = 0.0;
+    loop {
+        if turning {
+            scale += 0.005;
+        }
+
+        // Render
+        render_scene(&display, &pyramid, &program, &highlight, &mut picking, &mut camera, scale, &textures,
+            texture, click, &mut picked);
+        click = false;
+
+        // Handle events
+        for event in display.poll_events() {
+            match event {
+                Event::Closed => return,
+                Event::KeyboardInput(_, _, Some(VirtualKeyCode::Q)) => {
+                    std::process::exit(0);
+                },
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VirtualKeyCode::P)) => {
+                    turning = !turning;
+                },
+                Event::KeyboardInput(_, _, Some(key)) => {
+                    camera.on_key_board(key);
+                },
+                Event::MouseInput(ElementState::Pressed, MouseButton::Left) => {
+                    click = true;
+                },
+                Event::MouseMoved(x, y) => {
+                    camera.on_mouse(x, y);
+                },
+                _ => ()
+            }
+        }
+    }
+}
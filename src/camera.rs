@@ -1,5 +1,5 @@
 
-use cgmath::{Deg, InnerSpace, Vector2, Vector3, Matrix3};
+use cgmath::{InnerSpace, Vector2, Vector3};
 use glium::glutin::VirtualKeyCode;
 
 const STEP_SCALE: f32 = 0.2;
@@ -14,8 +14,8 @@ pub struct Camera {
     // For control the camera
     window_width: i32,
     window_height: i32,
-    angle_h: f32,
-    angle_v: f32,
+    yaw: f32,
+    pitch: f32,
     on_upper_edge: bool,
     on_lower_edge: bool,
     on_left_edge: bool,
@@ -32,8 +32,8 @@ impl Camera {
             up: Vector3::new(0.0, 1.0, 0.0),
             window_width: window_width as i32,
             window_height: window_height as i32,
-            angle_h: 0.0,
-            angle_v: 0.0,
+            yaw: 0.0,
+            pitch: 0.0,
             on_upper_edge: false,
             on_lower_edge: false,
             on_left_edge: false,
@@ -67,6 +67,24 @@ impl Camera {
         self.up
     }
 
+    // Used when the window (or the viewport holding this camera) is resized
+    pub fn set_window_size(&mut self, window_width: u32, window_height: u32) {
+        self.window_width = window_width as i32;
+        self.window_height = window_height as i32;
+    }
+
+    // The last position given to `on_mouse()` or `set_mouse_pos()`, in window coordinates
+    pub fn get_mouse_pos(&self) -> (i32, i32) {
+        (self.mouse_pos.x, self.mouse_pos.y)
+    }
+
+    // Move the remembered mouse position without rotating the camera. This avoids a sudden jump
+    // when the mouse comes back from somewhere else.
+    pub fn set_mouse_pos(&mut self, x: i32, y: i32) {
+        self.mouse_pos.x = x;
+        self.mouse_pos.y = y;
+    }
+
     pub fn on_key_board(&mut self, key: VirtualKeyCode) -> bool {
         match key {
             VirtualKeyCode::Up => {
@@ -102,8 +120,8 @@ impl Camera {
         self.mouse_pos.x = x;
         self.mouse_pos.y = y;
 
-        self.angle_h += (delta_x as f32) / 20.0;
-        self.angle_v += (delta_y as f32) / 20.0;
+        self.yaw += (delta_x as f32) / 20.0;
+        self.pitch += (delta_y as f32) / 20.0;
 
         // Horizontal edge detection
         if delta_x == 0 {
@@ -136,21 +154,21 @@ impl Camera {
         let mut should_update = false;
 
         if self.on_left_edge {
-            self.angle_h -= 0.1;
+            self.yaw -= 0.1;
             should_update = true;
         } else if self.on_right_edge {
-            self.angle_h += 0.1;
+            self.yaw += 0.1;
             should_update = true;
         }
 
         if self.on_upper_edge {
-            if self.angle_v > -90.0 {
-                self.angle_v -= 0.1;
+            if self.pitch > -90.0 {
+                self.pitch -= 0.1;
                 should_update = true;
             }
         } else if self.on_lower_edge {
-            if self.angle_v < 90.0 {
-                self.angle_v += 0.1;
+            if self.pitch < 90.0 {
+                self.pitch += 0.1;
                 should_update = true;
             }
         }
@@ -161,23 +179,12 @@ impl Camera {
     }
 
     fn init(&mut self) {
-        let h_target = Vector3::new(self.target.x, 0.0, self.target.z).normalize();
-
-        if h_target.z >= 0.0 {
-            if h_target.x >= 0.0 {
-                self.angle_h = 360.0 - h_target.z.asin().to_degrees();
-            } else {
-                self.angle_h = 180.0 - h_target.z.asin().to_degrees();
-            }
-        } else {
-            if h_target.x >= 0.0 {
-                self.angle_h = (-h_target.z).asin().to_degrees();
-            } else {
-                self.angle_h = 90.0 + (-h_target.z).asin().to_degrees();
-            }
-        }
-
-        self.angle_v = - h_target.y.asin().to_degrees();
+        // Recover the angles from the target vector, so that `update()` would rebuild the same
+        // vector. The horizontal angle rotates +X toward -Z around the vertical axis and the
+        // vertical angle tilts the view downward.
+        let (yaw, pitch) = yaw_pitch_from_target(self.target);
+        self.yaw = yaw;
+        self.pitch = pitch;
 
         // NOTE: The flags for edges have been initialized in default()
 
@@ -186,24 +193,98 @@ impl Camera {
     }
 
     fn update(&mut self) {
-        let v_axis = Vector3::new(0.0, 1.0, 0.0);
-
-        // Rotate the view vector by the horizontal angle around the vertical axis
-        let mut view = Vector3::new(1.0, 0.0, 0.0);
-        // There is something different here. Crate cgmath provides another way to rotate a vector
-        // using a transfromation matrix. So this looks different from the original tutorial
-        let mut rotate_matrix = Matrix3::from_axis_angle(v_axis, Deg(self.angle_h));
-        view = rotate_matrix * view;
-        view = view.normalize();
-
-        // Rotate the view vector by the vertical angle around the horizontal axis
-        let mut h_axis = v_axis.cross(view);
-        h_axis = h_axis.normalize();
-        rotate_matrix = Matrix3::from_axis_angle(h_axis, Deg(self.angle_v));
-        view = rotate_matrix * view;
-        view = view.normalize();
-
-        self.target = view;
-        self.up = self.target.cross(h_axis).normalize();
+        self.target = target_from_yaw_pitch(self.yaw, self.pitch);
+        self.up = up_from_target(self.target, self.yaw);
+    }
+}
+
+// The inverse of `yaw_pitch_from_target()`
+fn target_from_yaw_pitch(yaw: f32, pitch: f32) -> Vector3<f32> {
+    let (yaw_sin, yaw_cos) = yaw.to_radians().sin_cos();
+    let (pitch_sin, pitch_cos) = pitch.to_radians().sin_cos();
+
+    Vector3::new(yaw_cos * pitch_cos, -pitch_sin, -yaw_sin * pitch_cos)
+}
+
+// Compute the horizontal angle (yaw) and the vertical angle (pitch) in degrees of a target vector.
+// Using `atan2` covers all four quadrants at once and keeps the pitch of the original vector.
+fn yaw_pitch_from_target(target: Vector3<f32>) -> (f32, f32) {
+    let yaw = (-target.z).atan2(target.x).to_degrees();
+    let horizontal_len = (target.x * target.x + target.z * target.z).sqrt();
+    let pitch = (-target.y).atan2(horizontal_len).to_degrees();
+
+    (yaw, pitch)
+}
+
+fn up_from_target(target: Vector3<f32>, yaw: f32) -> Vector3<f32> {
+    // The horizontal axis only depends on the yaw, so it stays valid when looking straight
+    // up or down
+    let (yaw_sin, yaw_cos) = yaw.to_radians().sin_cos();
+    let h_axis = Vector3::new(-yaw_sin, 0.0, -yaw_cos);
+
+    target.cross(h_axis).normalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Vector3};
+
+    use super::{target_from_yaw_pitch, yaw_pitch_from_target, Camera};
+
+    // Spread over the whole sphere, with two points close to each pole
+    fn targets() -> Vec<Vector3<f32>> {
+        let count = 500;
+        let golden_angle = ::std::f32::consts::PI * (3.0 - 5.0f32.sqrt());
+        let mut targets: Vec<Vector3<f32>> = (0..count).map(|i| {
+            let y = 1.0 - (i as f32 + 0.5) / count as f32 * 2.0;
+            let r = (1.0 - y * y).sqrt();
+            let angle = golden_angle * i as f32;
+            Vector3::new(r * angle.cos(), y, r * angle.sin())
+        }).collect();
+        for &y in &[0.9999, -0.9999] {
+            targets.push(Vector3::new(0.3, y, -0.2).normalize());
+            targets.push(Vector3::new(-0.01, y, 0.01).normalize());
+        }
+        targets
+    }
+
+    #[test]
+    fn targets_survive_the_angles() {
+        for target in targets() {
+            let (yaw, pitch) = yaw_pitch_from_target(target);
+            let rebuilt = target_from_yaw_pitch(yaw, pitch);
+            assert!((rebuilt - target).magnitude() < 1e-4, "{:?} became {:?}", target, rebuilt);
+        }
+    }
+
+    #[test]
+    fn angles_survive_the_targets() {
+        // The pitch stays away from the poles, where the yaw is lost
+        for yaw_step in -17..19 {
+            for pitch_step in -8..9 {
+                let (yaw, pitch) = (yaw_step as f32 * 10.0, pitch_step as f32 * 10.0 + 0.5);
+                let (yaw2, pitch2) = yaw_pitch_from_target(target_from_yaw_pitch(yaw, pitch));
+                // 180 and -180 degrees are the same yaw
+                let yaw_error = ((yaw2 - yaw + 540.0) % 360.0 - 180.0).abs();
+                assert!(yaw_error < 1e-3 && (pitch2 - pitch).abs() < 1e-3,
+                    "({}, {}) became ({}, {})", yaw, pitch, yaw2, pitch2);
+            }
+        }
+    }
+
+    #[test]
+    fn camera_keeps_its_target_when_updated() {
+        for target in targets() {
+            // Not normalized, the camera does it
+            let mut camera = Camera::new(800, 600, Vector3::new(1.0, 2.0, 3.0), target * 3.0,
+                Vector3::new(0.0, 1.0, 0.0));
+            assert!((camera.get_target() - target).magnitude() < 1e-5);
+
+            // What happens when the mouse moves by nothing
+            camera.update();
+            assert!((camera.get_target() - target).magnitude() < 1e-4,
+                "{:?} became {:?}", target, camera.get_target());
+            assert!(camera.get_up().dot(camera.get_target()).abs() < 1e-4);
+        }
     }
 }
@@ -0,0 +1,66 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use glium::texture::TextureCreationError;
+use image::ImageError;
+
+// Errors of the asset loaders
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    Image(ImageError),
+    Texture(TextureCreationError),
+    // The content of a file is malformed. The message tells where.
+    Parse(String)
+}
+
+impl LoadError {
+    pub fn parse<S: Into<String>>(message: S) -> LoadError {
+        LoadError::Parse(message.into())
+    }
+
+    pub fn at_line<S: Into<String>>(line: usize, message: S) -> LoadError {
+        LoadError::Parse(format!("line {}: {}", line, message.into()))
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LoadError::Io(ref err) => write!(f, "I/O error: {}", err),
+            LoadError::Image(ref err) => write!(f, "image error: {}", err),
+            LoadError::Texture(ref err) => write!(f, "texture error: {}", err),
+            LoadError::Parse(ref message) => write!(f, "parse error: {}", message)
+        }
+    }
+}
+
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            LoadError::Io(ref err) => Some(err),
+            LoadError::Image(ref err) => Some(err),
+            LoadError::Texture(ref err) => Some(err),
+            LoadError::Parse(_) => None
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(err: io::Error) -> LoadError {
+        LoadError::Io(err)
+    }
+}
+
+impl From<ImageError> for LoadError {
+    fn from(err: ImageError) -> LoadError {
+        LoadError::Image(err)
+    }
+}
+
+impl From<TextureCreationError> for LoadError {
+    fn from(err: TextureCreationError) -> LoadError {
+        LoadError::Texture(err)
+    }
+}
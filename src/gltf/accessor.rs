@@ -0,0 +1,251 @@
+// Buffers, buffer views and accessors: everything needed to turn the binary payload of a glTF
+// file into numbers.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use error::LoadError;
+use gltf::json::Json;
+
+const BYTE: u32 = 5120;
+const UNSIGNED_BYTE: u32 = 5121;
+const SHORT: u32 = 5122;
+const UNSIGNED_SHORT: u32 = 5123;
+const UNSIGNED_INT: u32 = 5125;
+const FLOAT: u32 = 5126;
+
+// The most values an accessor may hold. Accessors without a bufferView take no room in the file,
+// so their count must be bounded before the zeros are allocated.
+const MAX_VALUES: usize = 1 << 26;
+
+// Read the content of a buffer or an image. `uri` is either a data URI or a path relative to
+// the glTF file.
+pub fn read_uri(uri: &str, base_dir: &Path) -> Result<Vec<u8>, LoadError> {
+    if uri.starts_with("data:") {
+        let comma = uri.find(',').ok_or_else(|| LoadError::parse("malformed data URI"))?;
+        if !uri[..comma].ends_with(";base64") {
+            return Err(LoadError::parse("only base64 data URIs are supported"));
+        }
+        decode_base64(&uri[comma + 1..])
+    } else {
+        let mut data = Vec::new();
+        File::open(base_dir.join(decode_percent(uri)))?.read_to_end(&mut data)?;
+        Ok(data)
+    }
+}
+
+// The MIME type of a data URI, e.g. "image/png"
+pub fn data_uri_mime_type(uri: &str) -> Option<&str> {
+    if !uri.starts_with("data:") {
+        return None;
+    }
+    let end = uri.find([';', ',']).unwrap_or(uri.len());
+    Some(&uri[5..end])
+}
+
+fn decode_base64(text: &str) -> Result<Vec<u8>, LoadError> {
+    let mut data = Vec::with_capacity(text.len() * 3 / 4);
+    let mut accumulator: u32 = 0;
+    let mut bits = 0;
+
+    for byte in text.bytes() {
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            b'=' => break,
+            _ => return Err(LoadError::parse("invalid base64 data"))
+        };
+
+        accumulator = (accumulator << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            data.push((accumulator >> bits) as u8);
+            accumulator &= (1 << bits) - 1;
+        }
+    }
+
+    Ok(data)
+}
+
+// URIs may escape spaces and other characters in file names
+pub fn decode_percent(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = String::from_utf8_lossy(&bytes[i + 1..i + 3]).into_owned();
+            if let Ok(value) = u8::from_str_radix(&hex, 16) {
+                decoded.push(value);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn component_size(component_type: u32) -> Result<usize, LoadError> {
+    match component_type {
+        BYTE | UNSIGNED_BYTE => Ok(1),
+        SHORT | UNSIGNED_SHORT => Ok(2),
+        UNSIGNED_INT | FLOAT => Ok(4),
+        _ => Err(LoadError::parse(format!("unknown component type {}", component_type)))
+    }
+}
+
+fn component_count(kind: &str) -> Result<usize, LoadError> {
+    match kind {
+        "SCALAR" => Ok(1),
+        "VEC2" => Ok(2),
+        "VEC3" => Ok(3),
+        "VEC4" => Ok(4),
+        "MAT2" => Ok(4),
+        "MAT3" => Ok(9),
+        "MAT4" => Ok(16),
+        _ => Err(LoadError::parse(format!("unknown accessor type {}", kind)))
+    }
+}
+
+// Read one component. Normalized integers are mapped to [0, 1] or [-1, 1] as the specification
+// says.
+fn read_component(data: &[u8], component_type: u32, normalized: bool) -> f64 {
+    match component_type {
+        BYTE => {
+            let value = data[0] as i8 as f64;
+            if normalized { (value / 127.0).max(-1.0) } else { value }
+        },
+        UNSIGNED_BYTE => {
+            let value = data[0] as f64;
+            if normalized { value / 255.0 } else { value }
+        },
+        SHORT => {
+            let value = i16::from_le_bytes([data[0], data[1]]) as f64;
+            if normalized { (value / 32767.0).max(-1.0) } else { value }
+        },
+        UNSIGNED_SHORT => {
+            let value = u16::from_le_bytes([data[0], data[1]]) as f64;
+            if normalized { value / 65535.0 } else { value }
+        },
+        UNSIGNED_INT => u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as f64,
+        _ => f32::from_le_bytes([data[0], data[1], data[2], data[3]]) as f64
+    }
+}
+
+// The bytes of a buffer view with its stride, or None for tightly packed elements
+pub fn buffer_view<'a>(root: &Json, buffers: &'a [Vec<u8>], index: usize)
+    -> Result<(&'a [u8], Option<usize>), LoadError> {
+    let view = root.get("bufferViews").and_then(|views| views.as_array()).and_then(|views| views.get(index))
+        .ok_or_else(|| LoadError::parse(format!("bufferView {} does not exist", index)))?;
+
+    let buffer = view.get("buffer").and_then(Json::as_usize)
+        .and_then(|buffer| buffers.get(buffer))
+        .ok_or_else(|| LoadError::parse(format!("bufferView {} has an invalid buffer", index)))?;
+    let offset = view.get("byteOffset").and_then(Json::as_usize).unwrap_or(0);
+    let length = view.get("byteLength").and_then(Json::as_usize)
+        .ok_or_else(|| LoadError::parse(format!("bufferView {} has no byteLength", index)))?;
+    let stride = view.get("byteStride").and_then(Json::as_usize);
+
+    if offset.checked_add(length).is_none_or(|end| end > buffer.len()) {
+        return Err(LoadError::parse(format!("bufferView {} is out of its buffer", index)));
+    }
+
+    Ok((&buffer[offset..offset + length], stride))
+}
+
+// Read `count` tightly packed or strided elements
+fn read_elements(data: &[u8], offset: usize, stride: Option<usize>, count: usize, components: usize,
+                 component_type: u32, normalized: bool) -> Result<Vec<f64>, LoadError> {
+    let size = component_size(component_type)?;
+    let element_size = components * size;
+    let stride = stride.unwrap_or(element_size);
+
+    if count > 0 {
+        let end = offset.checked_add(stride.checked_mul(count - 1).and_then(|s| s.checked_add(element_size))
+            .ok_or_else(|| LoadError::parse("accessor is too large"))?);
+        if end.is_none_or(|end| end > data.len()) {
+            return Err(LoadError::parse("accessor is out of its bufferView"));
+        }
+    }
+
+    let mut values = Vec::with_capacity(count * components);
+    for i in 0..count {
+        let start = offset + i * stride;
+        for c in 0..components {
+            values.push(read_component(&data[start + c * size..], component_type, normalized));
+        }
+    }
+
+    Ok(values)
+}
+
+// The values of an accessor, flattened, with the number of components per element
+pub fn read_accessor(root: &Json, buffers: &[Vec<u8>], index: usize) -> Result<(Vec<f64>, usize), LoadError> {
+    let accessor = root.get("accessors").and_then(|accessors| accessors.as_array())
+        .and_then(|accessors| accessors.get(index))
+        .ok_or_else(|| LoadError::parse(format!("accessor {} does not exist", index)))?;
+    let error = |message: &str| LoadError::parse(format!("accessor {}: {}", index, message));
+
+    let count = accessor.get("count").and_then(Json::as_usize).ok_or_else(|| error("missing count"))?;
+    let component_type = accessor.get("componentType").and_then(Json::as_usize)
+        .ok_or_else(|| error("missing componentType"))? as u32;
+    let components = component_count(accessor.get("type").and_then(Json::as_str).unwrap_or(""))?;
+    let normalized = accessor.get("normalized").and_then(Json::as_bool).unwrap_or(false);
+    let value_count = count.checked_mul(components).filter(|&value_count| value_count <= MAX_VALUES)
+        .ok_or_else(|| error("too many elements"))?;
+
+    // Without a bufferView, the accessor is all zeros (but sparse values may follow)
+    let mut values = match accessor.get("bufferView").and_then(Json::as_usize) {
+        Some(view) => {
+            let (data, stride) = buffer_view(root, buffers, view)?;
+            let offset = accessor.get("byteOffset").and_then(Json::as_usize).unwrap_or(0);
+            read_elements(data, offset, stride, count, components, component_type, normalized)?
+        },
+        None => vec![0.0; value_count]
+    };
+
+    if let Some(sparse) = accessor.get("sparse") {
+        let sparse_count = sparse.get("count").and_then(Json::as_usize)
+            .ok_or_else(|| error("sparse without count"))?;
+        if sparse_count > count {
+            return Err(error("more sparse values than elements"));
+        }
+        let sparse_indices = sparse.get("indices").ok_or_else(|| error("sparse without indices"))?;
+        let sparse_values = sparse.get("values").ok_or_else(|| error("sparse without values"))?;
+
+        let (data, _) = buffer_view(root, buffers, sparse_indices.get("bufferView").and_then(Json::as_usize)
+            .ok_or_else(|| error("sparse indices without bufferView"))?)?;
+        let index_type = sparse_indices.get("componentType").and_then(Json::as_usize)
+            .ok_or_else(|| error("sparse indices without componentType"))? as u32;
+        if index_type != UNSIGNED_BYTE && index_type != UNSIGNED_SHORT && index_type != UNSIGNED_INT {
+            return Err(error("sparse indices must be unsigned integers"));
+        }
+        let offset = sparse_indices.get("byteOffset").and_then(Json::as_usize).unwrap_or(0);
+        let indices = read_elements(data, offset, None, sparse_count, 1, index_type, false)?;
+
+        let (data, _) = buffer_view(root, buffers, sparse_values.get("bufferView").and_then(Json::as_usize)
+            .ok_or_else(|| error("sparse values without bufferView"))?)?;
+        let offset = sparse_values.get("byteOffset").and_then(Json::as_usize).unwrap_or(0);
+        let substitutes = read_elements(data, offset, None, sparse_count, components, component_type, normalized)?;
+
+        for (i, &target) in indices.iter().enumerate() {
+            let target = target as usize;
+            if target >= count {
+                return Err(error("sparse index out of range"));
+            }
+            values[target * components..(target + 1) * components]
+                .copy_from_slice(&substitutes[i * components..(i + 1) * components]);
+        }
+    }
+
+    Ok((values, components))
+}
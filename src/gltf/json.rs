@@ -0,0 +1,284 @@
+// Just enough JSON for reading glTF files
+
+use std::collections::BTreeMap;
+use std::char;
+
+use error::LoadError;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(BTreeMap<String, Json>)
+}
+
+impl Json {
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match *self {
+            Json::Object(ref map) => map.get(key),
+            _ => None
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Json::Number(value) => Some(value),
+            _ => None
+        }
+    }
+
+    pub fn as_usize(&self) -> Option<usize> {
+        match *self {
+            Json::Number(value) if value >= 0.0 && value.fract() == 0.0 => Some(value as usize),
+            _ => None
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Json::Bool(value) => Some(value),
+            _ => None
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Json::String(ref value) => Some(value),
+            _ => None
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match *self {
+            Json::Array(ref values) => Some(values),
+            _ => None
+        }
+    }
+}
+
+pub fn parse(text: &str) -> Result<Json, LoadError> {
+    let mut parser = Parser {
+        bytes: text.as_bytes(),
+        text: text,
+        position: 0
+    };
+
+    let value = parser.parse_value(0)?;
+    parser.skip_whitespace();
+    if parser.position != parser.bytes.len() {
+        return Err(parser.error("trailing characters"));
+    }
+    Ok(value)
+}
+
+// Deeply nested documents are rejected instead of overflowing the stack
+const MAX_DEPTH: usize = 128;
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    text: &'a str,
+    position: usize
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: &str) -> LoadError {
+        LoadError::parse(format!("JSON offset {}: {}", self.position, message))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.position < self.bytes.len() {
+            match self.bytes[self.position] {
+                b' ' | b'\t' | b'\n' | b'\r' => self.position += 1,
+                _ => break
+            }
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.position).cloned()
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), LoadError> {
+        self.skip_whitespace();
+        if self.peek() == Some(byte) {
+            self.position += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", byte as char)))
+        }
+    }
+
+    fn parse_value(&mut self, depth: usize) -> Result<Json, LoadError> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("too deeply nested"));
+        }
+
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'{') => self.parse_object(depth),
+            Some(b'[') => self.parse_array(depth),
+            Some(b'"') => Ok(Json::String(self.parse_string()?)),
+            Some(b't') => self.parse_literal("true", Json::Bool(true)),
+            Some(b'f') => self.parse_literal("false", Json::Bool(false)),
+            Some(b'n') => self.parse_literal("null", Json::Null),
+            Some(b'-') | Some(b'0'..=b'9') => self.parse_number(),
+            Some(_) => Err(self.error("unexpected character")),
+            None => Err(self.error("unexpected end of input"))
+        }
+    }
+
+    fn parse_literal(&mut self, literal: &str, value: Json) -> Result<Json, LoadError> {
+        if self.bytes[self.position..].starts_with(literal.as_bytes()) {
+            self.position += literal.len();
+            Ok(value)
+        } else {
+            Err(self.error("invalid literal"))
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<Json, LoadError> {
+        let start = self.position;
+        while let Some(byte) = self.peek() {
+            match byte {
+                b'0'..=b'9' | b'-' | b'+' | b'.' | b'e' | b'E' => self.position += 1,
+                _ => break
+            }
+        }
+
+        self.text[start..self.position].parse::<f64>()
+            .map(Json::Number)
+            .map_err(|_| self.error("invalid number"))
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, LoadError> {
+        if self.position + 4 > self.bytes.len() ||
+                !self.bytes[self.position..self.position + 4].iter().all(|byte| byte.is_ascii_hexdigit()) {
+            return Err(self.error("invalid unicode escape"));
+        }
+        let hex = &self.text[self.position..self.position + 4];
+        let value = u32::from_str_radix(hex, 16).map_err(|_| self.error("invalid unicode escape"))?;
+        self.position += 4;
+        Ok(value)
+    }
+
+    fn parse_string(&mut self) -> Result<String, LoadError> {
+        self.expect(b'"')?;
+        let mut value = String::new();
+
+        loop {
+            // Copy the run of plain characters at once, it is valid UTF-8 since `text` is
+            let start = self.position;
+            while let Some(byte) = self.peek() {
+                if byte == b'"' || byte == b'\\' || byte < 0x20 {
+                    break;
+                }
+                self.position += 1;
+            }
+            value.push_str(&self.text[start..self.position]);
+
+            match self.peek() {
+                Some(b'"') => {
+                    self.position += 1;
+                    return Ok(value);
+                },
+                Some(b'\\') => {
+                    self.position += 1;
+                    let escaped = match self.peek() {
+                        Some(byte) => byte,
+                        None => return Err(self.error("unterminated string"))
+                    };
+                    self.position += 1;
+
+                    match escaped {
+                        b'"' => value.push('"'),
+                        b'\\' => value.push('\\'),
+                        b'/' => value.push('/'),
+                        b'b' => value.push('\u{8}'),
+                        b'f' => value.push('\u{c}'),
+                        b'n' => value.push('\n'),
+                        b'r' => value.push('\r'),
+                        b't' => value.push('\t'),
+                        b'u' => {
+                            let mut code = self.parse_hex4()?;
+                            // A surrogate pair
+                            if (0xD800..0xDC00).contains(&code) {
+                                if !self.bytes[self.position..].starts_with(b"\\u") {
+                                    return Err(self.error("unpaired surrogate"));
+                                }
+                                self.position += 2;
+                                let low = self.parse_hex4()?;
+                                if !(0xDC00..0xE000).contains(&low) {
+                                    return Err(self.error("invalid surrogate pair"));
+                                }
+                                code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                            }
+                            match char::from_u32(code) {
+                                Some(c) => value.push(c),
+                                None => return Err(self.error("invalid unicode escape"))
+                            }
+                        },
+                        _ => return Err(self.error("invalid escape"))
+                    }
+                },
+                Some(_) => return Err(self.error("control character in string")),
+                None => return Err(self.error("unterminated string"))
+            }
+        }
+    }
+
+    fn parse_array(&mut self, depth: usize) -> Result<Json, LoadError> {
+        self.expect(b'[')?;
+        let mut values = Vec::new();
+
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.position += 1;
+            return Ok(Json::Array(values));
+        }
+
+        loop {
+            values.push(self.parse_value(depth + 1)?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b']') => {
+                    self.position += 1;
+                    return Ok(Json::Array(values));
+                },
+                _ => return Err(self.error("expected ',' or ']'"))
+            }
+        }
+    }
+
+    fn parse_object(&mut self, depth: usize) -> Result<Json, LoadError> {
+        self.expect(b'{')?;
+        let mut map = BTreeMap::new();
+
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.position += 1;
+            return Ok(Json::Object(map));
+        }
+
+        loop {
+            self.skip_whitespace();
+            let key = self.parse_string()?;
+            self.expect(b':')?;
+            let value = self.parse_value(depth + 1)?;
+            map.insert(key, value);
+
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b'}') => {
+                    self.position += 1;
+                    return Ok(Json::Object(map));
+                },
+                _ => return Err(self.error("expected ',' or '}'"))
+            }
+        }
+    }
+}
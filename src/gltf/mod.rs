@@ -0,0 +1,1086 @@
+// A loader for glTF 2.0 scenes, both as .gltf (with external or embedded buffers) and as binary
+// .glb files.
+//
+// glTF is right-handed while the projections of this crate are left-handed, so the scenes are
+// mirrored along Z when they are imported: positions, normals, tangents and node transforms get
+// their Z negated, and the triangles are reversed to keep `Mesh` winding them outward. Texture
+// coordinates are flipped vertically so that they match images loaded upside down by
+// `RawImage2d::from_raw_rgba_reversed`.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use cgmath::{InnerSpace, Matrix4, Quaternion, Vector3};
+use glium::backend::Facade;
+use glium::texture::{RawImage2d, Texture2d};
+use image::{self, RgbaImage};
+
+use error::LoadError;
+use graphical_math::{OrthoProjInfo, PersProjInfo};
+use mesh::Mesh;
+use transform::Transform;
+
+use self::json::Json;
+
+mod accessor;
+mod json;
+
+const GLB_MAGIC: u32 = 0x4654_6C67;
+const GLB_CHUNK_JSON: u32 = 0x4E4F_534A;
+const GLB_CHUNK_BIN: u32 = 0x004E_4942;
+
+// Extensions that do not change how the data above must be read
+const SUPPORTED_EXTENSIONS: &[&str] = &[];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AlphaMode {
+    Opaque,
+    Mask,
+    Blend
+}
+
+// A texture used by a material, with the set of texture coordinates to read it with
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextureRef {
+    pub texture: usize,
+    pub tex_coord: usize,
+    // `scale` of normal textures and `strength` of occlusion textures, 1.0 for the others
+    pub scale: f32
+}
+
+// A metallic-roughness material
+#[derive(Clone, Debug, PartialEq)]
+pub struct PbrMaterial {
+    pub name: String,
+    pub base_color_factor: [f32; 4],
+    pub base_color_texture: Option<TextureRef>,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    // Roughness in the green channel, metalness in the blue channel
+    pub metallic_roughness_texture: Option<TextureRef>,
+    pub normal_texture: Option<TextureRef>,
+    pub occlusion_texture: Option<TextureRef>,
+    pub emissive_texture: Option<TextureRef>,
+    pub emissive_factor: [f32; 3],
+    pub alpha_mode: AlphaMode,
+    pub alpha_cutoff: f32,
+    pub double_sided: bool
+}
+
+impl Default for PbrMaterial {
+    // The material of primitives without one
+    fn default() -> PbrMaterial {
+        PbrMaterial {
+            name: String::new(),
+            base_color_factor: [1.0, 1.0, 1.0, 1.0],
+            base_color_texture: None,
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            occlusion_texture: None,
+            emissive_texture: None,
+            emissive_factor: [0.0, 0.0, 0.0],
+            alpha_mode: AlphaMode::Opaque,
+            alpha_cutoff: 0.5,
+            double_sided: false
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ImageSource {
+    // Resolved against the directory of the glTF file
+    Path(PathBuf),
+    // A data URI or a bufferView
+    Embedded { data: Vec<u8>, mime_type: Option<String> }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GltfImage {
+    pub name: String,
+    pub source: ImageSource
+}
+
+impl GltfImage {
+    pub fn decode(&self) -> Result<RgbaImage, LoadError> {
+        let image = match self.source {
+            ImageSource::Path(ref path) => image::open(path)?,
+            ImageSource::Embedded { ref data, .. } => image::load_from_memory(data)?
+        };
+        Ok(image.to_rgba())
+    }
+
+    pub fn load_texture<F: Facade>(&self, facade: &F) -> Result<Texture2d, LoadError> {
+        let image = self.decode()?;
+        let image_dim = image.dimensions();
+        let image = RawImage2d::from_raw_rgba_reversed(image.into_raw(), image_dim);
+        Ok(Texture2d::new(facade, image)?)
+    }
+}
+
+// Filters and wrap modes are kept as the raw OpenGL enums of the file
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GltfSampler {
+    pub mag_filter: Option<u32>,
+    pub min_filter: Option<u32>,
+    pub wrap_s: u32,
+    pub wrap_t: u32
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GltfTexture {
+    pub source: Option<usize>,
+    pub sampler: Option<usize>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GltfCamera {
+    // `yfov` is in degrees like `PersProjInfo::fov`. A missing `z_far` means an infinite projection.
+    Perspective { name: String, yfov: f32, aspect_ratio: Option<f32>, z_near: f32, z_far: Option<f32> },
+    Orthographic { name: String, xmag: f32, ymag: f32, z_near: f32, z_far: f32 }
+}
+
+impl GltfCamera {
+    // The aspect ratio of the file wins over the one of the window
+    pub fn to_pers_proj_info(&self, width: f32, height: f32) -> Option<PersProjInfo> {
+        match *self {
+            GltfCamera::Perspective { yfov, aspect_ratio, z_near, z_far, .. } => Some(PersProjInfo {
+                fov: yfov,
+                width: aspect_ratio.map_or(width, |ratio| height * ratio),
+                height: height,
+                z_near: z_near,
+                // The projection of `Pipeline` needs a far plane, put it far enough
+                z_far: z_far.unwrap_or(z_near * 10000.0)
+            }),
+            GltfCamera::Orthographic { .. } => None
+        }
+    }
+
+    pub fn to_ortho_proj_info(&self) -> Option<OrthoProjInfo> {
+        match *self {
+            GltfCamera::Orthographic { xmag, ymag, z_near, z_far, .. } => Some(OrthoProjInfo {
+                left: -xmag,
+                right: xmag,
+                bottom: -ymag,
+                top: ymag,
+                z_near: z_near,
+                z_far: z_far
+            }),
+            GltfCamera::Perspective { .. } => None
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GltfNode {
+    pub name: String,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    pub mesh: Option<usize>,
+    pub camera: Option<usize>,
+    // Relative to the parent. A node matrix is decomposed into this.
+    pub transform: Transform
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GltfScene {
+    pub name: String,
+    pub nodes: Vec<usize>
+}
+
+pub struct GltfDocument {
+    pub scenes: Vec<GltfScene>,
+    pub default_scene: Option<usize>,
+    pub nodes: Vec<GltfNode>,
+    // One mesh per glTF mesh, with one submesh per triangle primitive. The material of a
+    // submesh is an index into `materials`.
+    pub meshes: Vec<Mesh>,
+    pub materials: Vec<PbrMaterial>,
+    pub textures: Vec<GltfTexture>,
+    pub images: Vec<GltfImage>,
+    pub samplers: Vec<GltfSampler>,
+    pub cameras: Vec<GltfCamera>
+}
+
+impl GltfDocument {
+    pub fn world_matrix(&self, node: usize) -> Matrix4<f32> {
+        let mut matrix = self.nodes[node].transform.to_matrix();
+        let mut current = self.nodes[node].parent;
+        while let Some(parent) = current {
+            matrix = self.nodes[parent].transform.to_matrix() * matrix;
+            current = self.nodes[parent].parent;
+        }
+        matrix
+    }
+
+    // The world transformation of a node, ready for `Transform::apply_to`
+    pub fn world_transform(&self, node: usize) -> Transform {
+        Transform::from_matrix(self.world_matrix(node))
+    }
+
+    // The position, target and up vectors of a camera node, as `Pipeline::set_camera` takes
+    // them. glTF cameras look down their -Z axis, which is +Z once mirrored.
+    pub fn camera_view(&self, node: usize) -> (Vector3<f32>, Vector3<f32>, Vector3<f32>) {
+        let transform = self.world_transform(node);
+        let target = transform.rotation * Vector3::new(0.0, 0.0, 1.0);
+        let up = transform.rotation * Vector3::new(0.0, 1.0, 0.0);
+        (transform.translation, target.normalize(), up.normalize())
+    }
+
+    // Every node of a scene which has a mesh, with its world matrix
+    pub fn mesh_instances(&self, scene: usize) -> Vec<(usize, Matrix4<f32>)> {
+        let mut instances = Vec::new();
+        let mut stack: Vec<usize> = self.scenes[scene].nodes.iter().rev().cloned().collect();
+
+        while let Some(node) = stack.pop() {
+            if self.nodes[node].mesh.is_some() {
+                instances.push((node, self.world_matrix(node)));
+            }
+            stack.extend(self.nodes[node].children.iter().rev());
+        }
+
+        instances
+    }
+
+    pub fn load_textures<F: Facade>(&self, facade: &F) -> Result<Vec<Option<Texture2d>>, LoadError> {
+        self.textures.iter().map(|texture| match texture.source {
+            Some(image) => self.images[image].load_texture(facade).map(Some),
+            None => Ok(None)
+        }).collect()
+    }
+}
+
+// Load a .gltf or a .glb file, told apart by their content
+pub fn load_gltf<P: AsRef<Path>>(path: P) -> Result<GltfDocument, LoadError> {
+    let path = path.as_ref();
+    let mut data = Vec::new();
+    File::open(path)?.read_to_end(&mut data)?;
+
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    if data.len() >= 4 && read_u32(&data, 0) == GLB_MAGIC {
+        parse_glb(&data, base_dir)
+    } else {
+        parse_gltf(&data, base_dir)
+    }
+}
+
+pub fn parse_gltf(data: &[u8], base_dir: &Path) -> Result<GltfDocument, LoadError> {
+    let text = String::from_utf8_lossy(data);
+    // A UTF-8 byte order mark is not allowed, but common
+    let root = json::parse(text.trim_start_matches('\u{feff}'))?;
+    parse_document(&root, None, base_dir)
+}
+
+pub fn parse_glb(data: &[u8], base_dir: &Path) -> Result<GltfDocument, LoadError> {
+    if data.len() < 12 || read_u32(data, 0) != GLB_MAGIC {
+        return Err(LoadError::parse("not a GLB file"));
+    }
+    if read_u32(data, 4) != 2 {
+        return Err(LoadError::parse(format!("unsupported GLB version {}", read_u32(data, 4))));
+    }
+    let length = (read_u32(data, 8) as usize).min(data.len());
+
+    let mut json_chunk = None;
+    let mut bin_chunk = None;
+    let mut offset = 12;
+    while offset + 8 <= length {
+        let chunk_length = read_u32(data, offset) as usize;
+        let chunk_type = read_u32(data, offset + 4);
+        let start = offset + 8;
+        if start + chunk_length > length {
+            return Err(LoadError::parse("GLB chunk out of the file"));
+        }
+
+        let chunk = &data[start..start + chunk_length];
+        match chunk_type {
+            GLB_CHUNK_JSON if json_chunk.is_none() => json_chunk = Some(chunk),
+            GLB_CHUNK_BIN if bin_chunk.is_none() => bin_chunk = Some(chunk),
+            // Unknown chunks must be ignored
+            _ => ()
+        }
+
+        // Chunks are aligned on 4 bytes
+        offset = start + chunk_length.div_ceil(4) * 4;
+    }
+
+    let json_chunk = json_chunk.ok_or_else(|| LoadError::parse("GLB file without JSON chunk"))?;
+    let text = String::from_utf8_lossy(json_chunk);
+    let root = json::parse(text.trim_end_matches([' ', '\0']))?;
+    parse_document(&root, bin_chunk, base_dir)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+// The elements of a top level array, empty when it is missing
+fn array<'a>(root: &'a Json, key: &str) -> &'a [Json] {
+    root.get(key).and_then(Json::as_array).unwrap_or(&[])
+}
+
+fn name(value: &Json) -> String {
+    value.get("name").and_then(Json::as_str).unwrap_or("").to_string()
+}
+
+fn float(value: &Json, key: &str, default: f32) -> f32 {
+    value.get(key).and_then(Json::as_f64).map_or(default, |value| value as f32)
+}
+
+fn floats(value: &Json, key: &str, count: usize) -> Result<Option<Vec<f32>>, LoadError> {
+    match value.get(key) {
+        Some(array) => {
+            let values: Vec<f32> = array.as_array().unwrap_or(&[]).iter()
+                .filter_map(Json::as_f64).map(|value| value as f32).collect();
+            if values.len() != count {
+                return Err(LoadError::parse(format!("'{}' must have {} numbers", key, count)));
+            }
+            Ok(Some(values))
+        },
+        None => Ok(None)
+    }
+}
+
+// An optional index into an array of `count` elements
+fn index(value: &Json, key: &str, count: usize) -> Result<Option<usize>, LoadError> {
+    match value.get(key) {
+        Some(index) => match index.as_usize() {
+            Some(index) if index < count => Ok(Some(index)),
+            _ => Err(LoadError::parse(format!("invalid '{}' index", key)))
+        },
+        None => Ok(None)
+    }
+}
+
+fn parse_document(root: &Json, bin_chunk: Option<&[u8]>, base_dir: &Path) -> Result<GltfDocument, LoadError> {
+    let version = root.get("asset").and_then(|asset| asset.get("version")).and_then(Json::as_str)
+        .ok_or_else(|| LoadError::parse("missing asset version"))?;
+    if !version.starts_with("2.") {
+        return Err(LoadError::parse(format!("unsupported glTF version {}", version)));
+    }
+
+    for extension in array(root, "extensionsRequired") {
+        let extension = extension.as_str().unwrap_or("");
+        if !SUPPORTED_EXTENSIONS.contains(&extension) {
+            return Err(LoadError::parse(format!("required extension {} is not supported", extension)));
+        }
+    }
+
+    // Buffers
+    let mut buffers = Vec::new();
+    for (i, buffer) in array(root, "buffers").iter().enumerate() {
+        let data = match buffer.get("uri").and_then(Json::as_str) {
+            Some(uri) => accessor::read_uri(uri, base_dir)?,
+            // Only the first buffer of a GLB file may refer to the BIN chunk
+            None => match bin_chunk {
+                Some(chunk) if i == 0 => chunk.to_vec(),
+                _ => return Err(LoadError::parse(format!("buffer {} has no data", i)))
+            }
+        };
+
+        let length = buffer.get("byteLength").and_then(Json::as_usize).unwrap_or(data.len());
+        if data.len() < length {
+            return Err(LoadError::parse(format!("buffer {} is shorter than its byteLength", i)));
+        }
+        buffers.push(data);
+    }
+
+    let images = array(root, "images").iter()
+        .map(|image| parse_image(root, &buffers, image, base_dir))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let samplers = array(root, "samplers").iter().map(|sampler| GltfSampler {
+        mag_filter: sampler.get("magFilter").and_then(Json::as_usize).map(|filter| filter as u32),
+        min_filter: sampler.get("minFilter").and_then(Json::as_usize).map(|filter| filter as u32),
+        // REPEAT
+        wrap_s: sampler.get("wrapS").and_then(Json::as_usize).map_or(10497, |wrap| wrap as u32),
+        wrap_t: sampler.get("wrapT").and_then(Json::as_usize).map_or(10497, |wrap| wrap as u32)
+    }).collect::<Vec<_>>();
+
+    let textures = array(root, "textures").iter().map(|texture| Ok(GltfTexture {
+        source: index(texture, "source", images.len())?,
+        sampler: index(texture, "sampler", samplers.len())?
+    })).collect::<Result<Vec<_>, LoadError>>()?;
+
+    let materials = array(root, "materials").iter()
+        .map(|material| parse_material(material, textures.len()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let meshes = array(root, "meshes").iter()
+        .map(|mesh| parse_mesh(root, &buffers, mesh, materials.len()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let cameras = array(root, "cameras").iter().map(parse_camera).collect::<Result<Vec<_>, _>>()?;
+
+    let nodes = parse_nodes(root, meshes.len(), cameras.len())?;
+
+    let scenes = array(root, "scenes").iter().map(|scene| {
+        let nodes = array(scene, "nodes").iter().map(|node| match node.as_usize() {
+            Some(node) if node < nodes.len() => Ok(node),
+            _ => Err(LoadError::parse("invalid scene node"))
+        }).collect::<Result<Vec<_>, _>>()?;
+        Ok(GltfScene {
+            name: name(scene),
+            nodes: nodes
+        })
+    }).collect::<Result<Vec<_>, LoadError>>()?;
+
+    Ok(GltfDocument {
+        default_scene: index(root, "scene", scenes.len())?,
+        scenes: scenes,
+        nodes: nodes,
+        meshes: meshes,
+        materials: materials,
+        textures: textures,
+        images: images,
+        samplers: samplers,
+        cameras: cameras
+    })
+}
+
+fn parse_image(root: &Json, buffers: &[Vec<u8>], image: &Json, base_dir: &Path) -> Result<GltfImage, LoadError> {
+    let mime_type = image.get("mimeType").and_then(Json::as_str).map(|mime_type| mime_type.to_string());
+
+    let source = if let Some(uri) = image.get("uri").and_then(Json::as_str) {
+        if uri.starts_with("data:") {
+            ImageSource::Embedded {
+                data: accessor::read_uri(uri, base_dir)?,
+                mime_type: mime_type.or_else(|| accessor::data_uri_mime_type(uri).map(|m| m.to_string()))
+            }
+        } else {
+            ImageSource::Path(base_dir.join(accessor::decode_percent(uri)))
+        }
+    } else if let Some(view) = image.get("bufferView").and_then(Json::as_usize) {
+        ImageSource::Embedded {
+            data: accessor::buffer_view(root, buffers, view)?.0.to_vec(),
+            mime_type: mime_type
+        }
+    } else {
+        return Err(LoadError::parse("image without uri nor bufferView"));
+    };
+
+    Ok(GltfImage {
+        name: name(image),
+        source: source
+    })
+}
+
+fn parse_texture_ref(value: Option<&Json>, scale_key: &str, texture_count: usize)
+    -> Result<Option<TextureRef>, LoadError> {
+    match value {
+        Some(value) => Ok(Some(TextureRef {
+            texture: index(value, "index", texture_count)?
+                .ok_or_else(|| LoadError::parse("texture reference without index"))?,
+            tex_coord: value.get("texCoord").and_then(Json::as_usize).unwrap_or(0),
+            scale: float(value, scale_key, 1.0)
+        })),
+        None => Ok(None)
+    }
+}
+
+fn parse_material(material: &Json, texture_count: usize) -> Result<PbrMaterial, LoadError> {
+    let default = PbrMaterial::default();
+    let empty = Json::Null;
+    let pbr = material.get("pbrMetallicRoughness").unwrap_or(&empty);
+
+    Ok(PbrMaterial {
+        name: name(material),
+        base_color_factor: floats(pbr, "baseColorFactor", 4)?
+            .map_or(default.base_color_factor, |f| [f[0], f[1], f[2], f[3]]),
+        base_color_texture: parse_texture_ref(pbr.get("baseColorTexture"), "", texture_count)?,
+        metallic_factor: float(pbr, "metallicFactor", 1.0),
+        roughness_factor: float(pbr, "roughnessFactor", 1.0),
+        metallic_roughness_texture: parse_texture_ref(pbr.get("metallicRoughnessTexture"), "", texture_count)?,
+        normal_texture: parse_texture_ref(material.get("normalTexture"), "scale", texture_count)?,
+        occlusion_texture: parse_texture_ref(material.get("occlusionTexture"), "strength", texture_count)?,
+        emissive_texture: parse_texture_ref(material.get("emissiveTexture"), "", texture_count)?,
+        emissive_factor: floats(material, "emissiveFactor", 3)?
+            .map_or(default.emissive_factor, |f| [f[0], f[1], f[2]]),
+        alpha_mode: match material.get("alphaMode").and_then(Json::as_str) {
+            None | Some("OPAQUE") => AlphaMode::Opaque,
+            Some("MASK") => AlphaMode::Mask,
+            Some("BLEND") => AlphaMode::Blend,
+            Some(mode) => return Err(LoadError::parse(format!("unknown alpha mode {}", mode)))
+        },
+        alpha_cutoff: float(material, "alphaCutoff", 0.5),
+        double_sided: material.get("doubleSided").and_then(Json::as_bool).unwrap_or(false)
+    })
+}
+
+// Read an optional vertex attribute, element by element
+fn read_attribute<F>(root: &Json, buffers: &[Vec<u8>], attributes: &Json, name: &str, count: usize,
+                     mut push: F) -> Result<(), LoadError> where F: FnMut(&[f64]) {
+    let accessor = match attributes.get(name).and_then(Json::as_usize) {
+        Some(accessor) => accessor,
+        None => return Ok(())
+    };
+
+    let (values, components) = accessor::read_accessor(root, buffers, accessor)?;
+    if values.len() != count * components {
+        return Err(LoadError::parse(format!("attribute {} does not have one value per vertex", name)));
+    }
+    for element in values.chunks(components) {
+        push(element);
+    }
+    Ok(())
+}
+
+fn parse_primitive(root: &Json, buffers: &[Vec<u8>], primitive: &Json) -> Result<Option<Mesh>, LoadError> {
+    let mode = primitive.get("mode").and_then(Json::as_usize).unwrap_or(4);
+    if mode != 4 && mode != 5 && mode != 6 {
+        // Points and lines have no place in a `Mesh`
+        return Ok(None);
+    }
+
+    let attributes = primitive.get("attributes").ok_or_else(|| LoadError::parse("primitive without attributes"))?;
+    let position = attributes.get("POSITION").and_then(Json::as_usize)
+        .ok_or_else(|| LoadError::parse("primitive without POSITION"))?;
+
+    let mut mesh = Mesh::new();
+    let (positions, components) = accessor::read_accessor(root, buffers, position)?;
+    if components != 3 {
+        return Err(LoadError::parse("POSITION must be a VEC3"));
+    }
+    mesh.positions = positions.chunks(3).map(|p| [p[0] as f32, p[1] as f32, -p[2] as f32]).collect();
+    let count = mesh.positions.len();
+
+    // Without normals, the specification asks for flat normals. They are left out here.
+    read_attribute(root, buffers, attributes, "NORMAL", count,
+                   |n| mesh.normals.push([n[0] as f32, n[1] as f32, -n[2] as f32]))?;
+    read_attribute(root, buffers, attributes, "TEXCOORD_0", count,
+                   |t| mesh.tex_coords.push([t[0] as f32, 1.0 - t[1] as f32]))?;
+    read_attribute(root, buffers, attributes, "COLOR_0", count, |c| {
+        let alpha = if c.len() > 3 { c[3] as f32 } else { 1.0 };
+        mesh.colors.push([c[0] as f32, c[1] as f32, c[2] as f32, alpha]);
+    })?;
+    // The mirror turns the bitangent around too
+    read_attribute(root, buffers, attributes, "TANGENT", count,
+                   |t| mesh.tangents.push([t[0] as f32, t[1] as f32, -t[2] as f32, -t[3] as f32]))?;
+
+    let indices: Vec<u32> = match primitive.get("indices").and_then(Json::as_usize) {
+        Some(accessor) => accessor::read_accessor(root, buffers, accessor)?.0.iter().map(|&i| i as u32).collect(),
+        None => (0..count as u32).collect()
+    };
+    if indices.iter().any(|&index| index as usize >= count) {
+        return Err(LoadError::parse("primitive index out of range"));
+    }
+
+    let triangles: Vec<u32> = match mode {
+        5 => (0..indices.len().saturating_sub(2)).flat_map(|i| {
+            // Every other triangle of a strip is reversed
+            if i % 2 == 0 {
+                vec![indices[i], indices[i + 1], indices[i + 2]]
+            } else {
+                vec![indices[i + 1], indices[i], indices[i + 2]]
+            }
+        }).collect(),
+        6 => (1..indices.len().saturating_sub(1))
+            .flat_map(|i| vec![indices[0], indices[i], indices[i + 1]]).collect(),
+        _ => {
+            if !indices.len().is_multiple_of(3) {
+                return Err(LoadError::parse("triangle primitive with a partial triangle"));
+            }
+            indices
+        }
+    };
+    // Mirrored triangles would point inward
+    mesh.indices = triangles.chunks(3).flat_map(|t| vec![t[0], t[2], t[1]]).collect();
+
+    Ok(Some(mesh))
+}
+
+fn parse_mesh(root: &Json, buffers: &[Vec<u8>], mesh: &Json, material_count: usize) -> Result<Mesh, LoadError> {
+    let mesh_name = name(mesh);
+    let mut result = Mesh::new();
+
+    for (i, primitive) in array(mesh, "primitives").iter().enumerate() {
+        if let Some(part) = parse_primitive(root, buffers, primitive)? {
+            let material = index(primitive, "material", material_count)?;
+            result.append(&part, &format!("{}#{}", mesh_name, i), material);
+        }
+    }
+
+    Ok(result)
+}
+
+fn parse_camera(camera: &Json) -> Result<GltfCamera, LoadError> {
+    match camera.get("type").and_then(Json::as_str) {
+        Some("perspective") => {
+            let perspective = camera.get("perspective")
+                .ok_or_else(|| LoadError::parse("perspective camera without parameters"))?;
+            Ok(GltfCamera::Perspective {
+                name: name(camera),
+                yfov: float(perspective, "yfov", 0.0).to_degrees(),
+                aspect_ratio: perspective.get("aspectRatio").and_then(Json::as_f64).map(|ratio| ratio as f32),
+                z_near: float(perspective, "znear", 0.0),
+                z_far: perspective.get("zfar").and_then(Json::as_f64).map(|z_far| z_far as f32)
+            })
+        },
+        Some("orthographic") => {
+            let orthographic = camera.get("orthographic")
+                .ok_or_else(|| LoadError::parse("orthographic camera without parameters"))?;
+            Ok(GltfCamera::Orthographic {
+                name: name(camera),
+                xmag: float(orthographic, "xmag", 1.0),
+                ymag: float(orthographic, "ymag", 1.0),
+                z_near: float(orthographic, "znear", 0.0),
+                z_far: float(orthographic, "zfar", 1.0)
+            })
+        },
+        _ => Err(LoadError::parse("unknown camera type"))
+    }
+}
+
+fn parse_nodes(root: &Json, mesh_count: usize, camera_count: usize) -> Result<Vec<GltfNode>, LoadError> {
+    let values = array(root, "nodes");
+    let mut nodes = Vec::with_capacity(values.len());
+
+    for node in values {
+        // Mirrored along Z like the meshes
+        let transform = match floats(node, "matrix", 16)? {
+            Some(m) => {
+                let mirror = Matrix4::from_nonuniform_scale(1.0, 1.0, -1.0);
+                Transform::from_matrix(mirror * Matrix4::new(
+                    m[0], m[1], m[2], m[3], m[4], m[5], m[6], m[7],
+                    m[8], m[9], m[10], m[11], m[12], m[13], m[14], m[15]) * mirror)
+            },
+            None => {
+                let mut transform = Transform::identity();
+                if let Some(t) = floats(node, "translation", 3)? {
+                    transform.translation = Vector3::new(t[0], t[1], -t[2]);
+                }
+                // glTF stores quaternions as (x, y, z, w). The mirror turns the rotations the other
+                // way around the mirrored axis.
+                if let Some(r) = floats(node, "rotation", 4)? {
+                    transform.rotation = Quaternion::new(r[3], -r[0], -r[1], r[2]).normalize();
+                }
+                if let Some(s) = floats(node, "scale", 3)? {
+                    transform.scale = Vector3::new(s[0], s[1], s[2]);
+                }
+                transform
+            }
+        };
+
+        let children = array(node, "children").iter().map(|child| match child.as_usize() {
+            Some(child) if child < values.len() => Ok(child),
+            _ => Err(LoadError::parse("invalid node child"))
+        }).collect::<Result<Vec<_>, _>>()?;
+
+        nodes.push(GltfNode {
+            name: name(node),
+            parent: None,
+            children: children,
+            mesh: index(node, "mesh", mesh_count)?,
+            camera: index(node, "camera", camera_count)?,
+            transform: transform
+        });
+    }
+
+    // The hierarchy must be a forest: one parent at most and no cycle
+    for i in 0..nodes.len() {
+        for child in nodes[i].children.clone() {
+            if nodes[child].parent.is_some() {
+                return Err(LoadError::parse(format!("node {} has several parents", child)));
+            }
+            nodes[child].parent = Some(i);
+        }
+    }
+    for i in 0..nodes.len() {
+        let mut current = nodes[i].parent;
+        let mut depth = 0;
+        while let Some(parent) = current {
+            depth += 1;
+            if depth > nodes.len() {
+                return Err(LoadError::parse("the node hierarchy has a cycle"));
+            }
+            current = nodes[parent].parent;
+        }
+    }
+
+    Ok(nodes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use cgmath::{Deg, InnerSpace, Matrix4, Quaternion, Rotation3, SquareMatrix, Vector2, Vector3, Vector4};
+
+    use error::LoadError;
+    use pipeline::Pipeline;
+    use super::{parse_glb, parse_gltf, AlphaMode, GltfCamera, GltfDocument, GltfSampler, GltfTexture, ImageSource,
+        PbrMaterial, TextureRef};
+
+    // Positions of a unit quad, u16 indices 0 to 3, then one sparse index and its new position
+    fn buffer() -> Vec<u8> {
+        let mut data = Vec::new();
+        for &value in &[0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        for &index in &[0u16, 1, 2, 3, 2, 0] {
+            data.extend_from_slice(&index.to_le_bytes());
+        }
+        for &value in &[5.0f32, 5.0, 5.0] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data
+    }
+
+    // The scene around `buffer()`: a strip and a fan, sparse positions over the quad and over
+    // nothing, a node matrix parenting TRS, and two cameras
+    const SCENE: &str = r#"
+        "bufferViews": [
+            {"buffer": 0, "byteOffset": 0, "byteLength": 48},
+            {"buffer": 0, "byteOffset": 48, "byteLength": 8},
+            {"buffer": 0, "byteOffset": 56, "byteLength": 2},
+            {"buffer": 0, "byteOffset": 60, "byteLength": 12}
+        ],
+        "accessors": [
+            {"bufferView": 0, "componentType": 5126, "count": 4, "type": "VEC3"},
+            {"bufferView": 1, "componentType": 5123, "count": 4, "type": "SCALAR"},
+            {"bufferView": 0, "componentType": 5126, "count": 4, "type": "VEC3", "sparse": {"count": 1,
+                "indices": {"bufferView": 2, "componentType": 5123}, "values": {"bufferView": 3}}},
+            {"componentType": 5126, "count": 3, "type": "VEC3", "sparse": {"count": 1,
+                "indices": {"bufferView": 2, "componentType": 5123}, "values": {"bufferView": 3}}}
+        ],
+        "meshes": [
+            {"name": "quad", "primitives": [
+                {"attributes": {"POSITION": 0}, "indices": 1, "mode": 5},
+                {"attributes": {"POSITION": 0}, "indices": 1, "mode": 6}
+            ]},
+            {"primitives": [{"attributes": {"POSITION": 2}, "indices": 1, "mode": 5}]},
+            {"primitives": [{"attributes": {"POSITION": 3}}]}
+        ],
+        "cameras": [
+            {"type": "perspective", "perspective": {"yfov": 0.5, "aspectRatio": 1.5, "znear": 0.1, "zfar": 100}},
+            {"type": "orthographic", "orthographic": {"xmag": 2, "ymag": 1, "znear": 0.1, "zfar": 10}}
+        ],
+        "nodes": [
+            {"matrix": [2, 0, 0, 0, 0, 2, 0, 0, 0, 0, 2, 0, 1, 2, 3, 1], "children": [1]},
+            {"translation": [1, 0, 0], "rotation": [0, 0.70710678, 0, 0.70710678], "scale": [1, 3, 1],
+                "mesh": 0},
+            {"camera": 0, "translation": [0, 0, 5]},
+            {"camera": 1, "rotation": [0, 0.70710678, 0, 0.70710678]}
+        ],
+        "scenes": [{"nodes": [0, 2, 3]}],
+        "scene": 0
+    "#;
+
+    fn base64(data: &[u8]) -> String {
+        const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut text = String::new();
+        for chunk in data.chunks(3) {
+            let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+            let bits = (u32::from(bytes[0]) << 16) | (u32::from(bytes[1]) << 8) | u32::from(bytes[2]);
+            for k in 0..4 {
+                if k <= chunk.len() {
+                    text.push(ALPHABET[(bits >> (18 - 6 * k) & 63) as usize] as char);
+                } else {
+                    text.push('=');
+                }
+            }
+        }
+        text
+    }
+
+    fn gltf_with(body: &str, data: &[u8]) -> Result<GltfDocument, LoadError> {
+        let text = format!(r#"{{"asset": {{"version": "2.0"}}, "buffers": [{{"byteLength": {},
+            "uri": "data:application/octet-stream;base64,{}"}}], {}}}"#, data.len(), base64(data), body);
+        parse_gltf(text.as_bytes(), Path::new(""))
+    }
+
+    fn glb_file() -> Vec<u8> {
+        let mut json = format!(r#"{{"asset": {{"version": "2.0"}}, "buffers": [{{"byteLength": 72}}], {}}}"#,
+            SCENE).into_bytes();
+        while json.len() % 4 != 0 {
+            json.push(b' ');
+        }
+        let bin = buffer();
+
+        let mut data = Vec::new();
+        for &word in &[0x4654_6C67, 2, (12 + 8 + json.len() + 8 + bin.len()) as u32] {
+            data.extend_from_slice(&u32::to_le_bytes(word));
+        }
+        data.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        data.extend_from_slice(&0x4E4F_534Au32.to_le_bytes());
+        data.extend_from_slice(&json);
+        data.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+        data.extend_from_slice(&0x004E_4942u32.to_le_bytes());
+        data.extend_from_slice(&bin);
+        data
+    }
+
+    fn close(a: Matrix4<f32>, b: Matrix4<f32>) -> bool {
+        let (a, b): ([[f32; 4]; 4], [[f32; 4]; 4]) = (a.into(), b.into());
+        a.iter().zip(b.iter()).all(|(a, b)| a.iter().zip(b.iter()).all(|(a, b)| (a - b).abs() < 1e-4))
+    }
+
+    fn check_document(document: &GltfDocument) {
+        // The strip alternates its winding, the fan turns around the first vertex, and both are
+        // reversed by the mirror
+        let quad = &document.meshes[0];
+        assert_eq!(quad.indices, vec![0, 2, 1, 2, 3, 1, 4, 6, 5, 4, 7, 6]);
+        assert_eq!(quad.submeshes.len(), 2);
+        assert_eq!(quad.submeshes[1].name, "quad#1");
+        assert_eq!(quad.positions[3], [1.0, 1.0, 0.0]);
+
+        // Sparse values replace the ones of the bufferView, or the zeros without one
+        let sparse = &document.meshes[1];
+        assert_eq!(sparse.positions, vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [5.0, 5.0, -5.0], [1.0, 1.0, 0.0]]);
+        let zeros = &document.meshes[2];
+        assert_eq!(zeros.positions, vec![[0.0, 0.0, 0.0], [0.0, 0.0, 0.0], [5.0, 5.0, -5.0]]);
+        assert_eq!(zeros.indices, vec![0, 2, 1]);
+
+        // A matrix and its TRS decomposition are the same thing, mirrored along Z
+        let parent = Matrix4::from_translation(Vector3::new(1.0, 2.0, -3.0)) * Matrix4::from_scale(2.0);
+        assert!(close(document.nodes[0].transform.to_matrix(), parent));
+        let child = Matrix4::from_translation(Vector3::new(1.0, 0.0, 0.0))
+            * Matrix4::from(Quaternion::from_angle_y(Deg(-90.0)))
+            * Matrix4::from_nonuniform_scale(1.0, 3.0, 1.0);
+        assert!(close(document.nodes[1].transform.to_matrix(), child));
+        assert!(close(document.world_matrix(1), parent * child));
+        assert_eq!(document.nodes[1].parent, Some(0));
+        assert_eq!(document.mesh_instances(0).len(), 1);
+        let corner = document.world_matrix(1) * Vector4::new(0.0, 1.0, -1.0, 1.0);
+        assert!((corner.truncate() - Vector3::new(5.0, 8.0, -3.0)).magnitude() < 1e-4);
+
+        // Cameras look down their -Z, which is +Z once mirrored
+        let (position, target, up) = document.camera_view(2);
+        assert_eq!(position, Vector3::new(0.0, 0.0, -5.0));
+        assert!((target - Vector3::new(0.0, 0.0, 1.0)).magnitude() < 1e-5);
+        assert!((up - Vector3::new(0.0, 1.0, 0.0)).magnitude() < 1e-5);
+        let (_, target, _) = document.camera_view(3);
+        assert!((target - Vector3::new(-1.0, 0.0, 0.0)).magnitude() < 1e-5);
+
+        let perspective = document.cameras[0].to_pers_proj_info(800.0, 600.0).unwrap();
+        assert!((perspective.fov - 0.5f32.to_degrees()).abs() < 1e-4);
+        assert_eq!((perspective.width, perspective.height), (900.0, 600.0));
+        assert_eq!((perspective.z_near, perspective.z_far), (0.1, 100.0));
+        assert!(document.cameras[0].to_ortho_proj_info().is_none());
+
+        let orthographic = document.cameras[1].to_ortho_proj_info().unwrap();
+        assert_eq!((orthographic.left, orthographic.right), (-2.0, 2.0));
+        assert_eq!((orthographic.bottom, orthographic.top), (-1.0, 1.0));
+        assert_eq!((orthographic.z_near, orthographic.z_far), (0.1, 10.0));
+        match document.cameras[1] {
+            GltfCamera::Orthographic { .. } => (),
+            ref camera => panic!("{:?}", camera)
+        }
+    }
+
+    #[test]
+    fn gltf_with_data_uri() {
+        let document = gltf_with(SCENE, &buffer()).unwrap();
+        assert_eq!(document.default_scene, Some(0));
+        check_document(&document);
+    }
+
+    #[test]
+    fn glb_with_bin_chunk() {
+        let document = parse_glb(&glb_file(), Path::new("")).unwrap();
+        check_document(&document);
+    }
+
+    #[test]
+    fn scenes_are_seen_as_in_gltf() {
+        // A triangle facing +Z, seen from the front and from the right at 60 degrees
+        let body = r#"
+            "bufferViews": [{"buffer": 0, "byteLength": 48}],
+            "accessors": [{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3"}],
+            "meshes": [{"primitives": [{"attributes": {"POSITION": 0}}]}],
+            "cameras": [{"type": "perspective", "perspective": {"yfov": 0.8, "aspectRatio": 1.5, "znear": 0.1,
+                "zfar": 100}}],
+            "nodes": [
+                {"mesh": 0, "translation": [0.5, -0.25, -1]},
+                {"camera": 0, "translation": [0.2, 0.1, 4]},
+                {"camera": 0, "translation": [4, 0.5, 0], "rotation": [0, 0.5, 0, 0.8660254]}
+            ],
+            "scenes": [{"nodes": [0, 1, 2]}]
+        "#;
+        let document = gltf_with(body, &buffer()).unwrap();
+        let (_, world) = document.mesh_instances(0)[0];
+        let mesh = &document.meshes[0];
+        let info = document.cameras[0].to_pers_proj_info(800.0, 600.0).unwrap();
+        let tan_half_fov = 0.4f32.tan();
+
+        let cameras = [
+            (Vector3::new(0.2, 0.1, 4.0), Quaternion::from_angle_y(Deg(0.0))),
+            (Vector3::new(4.0, 0.5, 0.0), Quaternion::from_angle_y(Deg(60.0)))
+        ];
+        for (node, &(position, rotation)) in cameras.iter().enumerate().map(|(i, camera)| (i + 1, camera)) {
+            let (pos, target, up) = document.camera_view(node);
+            let mut pipeline = Pipeline::new();
+            pipeline.set_camera(pos, target, up);
+            pipeline.set_perspective_proj(info.fov, info.width, info.height, info.z_near, info.z_far);
+            let wvp = pipeline.get_project_trans() * pipeline.get_view_trans() * world;
+
+            // Where the right-handed glTF camera, looking down its -Z, sees the corners
+            let view = (Matrix4::from_translation(position) * Matrix4::from(rotation)).invert().unwrap();
+            let corners = [Vector3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0)];
+            let mut screen = Vec::new();
+            for (p, corner) in mesh.positions.iter().zip(corners.iter()) {
+                let seen = view * (corner + Vector3::new(0.5, -0.25, -1.0)).extend(1.0);
+                let expected = Vector2::new(seen.x / (-seen.z * tan_half_fov * 1.5), seen.y / (-seen.z * tan_half_fov));
+
+                let clip = wvp * Vector4::new(p[0], p[1], p[2], 1.0);
+                let projected = Vector2::new(clip.x / clip.w, clip.y / clip.w);
+                assert!((projected - expected).magnitude() < 1e-4, "{:?} != {:?}", projected, expected);
+                screen.push(projected);
+            }
+            assert!(screen[1].x > screen[0].x && screen[2].y > screen[0].y, "{:?}", screen);
+
+            // The triangle faces the camera, so it is clockwise on the screen like the front faces of `Mesh`
+            let t: Vec<usize> = mesh.indices.iter().map(|&index| index as usize).collect();
+            let (a, b, c) = (screen[t[0]], screen[t[1]], screen[t[2]]);
+            assert!((b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x) < 0.0, "{:?}", t);
+        }
+    }
+
+    // Images from a file, a bufferView and a data URI, a sampler with and one without its
+    // fields, and the textures and materials using them
+    const MATERIALS: &str = r#"
+        "bufferViews": [
+            {"buffer": 0, "byteOffset": 0, "byteLength": 48},
+            {"buffer": 0, "byteOffset": 48, "byteLength": 8}
+        ],
+        "accessors": [{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3"}],
+        "images": [
+            {"name": "wood", "uri": "textures/old%20wood.png"},
+            {"bufferView": 1, "mimeType": "image/jpeg"},
+            {"uri": "data:image/png;base64,iVBORw0K"}
+        ],
+        "samplers": [{"magFilter": 9728, "minFilter": 9987, "wrapS": 33071, "wrapT": 33648}, {}],
+        "textures": [{"source": 0, "sampler": 0}, {"source": 1, "sampler": 1}, {"source": 2}, {}],
+        "materials": [
+            {
+                "name": "varnish",
+                "pbrMetallicRoughness": {
+                    "baseColorFactor": [0.5, 0.25, 1, 0.75],
+                    "baseColorTexture": {"index": 0},
+                    "metallicFactor": 0.1,
+                    "roughnessFactor": 0.6,
+                    "metallicRoughnessTexture": {"index": 1, "texCoord": 1}
+                },
+                "normalTexture": {"index": 2, "scale": 0.5},
+                "occlusionTexture": {"index": 1, "strength": 0.25},
+                "emissiveTexture": {"index": 3},
+                "emissiveFactor": [1, 0.5, 0],
+                "alphaMode": "MASK",
+                "alphaCutoff": 0.3,
+                "doubleSided": true
+            },
+            {"alphaMode": "BLEND"}
+        ],
+        "meshes": [{"primitives": [
+            {"attributes": {"POSITION": 0}, "material": 1},
+            {"attributes": {"POSITION": 0}}
+        ]}]
+    "#;
+
+    #[test]
+    fn materials_and_textures() {
+        let document = gltf_with(MATERIALS, &buffer()).unwrap();
+
+        assert_eq!(document.images.len(), 3);
+        assert_eq!(document.images[0].name, "wood");
+        assert_eq!(document.images[0].source, ImageSource::Path(Path::new("textures/old wood.png").to_path_buf()));
+        assert_eq!(document.images[1].source, ImageSource::Embedded {
+            data: vec![0, 0, 1, 0, 2, 0, 3, 0],
+            mime_type: Some("image/jpeg".to_string())
+        });
+        assert_eq!(document.images[2].source, ImageSource::Embedded {
+            data: vec![0x89, b'P', b'N', b'G', b'\r', b'\n'],
+            mime_type: Some("image/png".to_string())
+        });
+
+        // Filters stay unset and the wrap modes repeat by default
+        assert_eq!(document.samplers, vec![
+            GltfSampler { mag_filter: Some(9728), min_filter: Some(9987), wrap_s: 33071, wrap_t: 33648 },
+            GltfSampler { mag_filter: None, min_filter: None, wrap_s: 10497, wrap_t: 10497 }
+        ]);
+        assert_eq!(document.textures, vec![
+            GltfTexture { source: Some(0), sampler: Some(0) },
+            GltfTexture { source: Some(1), sampler: Some(1) },
+            GltfTexture { source: Some(2), sampler: None },
+            GltfTexture { source: None, sampler: None }
+        ]);
+
+        let texture = |index, tex_coord, scale| Some(TextureRef { texture: index, tex_coord: tex_coord, scale: scale });
+        assert_eq!(document.materials[0], PbrMaterial {
+            name: "varnish".to_string(),
+            base_color_factor: [0.5, 0.25, 1.0, 0.75],
+            base_color_texture: texture(0, 0, 1.0),
+            metallic_factor: 0.1,
+            roughness_factor: 0.6,
+            metallic_roughness_texture: texture(1, 1, 1.0),
+            normal_texture: texture(2, 0, 0.5),
+            occlusion_texture: texture(1, 0, 0.25),
+            emissive_texture: texture(3, 0, 1.0),
+            emissive_factor: [1.0, 0.5, 0.0],
+            alpha_mode: AlphaMode::Mask,
+            alpha_cutoff: 0.3,
+            double_sided: true
+        });
+        assert_eq!(document.materials[1], PbrMaterial { alpha_mode: AlphaMode::Blend, .. PbrMaterial::default() });
+
+        // The submeshes refer to the materials, or to none
+        let mesh = &document.meshes[0];
+        assert_eq!(mesh.submeshes.iter().map(|submesh| submesh.material).collect::<Vec<_>>(), vec![Some(1), None]);
+    }
+
+    #[test]
+    fn bad_materials_and_textures_are_rejected() {
+        let data = buffer();
+        for &(from, to) in &[
+            // Texture indices out of range
+            (r#""baseColorTexture": {"index": 0}"#, r#""baseColorTexture": {"index": 4}"#),
+            (r#""normalTexture": {"index": 2, "scale": 0.5}"#, r#""normalTexture": {"index": -1}"#),
+            (r#""emissiveTexture": {"index": 3}"#, r#""emissiveTexture": {"texCoord": 0}"#),
+            (r#"{"source": 0, "sampler": 0}"#, r#"{"source": 3}"#),
+            (r#"{"source": 2}"#, r#"{"sampler": 2}"#),
+            (r#""alphaMode": "MASK""#, r#""alphaMode": "CUTOUT""#),
+            (r#""emissiveFactor": [1, 0.5, 0]"#, r#""emissiveFactor": [1, 0.5]"#),
+            (r#""material": 1"#, r#""material": 2"#),
+            (r#""bufferView": 1, "mimeType""#, r#""bufferView": 2, "mimeType""#),
+            (r#"{"uri": "data:image/png;base64,iVBORw0K"}"#, r#"{"name": "nothing"}"#)
+        ] {
+            assert!(MATERIALS.contains(from), "{}", from);
+            let body = MATERIALS.replace(from, to);
+            assert!(gltf_with(&body, &data).is_err(), "{} was accepted", to);
+        }
+    }
+
+    #[test]
+    fn truncated_glb_is_rejected() {
+        let mut data = glb_file();
+        let length = data.len();
+        data.truncate(length - 8);
+        data[8..12].copy_from_slice(&(length as u32 - 8).to_le_bytes());
+        assert!(parse_glb(&data, Path::new("")).is_err());
+    }
+
+    #[test]
+    fn node_cycles_are_rejected() {
+        let data = buffer();
+        assert!(gltf_with(r#""nodes": [{"children": [1]}, {"children": [0]}]"#, &data).is_err());
+        assert!(gltf_with(r#""nodes": [{"children": [0]}]"#, &data).is_err());
+        // Several parents
+        assert!(gltf_with(r#""nodes": [{"children": [2]}, {"children": [2]}, {}]"#, &data).is_err());
+        assert!(gltf_with(r#""nodes": [{"children": [1]}, {"children": [2]}, {}]"#, &data).is_ok());
+    }
+
+    #[test]
+    fn huge_accessors_are_rejected() {
+        let data = buffer();
+        for count in &["4294967295", "1e15", "4611686018427387904"] {
+            let body = format!(r#""accessors": [{{"componentType": 5126, "count": {}, "type": "MAT4"}}],
+                "meshes": [{{"primitives": [{{"attributes": {{"POSITION": 0}}}}]}}]"#, count);
+            match gltf_with(&body, &data) {
+                Err(LoadError::Parse(_)) => (),
+                Err(err) => panic!("{}", err),
+                Ok(_) => panic!("{} elements were accepted", count)
+            }
+        }
+    }
+}
@@ -1,5 +1,5 @@
 
-use cgmath::{InnerSpace, Vector3, Matrix, Matrix4};
+use cgmath::{InnerSpace, Vector3, Matrix, Matrix3, Matrix4, SquareMatrix};
 
 #[derive(Default, Clone, Copy)]
 pub struct PersProjInfo {
@@ -10,6 +10,16 @@ pub struct PersProjInfo {
     pub z_far: f32
 }
 
+#[derive(Default, Clone, Copy)]
+pub struct OrthoProjInfo {
+    pub left: f32,
+    pub right: f32,
+    pub bottom: f32,
+    pub top: f32,
+    pub z_near: f32,
+    pub z_far: f32
+}
+
 pub fn init_scale_transform(scale_x: f32, scale_y: f32, scale_z: f32) -> Matrix4<f32> {
     Matrix4::new(
         scale_x, 0.0, 0.0, 0.0,
@@ -70,6 +80,20 @@ pub fn init_pers_proj_transform(p: PersProjInfo) -> Matrix4<f32> {
     ).transpose()
 }
 
+pub fn init_ortho_proj_transform(p: OrthoProjInfo) -> Matrix4<f32> {
+    let width = p.right - p.left;
+    let height = p.top - p.bottom;
+    let depth = p.z_far - p.z_near;
+
+    // Same as the perspective projection, z_near is mapped to -1 and z_far is mapped to 1
+    Matrix4::new(
+        2.0 / width, 0.0, 0.0, -(p.right + p.left) / width,
+        0.0, 2.0 / height, 0.0, -(p.top + p.bottom) / height,
+        0.0, 0.0, 2.0 / depth, -(p.z_far + p.z_near) / depth,
+        0.0, 0.0, 0.0, 1.0
+    ).transpose()
+}
+
 pub fn init_camera_transform(target: Vector3<f32>, up: Vector3<f32>) -> Matrix4<f32> {
     let mut n: Vector3<f32> = target;
     n = n.normalize();
@@ -86,6 +110,29 @@ pub fn init_camera_transform(target: Vector3<f32>, up: Vector3<f32>) -> Matrix4<
     ).transpose()
 }
 
+// The rotation of a camera rendering the face `face` of a cube map, faces in the OpenGL order
+// (+X, -X, +Y, -Y, +Z, -Z). The rows of a face go down from its top while the rows of a
+// framebuffer go up, so the faces are rendered with the up vectors upside down, and mirrored in x
+// to stay the same side around.
+pub fn init_cube_face_transform(face: usize) -> Matrix4<f32> {
+    let (target, up) = match face {
+        0 => (Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, -1.0, 0.0)),
+        1 => (Vector3::new(-1.0, 0.0, 0.0), Vector3::new(0.0, -1.0, 0.0)),
+        2 => (Vector3::new(0.0, 1.0, 0.0), Vector3::new(0.0, 0.0, 1.0)),
+        3 => (Vector3::new(0.0, -1.0, 0.0), Vector3::new(0.0, 0.0, -1.0)),
+        4 => (Vector3::new(0.0, 0.0, 1.0), Vector3::new(0.0, -1.0, 0.0)),
+        _ => (Vector3::new(0.0, 0.0, -1.0), Vector3::new(0.0, -1.0, 0.0))
+    };
+    init_scale_transform(-1.0, 1.0, 1.0) * init_camera_transform(target, up)
+}
+
+// The inverse transpose of the upper 3x3 of `matrix`, which keeps the normals perpendicular to the
+// surfaces under non uniform scaling. A singular matrix gives its upper 3x3.
+pub fn normal_matrix(matrix: Matrix4<f32>) -> Matrix3<f32> {
+    let linear = Matrix3::from_cols(matrix.x.truncate(), matrix.y.truncate(), matrix.z.truncate());
+    linear.invert().map(|inverse| inverse.transpose()).unwrap_or(linear)
+}
+
 // == Matrix Tamplate ==
 // Matrix4::new(
 //     1.0, 0.0, 0.0, 0.0,
@@ -93,3 +140,70 @@ pub fn init_camera_transform(target: Vector3<f32>, up: Vector3<f32>) -> Matrix4<
 //     0.0, 0.0, 1.0, 0.0,
 //     0.0, 0.0, 0.0, 1.0
 // ).transpose()
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Vector3};
+
+    use super::*;
+
+    #[test]
+    fn normal_matrix_keeps_normals_perpendicular() {
+        let world = init_translation_transform(1.0, -2.0, 3.0) * init_rotate_transform(30.0, 45.0, 60.0)
+            * init_scale_transform(4.0, 0.5, 2.0);
+        let linear = Matrix3::from_cols(world.x.truncate(), world.y.truncate(), world.z.truncate());
+        let normals = normal_matrix(world);
+
+        let surfaces = [
+            (Vector3::new(0.0, 1.0, 0.0), Vector3::new(1.0, 0.0, 0.0)),
+            (Vector3::new(1.0, 1.0, 0.0).normalize(), Vector3::new(1.0, -1.0, 0.0).normalize()),
+            (Vector3::new(1.0, 2.0, 3.0).normalize(), Vector3::new(3.0, 0.0, -1.0).normalize())
+        ];
+        for &(normal, tangent) in &surfaces {
+            let bitangent = normal.cross(tangent);
+            let normal = (normals * normal).normalize();
+            for &direction in &[tangent, bitangent] {
+                let direction = (linear * direction).normalize();
+                assert!(normal.dot(direction).abs() < 1e-5, "{:?} against {:?}", normal, direction);
+            }
+        }
+
+        // The upper 3x3 alone would bend the normals
+        let (normal, tangent) = surfaces[1];
+        assert!((linear * normal).normalize().dot((linear * tangent).normalize()).abs() > 0.1);
+    }
+
+    #[test]
+    fn cube_faces_look_along_their_axis() {
+        let projection = init_pers_proj_transform(PersProjInfo {
+            fov: 90.0,
+            width: 1.0,
+            height: 1.0,
+            z_near: 0.1,
+            z_far: 10.0
+        });
+        let axes = [
+            Vector3::new(1.0, 0.0, 0.0), Vector3::new(-1.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0),
+            Vector3::new(0.0, -1.0, 0.0), Vector3::new(0.0, 0.0, 1.0), Vector3::new(0.0, 0.0, -1.0)
+        ];
+        for (face, &axis) in axes.iter().enumerate() {
+            let view = init_cube_face_transform(face);
+            // In front of the camera, whatever the mirroring
+            assert!(((view * axis.extend(1.0)).truncate() - Vector3::new(0.0, 0.0, 1.0)).magnitude() < 1e-6);
+
+            let p = projection * view * (axis * 5.0).extend(1.0);
+            assert!((p.x / p.w).abs() < 1e-6 && (p.y / p.w).abs() < 1e-6, "face {}: {:?}", face, p);
+            assert!((p.z / p.w).abs() < 1.0);
+        }
+    }
+
+    #[test]
+    fn normal_matrix_of_a_rotation_is_the_rotation() {
+        let rotation = init_rotate_transform(10.0, 20.0, 30.0);
+        let normals = normal_matrix(rotation);
+        let linear = Matrix3::from_cols(rotation.x.truncate(), rotation.y.truncate(), rotation.z.truncate());
+        for (a, b) in [normals.x, normals.y, normals.z].iter().zip([linear.x, linear.y, linear.z].iter()) {
+            assert!((a - b).magnitude() < 1e-5);
+        }
+    }
+}
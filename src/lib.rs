@@ -1,11 +1,34 @@
 extern crate cgmath;
+#[macro_use]
 extern crate glium;
+extern crate image;
 
 // Re-export
 pub use pipeline::Pipeline;
+pub use graphical_math::{OrthoProjInfo, PersProjInfo};
+pub use transform::Transform;
+pub use error::LoadError;
 pub use camera::Camera;
+pub use rail_camera::{RailCamera, RailOrientation};
+pub use viewport::{Region, ViewProjection, Viewport, ViewportLayout};
+pub use skybox::{Skybox, SkyboxError};
 
 // Modules
+pub mod spline;
+pub mod mesh;
+pub mod gltf;
+pub mod asset;
+pub mod texture;
+pub mod lighting;
+pub mod shadow;
+pub mod billboard;
+pub mod particles;
+pub mod picking;
 mod pipeline;
 mod graphical_math;
+mod transform;
 mod camera;
+mod rail_camera;
+mod viewport;
+mod skybox;
+mod error;
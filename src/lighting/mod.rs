@@ -0,0 +1,464 @@
+// Lights, their GLSL counterparts and the CPU versions of the same equations.
+//
+// `LightUniforms` adds the lights to the uniforms of a `uniform!` block under the names of the
+// GLSL structs, e.g. `gDirectionalLight.Color`, which `uniform!` cannot name itself. The shaders
+// include the snippets of this module to get the structs and the functions which use them.
+//
+// The direction of a light is where its light goes, like in OGLdev, so a surface faces the light
+// when its normal points against the direction.
+
+use cgmath::{InnerSpace, Vector3};
+use glium::uniforms::{UniformValue, Uniforms};
+
+use camera::Camera;
+use texture::{MaterialTextures, TextureHandle, TextureManager};
+
+pub use self::normal_map::{NormalMapConvention, bumped_normal, decode_normal, normal_map_from_heights, tbn};
+pub use self::point::{Attenuation, MAX_POINT_LIGHTS, MAX_SPOT_LIGHTS, PointLight, SpotLight, cull_point_lights,
+    cull_spot_lights, local_lights_glsl};
+
+mod normal_map;
+mod point;
+
+// The GLSL struct of `DirectionalLight` and the Lambert shading it gives. `CalcDirectionalLight`
+// returns the factor to multiply the color of the surface with.
+pub const DIRECTIONAL_LIGHT_GLSL: &str = r#"
+    struct DirectionalLight {
+        vec3 Color;
+        float AmbientIntensity;
+        float DiffuseIntensity;
+        vec3 Direction;
+    };
+
+    uniform DirectionalLight gDirectionalLight;
+
+    vec3 CalcDirectionalLight(vec3 normal) {
+        vec3 ambient = gDirectionalLight.Color * gDirectionalLight.AmbientIntensity;
+        float diffuseFactor = max(dot(normalize(normal), -gDirectionalLight.Direction), 0.0);
+        vec3 diffuse = gDirectionalLight.Color * gDirectionalLight.DiffuseIntensity * diffuseFactor;
+        return ambient + diffuse;
+    }
+"#;
+
+// The GLSL struct of `Material` and the eye position. `CalcSpecularFactor` gives how much of a
+// light going along `lightDirection` is reflected toward the eye, with the specular map applied.
+// `MaterialNormal` bends the interpolated normal by the normal map, if any, with the tangent
+// frame `CalcTBN` builds from the `tangent` attribute.
+pub const MATERIAL_GLSL: &str = r#"
+    struct Material {
+        float SpecularIntensity;
+        float SpecularPower;
+        bool BlinnPhong;
+        bool HasDiffuseMap;
+        bool HasSpecularMap;
+        bool HasNormalMap;
+        bool FlipGreen;
+        sampler2D DiffuseMap;
+        sampler2D SpecularMap;
+        sampler2D NormalMap;
+    };
+
+    uniform Material gMaterial;
+    uniform vec3 gEyeWorldPos;
+
+    vec3 MaterialDiffuse(vec2 texCoord) {
+        return gMaterial.HasDiffuseMap ? texture(gMaterial.DiffuseMap, texCoord).rgb : vec3(1.0);
+    }
+
+    // The columns are the tangent, the bitangent and the normal. The handedness is in tangent.w.
+    mat3 CalcTBN(vec3 normal, vec4 tangent) {
+        vec3 n = normalize(normal);
+        vec3 t = normalize(tangent.xyz - n * dot(n, tangent.xyz));
+        vec3 b = (tangent.w < 0.0 ? -1.0 : 1.0) * cross(n, t);
+        return mat3(t, b, n);
+    }
+
+    vec3 MaterialNormal(vec3 normal, vec4 tangent, vec2 texCoord) {
+        if (!gMaterial.HasNormalMap) {
+            return normalize(normal);
+        }
+
+        vec3 mapped = texture(gMaterial.NormalMap, texCoord).rgb * 2.0 - 1.0;
+        if (gMaterial.FlipGreen) {
+            mapped.y = -mapped.y;
+        }
+        return normalize(CalcTBN(normal, tangent) * mapped);
+    }
+
+    float CalcSpecularFactor(vec3 lightDirection, vec3 normal, vec3 worldPos, vec2 texCoord) {
+        normal = normalize(normal);
+        if (dot(normal, -lightDirection) <= 0.0) {
+            return 0.0;
+        }
+
+        vec3 toEye = normalize(gEyeWorldPos - worldPos);
+        float factor;
+        if (gMaterial.BlinnPhong) {
+            factor = max(dot(normal, normalize(toEye - lightDirection)), 0.0);
+        } else {
+            factor = max(dot(toEye, reflect(lightDirection, normal)), 0.0);
+        }
+
+        float intensity = gMaterial.SpecularIntensity;
+        if (gMaterial.HasSpecularMap) {
+            intensity *= texture(gMaterial.SpecularMap, texCoord).r;
+        }
+        return intensity * pow(factor, gMaterial.SpecularPower);
+    }
+"#;
+
+// The highlight of `gDirectionalLight`, added to the lit color of the surface. Needs both
+// `DIRECTIONAL_LIGHT_GLSL` and `MATERIAL_GLSL` before it.
+pub const DIRECTIONAL_SPECULAR_GLSL: &str = r#"
+    vec3 CalcDirectionalSpecular(vec3 normal, vec3 worldPos, vec2 texCoord) {
+        return gDirectionalLight.Color *
+            CalcSpecularFactor(gDirectionalLight.Direction, normal, worldPos, texCoord);
+    }
+"#;
+
+// A light infinitely far away, like the sun. Its ambient part lights every surface the same.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DirectionalLight {
+    pub color: Vector3<f32>,
+    pub ambient_intensity: f32,
+    pub diffuse_intensity: f32,
+    // Does not need to be normalized
+    pub direction: Vector3<f32>
+}
+
+impl Default for DirectionalLight {
+    // White, from above and behind the default camera
+    fn default() -> DirectionalLight {
+        DirectionalLight {
+            color: Vector3::new(1.0, 1.0, 1.0),
+            ambient_intensity: 0.1,
+            diffuse_intensity: 0.9,
+            direction: Vector3::new(0.0, -1.0, 1.0)
+        }
+    }
+}
+
+impl DirectionalLight {
+    pub fn new(color: Vector3<f32>, ambient_intensity: f32, diffuse_intensity: f32, direction: Vector3<f32>)
+            -> DirectionalLight {
+        DirectionalLight {
+            color: color,
+            ambient_intensity: ambient_intensity,
+            diffuse_intensity: diffuse_intensity,
+            direction: direction
+        }
+    }
+
+    pub fn ambient(&self) -> Vector3<f32> {
+        self.color * self.ambient_intensity
+    }
+
+    // Lambert's cosine law: the light is spread over a larger area when it comes at an angle
+    pub fn diffuse(&self, normal: Vector3<f32>) -> Vector3<f32> {
+        let factor = normal.normalize().dot(-self.direction.normalize()).max(0.0);
+        self.color * (self.diffuse_intensity * factor)
+    }
+
+    // What `CalcDirectionalLight` returns for the same normal
+    pub fn shade(&self, normal: Vector3<f32>) -> Vector3<f32> {
+        self.ambient() + self.diffuse(normal)
+    }
+
+    // The values of the GLSL struct, with their field names
+    pub fn uniform_values(&self) -> Vec<(&'static str, UniformValue<'static>)> {
+        vec![
+            ("Color", UniformValue::Vec3(self.color.into())),
+            ("AmbientIntensity", UniformValue::Float(self.ambient_intensity)),
+            ("DiffuseIntensity", UniformValue::Float(self.diffuse_intensity)),
+            ("Direction", UniformValue::Vec3(self.direction.normalize().into()))
+        ]
+    }
+}
+
+// The color of a surface of color `albedo` lit by `light`, computed like the shaders do
+pub fn shade_lambert(albedo: Vector3<f32>, normal: Vector3<f32>, light: &DirectionalLight) -> Vector3<f32> {
+    let light = light.shade(normal);
+    Vector3::new(albedo.x * light.x, albedo.y * light.y, albedo.z * light.z)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpecularModel {
+    // The angle between the reflected light and the eye, as in OGLdev
+    Phong,
+    // The angle between the normal and the half vector, which keeps wide highlights at grazing
+    // angles
+    BlinnPhong
+}
+
+// How a surface reflects light. The specular map, if any, scales the specular intensity by its
+// red channel. The normal map, if any, is read with `normal_convention`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Material {
+    pub specular_intensity: f32,
+    // The larger, the smaller and sharper the highlight
+    pub shininess: f32,
+    pub model: SpecularModel,
+    pub diffuse_map: Option<TextureHandle>,
+    pub specular_map: Option<TextureHandle>,
+    pub normal_map: Option<TextureHandle>,
+    pub normal_convention: NormalMapConvention
+}
+
+impl Default for Material {
+    fn default() -> Material {
+        Material {
+            specular_intensity: 1.0,
+            shininess: 32.0,
+            model: SpecularModel::Phong,
+            diffuse_map: None,
+            specular_map: None,
+            normal_map: None,
+            normal_convention: NormalMapConvention::OpenGl
+        }
+    }
+}
+
+impl Material {
+    pub fn new(specular_intensity: f32, shininess: f32) -> Material {
+        Material {
+            specular_intensity: specular_intensity,
+            shininess: shininess,
+            .. Material::default()
+        }
+    }
+
+    // Use the base color, specular and normal maps loaded by `TextureManager::load_material`
+    pub fn with_textures(mut self, textures: &MaterialTextures) -> Material {
+        self.diffuse_map = textures.base_color;
+        self.specular_map = textures.specular;
+        self.normal_map = textures.normal;
+        self
+    }
+
+    pub fn with_normal_map(mut self, normal_map: TextureHandle, convention: NormalMapConvention) -> Material {
+        self.normal_map = Some(normal_map);
+        self.normal_convention = convention;
+        self
+    }
+
+    // What `CalcSpecularFactor` returns without a specular map. `to_eye` goes from the surface to
+    // the eye.
+    pub fn specular_factor(&self, light_direction: Vector3<f32>, normal: Vector3<f32>, to_eye: Vector3<f32>)
+            -> f32 {
+        let (light_direction, normal, to_eye) = (light_direction.normalize(), normal.normalize(), to_eye.normalize());
+        if normal.dot(-light_direction) <= 0.0 {
+            return 0.0;
+        }
+
+        let factor = match self.model {
+            SpecularModel::Phong => {
+                let reflected = light_direction - normal * (2.0 * normal.dot(light_direction));
+                to_eye.dot(reflected)
+            },
+            SpecularModel::BlinnPhong => normal.dot((to_eye - light_direction).normalize())
+        };
+        self.specular_intensity * factor.max(0.0).powf(self.shininess)
+    }
+
+    // The values of the GLSL struct. The maps are sampled with the settings `textures` has for them.
+    pub fn uniform_values<'a>(&self, textures: &'a TextureManager) -> Vec<(&'static str, UniformValue<'a>)> {
+        let mut values = vec![
+            ("SpecularIntensity", UniformValue::Float(self.specular_intensity)),
+            ("SpecularPower", UniformValue::Float(self.shininess)),
+            ("BlinnPhong", UniformValue::Bool(self.model == SpecularModel::BlinnPhong)),
+            ("HasDiffuseMap", UniformValue::Bool(self.diffuse_map.is_some())),
+            ("HasSpecularMap", UniformValue::Bool(self.specular_map.is_some())),
+            ("HasNormalMap", UniformValue::Bool(self.normal_map.is_some())),
+            ("FlipGreen", UniformValue::Bool(self.normal_convention == NormalMapConvention::DirectX))
+        ];
+        if let Some(map) = self.diffuse_map {
+            values.push(("DiffuseMap", textures.sampled(map).uniform_value()));
+        }
+        if let Some(map) = self.specular_map {
+            values.push(("SpecularMap", textures.sampled(map).uniform_value()));
+        }
+        if let Some(map) = self.normal_map {
+            values.push(("NormalMap", textures.sampled(map).uniform_value()));
+        }
+        values
+    }
+}
+
+// The color of a surface of color `albedo` at `position`, lit by `light` and seen from `eye`,
+// computed like the shaders of the specular tutorial do. The highlight takes the color of the
+// light, not of the surface.
+pub fn shade_phong(albedo: Vector3<f32>, normal: Vector3<f32>, position: Vector3<f32>, eye: Vector3<f32>,
+        light: &DirectionalLight, material: &Material) -> Vector3<f32> {
+    let specular = material.specular_factor(light.direction, normal, eye - position);
+    shade_lambert(albedo, normal, light) + light.color * specular
+}
+
+// Other uniforms, usually a `uniform!` block, plus the values of lights and materials
+pub struct LightUniforms<'a, U> {
+    uniforms: U,
+    values: Vec<(String, UniformValue<'a>)>
+}
+
+impl<'a, U: Uniforms> LightUniforms<'a, U> {
+    pub fn new(uniforms: U) -> LightUniforms<'a, U> {
+        LightUniforms {
+            uniforms: uniforms,
+            values: Vec::new()
+        }
+    }
+
+    pub fn with_value(mut self, name: &str, value: UniformValue<'a>) -> LightUniforms<'a, U> {
+        self.values.push((name.to_string(), value));
+        self
+    }
+
+    // Add a struct uniform, e.g. `name` = "gDirectionalLight"
+    pub fn with_struct(mut self, name: &str, values: Vec<(&'static str, UniformValue<'a>)>) -> LightUniforms<'a, U> {
+        for (field, value) in values {
+            self.values.push((format!("{}.{}", name, field), value));
+        }
+        self
+    }
+
+    pub fn with_directional(self, name: &str, light: &DirectionalLight) -> LightUniforms<'a, U> {
+        self.with_struct(name, light.uniform_values())
+    }
+
+    pub fn with_material(self, name: &str, material: &Material, textures: &'a TextureManager) -> LightUniforms<'a, U> {
+        self.with_struct(name, material.uniform_values(textures))
+    }
+
+    // `gEyeWorldPos`, where the camera is
+    pub fn with_eye(self, camera: &Camera) -> LightUniforms<'a, U> {
+        self.with_value("gEyeWorldPos", UniformValue::Vec3(camera.get_pos().into()))
+    }
+}
+
+impl<'a, U: Uniforms> Uniforms for LightUniforms<'a, U> {
+    fn visit_values<'b, F: FnMut(&str, UniformValue<'b>)>(&'b self, mut visit: F) {
+        self.uniforms.visit_values(&mut visit);
+        for &(ref name, value) in &self.values {
+            visit(name, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Vector3};
+
+    use super::*;
+
+    fn close(a: Vector3<f32>, b: Vector3<f32>) -> bool {
+        (a - b).magnitude() < 1e-5
+    }
+
+    // Straight down, ambient 0.2 and diffuse 0.6
+    fn light() -> DirectionalLight {
+        DirectionalLight::new(Vector3::new(1.0, 1.0, 1.0), 0.2, 0.6, Vector3::new(0.0, -1.0, 0.0))
+    }
+
+    // Reflects more red than blue
+    fn albedo() -> Vector3<f32> {
+        Vector3::new(1.0, 0.5, 0.25)
+    }
+
+    #[test]
+    fn lambert_facing_the_light() {
+        let color = shade_lambert(albedo(), Vector3::new(0.0, 2.0, 0.0), &light());
+        assert!(close(color, albedo() * 0.8), "{:?}", color);
+    }
+
+    #[test]
+    fn lambert_at_an_angle() {
+        let color = shade_lambert(albedo(), Vector3::new(1.0, 1.0, 0.0), &light());
+        assert!(close(color, albedo() * (0.2 + 0.6 * 0.5f32.sqrt())), "{:?}", color);
+    }
+
+    #[test]
+    fn lambert_perpendicular_is_ambient() {
+        let color = shade_lambert(albedo(), Vector3::new(1.0, 0.0, 0.0), &light());
+        assert!(close(color, albedo() * 0.2), "{:?}", color);
+    }
+
+    #[test]
+    fn lambert_facing_away_is_ambient() {
+        // No negative diffuse darkening the ambient
+        for &normal in &[Vector3::new(0.0, -1.0, 0.0), Vector3::new(1.0, -0.1, 0.0)] {
+            let color = shade_lambert(albedo(), normal, &light());
+            assert!(close(color, albedo() * 0.2), "{:?}", color);
+            assert!(close(light().diffuse(normal), Vector3::new(0.0, 0.0, 0.0)));
+        }
+    }
+
+    fn material(model: SpecularModel) -> Material {
+        Material {
+            model: model,
+            .. Material::new(0.5, 8.0)
+        }
+    }
+
+    // At `degrees` from the normal +Y, toward +X
+    fn tilted(degrees: f32) -> Vector3<f32> {
+        let angle = degrees.to_radians();
+        Vector3::new(angle.sin(), angle.cos(), 0.0)
+    }
+
+    #[test]
+    fn specular_at_the_mirror_direction() {
+        let normal = Vector3::new(0.0, 1.0, 0.0);
+        for &angle in &[0.0, 30.0, 85.0] {
+            // Coming from -X, reflected toward +X
+            let light_direction = -tilted(-angle);
+            for &model in &[SpecularModel::Phong, SpecularModel::BlinnPhong] {
+                let factor = material(model).specular_factor(light_direction, normal, tilted(angle));
+                assert!((factor - 0.5).abs() < 1e-5, "{:?} at {}: {}", model, angle, factor);
+            }
+        }
+    }
+
+    #[test]
+    fn specular_at_a_grazing_angle() {
+        // The light comes at 85 degrees and the eye is 20 degrees off the reflection: Phong uses
+        // those 20 degrees, Blinn-Phong the 10 degrees between the normal and the half vector
+        let normal = Vector3::new(0.0, 1.0, 0.0);
+        let (light_direction, to_eye) = (-tilted(-85.0), tilted(65.0));
+        let phong = material(SpecularModel::Phong).specular_factor(light_direction, normal, to_eye);
+        let blinn = material(SpecularModel::BlinnPhong).specular_factor(light_direction, normal, to_eye);
+        assert!((phong - 0.5 * 20f32.to_radians().cos().powf(8.0)).abs() < 1e-5, "{}", phong);
+        assert!((blinn - 0.5 * 10f32.to_radians().cos().powf(8.0)).abs() < 1e-5, "{}", blinn);
+
+        // Looking straight down, Phong is almost gone but Blinn-Phong keeps a wide highlight
+        let up = Vector3::new(0.0, 1.0, 0.0);
+        let phong = material(SpecularModel::Phong).specular_factor(light_direction, normal, up);
+        let blinn = material(SpecularModel::BlinnPhong).specular_factor(light_direction, normal, up);
+        assert!(phong < 1e-8, "{}", phong);
+        assert!((blinn - 0.5 * 42.5f32.to_radians().cos().powf(8.0)).abs() < 1e-5, "{}", blinn);
+    }
+
+    #[test]
+    fn no_specular_from_behind() {
+        // Coming from below the surface, where the half vector would still face the normal
+        let normal = Vector3::new(0.0, 1.0, 0.0);
+        let light_direction = Vector3::new(0.0, 0.1, 1.0);
+        for &model in &[SpecularModel::Phong, SpecularModel::BlinnPhong] {
+            assert_eq!(material(model).specular_factor(light_direction, normal, normal), 0.0);
+        }
+    }
+
+    #[test]
+    fn phong_adds_the_light_color() {
+        let light = DirectionalLight::new(Vector3::new(1.0, 0.5, 0.5), 0.2, 0.6, -tilted(-45.0));
+        let material = material(SpecularModel::Phong);
+        let normal = Vector3::new(0.0, 1.0, 0.0);
+        let position = Vector3::new(1.0, 2.0, 3.0);
+
+        let lit = shade_phong(albedo(), normal, position, position + tilted(45.0) * 4.0, &light, &material);
+        let expected = shade_lambert(albedo(), normal, &light) + light.color * 0.5;
+        assert!(close(lit, expected), "{:?}", lit);
+
+        // Seen from the side of the light, 90 degrees off the reflection, only the diffuse part is left
+        let lit = shade_phong(albedo(), normal, position, position + tilted(-45.0), &light, &material);
+        assert!(close(lit, shade_lambert(albedo(), normal, &light)), "{:?}", lit);
+    }
+}
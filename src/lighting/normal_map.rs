@@ -0,0 +1,165 @@
+// Normal maps: normals stored in textures, in the tangent space of the surface. x goes along the
+// tangent (where U grows), y along the bitangent (where V grows) and z along the normal.
+//
+// The tangents come from `mesh::normals::compute_tangents()` or the generators of
+// `mesh::primitives`, with the handedness in w: the bitangent is w * normal.cross(tangent).
+
+use cgmath::{InnerSpace, Matrix3, Vector3};
+use image::{Rgba, RgbaImage};
+
+// Which way the green channel goes. OpenGL tools store y up the image, DirectX tools store it
+// down. The textures are flipped when they are loaded, so OpenGL maps are read as they are.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NormalMapConvention {
+    OpenGl,
+    DirectX
+}
+
+// From tangent space to the space of `normal` and `tangent`, as the columns (T, B, N)
+pub fn tbn(normal: Vector3<f32>, tangent: [f32; 4]) -> Matrix3<f32> {
+    let normal = normal.normalize();
+    let handedness = if tangent[3] < 0.0 { -1.0 } else { 1.0 };
+    let tangent = Vector3::new(tangent[0], tangent[1], tangent[2]);
+    // Gram-Schmidt, the interpolated tangents are not quite perpendicular to the normals anymore
+    let tangent = (tangent - normal * normal.dot(tangent)).normalize();
+    let bitangent = normal.cross(tangent) * handedness;
+    Matrix3::from_cols(tangent, bitangent, normal)
+}
+
+// A texel of a normal map as a unit vector in tangent space
+pub fn decode_normal(texel: [u8; 3], convention: NormalMapConvention) -> Vector3<f32> {
+    let decode = |value: u8| f32::from(value) / 255.0 * 2.0 - 1.0;
+    let y = match convention {
+        NormalMapConvention::OpenGl => decode(texel[1]),
+        NormalMapConvention::DirectX => -decode(texel[1])
+    };
+    Vector3::new(decode(texel[0]), y, decode(texel[2])).normalize()
+}
+
+// What `CalcBumpedNormal` returns
+pub fn bumped_normal(normal: Vector3<f32>, tangent: [f32; 4], texel: [u8; 3], convention: NormalMapConvention)
+        -> Vector3<f32> {
+    (tbn(normal, tangent) * decode_normal(texel, convention)).normalize()
+}
+
+// A normal map of a height field of `width` x `height` values between 0 and 1, from the bottom
+// row up like the loaded textures. `strength` scales the slopes. The field wraps around, so that
+// the map tiles. Panics when `heights` is too short.
+pub fn normal_map_from_heights(heights: &[f32], width: u32, height: u32, strength: f32,
+        convention: NormalMapConvention) -> RgbaImage {
+    let (w, h) = (width as usize, height as usize);
+    assert!(heights.len() >= w * h, "{} heights for a {}x{} normal map", heights.len(), width, height);
+    let at = |x: usize, y: usize| heights[(y % h) * w + x % w];
+    let encode = |value: f32| ((value * 0.5 + 0.5) * 255.0).round().clamp(0.0, 255.0) as u8;
+
+    // The rows of an image go down, the field goes up
+    RgbaImage::from_fn(width, height, |x, row| {
+        let (x, y) = (x as usize, h - 1 - row as usize);
+        let dx = (at(x + 1, y) - at(x + w - 1, y)) * 0.5 * strength;
+        let dy = (at(x, y + 1) - at(x, y + h - 1)) * 0.5 * strength;
+        let normal = Vector3::new(-dx, -dy, 1.0).normalize();
+        let green = match convention {
+            NormalMapConvention::OpenGl => normal.y,
+            NormalMapConvention::DirectX => -normal.y
+        };
+        Rgba { data: [encode(normal.x), encode(green), encode(normal.z), 255] }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Vector3};
+
+    use mesh::{primitives, Mesh};
+    use super::*;
+
+    fn vector(values: &[f32]) -> Vector3<f32> {
+        Vector3::new(values[0], values[1], values[2])
+    }
+
+    // Compare the tangent frame of every vertex with the directions where U and V grow on its
+    // triangles
+    fn check_tangents(mesh: &Mesh, name: &str) {
+        assert_eq!(mesh.tangents.len(), mesh.positions.len(), "{}", name);
+        for triangle in mesh.indices.chunks(3) {
+            let [a, b, c] = [triangle[0] as usize, triangle[1] as usize, triangle[2] as usize];
+            let (e1, e2) = (vector(&mesh.positions[b]) - vector(&mesh.positions[a]),
+                vector(&mesh.positions[c]) - vector(&mesh.positions[a]));
+            let (uv0, uv1, uv2) = (mesh.tex_coords[a], mesh.tex_coords[b], mesh.tex_coords[c]);
+            let (du1, dv1, du2, dv2) = (uv1[0] - uv0[0], uv1[1] - uv0[1], uv2[0] - uv0[0], uv2[1] - uv0[1]);
+            let det = du1 * dv2 - du2 * dv1;
+            let dp_du = (e1 * dv2 - e2 * dv1) / det;
+            let dp_dv = (e2 * du1 - e1 * du2) / det;
+            // The triangles which touch the poles of the sphere
+            if det.abs() < 1e-9 || dp_du.magnitude() < 1e-4 {
+                continue;
+            }
+
+            for &vertex in &[a, b, c] {
+                let normal = vector(&mesh.normals[vertex]);
+                let tangent = mesh.tangents[vertex];
+                let (direction, handedness) = (vector(&tangent), tangent[3]);
+                assert!(normal.dot(direction).abs() < 1e-4, "{}: tangent {:?} of normal {:?}", name, tangent, normal);
+                assert!(direction.normalize().dot(dp_du.normalize()) > 0.9, "{}: tangent {:?} instead of {:?}",
+                    name, tangent, dp_du);
+                let bitangent = normal.cross(direction) * handedness;
+                assert!(bitangent.normalize().dot(dp_dv.normalize()) > 0.9, "{}: bitangent {:?} instead of {:?}",
+                    name, bitangent, dp_dv);
+            }
+        }
+    }
+
+    #[test]
+    fn primitive_tangents_follow_the_tex_coords() {
+        check_tangents(&primitives::plane(4.0, 2.0, 3, 2), "plane");
+        check_tangents(&primitives::cube(2.0, 2), "cube");
+        check_tangents(&primitives::uv_sphere(1.5, 32, 16), "uv_sphere");
+    }
+
+    #[test]
+    fn tbn_of_a_flat_texel_is_the_normal() {
+        let normal = Vector3::new(0.0, 1.0, 0.0);
+        for &handedness in &[1.0, -1.0] {
+            // A tangent which is not quite perpendicular, as interpolated
+            let bumped = bumped_normal(normal, [1.0, 0.1, 0.0, handedness], [128, 128, 255],
+                NormalMapConvention::OpenGl);
+            assert!((bumped - normal).magnitude() < 1e-2, "{:?}", bumped);
+        }
+    }
+
+    #[test]
+    fn conventions_only_flip_green() {
+        for &texel in &[[128, 128, 255], [200, 40, 180], [10, 250, 128], [255, 0, 200]] {
+            let opengl = decode_normal(texel, NormalMapConvention::OpenGl);
+            let directx = decode_normal(texel, NormalMapConvention::DirectX);
+            assert!((opengl.magnitude() - 1.0).abs() < 1e-5);
+            assert_eq!((opengl.x, opengl.y, opengl.z), (directx.x, -directx.y, directx.z));
+        }
+    }
+
+    #[test]
+    fn height_ramps() {
+        // Rising to the right: the normals lean left
+        let heights: Vec<f32> = (0..16).map(|i| (i % 4) as f32 / 4.0).collect();
+        let opengl = normal_map_from_heights(&heights, 4, 4, 1.0, NormalMapConvention::OpenGl);
+        let directx = normal_map_from_heights(&heights, 4, 4, 1.0, NormalMapConvention::DirectX);
+        let (a, b) = (opengl.get_pixel(1, 1).data, directx.get_pixel(1, 1).data);
+        assert!(a[0] < 128 && a[1] == 128 && a[2] > 200, "{:?}", a);
+        assert_eq!(a, b);
+
+        // Rising up the field: the normals lean down, which DirectX stores as up
+        let heights: Vec<f32> = (0..16).map(|i| (i / 4) as f32 / 4.0).collect();
+        let opengl = normal_map_from_heights(&heights, 4, 4, 1.0, NormalMapConvention::OpenGl);
+        let directx = normal_map_from_heights(&heights, 4, 4, 1.0, NormalMapConvention::DirectX);
+        let (a, b) = (opengl.get_pixel(1, 1).data, directx.get_pixel(1, 1).data);
+        assert!(a[1] < 128 && b[1] > 128, "{:?} and {:?}", a, b);
+        assert_eq!((a[0], a[2]), (b[0], b[2]));
+        assert_eq!(u32::from(a[1]) + u32::from(b[1]), 255);
+    }
+
+    #[test]
+    #[should_panic(expected = "15 heights for a 4x4 normal map")]
+    fn short_height_fields_panic() {
+        normal_map_from_heights(&[0.5; 15], 4, 4, 1.0, NormalMapConvention::OpenGl);
+    }
+}
@@ -0,0 +1,474 @@
+// Point and spot lights, which light the objects around them and fade with the distance.
+//
+// The shaders get them in uniform arrays of a fixed size, `MAX_POINT_LIGHTS` and
+// `MAX_SPOT_LIGHTS`, which `local_lights_glsl()` writes into the GLSL. A scene may have more
+// lights than that, so each object only gets the lights which reach its bounding sphere, see
+// `cull_point_lights()` and `cull_spot_lights()`.
+
+use cgmath::{InnerSpace, Vector3};
+use glium::uniforms::{UniformValue, Uniforms};
+
+use lighting::LightUniforms;
+
+pub const MAX_POINT_LIGHTS: usize = 8;
+pub const MAX_SPOT_LIGHTS: usize = 4;
+
+// A light contributes nothing visible under this intensity, about one step of an 8 bits color.
+// It does not depend on the light: a brighter light reaches further.
+const CUTOFF_INTENSITY: f32 = 1.0 / 256.0;
+
+// The structs and arrays of the lights, with `CalcLocalLights` which adds up their lighting. The
+// specular part uses `MATERIAL_GLSL`, which must come before.
+pub fn local_lights_glsl() -> String {
+    format!(r#"
+    #define MAX_POINT_LIGHTS {}
+    #define MAX_SPOT_LIGHTS {}
+
+    struct Attenuation {{
+        float Constant;
+        float Linear;
+        float Exp;
+    }};
+
+    struct PointLight {{
+        vec3 Color;
+        float AmbientIntensity;
+        float DiffuseIntensity;
+        vec3 Position;
+        Attenuation Atten;
+    }};
+
+    struct SpotLight {{
+        PointLight Base;
+        vec3 Direction;
+        float Cutoff;
+        float InnerCutoff;
+    }};
+
+    uniform PointLight gPointLights[MAX_POINT_LIGHTS];
+    uniform int gNumPointLights;
+    uniform SpotLight gSpotLights[MAX_SPOT_LIGHTS];
+    uniform int gNumSpotLights;
+
+    vec3 CalcPointLight(PointLight light, vec3 albedo, vec3 normal, vec3 worldPos, vec2 texCoord) {{
+        vec3 direction = worldPos - light.Position;
+        float distance = length(direction);
+        direction = distance > 0.0 ? direction / distance : vec3(0.0, -1.0, 0.0);
+
+        float diffuseFactor = max(dot(normalize(normal), -direction), 0.0);
+        vec3 color = albedo * light.Color * (light.AmbientIntensity + light.DiffuseIntensity * diffuseFactor) +
+            light.Color * CalcSpecularFactor(direction, normal, worldPos, texCoord);
+
+        float attenuation = light.Atten.Constant + light.Atten.Linear * distance +
+            light.Atten.Exp * distance * distance;
+        return color / max(attenuation, 1e-4);
+    }}
+
+    vec3 CalcSpotLight(SpotLight light, vec3 albedo, vec3 normal, vec3 worldPos, vec2 texCoord) {{
+        // The cosines of the angles, so the light fades from the inner cone to the outer one
+        vec3 toPixel = normalize(worldPos - light.Base.Position);
+        float spotFactor = smoothstep(light.Cutoff, light.InnerCutoff, dot(toPixel, light.Direction));
+        if (spotFactor <= 0.0) {{
+            return vec3(0.0);
+        }}
+        return CalcPointLight(light.Base, albedo, normal, worldPos, texCoord) * spotFactor;
+    }}
+
+    vec3 CalcLocalLights(vec3 albedo, vec3 normal, vec3 worldPos, vec2 texCoord) {{
+        vec3 color = vec3(0.0);
+        for (int i = 0; i < gNumPointLights; i++) {{
+            color += CalcPointLight(gPointLights[i], albedo, normal, worldPos, texCoord);
+        }}
+        for (int i = 0; i < gNumSpotLights; i++) {{
+            color += CalcSpotLight(gSpotLights[i], albedo, normal, worldPos, texCoord);
+        }}
+        return color;
+    }}
+"#, MAX_POINT_LIGHTS, MAX_SPOT_LIGHTS)
+}
+
+// The light is divided by `constant + linear * d + exp * d * d` at a distance `d`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Attenuation {
+    pub constant: f32,
+    pub linear: f32,
+    pub exp: f32
+}
+
+impl Default for Attenuation {
+    fn default() -> Attenuation {
+        Attenuation {
+            constant: 1.0,
+            linear: 0.0,
+            exp: 0.1
+        }
+    }
+}
+
+impl Attenuation {
+    pub fn at(&self, distance: f32) -> f32 {
+        self.constant + self.linear * distance + self.exp * distance * distance
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PointLight {
+    pub color: Vector3<f32>,
+    pub ambient_intensity: f32,
+    pub diffuse_intensity: f32,
+    pub position: Vector3<f32>,
+    pub attenuation: Attenuation
+}
+
+impl Default for PointLight {
+    fn default() -> PointLight {
+        PointLight {
+            color: Vector3::new(1.0, 1.0, 1.0),
+            ambient_intensity: 0.0,
+            diffuse_intensity: 1.0,
+            position: Vector3::new(0.0, 0.0, 0.0),
+            attenuation: Attenuation::default()
+        }
+    }
+}
+
+impl PointLight {
+    pub fn new(color: Vector3<f32>, diffuse_intensity: f32, position: Vector3<f32>, attenuation: Attenuation)
+            -> PointLight {
+        PointLight {
+            color: color,
+            diffuse_intensity: diffuse_intensity,
+            position: position,
+            attenuation: attenuation,
+            .. PointLight::default()
+        }
+    }
+
+    // The brightest the light gets on a surface facing it, before the attenuation
+    fn peak_intensity(&self) -> f32 {
+        self.color.x.max(self.color.y).max(self.color.z) * (self.ambient_intensity + self.diffuse_intensity)
+    }
+
+    // The distance where the light falls under `CUTOFF_INTENSITY`, infinite when it does not
+    // fade with the distance
+    pub fn range(&self) -> f32 {
+        let Attenuation { constant, linear, exp } = self.attenuation;
+        // Solve exp * d * d + linear * d + constant = peak / CUTOFF_INTENSITY
+        let target = self.peak_intensity() / CUTOFF_INTENSITY;
+        if target <= constant {
+            0.0
+        } else if exp > 0.0 {
+            (-linear + (linear * linear + 4.0 * exp * (target - constant)).sqrt()) / (2.0 * exp)
+        } else if linear > 0.0 {
+            (target - constant) / linear
+        } else {
+            f32::INFINITY
+        }
+    }
+
+    // Whether the light reaches a bounding sphere
+    pub fn affects_sphere(&self, center: Vector3<f32>, radius: f32) -> bool {
+        (center - self.position).magnitude() <= self.range() + radius
+    }
+
+    // How bright the light is at a point, used to keep the strongest lights of an object
+    pub fn intensity_at(&self, position: Vector3<f32>) -> f32 {
+        self.peak_intensity() / self.attenuation.at((position - self.position).magnitude()).max(1e-4)
+    }
+
+    // What `CalcPointLight` returns for a white surface, without the specular part
+    pub fn shade(&self, normal: Vector3<f32>, position: Vector3<f32>) -> Vector3<f32> {
+        let offset = position - self.position;
+        let distance = offset.magnitude();
+        let direction = if distance > 0.0 { offset / distance } else { Vector3::new(0.0, -1.0, 0.0) };
+
+        let diffuse_factor = normal.normalize().dot(-direction).max(0.0);
+        let color = self.color * (self.ambient_intensity + self.diffuse_intensity * diffuse_factor);
+        color / self.attenuation.at(distance).max(1e-4)
+    }
+
+    pub fn uniform_values(&self) -> Vec<(&'static str, UniformValue<'static>)> {
+        vec![
+            ("Color", UniformValue::Vec3(self.color.into())),
+            ("AmbientIntensity", UniformValue::Float(self.ambient_intensity)),
+            ("DiffuseIntensity", UniformValue::Float(self.diffuse_intensity)),
+            ("Position", UniformValue::Vec3(self.position.into())),
+            ("Atten.Constant", UniformValue::Float(self.attenuation.constant)),
+            ("Atten.Linear", UniformValue::Float(self.attenuation.linear)),
+            ("Atten.Exp", UniformValue::Float(self.attenuation.exp))
+        ]
+    }
+}
+
+// A point light limited to a cone. The angles are in degrees, from the axis of the cone to its
+// edge: the light is full inside `inner_cutoff` and fades out until `cutoff`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpotLight {
+    pub base: PointLight,
+    // Does not need to be normalized
+    pub direction: Vector3<f32>,
+    pub cutoff: f32,
+    pub inner_cutoff: f32
+}
+
+impl Default for SpotLight {
+    fn default() -> SpotLight {
+        SpotLight {
+            base: PointLight::default(),
+            direction: Vector3::new(0.0, 0.0, 1.0),
+            cutoff: 20.0,
+            inner_cutoff: 15.0
+        }
+    }
+}
+
+impl SpotLight {
+    pub fn new(base: PointLight, direction: Vector3<f32>, cutoff: f32) -> SpotLight {
+        SpotLight {
+            base: base,
+            direction: direction,
+            cutoff: cutoff,
+            inner_cutoff: cutoff * 0.75
+        }
+    }
+
+    // The cosines given to the shaders. `smoothstep` needs the inner one to be strictly larger.
+    fn cosines(&self) -> (f32, f32) {
+        let cos_cutoff = self.cutoff.to_radians().cos();
+        let cos_inner = self.inner_cutoff.min(self.cutoff).to_radians().cos().max(cos_cutoff + 1e-4);
+        (cos_cutoff, cos_inner)
+    }
+
+    // The fraction of the light in the direction of `position`, like `smoothstep` in the shaders
+    pub fn spot_factor(&self, position: Vector3<f32>) -> f32 {
+        let offset = position - self.base.position;
+        if offset.magnitude2() == 0.0 {
+            return 0.0;
+        }
+
+        let (cos_cutoff, cos_inner) = self.cosines();
+        let t = ((offset.normalize().dot(self.direction.normalize()) - cos_cutoff) / (cos_inner - cos_cutoff))
+            .clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    }
+
+    // Whether the cone, up to the range of the light, touches a bounding sphere
+    pub fn affects_sphere(&self, center: Vector3<f32>, radius: f32) -> bool {
+        let offset = center - self.base.position;
+        let along = offset.dot(self.direction.normalize());
+        if along > self.base.range() + radius || along < -radius {
+            return false;
+        }
+
+        // The distance from the center to the surface of the cone
+        let angle = self.cutoff.to_radians();
+        let across = (offset.magnitude2() - along * along).max(0.0).sqrt();
+        angle.cos() * across - angle.sin() * along <= radius
+    }
+
+    // What `CalcSpotLight` returns for a white surface, without the specular part
+    pub fn shade(&self, normal: Vector3<f32>, position: Vector3<f32>) -> Vector3<f32> {
+        self.base.shade(normal, position) * self.spot_factor(position)
+    }
+
+    // The fields of the spot itself, those of the base go under "Base"
+    pub fn uniform_values(&self) -> Vec<(&'static str, UniformValue<'static>)> {
+        let (cos_cutoff, cos_inner) = self.cosines();
+        vec![
+            ("Direction", UniformValue::Vec3(self.direction.normalize().into())),
+            ("Cutoff", UniformValue::Float(cos_cutoff)),
+            ("InnerCutoff", UniformValue::Float(cos_inner))
+        ]
+    }
+}
+
+// The lights which reach a bounding sphere, at most `max` of them. When there are too many, the
+// brightest at the center of the sphere are kept.
+fn cull<T: Copy, F: Fn(&T) -> bool, I: Fn(&T) -> f32>(lights: &[T], max: usize, affects: F, intensity: I) -> Vec<T> {
+    let mut kept: Vec<T> = lights.iter().cloned().filter(|light| affects(light)).collect();
+    if kept.len() > max {
+        kept.sort_by(|a, b| intensity(b).partial_cmp(&intensity(a)).unwrap_or(::std::cmp::Ordering::Equal));
+        kept.truncate(max);
+    }
+    kept
+}
+
+// The point lights to give to an object with this bounding sphere, in world space
+pub fn cull_point_lights(lights: &[PointLight], center: Vector3<f32>, radius: f32) -> Vec<PointLight> {
+    cull(lights, MAX_POINT_LIGHTS, |light| light.affects_sphere(center, radius),
+        |light| light.intensity_at(center))
+}
+
+pub fn cull_spot_lights(lights: &[SpotLight], center: Vector3<f32>, radius: f32) -> Vec<SpotLight> {
+    cull(lights, MAX_SPOT_LIGHTS, |light| light.affects_sphere(center, radius),
+        |light| light.base.intensity_at(center) * light.spot_factor(center))
+}
+
+impl<'a, U: Uniforms> LightUniforms<'a, U> {
+    // Fill `gPointLights` and `gNumPointLights`. Only the first `MAX_POINT_LIGHTS` are used.
+    pub fn with_point_lights(mut self, lights: &[PointLight]) -> LightUniforms<'a, U> {
+        let count = lights.len().min(MAX_POINT_LIGHTS);
+        for (i, light) in lights[..count].iter().enumerate() {
+            self = self.with_struct(&format!("gPointLights[{}]", i), light.uniform_values());
+        }
+        self.with_value("gNumPointLights", UniformValue::SignedInt(count as i32))
+    }
+
+    // Fill `gSpotLights` and `gNumSpotLights`. Only the first `MAX_SPOT_LIGHTS` are used.
+    pub fn with_spot_lights(mut self, lights: &[SpotLight]) -> LightUniforms<'a, U> {
+        let count = lights.len().min(MAX_SPOT_LIGHTS);
+        for (i, light) in lights[..count].iter().enumerate() {
+            self = self.with_struct(&format!("gSpotLights[{}].Base", i), light.base.uniform_values())
+                .with_struct(&format!("gSpotLights[{}]", i), light.uniform_values());
+        }
+        self.with_value("gNumSpotLights", UniformValue::SignedInt(count as i32))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Vector3};
+
+    use super::*;
+
+    fn attenuations() -> Vec<Attenuation> {
+        vec![
+            Attenuation::default(),
+            Attenuation { constant: 1.0, linear: 0.5, exp: 0.0 },
+            Attenuation { constant: 0.5, linear: 0.2, exp: 0.05 }
+        ]
+    }
+
+    fn light_at(x: f32) -> PointLight {
+        PointLight::new(Vector3::new(1.0, 0.8, 0.5), 1.0, Vector3::new(x, 0.0, 0.0), Attenuation::default())
+    }
+
+    // A spot at the origin looking along +Z, full up to 15 degrees and dark past 20
+    fn spot() -> SpotLight {
+        SpotLight::default()
+    }
+
+    // The GLSL smoothstep
+    fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+        let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    }
+
+    // At `angle` degrees from the axis of `spot()`, `distance` away
+    fn around_the_spot(angle: f32, distance: f32) -> Vector3<f32> {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        Vector3::new(sin * distance, 0.0, cos * distance)
+    }
+
+    #[test]
+    fn range_is_where_the_light_fades_out() {
+        for attenuation in attenuations() {
+            for &diffuse_intensity in &[0.5, 1.0, 4.0] {
+                let mut light = light_at(0.0);
+                light.ambient_intensity = 0.2;
+                light.diffuse_intensity = diffuse_intensity;
+                light.attenuation = attenuation;
+                let range = light.range();
+                assert!(range.is_finite() && range > 0.0, "{:?}", attenuation);
+
+                let intensity = light.intensity_at(Vector3::new(0.0, range, 0.0));
+                assert!((intensity - CUTOFF_INTENSITY).abs() < CUTOFF_INTENSITY * 1e-3, "{} for {:?}", intensity,
+                    attenuation);
+                // Brighter lights reach further
+                let dimmer = PointLight { diffuse_intensity: diffuse_intensity / 2.0, ..light };
+                assert!(dimmer.range() < range);
+            }
+        }
+
+        let mut light = light_at(0.0);
+        light.attenuation = Attenuation { constant: 1.0, linear: 0.0, exp: 0.0 };
+        assert_eq!(light.range(), f32::INFINITY);
+        light.attenuation.constant = 1000.0;
+        assert_eq!(light.range(), 0.0);
+    }
+
+    #[test]
+    fn point_lights_reach_spheres_within_their_range() {
+        let light = light_at(0.0);
+        let range = light.range();
+        assert!(light.affects_sphere(Vector3::new(0.0, 0.0, range + 1.99), 2.0));
+        assert!(!light.affects_sphere(Vector3::new(0.0, 0.0, range + 2.01), 2.0));
+        assert!(light.affects_sphere(Vector3::new(0.0, 0.0, 0.0), 0.0));
+    }
+
+    #[test]
+    fn spot_lights_reach_spheres_in_their_cone() {
+        let spot = spot();
+        let range = spot.base.range();
+        let radius = 0.5;
+
+        // Along the axis, up to the range
+        assert!(spot.affects_sphere(Vector3::new(0.0, 0.0, range + radius - 0.01), radius));
+        assert!(!spot.affects_sphere(Vector3::new(0.0, 0.0, range + radius + 0.01), radius));
+
+        // Next to the cone, where the sphere touches its surface
+        let along = 5.0;
+        let (sin, cos) = spot.cutoff.to_radians().sin_cos();
+        let touching = (radius + sin * along) / cos;
+        assert!(spot.affects_sphere(Vector3::new(touching - 0.01, 0.0, along), radius));
+        assert!(!spot.affects_sphere(Vector3::new(touching + 0.01, 0.0, along), radius));
+        assert!(!spot.affects_sphere(Vector3::new(0.0, touching + 0.01, along), radius));
+
+        // Behind the light
+        assert!(spot.affects_sphere(Vector3::new(0.0, 0.0, -radius + 0.01), radius));
+        assert!(!spot.affects_sphere(Vector3::new(0.0, 0.0, -radius - 0.01), radius));
+    }
+
+    #[test]
+    fn spot_factor_matches_the_shaders() {
+        let spot = spot();
+        assert_eq!(spot.spot_factor(around_the_spot(0.0, 2.0)), 1.0);
+        assert_eq!(spot.spot_factor(around_the_spot(14.0, 2.0)), 1.0);
+        assert_eq!(spot.spot_factor(around_the_spot(21.0, 2.0)), 0.0);
+        assert_eq!(spot.spot_factor(around_the_spot(90.0, 2.0)), 0.0);
+        assert_eq!(spot.spot_factor(Vector3::new(0.0, 0.0, 0.0)), 0.0);
+
+        let (cos_cutoff, cos_inner) = spot.cosines();
+        for &angle in &[15.5, 17.5, 19.5] {
+            let position = around_the_spot(angle, 3.0);
+            let expected = smoothstep(cos_cutoff, cos_inner, position.normalize().dot(spot.direction));
+            let factor = spot.spot_factor(position);
+            assert!(factor > 0.0 && factor < 1.0 && (factor - expected).abs() < 1e-5, "{} at {}", factor, angle);
+        }
+        assert!(spot.spot_factor(around_the_spot(16.0, 1.0)) > spot.spot_factor(around_the_spot(19.0, 1.0)));
+    }
+
+    #[test]
+    fn culling_keeps_the_brightest_lights() {
+        // Every light reaches the origin, the nearest are the brightest
+        let lights: Vec<PointLight> = [7, 3, 11, 1, 9, 5, 12, 2, 10, 4, 8, 6].iter()
+            .map(|&x| light_at(x as f32))
+            .collect();
+        assert!(lights.iter().all(|light| light.affects_sphere(Vector3::new(0.0, 0.0, 0.0), 1.0)));
+
+        let kept = cull_point_lights(&lights, Vector3::new(0.0, 0.0, 0.0), 1.0);
+        let mut positions: Vec<f32> = kept.iter().map(|light| light.position.x).collect();
+        positions.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(positions, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0]);
+
+        // Fewer than the maximum are all kept, in their order, and those out of range are dropped
+        let far = light_at(1000.0);
+        assert_eq!(cull_point_lights(&[lights[0], far, lights[1]], Vector3::new(0.0, 0.0, 0.0), 1.0),
+            vec![lights[0], lights[1]]);
+
+        // Spots looking at the origin, and one looking away which is closer but does not count
+        let mut spots: Vec<SpotLight> = [6.0, 2.0, 5.0, 1.5, 4.0, 3.0].iter().map(|&z| SpotLight {
+            base: PointLight { position: Vector3::new(0.0, 0.0, -z), ..light_at(0.0) },
+            ..spot()
+        }).collect();
+        spots.push(SpotLight {
+            base: PointLight { position: Vector3::new(0.0, 0.0, -1.0), ..light_at(0.0) },
+            direction: Vector3::new(0.0, 0.0, -1.0),
+            ..spot()
+        });
+        let kept = cull_spot_lights(&spots, Vector3::new(0.0, 0.0, 0.0), 0.1);
+        let mut distances: Vec<f32> = kept.iter().map(|spot| -spot.base.position.z).collect();
+        distances.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(distances, vec![1.5, 2.0, 3.0, 4.0]);
+    }
+}
@@ -0,0 +1,296 @@
+use std::borrow::Cow;
+use std::mem;
+use std::slice;
+
+use glium::{DrawError, DrawParameters, Program, Surface, VertexBuffer};
+use glium::backend::Facade;
+use glium::index::{self, IndexBuffer, PrimitiveType};
+use glium::uniforms::Uniforms;
+use glium::vertex::{self, AttributeType, VertexBufferAny, VertexFormat};
+
+use mesh::{IndexFormat, Mesh, SubMesh};
+use mesh::{POSITION_ATTRIBUTE, NORMAL_ATTRIBUTE, TEX_COORDS_ATTRIBUTE, COLOR_ATTRIBUTE,
+    TANGENT_ATTRIBUTE};
+
+#[derive(Debug)]
+pub enum UploadError {
+    Vertex(vertex::BufferCreationError),
+    Index(index::BufferCreationError),
+    // What `Mesh::validate()` or `GpuMesh::new()` found wrong
+    InvalidMesh(String),
+    // An index too large for the requested index format
+    IndexTooLarge(u32),
+    // A vertex layout glium cannot describe
+    Layout(String)
+}
+
+impl From<vertex::BufferCreationError> for UploadError {
+    fn from(err: vertex::BufferCreationError) -> UploadError {
+        UploadError::Vertex(err)
+    }
+}
+
+impl From<index::BufferCreationError> for UploadError {
+    fn from(err: index::BufferCreationError) -> UploadError {
+        UploadError::Index(err)
+    }
+}
+
+pub enum GpuIndices {
+    U16(IndexBuffer<u16>),
+    U32(IndexBuffer<u32>)
+}
+
+// A mesh living in GPU buffers. The vertex buffer holds only the attributes the mesh has,
+// interleaved, under the names `position`, `normal`, `tex_coords`, `color` and `tangent`, so it
+// works with the shaders of the tutorials as long as they use the same names.
+pub struct GpuMesh {
+    vertices: VertexBufferAny,
+    indices: GpuIndices,
+    submeshes: Vec<SubMesh>
+}
+
+impl GpuMesh {
+    // Fails if a submesh goes past the end of the indices
+    pub fn new(vertices: VertexBufferAny, indices: GpuIndices, submeshes: Vec<SubMesh>)
+            -> Result<GpuMesh, UploadError> {
+        let index_count = match indices {
+            GpuIndices::U16(ref indices) => indices.len(),
+            GpuIndices::U32(ref indices) => indices.len()
+        };
+        if let Some(submesh) = submeshes.iter().find(|submesh| submesh.start + submesh.count > index_count) {
+            return Err(UploadError::InvalidMesh(format!("submesh '{}' out of {} indices", submesh.name,
+                index_count)));
+        }
+
+        Ok(GpuMesh {
+            vertices: vertices,
+            indices: indices,
+            submeshes: submeshes
+        })
+    }
+
+    pub fn vertices(&self) -> &VertexBufferAny {
+        &self.vertices
+    }
+
+    pub fn indices(&self) -> &GpuIndices {
+        &self.indices
+    }
+
+    pub fn submeshes(&self) -> &[SubMesh] {
+        &self.submeshes
+    }
+
+    // Draw every submesh with the same uniforms
+    pub fn draw<S, U>(&self, surface: &mut S, program: &Program, uniforms: &U,
+            params: &DrawParameters) -> Result<(), DrawError>
+            where S: Surface, U: Uniforms {
+        match self.indices {
+            GpuIndices::U16(ref indices) => surface.draw(&self.vertices, indices, program, uniforms, params),
+            GpuIndices::U32(ref indices) => surface.draw(&self.vertices, indices, program, uniforms, params)
+        }
+    }
+
+    // Draw one submesh, e.g. with the uniforms of its material. `index` must be below the length
+    // of `submeshes()`.
+    pub fn draw_submesh<S, U>(&self, index: usize, surface: &mut S, program: &Program,
+            uniforms: &U, params: &DrawParameters) -> Result<(), DrawError>
+            where S: Surface, U: Uniforms {
+        let submesh = &self.submeshes[index];
+        let range = submesh.start..(submesh.start + submesh.count);
+        const IN_RANGE: &str = "the submeshes are checked by GpuMesh::new";
+
+        match self.indices {
+            GpuIndices::U16(ref indices) => surface.draw(&self.vertices,
+                indices.slice(range).expect(IN_RANGE), program, uniforms, params),
+            GpuIndices::U32(ref indices) => surface.draw(&self.vertices,
+                indices.slice(range).expect(IN_RANGE), program, uniforms, params)
+        }
+    }
+}
+
+impl Mesh {
+    // Upload the mesh, choosing the smallest index type
+    pub fn upload<F: Facade>(&self, facade: &F) -> Result<GpuMesh, UploadError> {
+        let format = self.index_format();
+        self.upload_with_format(facade, format)
+    }
+
+    // Fails with `IndexTooLarge` if U16 is asked for more than 65536 vertices
+    pub fn upload_with_format<F: Facade>(&self, facade: &F, format: IndexFormat)
+            -> Result<GpuMesh, UploadError> {
+        let vertices = self.upload_vertices(facade)?;
+
+        let indices = match format {
+            IndexFormat::U16 => {
+                if let Some(&index) = self.indices.iter().find(|&&index| index > u32::from(u16::MAX)) {
+                    return Err(UploadError::IndexTooLarge(index));
+                }
+                let indices: Vec<u16> = self.indices.iter().map(|&index| index as u16).collect();
+                GpuIndices::U16(IndexBuffer::new(facade, PrimitiveType::TrianglesList, &indices)?)
+            },
+            IndexFormat::U32 => {
+                GpuIndices::U32(self.upload_indices(facade)?)
+            }
+        };
+
+        GpuMesh::new(vertices, indices, self.parts())
+    }
+
+    // Interleave the available attributes into one vertex buffer
+    pub fn upload_vertices<F: Facade>(&self, facade: &F) -> Result<VertexBufferAny, UploadError> {
+        self.validate().map_err(UploadError::InvalidMesh)?;
+        upload_interleaved(facade, &self.vertex_layout(), &self.interleave())
+    }
+
+    // The layout `interleave()` uses: the attributes the mesh has, in the order of the fields
+    pub fn vertex_layout(&self) -> VertexLayout {
+        let mut layout = VertexLayout {
+            attributes: Vec::new(),
+            stride: 0
+        };
+
+        layout.push(POSITION_ATTRIBUTE, 3);
+        if self.has_normals() {
+            layout.push(NORMAL_ATTRIBUTE, 3);
+        }
+        if self.has_tex_coords() {
+            layout.push(TEX_COORDS_ATTRIBUTE, 2);
+        }
+        if self.has_colors() {
+            layout.push(COLOR_ATTRIBUTE, 4);
+        }
+        if self.has_tangents() {
+            layout.push(TANGENT_ATTRIBUTE, 4);
+        }
+
+        layout
+    }
+
+    pub fn interleave(&self) -> Vec<f32> {
+        let mut data: Vec<f32> = Vec::with_capacity(self.vertex_layout().stride * self.vertex_count());
+        for i in 0..self.vertex_count() {
+            data.extend_from_slice(&self.positions[i]);
+            if self.has_normals() {
+                data.extend_from_slice(&self.normals[i]);
+            }
+            if self.has_tex_coords() {
+                data.extend_from_slice(&self.tex_coords[i]);
+            }
+            if self.has_colors() {
+                data.extend_from_slice(&self.colors[i]);
+            }
+            if self.has_tangents() {
+                data.extend_from_slice(&self.tangents[i]);
+            }
+        }
+        data
+    }
+
+    // The indices as the tutorials create them
+    pub fn upload_indices<F: Facade>(&self, facade: &F)
+            -> Result<IndexBuffer<u32>, index::BufferCreationError> {
+        IndexBuffer::new(facade, PrimitiveType::TrianglesList, &self.indices)
+    }
+}
+
+// Interleaved vertices made of floats
+#[derive(Clone, Debug, PartialEq)]
+pub struct VertexLayout {
+    // Name, offset and number of components of each attribute, in floats
+    pub attributes: Vec<(&'static str, usize, usize)>,
+    // Floats per vertex
+    pub stride: usize
+}
+
+impl VertexLayout {
+    pub fn push(&mut self, name: &'static str, components: usize) {
+        self.attributes.push((name, self.stride, components));
+        self.stride += components;
+    }
+
+    // Fails for attributes of more than 4 floats
+    pub fn format(&self) -> Result<VertexFormat, UploadError> {
+        let mut bindings = Vec::with_capacity(self.attributes.len());
+        for &(name, offset, components) in self.attributes.iter() {
+            let ty = match components {
+                1 => AttributeType::F32,
+                2 => AttributeType::F32F32,
+                3 => AttributeType::F32F32F32,
+                4 => AttributeType::F32F32F32F32,
+                _ => return Err(UploadError::Layout(format!("attribute '{}' has {} components", name,
+                    components)))
+            };
+            bindings.push((Cow::Borrowed(name), offset * mem::size_of::<f32>(), ty));
+        }
+        Ok(Cow::Owned(bindings))
+    }
+}
+
+// Upload vertices already interleaved as `layout` describes them, without copying them. The
+// stride can be at most 16 floats.
+pub fn upload_interleaved<F: Facade>(facade: &F, layout: &VertexLayout, data: &[f32])
+        -> Result<VertexBufferAny, UploadError> {
+    if layout.stride == 0 || !data.len().is_multiple_of(layout.stride) {
+        return Err(UploadError::Layout(format!("{} floats is not a multiple of the stride {}", data.len(),
+            layout.stride)));
+    }
+    let format = layout.format()?;
+
+    // glium takes the stride of a raw buffer from the size of its element type, so the vertices
+    // are given as arrays of `stride` floats
+    macro_rules! upload_by_stride {
+        ($($floats:expr),*) => {
+            match layout.stride {
+                $($floats => upload_as::<F, [f32; $floats]>(facade, data, format),)*
+                stride => Err(UploadError::Layout(format!("no vertex type of {} floats", stride)))
+            }
+        }
+    }
+    upload_by_stride!(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16)
+}
+
+fn upload_as<F: Facade, T: Copy + Send + 'static>(facade: &F, data: &[f32], format: VertexFormat)
+        -> Result<VertexBufferAny, UploadError> {
+    let buffer = unsafe {
+        // `T` is an array of floats, so it has the alignment of `data` and its size divides the
+        // length of `data`. The format describes the layout of each `T`.
+        let vertices = slice::from_raw_parts(data.as_ptr() as *const T,
+            mem::size_of_val(data) / mem::size_of::<T>());
+        VertexBuffer::new_raw(facade, vertices, format, mem::size_of::<T>())?
+    };
+    Ok(buffer.into_vertex_buffer_any())
+}
+
+#[cfg(test)]
+mod tests {
+    use mesh::{primitives, Mesh, UploadError, VertexLayout};
+
+    #[test]
+    fn layout_follows_the_attributes() {
+        let mut mesh = primitives::plane(1.0, 1.0, 1, 1);
+        mesh.tangents.clear();
+        let layout = mesh.vertex_layout();
+        assert_eq!(layout.attributes, vec![("position", 0, 3), ("normal", 3, 3), ("tex_coords", 6, 2)]);
+        assert_eq!(layout.stride, 8);
+        assert_eq!(mesh.interleave().len(), 8 * mesh.vertex_count());
+        assert_eq!(layout.format().unwrap().len(), 3);
+
+        assert_eq!(Mesh::new().vertex_layout().stride, 3);
+    }
+
+    #[test]
+    fn layout_rejects_wide_attributes() {
+        let mut layout = VertexLayout {
+            attributes: Vec::new(),
+            stride: 0
+        };
+        layout.push("position", 3);
+        layout.push("weights", 5);
+        match layout.format() {
+            Err(UploadError::Layout(_)) => (),
+            other => panic!("{:?}", other.map(|_| ()))
+        }
+    }
+}
@@ -0,0 +1,125 @@
+// Levels of detail: a chain of simplified versions of a mesh, and the choice of the level to
+// draw from the size of the object on the screen.
+
+use cgmath::{InnerSpace, Vector3};
+
+use camera::Camera;
+use graphical_math::PersProjInfo;
+use mesh::Mesh;
+use mesh::simplify;
+
+// The height of a sphere on the screen, as a fraction of the height of the viewport (1.0 fills
+// it). Objects around the camera are as big as it gets.
+pub fn screen_size(center: Vector3<f32>, radius: f32, eye: Vector3<f32>, proj: &PersProjInfo) -> f32 {
+    let distance = (center - eye).magnitude();
+    if distance <= radius {
+        return f32::INFINITY;
+    }
+
+    let tan_half_fov = (proj.fov / 2.0).to_radians().tan();
+    radius / (distance * tan_half_fov)
+}
+
+pub struct LodChain {
+    // From the full mesh to the coarsest one
+    pub levels: Vec<Mesh>,
+    // A level is good enough while the object is at most this big on the screen, see
+    // `screen_size()`. The first level is used above its threshold too.
+    pub thresholds: Vec<f32>,
+    // The bounding sphere of the full mesh, in model space
+    pub center: Vector3<f32>,
+    pub radius: f32
+}
+
+impl LodChain {
+    // Build the levels at the given fractions of the triangles of `mesh`, e.g. [0.5, 0.25, 0.1].
+    // Each level is simplified from the previous one, which is faster and keeps the levels
+    // consistent. A triangle budget scales with the area on the screen, hence the default
+    // thresholds: the square root of the ratios.
+    pub fn new(mesh: &Mesh, ratios: &[f32]) -> LodChain {
+        let mut levels = vec![mesh.clone()];
+        let mut thresholds = vec![1.0];
+
+        for &ratio in ratios {
+            let target = (mesh.triangle_count() as f32 * ratio.clamp(0.0, 1.0)).round() as usize;
+            let level = simplify::simplify(levels.last().unwrap(), target);
+            levels.push(level);
+            thresholds.push(ratio.clamp(0.0, 1.0).sqrt());
+        }
+
+        let (center, radius) = mesh.bounding_sphere();
+        LodChain {
+            levels: levels,
+            thresholds: thresholds,
+            center: center,
+            radius: radius
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.levels.is_empty()
+    }
+
+    // The coarsest level still good enough at this screen size
+    pub fn select_by_size(&self, size: f32) -> usize {
+        (0..self.levels.len()).rev()
+            .find(|&level| size <= self.thresholds[level])
+            .unwrap_or(0)
+    }
+
+    // The level to draw for an object placed at `position` with a uniform `scale` (its rotation
+    // is ignored), seen by `camera` through `proj`
+    pub fn select(&self, position: Vector3<f32>, scale: f32, camera: &Camera, proj: &PersProjInfo) -> usize {
+        let center = position + self.center * scale;
+        self.select_by_size(screen_size(center, self.radius * scale, camera.get_pos(), proj))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Vector3;
+
+    use graphical_math::PersProjInfo;
+    use mesh::primitives;
+    use super::*;
+
+    fn projection() -> PersProjInfo {
+        PersProjInfo { fov: 60.0, width: 800.0, height: 600.0, z_near: 0.1, z_far: 100.0 }
+    }
+
+    #[test]
+    fn screen_size_falls_off_with_distance() {
+        let eye = Vector3::new(0.0, 0.0, 0.0);
+        let proj = projection();
+        assert_eq!(screen_size(Vector3::new(0.0, 0.0, 0.5), 1.0, eye, &proj), f32::INFINITY);
+        assert_eq!(screen_size(Vector3::new(0.0, 0.0, 1.0), 1.0, eye, &proj), f32::INFINITY);
+
+        let near = screen_size(Vector3::new(0.0, 0.0, 5.0), 1.0, eye, &proj);
+        let far = screen_size(Vector3::new(0.0, 10.0, 0.0), 1.0, eye, &proj);
+        let tan_half_fov = 30.0f32.to_radians().tan();
+        assert!((near - 1.0 / (5.0 * tan_half_fov)).abs() < 1e-6, "{}", near);
+        assert!((near / far - 2.0).abs() < 1e-5, "{} {}", near, far);
+    }
+
+    #[test]
+    fn levels_are_selected_at_their_thresholds() {
+        let chain = LodChain::new(&primitives::icosphere(1.0, 2), &[0.5, 0.25]);
+        assert_eq!(chain.len(), 3);
+        assert_eq!(chain.thresholds, vec![1.0, 0.5f32.sqrt(), 0.5]);
+        assert!(chain.levels[1].triangle_count() < chain.levels[0].triangle_count());
+        assert!(chain.levels[2].triangle_count() < chain.levels[1].triangle_count());
+
+        for level in 0..chain.len() {
+            assert_eq!(chain.select_by_size(chain.thresholds[level]), level);
+        }
+        assert_eq!(chain.select_by_size(0.6), 1);
+        assert_eq!(chain.select_by_size(0.0), 2);
+        // Bigger than the screen, or around the camera
+        assert_eq!(chain.select_by_size(2.0), 0);
+        assert_eq!(chain.select_by_size(f32::INFINITY), 0);
+    }
+}
@@ -0,0 +1,240 @@
+// Meshes are stored as plain attribute arrays, ready to be copied into vertex buffers. The
+// loaders, the generators and the renderer all share this representation.
+//
+// Triangles are wound so that `(b - a).cross(c - a)` points outward. With the left-handed
+// projection of this crate, these triangles appear clockwise on the screen when they face the
+// camera, so `BackfaceCullingMode::CullCounterClockwise` removes the back faces (same as the
+// pyramids of the tutorials).
+
+use cgmath::{InnerSpace, Matrix3, Matrix4, SquareMatrix, Vector3};
+
+use graphical_math;
+
+pub use self::gpu::{GpuIndices, GpuMesh, UploadError, VertexLayout, upload_interleaved};
+
+pub mod primitives;
+pub mod normals;
+pub mod optimize;
+pub mod simplify;
+pub mod lod;
+pub mod obj;
+pub mod ply;
+pub mod stl;
+mod gpu;
+mod polygon;
+
+// Names of the vertex attributes in the shaders
+pub const POSITION_ATTRIBUTE: &str = "position";
+pub const NORMAL_ATTRIBUTE: &str = "normal";
+pub const TEX_COORDS_ATTRIBUTE: &str = "tex_coords";
+pub const COLOR_ATTRIBUTE: &str = "color";
+pub const TANGENT_ATTRIBUTE: &str = "tangent";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexFormat {
+    U16,
+    U32
+}
+
+// A range of triangles drawn with the same material
+#[derive(Clone, Debug, PartialEq)]
+pub struct SubMesh {
+    pub name: String,
+    // Offset and number of indices (not triangles) in `Mesh::indices`
+    pub start: usize,
+    pub count: usize,
+    // Index of the material in whatever list of materials comes with the mesh
+    pub material: Option<usize>
+}
+
+// Every attribute except the positions is optional: an empty `Vec` means the mesh does not have
+// it. Otherwise there must be one element per position.
+#[derive(Clone, Debug, Default)]
+pub struct Mesh {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub tex_coords: Vec<[f32; 2]>,
+    pub colors: Vec<[f32; 4]>,
+    // xyz is the tangent, w is the handedness of the bitangent (1.0 or -1.0):
+    // bitangent = w * normal.cross(tangent)
+    pub tangents: Vec<[f32; 4]>,
+
+    // A triangle list. The indices are always kept as u32 on the CPU side, use `index_format()`
+    // to know if they fit in u16.
+    pub indices: Vec<u32>,
+
+    // When empty, the whole mesh is one part
+    pub submeshes: Vec<SubMesh>
+}
+
+impl Mesh {
+    pub fn new() -> Mesh {
+        Mesh::default()
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    pub fn has_normals(&self) -> bool {
+        !self.normals.is_empty()
+    }
+
+    pub fn has_tex_coords(&self) -> bool {
+        !self.tex_coords.is_empty()
+    }
+
+    pub fn has_colors(&self) -> bool {
+        !self.colors.is_empty()
+    }
+
+    pub fn has_tangents(&self) -> bool {
+        !self.tangents.is_empty()
+    }
+
+    // The smallest index type able to address every vertex
+    pub fn index_format(&self) -> IndexFormat {
+        if self.positions.len() <= u16::MAX as usize + 1 {
+            IndexFormat::U16
+        } else {
+            IndexFormat::U32
+        }
+    }
+
+    // The parts of the mesh. A mesh without submeshes is one part covering every index.
+    pub fn parts(&self) -> Vec<SubMesh> {
+        if self.submeshes.is_empty() {
+            vec![SubMesh {
+                name: String::new(),
+                start: 0,
+                count: self.indices.len(),
+                material: None
+            }]
+        } else {
+            self.submeshes.clone()
+        }
+    }
+
+    // Check that the attributes have the same length and the indices are in range
+    pub fn validate(&self) -> Result<(), String> {
+        let count = self.positions.len();
+        let lengths = [
+            (NORMAL_ATTRIBUTE, self.normals.len()),
+            (TEX_COORDS_ATTRIBUTE, self.tex_coords.len()),
+            (COLOR_ATTRIBUTE, self.colors.len()),
+            (TANGENT_ATTRIBUTE, self.tangents.len())
+        ];
+        for &(name, length) in lengths.iter() {
+            if length != 0 && length != count {
+                return Err(format!("{} {} values for {} positions", length, name, count));
+            }
+        }
+
+        if !self.indices.len().is_multiple_of(3) {
+            return Err(format!("{} indices is not a triangle list", self.indices.len()));
+        }
+        if let Some(index) = self.indices.iter().find(|&&index| index as usize >= count) {
+            return Err(format!("index {} out of {} vertices", index, count));
+        }
+
+        for submesh in self.submeshes.iter() {
+            if submesh.start + submesh.count > self.indices.len() {
+                return Err(format!("submesh '{}' out of {} indices", submesh.name,
+                    self.indices.len()));
+            }
+        }
+
+        Ok(())
+    }
+
+    // A bounding sphere around the center of the bounding box. Not the smallest one, but close.
+    pub fn bounding_sphere(&self) -> (Vector3<f32>, f32) {
+        if self.positions.is_empty() {
+            return (Vector3::new(0.0, 0.0, 0.0), 0.0);
+        }
+
+        let mut min = Vector3::from(self.positions[0]);
+        let mut max = min;
+        for p in self.positions.iter() {
+            min = Vector3::new(min.x.min(p[0]), min.y.min(p[1]), min.z.min(p[2]));
+            max = Vector3::new(max.x.max(p[0]), max.y.max(p[1]), max.z.max(p[2]));
+        }
+
+        let center = (min + max) * 0.5;
+        let radius = self.positions.iter().map(|&p| (Vector3::from(p) - center).magnitude()).fold(0.0, f32::max);
+        (center, radius)
+    }
+
+    // Append another mesh as a new submesh. Attributes missing on one side are filled with
+    // default values so that the lengths still match.
+    pub fn append(&mut self, other: &Mesh, name: &str, material: Option<usize>) {
+        let base = self.positions.len();
+        let start = self.indices.len();
+
+        if self.submeshes.is_empty() && !self.indices.is_empty() {
+            self.submeshes = self.parts();
+        }
+
+        merge_attribute(&mut self.normals, &other.normals, base, other.positions.len(), [0.0, 0.0, 1.0]);
+        merge_attribute(&mut self.tex_coords, &other.tex_coords, base, other.positions.len(), [0.0, 0.0]);
+        merge_attribute(&mut self.colors, &other.colors, base, other.positions.len(), [1.0, 1.0, 1.0, 1.0]);
+        merge_attribute(&mut self.tangents, &other.tangents, base, other.positions.len(), [1.0, 0.0, 0.0, 1.0]);
+        self.positions.extend_from_slice(&other.positions);
+
+        self.indices.extend(other.indices.iter().map(|&index| index + base as u32));
+        self.submeshes.push(SubMesh {
+            name: name.to_string(),
+            start: start,
+            count: other.indices.len(),
+            material: material
+        });
+    }
+
+    // Move the mesh into another space: positions by `matrix`, normals by its inverse transpose
+    // and tangents by its upper 3x3. A mirroring matrix also flips the winding of the triangles.
+    pub fn transform(&mut self, matrix: Matrix4<f32>) {
+        let linear = Matrix3::from_cols(matrix.x.truncate(), matrix.y.truncate(), matrix.z.truncate());
+        let normal_matrix = graphical_math::normal_matrix(matrix);
+        let mirrored = linear.determinant() < 0.0;
+
+        for position in self.positions.iter_mut() {
+            *position = (matrix * Vector3::from(*position).extend(1.0)).truncate().into();
+        }
+        for normal in self.normals.iter_mut() {
+            let transformed = normal_matrix * Vector3::from(*normal);
+            if transformed.magnitude2() > 0.0 {
+                *normal = transformed.normalize().into();
+            }
+        }
+        for tangent in self.tangents.iter_mut() {
+            let transformed = linear * Vector3::new(tangent[0], tangent[1], tangent[2]);
+            let transformed = if transformed.magnitude2() > 0.0 { transformed.normalize() } else { transformed };
+            let handedness = if mirrored { -tangent[3] } else { tangent[3] };
+            *tangent = [transformed.x, transformed.y, transformed.z, handedness];
+        }
+
+        if mirrored {
+            for triangle in self.indices.chunks_mut(3) {
+                triangle.swap(1, 2);
+            }
+        }
+    }
+}
+
+fn merge_attribute<T: Copy>(values: &mut Vec<T>, others: &[T], count: usize, other_count: usize,
+        default: T) {
+    if values.is_empty() && others.is_empty() {
+        return;
+    }
+
+    values.resize(count, default);
+    if others.is_empty() {
+        values.resize(count + other_count, default);
+    } else {
+        values.extend_from_slice(others);
+    }
+}
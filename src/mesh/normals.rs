@@ -0,0 +1,441 @@
+// Normal and tangent generation for indexed triangle lists.
+//
+// The face normal of a triangle (a, b, c) is `(b - a).cross(c - a)`, the outward direction of
+// the winding convention of `Mesh`.
+
+use std::collections::HashMap;
+
+use cgmath::{InnerSpace, Vector2, Vector3};
+
+use mesh::Mesh;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NormalWeighting {
+    // Big triangles count more. Cheap, and good for most meshes.
+    Area,
+    // Each triangle counts as much as its angle at the vertex, which does not depend on how the
+    // faces around the vertex are tessellated.
+    Angle
+}
+
+fn position(positions: &[[f32; 3]], index: u32) -> Vector3<f32> {
+    Vector3::from(positions[index as usize])
+}
+
+// The normal of a triangle, with a length of twice its area
+fn face_normal(positions: &[[f32; 3]], triangle: &[u32]) -> Vector3<f32> {
+    let a = position(positions, triangle[0]);
+    (position(positions, triangle[1]) - a).cross(position(positions, triangle[2]) - a)
+}
+
+// The angle of a triangle at its corner `corner` (0, 1 or 2)
+fn corner_angle(positions: &[[f32; 3]], triangle: &[u32], corner: usize) -> f32 {
+    let p = position(positions, triangle[corner]);
+    let e1 = position(positions, triangle[(corner + 1) % 3]) - p;
+    let e2 = position(positions, triangle[(corner + 2) % 3]) - p;
+    if e1.magnitude2() == 0.0 || e2.magnitude2() == 0.0 {
+        return 0.0;
+    }
+    e1.normalize().dot(e2.normalize()).clamp(-1.0, 1.0).acos()
+}
+
+// The contribution of a triangle to the normal at one of its corners
+fn weighted_normal(positions: &[[f32; 3]], triangle: &[u32], corner: usize, weighting: NormalWeighting) -> Vector3<f32> {
+    let normal = face_normal(positions, triangle);
+    match weighting {
+        NormalWeighting::Area => normal,
+        NormalWeighting::Angle => {
+            if normal.magnitude2() == 0.0 {
+                normal
+            } else {
+                normal.normalize() * corner_angle(positions, triangle, corner)
+            }
+        }
+    }
+}
+
+fn normalize_or(v: Vector3<f32>, fallback: Vector3<f32>) -> Vector3<f32> {
+    if v.magnitude2() > 0.0 { v.normalize() } else { fallback }
+}
+
+// The unit normal of every triangle. Degenerate triangles get a zero vector.
+pub fn face_normals(positions: &[[f32; 3]], indices: &[u32]) -> Vec<[f32; 3]> {
+    indices.chunks(3)
+        .map(|triangle| normalize_or(face_normal(positions, triangle), Vector3::new(0.0, 0.0, 0.0)).into())
+        .collect()
+}
+
+// One normal per vertex, averaged over the triangles that use it. The vertices are not split,
+// so this works directly on the vertex and index arrays of the tutorials.
+pub fn smooth_normals(positions: &[[f32; 3]], indices: &[u32], weighting: NormalWeighting) -> Vec<[f32; 3]> {
+    let mut sums = vec![Vector3::new(0.0, 0.0, 0.0); positions.len()];
+    for triangle in indices.chunks(3) {
+        for corner in 0..3 {
+            sums[triangle[corner] as usize] += weighted_normal(positions, triangle, corner, weighting);
+        }
+    }
+    sums.into_iter().map(|sum| normalize_or(sum, Vector3::new(0.0, 1.0, 0.0)).into()).collect()
+}
+
+// Split the vertices used by corners with different keys. `keys[corner]` is the original vertex
+// of the corner and a variant: corners with the same key keep sharing a vertex, the others get a
+// copy of it with all its attributes.
+fn split_vertices(mesh: &mut Mesh, keys: &[(u32, u32)]) {
+    let mut remap: HashMap<(u32, u32), u32> = HashMap::new();
+    let mut used = vec![false; mesh.positions.len()];
+
+    for (corner, &key) in keys.iter().enumerate() {
+        let original = key.0;
+        let index = match remap.get(&key) {
+            Some(&index) => index,
+            None => {
+                // The first variant of a vertex keeps its place
+                let index = if !used[original as usize] {
+                    used[original as usize] = true;
+                    original
+                } else {
+                    duplicate_vertex(mesh, original as usize)
+                };
+                remap.insert(key, index);
+                index
+            }
+        };
+        mesh.indices[corner] = index;
+    }
+}
+
+fn duplicate_vertex(mesh: &mut Mesh, vertex: usize) -> u32 {
+    let index = mesh.positions.len() as u32;
+    mesh.positions.push(mesh.positions[vertex]);
+    if mesh.has_normals() {
+        mesh.normals.push(mesh.normals[vertex]);
+    }
+    if mesh.has_tex_coords() {
+        mesh.tex_coords.push(mesh.tex_coords[vertex]);
+    }
+    if mesh.has_colors() {
+        mesh.colors.push(mesh.colors[vertex]);
+    }
+    if mesh.has_tangents() {
+        mesh.tangents.push(mesh.tangents[vertex]);
+    }
+    index
+}
+
+// Give every triangle its own vertices, with the normal of the face
+pub fn compute_flat_normals(mesh: &mut Mesh) {
+    let normals = face_normals(&mesh.positions, &mesh.indices);
+    let keys: Vec<(u32, u32)> = (0..mesh.indices.len()).map(|corner| (mesh.indices[corner], corner as u32)).collect();
+
+    // Vertices used by no triangle keep their normal
+    if !mesh.has_normals() {
+        mesh.normals = vec![[0.0, 1.0, 0.0]; mesh.positions.len()];
+    }
+    split_vertices(mesh, &keys);
+
+    for (corner, &index) in mesh.indices.iter().enumerate() {
+        mesh.normals[index as usize] = normals[corner / 3];
+    }
+}
+
+// Smooth normals, shared by all the triangles around a vertex
+pub fn compute_smooth_normals(mesh: &mut Mesh, weighting: NormalWeighting) {
+    mesh.normals = smooth_normals(&mesh.positions, &mesh.indices, weighting);
+}
+
+// Smooth normals, except across the edges where the faces meet at more than `crease_angle`
+// degrees: the vertices there are split so that each side gets its own normal. Vertices at the
+// same position (e.g. on a texture seam) are smoothed together even if they are not shared.
+pub fn compute_normals_with_crease(mesh: &mut Mesh, crease_angle: f32, weighting: NormalWeighting) {
+    let cos_crease = crease_angle.to_radians().cos();
+    let normals = face_normals(&mesh.positions, &mesh.indices);
+
+    // The corners around each position
+    let mut around: HashMap<[u32; 3], Vec<usize>> = HashMap::new();
+    for (corner, &index) in mesh.indices.iter().enumerate() {
+        let p = mesh.positions[index as usize];
+        around.entry([p[0].to_bits(), p[1].to_bits(), p[2].to_bits()]).or_default().push(corner);
+    }
+
+    // Group the corners of a position whose faces are within the crease angle of each other,
+    // directly or through other faces
+    let mut group = vec![0u32; mesh.indices.len()];
+    let mut corner_normals = vec![Vector3::new(0.0, 0.0, 0.0); mesh.indices.len()];
+    for corners in around.values() {
+        let mut parents: Vec<usize> = (0..corners.len()).collect();
+        for i in 0..corners.len() {
+            for j in (i + 1)..corners.len() {
+                let a = Vector3::from(normals[corners[i] / 3]);
+                let b = Vector3::from(normals[corners[j] / 3]);
+                if a.dot(b) >= cos_crease {
+                    let root_i = find_root(&mut parents, i);
+                    let root_j = find_root(&mut parents, j);
+                    parents[root_i] = root_j;
+                }
+            }
+        }
+
+        let mut sums: HashMap<usize, Vector3<f32>> = HashMap::new();
+        for (i, &corner) in corners.iter().enumerate() {
+            let root = find_root(&mut parents, i);
+            let triangle = &mesh.indices[corner / 3 * 3..corner / 3 * 3 + 3];
+            *sums.entry(root).or_insert_with(|| Vector3::new(0.0, 0.0, 0.0)) +=
+                weighted_normal(&mesh.positions, triangle, corner % 3, weighting);
+            group[corner] = root as u32;
+        }
+        for (i, &corner) in corners.iter().enumerate() {
+            let fallback = normalize_or(Vector3::from(normals[corner / 3]), Vector3::new(0.0, 1.0, 0.0));
+            corner_normals[corner] = normalize_or(sums[&find_root(&mut parents, i)], fallback);
+        }
+    }
+
+    let keys: Vec<(u32, u32)> = mesh.indices.iter().zip(group.iter()).map(|(&index, &group)| (index, group)).collect();
+    // Vertices used by no triangle keep their normal
+    if !mesh.has_normals() {
+        mesh.normals = vec![[0.0, 1.0, 0.0]; mesh.positions.len()];
+    }
+    split_vertices(mesh, &keys);
+
+    for (corner, &index) in mesh.indices.iter().enumerate() {
+        mesh.normals[index as usize] = corner_normals[corner].into();
+    }
+}
+
+fn find_root(parents: &mut [usize], mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+    i
+}
+
+// The tangent and the bitangent of a triangle, along the directions where U and V grow. None
+// when the texture coordinates of the triangle are degenerate.
+fn triangle_tangent_frame(mesh: &Mesh, triangle: &[u32]) -> Option<(Vector3<f32>, Vector3<f32>)> {
+    let p0 = position(&mesh.positions, triangle[0]);
+    let edge1 = position(&mesh.positions, triangle[1]) - p0;
+    let edge2 = position(&mesh.positions, triangle[2]) - p0;
+    let t0 = Vector2::from(mesh.tex_coords[triangle[0] as usize]);
+    let delta1 = Vector2::from(mesh.tex_coords[triangle[1] as usize]) - t0;
+    let delta2 = Vector2::from(mesh.tex_coords[triangle[2] as usize]) - t0;
+
+    let determinant = delta1.x * delta2.y - delta2.x * delta1.y;
+    if determinant.abs() < 1e-12 {
+        return None;
+    }
+    let tangent = (edge1 * delta2.y - edge2 * delta1.y) / determinant;
+    let bitangent = (edge2 * delta1.x - edge1 * delta2.x) / determinant;
+    Some((tangent, bitangent))
+}
+
+// Per-vertex tangents from the texture coordinates: the tangent of each triangle is projected on
+// the plane of the vertex normal and weighted by the angle of the corner, and a vertex used by
+// triangles of opposite handedness (mirrored UVs) is split. The mesh needs normals and texture
+// coordinates, without them it is left as it is. The bitangent is w * normal.cross(tangent).
+//
+// This is not MikkTSpace. It follows the same ideas, but not its exact weighting, its handling of
+// degenerate triangles or its vertex splitting, so the tangents differ slightly from the ones
+// baking tools (Blender, Substance, xNormal...) use, and normal maps baked by these tools show
+// seams and shading errors with them. Keep the tangents of the file for such models, e.g. the
+// TANGENT attribute of glTF.
+pub fn compute_tangents(mesh: &mut Mesh) {
+    if !mesh.has_normals() || !mesh.has_tex_coords() {
+        return;
+    }
+
+    let frames: Vec<Option<(Vector3<f32>, Vector3<f32>)>> =
+        mesh.indices.chunks(3).map(|triangle| triangle_tangent_frame(mesh, triangle)).collect();
+
+    // Split the vertices by handedness first
+    let handedness: Vec<u32> = mesh.indices.chunks(3).zip(frames.iter()).flat_map(|(triangle, frame)| {
+        let normal = face_normal(&mesh.positions, triangle);
+        let flipped = match *frame {
+            Some((tangent, bitangent)) => normal.cross(tangent).dot(bitangent) < 0.0,
+            None => false
+        };
+        vec![flipped as u32; 3]
+    }).collect();
+    let keys: Vec<(u32, u32)> = mesh.indices.iter().zip(handedness.iter()).map(|(&index, &side)| (index, side)).collect();
+    mesh.tangents.clear();
+    split_vertices(mesh, &keys);
+
+    let mut tangents = vec![Vector3::new(0.0, 0.0, 0.0); mesh.positions.len()];
+    let mut bitangents = vec![Vector3::new(0.0, 0.0, 0.0); mesh.positions.len()];
+    for (t, triangle) in mesh.indices.chunks(3).enumerate() {
+        let (tangent, bitangent) = match frames[t] {
+            Some(frame) => frame,
+            None => continue
+        };
+
+        for corner in 0..3 {
+            let vertex = triangle[corner] as usize;
+            let normal = Vector3::from(mesh.normals[vertex]);
+            let angle = corner_angle(&mesh.positions, triangle, corner);
+            let project = |v: Vector3<f32>| normalize_or(v - normal * normal.dot(v), Vector3::new(0.0, 0.0, 0.0));
+            tangents[vertex] += project(tangent) * angle;
+            bitangents[vertex] += project(bitangent) * angle;
+        }
+    }
+
+    mesh.tangents = (0..mesh.positions.len()).map(|vertex| {
+        let normal = Vector3::from(mesh.normals[vertex]);
+        let tangent = tangents[vertex] - normal * normal.dot(tangents[vertex]);
+        let tangent = normalize_or(tangent, any_perpendicular(normal));
+        let w = if normal.cross(tangent).dot(bitangents[vertex]) < 0.0 { -1.0 } else { 1.0 };
+        [tangent.x, tangent.y, tangent.z, w]
+    }).collect();
+}
+
+// A unit vector perpendicular to `v`, for vertices without a usable tangent
+fn any_perpendicular(v: Vector3<f32>) -> Vector3<f32> {
+    let helper = if v.x.abs() < 0.9 { Vector3::new(1.0, 0.0, 0.0) } else { Vector3::new(0.0, 1.0, 0.0) };
+    normalize_or(helper.cross(v), Vector3::new(1.0, 0.0, 0.0))
+}
+
+// The bitangents of a mesh with tangents
+pub fn bitangents(mesh: &Mesh) -> Vec<[f32; 3]> {
+    mesh.normals.iter().zip(mesh.tangents.iter()).map(|(normal, tangent)| {
+        let bitangent = Vector3::from(*normal).cross(Vector3::new(tangent[0], tangent[1], tangent[2])) * tangent[3];
+        bitangent.into()
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Vector3};
+
+    use mesh::{primitives, Mesh};
+    use super::*;
+
+    // A cube with one vertex per corner, so that the normals decide how it is split
+    fn welded_cube() -> Mesh {
+        let mut mesh = Mesh::new();
+        for i in 0..8 {
+            mesh.positions.push([(i & 1) as f32 * 2.0 - 1.0, (i >> 1 & 1) as f32 * 2.0 - 1.0,
+                (i >> 2 & 1) as f32 * 2.0 - 1.0]);
+        }
+        mesh.indices = vec![
+            1, 3, 7, 1, 7, 5, 0, 4, 6, 0, 6, 2,
+            2, 6, 7, 2, 7, 3, 0, 1, 5, 0, 5, 4,
+            4, 5, 7, 4, 7, 6, 0, 2, 3, 0, 3, 1
+        ];
+        mesh
+    }
+
+    fn close(a: Vector3<f32>, b: Vector3<f32>) -> bool {
+        (a - b).magnitude() < 1e-5
+    }
+
+    // The normal of every corner is the axis its face looks along
+    fn check_flat(mesh: &Mesh) {
+        for triangle in mesh.indices.chunks(3) {
+            let a = Vector3::from(mesh.positions[triangle[0] as usize]);
+            let center = (a + Vector3::from(mesh.positions[triangle[1] as usize])
+                + Vector3::from(mesh.positions[triangle[2] as usize])) / 3.0;
+            let axis = if center.x.abs() > 0.9 {
+                Vector3::new(center.x.signum(), 0.0, 0.0)
+            } else if center.y.abs() > 0.9 {
+                Vector3::new(0.0, center.y.signum(), 0.0)
+            } else {
+                Vector3::new(0.0, 0.0, center.z.signum())
+            };
+            for &index in triangle {
+                assert!(close(Vector3::from(mesh.normals[index as usize]), axis));
+            }
+        }
+    }
+
+    #[test]
+    fn flat_normals_of_a_cube() {
+        let mut mesh = welded_cube();
+        compute_flat_normals(&mut mesh);
+        assert!(mesh.validate().is_ok());
+        assert_eq!(mesh.vertex_count(), 36);
+        check_flat(&mesh);
+    }
+
+    #[test]
+    fn smooth_normals_of_a_cube_point_to_the_corners() {
+        let mut mesh = welded_cube();
+        compute_smooth_normals(&mut mesh, NormalWeighting::Angle);
+        assert_eq!(mesh.vertex_count(), 8);
+        for (position, normal) in mesh.positions.iter().zip(mesh.normals.iter()) {
+            assert!(close(Vector3::from(*normal), Vector3::from(*position).normalize()));
+        }
+    }
+
+    #[test]
+    fn crease_splits_only_sharper_edges() {
+        // The faces of a cube meet at 90 degrees
+        let mut mesh = welded_cube();
+        compute_normals_with_crease(&mut mesh, 89.0, NormalWeighting::Angle);
+        assert!(mesh.validate().is_ok());
+        assert_eq!(mesh.vertex_count(), 24);
+        check_flat(&mesh);
+
+        let mut mesh = welded_cube();
+        compute_normals_with_crease(&mut mesh, 91.0, NormalWeighting::Angle);
+        assert_eq!(mesh.vertex_count(), 8);
+        for (position, normal) in mesh.positions.iter().zip(mesh.normals.iter()) {
+            assert!(close(Vector3::from(*normal), Vector3::from(*position).normalize()));
+        }
+    }
+
+    // Unit tangents perpendicular to the normals, going where U grows, with bitangents going
+    // where V grows
+    fn check_tangents(mesh: &Mesh) {
+        assert_eq!(mesh.tangents.len(), mesh.vertex_count());
+        let bitangents = bitangents(mesh);
+        for triangle in mesh.indices.chunks(3) {
+            let (tangent, bitangent) = match triangle_tangent_frame(mesh, triangle) {
+                Some(frame) => frame,
+                None => continue
+            };
+            for &index in triangle {
+                let i = index as usize;
+                let t = mesh.tangents[i];
+                let vertex_tangent = Vector3::new(t[0], t[1], t[2]);
+                let normal = Vector3::from(mesh.normals[i]);
+                assert!((vertex_tangent.magnitude() - 1.0).abs() < 1e-4);
+                assert!(vertex_tangent.dot(normal).abs() < 1e-4);
+                assert!(t[3] == 1.0 || t[3] == -1.0);
+                assert!(vertex_tangent.dot(tangent) > 0.0, "tangent {:?} against {:?}", vertex_tangent, tangent);
+                assert!(Vector3::from(bitangents[i]).dot(bitangent) > 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn tangents_of_a_uv_sphere() {
+        let mut mesh = primitives::uv_sphere(1.0, 24, 12);
+        mesh.tangents.clear();
+        compute_tangents(&mut mesh);
+        check_tangents(&mesh);
+    }
+
+    #[test]
+    fn tangents_of_a_quad() {
+        let mut mesh = primitives::plane(2.0, 2.0, 1, 1);
+        mesh.tangents.clear();
+        compute_tangents(&mut mesh);
+        check_tangents(&mesh);
+        // V goes toward -Z, which is normal.cross(tangent)
+        assert!(mesh.tangents.iter().all(|t| *t == [1.0, 0.0, 0.0, 1.0]));
+
+        // Mirrored along U, the tangent turns around and the handedness flips
+        for tex_coords in mesh.tex_coords.iter_mut() {
+            tex_coords[0] = 1.0 - tex_coords[0];
+        }
+        compute_tangents(&mut mesh);
+        check_tangents(&mesh);
+        assert!(mesh.tangents.iter().all(|t| *t == [-1.0, 0.0, 0.0, -1.0]));
+    }
+
+    #[test]
+    fn tangents_need_normals_and_tex_coords() {
+        let mut mesh = welded_cube();
+        compute_tangents(&mut mesh);
+        assert!(!mesh.has_tangents());
+        assert_eq!(mesh.vertex_count(), 8);
+    }
+}
@@ -0,0 +1,610 @@
+// A loader for Wavefront OBJ models and their MTL material libraries.
+//
+// The faces of an OBJ file are counterclockwise when seen from the outside, which matches the
+// winding convention of `Mesh`, so no flipping is needed.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::str::SplitWhitespace;
+
+use cgmath::{InnerSpace, Vector3};
+use glium::backend::Facade;
+use glium::texture::{RawImage2d, Texture2d};
+use image;
+
+use error::LoadError;
+use mesh::{Mesh, SubMesh};
+use mesh::polygon;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ObjMaterial {
+    pub name: String,
+    pub ambient: [f32; 3],
+    pub diffuse: [f32; 3],
+    pub specular: [f32; 3],
+    pub shininess: f32,
+    pub dissolve: f32,
+    // Texture paths, already resolved against the directory of the MTL file
+    pub diffuse_map: Option<PathBuf>,
+    pub specular_map: Option<PathBuf>,
+    pub normal_map: Option<PathBuf>
+}
+
+impl ObjMaterial {
+    pub fn new(name: &str) -> ObjMaterial {
+        ObjMaterial {
+            name: name.to_string(),
+            ambient: [0.0, 0.0, 0.0],
+            diffuse: [1.0, 1.0, 1.0],
+            specular: [0.0, 0.0, 0.0],
+            shininess: 0.0,
+            dissolve: 1.0,
+            diffuse_map: None,
+            specular_map: None,
+            normal_map: None
+        }
+    }
+
+    // Load `map_Kd` the same way tutorial 16 loads its texture
+    pub fn load_diffuse_texture<F: Facade>(&self, facade: &F) -> Result<Option<Texture2d>, LoadError> {
+        match self.diffuse_map {
+            Some(ref path) => {
+                let image = image::open(path)?.to_rgba();
+                let image_dim = image.dimensions();
+                let image = RawImage2d::from_raw_rgba_reversed(image.into_raw(), image_dim);
+                Ok(Some(Texture2d::new(facade, image)?))
+            },
+            None => Ok(None)
+        }
+    }
+}
+
+pub struct ObjModel {
+    // One submesh per run of faces sharing the same object, group and material. The material of
+    // a submesh is an index into `materials`.
+    pub mesh: Mesh,
+    pub materials: Vec<ObjMaterial>,
+    // What was skipped while loading, e.g. a material library which cannot be read
+    pub warnings: Vec<String>
+}
+
+impl ObjModel {
+    // The diffuse texture of every material, in the same order as `materials`
+    pub fn load_diffuse_textures<F: Facade>(&self, facade: &F) -> Result<Vec<Option<Texture2d>>, LoadError> {
+        self.materials.iter().map(|material| material.load_diffuse_texture(facade)).collect()
+    }
+}
+
+// Load an OBJ file. The material libraries are searched next to it.
+pub fn load_obj<P: AsRef<Path>>(path: P) -> Result<ObjModel, LoadError> {
+    let path = path.as_ref();
+    let file = File::open(path)?;
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    parse_obj(BufReader::new(file), base_dir)
+}
+
+pub fn load_mtl<P: AsRef<Path>>(path: P) -> Result<Vec<ObjMaterial>, LoadError> {
+    let path = path.as_ref();
+    let file = File::open(path)?;
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    parse_mtl(BufReader::new(file), base_dir)
+}
+
+// Read logical lines: comments removed, blank lines skipped and `\` continuations joined.
+// Each line comes with its 1-based line number.
+fn logical_lines<R: BufRead>(reader: R) -> Result<Vec<(usize, String)>, LoadError> {
+    let mut lines = Vec::new();
+    let mut pending = String::new();
+    let mut pending_start = 0;
+
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = match line.find('#') {
+            Some(position) => &line[..position],
+            None => &line[..]
+        };
+
+        if pending.is_empty() {
+            pending_start = number + 1;
+        }
+
+        let trimmed = line.trim_end();
+        if let Some(continued) = trimmed.strip_suffix('\\') {
+            pending.push_str(continued);
+            pending.push(' ');
+            continue;
+        }
+
+        pending.push_str(trimmed);
+        if !pending.trim().is_empty() {
+            lines.push((pending_start, pending.trim().to_string()));
+        }
+        pending.clear();
+    }
+
+    if !pending.trim().is_empty() {
+        lines.push((pending_start, pending.trim().to_string()));
+    }
+
+    Ok(lines)
+}
+
+fn parse_floats(tokens: SplitWhitespace, line: usize, min: usize, max: usize) -> Result<Vec<f32>, LoadError> {
+    let mut values = Vec::new();
+    for token in tokens {
+        let value = token.parse::<f32>().map_err(|_| {
+            LoadError::at_line(line, format!("'{}' is not a number", token))
+        })?;
+        values.push(value);
+    }
+
+    if values.len() < min || values.len() > max {
+        return Err(LoadError::at_line(line, format!("expected {} to {} numbers, found {}",
+            min, max, values.len())));
+    }
+    Ok(values)
+}
+
+// Turn a 1-based (or negative, relative) OBJ index into a 0-based one
+fn resolve_index(token: &str, count: usize, line: usize) -> Result<usize, LoadError> {
+    let index = token.parse::<i64>().map_err(|_| {
+        LoadError::at_line(line, format!("'{}' is not an index", token))
+    })?;
+
+    let resolved = if index > 0 {
+        index - 1
+    } else if index < 0 {
+        count as i64 + index
+    } else {
+        return Err(LoadError::at_line(line, "index 0 is not allowed"));
+    };
+
+    if resolved < 0 || resolved >= count as i64 {
+        return Err(LoadError::at_line(line, format!("index {} out of range ({} elements)", index, count)));
+    }
+    Ok(resolved as usize)
+}
+
+// Corners of faces are deduplicated by this key. Faces without normals get their normals
+// computed afterwards, smoothed among the faces of the same smoothing group, so the smoothing
+// group is a part of the key for them. Flat faces (smoothing off) never share their corners.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct CornerKey {
+    position: usize,
+    tex_coords: Option<usize>,
+    normal: Option<usize>,
+    smoothing: u32,
+    flat_face: Option<usize>
+}
+
+struct ObjBuilder {
+    positions: Vec<[f32; 3]>,
+    colors: Vec<[f32; 3]>,
+    tex_coords: Vec<[f32; 2]>,
+    normals: Vec<[f32; 3]>,
+
+    corners: HashMap<CornerKey, u32>,
+    corner_keys: Vec<CornerKey>,
+    indices: Vec<u32>,
+    submeshes: Vec<SubMesh>,
+    face_count: usize,
+
+    object: String,
+    group: String,
+    material: Option<usize>,
+    smoothing: u32
+}
+
+impl ObjBuilder {
+    // Start a new submesh when the object, the group or the material changes
+    fn begin_submesh(&mut self) {
+        let name = match (self.object.is_empty(), self.group.is_empty()) {
+            (false, false) => format!("{}/{}", self.object, self.group),
+            (false, true) => self.object.clone(),
+            _ => self.group.clone()
+        };
+
+        if let Some(last) = self.submeshes.last_mut() {
+            if last.count == 0 {
+                last.name = name;
+                last.material = self.material;
+                return;
+            }
+        }
+
+        self.submeshes.push(SubMesh {
+            name: name,
+            start: self.indices.len(),
+            count: 0,
+            material: self.material
+        });
+    }
+
+    fn add_face(&mut self, tokens: SplitWhitespace, line: usize) -> Result<(), LoadError> {
+        let mut corners = Vec::new();
+
+        for token in tokens {
+            let mut parts = token.split('/');
+            let position = resolve_index(parts.next().unwrap(), self.positions.len(), line)?;
+            let tex_coords = match parts.next() {
+                Some(part) if !part.is_empty() => Some(resolve_index(part, self.tex_coords.len(), line)?),
+                _ => None
+            };
+            let normal = match parts.next() {
+                Some(part) if !part.is_empty() => Some(resolve_index(part, self.normals.len(), line)?),
+                _ => None
+            };
+            if parts.next().is_some() {
+                return Err(LoadError::at_line(line, format!("malformed face corner '{}'", token)));
+            }
+
+            let generated_normal = normal.is_none();
+            corners.push(CornerKey {
+                position: position,
+                tex_coords: tex_coords,
+                normal: normal,
+                smoothing: if generated_normal { self.smoothing } else { 0 },
+                flat_face: if generated_normal && self.smoothing == 0 { Some(self.face_count) } else { None }
+            });
+        }
+
+        if corners.len() < 3 {
+            return Err(LoadError::at_line(line, format!("a face needs at least 3 corners, found {}",
+                corners.len())));
+        }
+
+        let points: Vec<Vector3<f32>> = corners.iter()
+            .map(|corner| Vector3::from(self.positions[corner.position]))
+            .collect();
+
+        for triangle in polygon::triangulate(&points) {
+            for &k in triangle.iter() {
+                let index = self.corner_index(corners[k]);
+                self.indices.push(index);
+            }
+        }
+
+        let length = self.indices.len();
+        let submesh = self.submeshes.last_mut().unwrap();
+        submesh.count = length - submesh.start;
+        self.face_count += 1;
+        Ok(())
+    }
+
+    fn corner_index(&mut self, key: CornerKey) -> u32 {
+        if let Some(&index) = self.corners.get(&key) {
+            return index;
+        }
+
+        let index = self.corner_keys.len() as u32;
+        self.corner_keys.push(key);
+        self.corners.insert(key, index);
+        index
+    }
+
+    fn build(self) -> Mesh {
+        let mut mesh = Mesh::new();
+
+        let has_tex_coords = self.corner_keys.iter().any(|key| key.tex_coords.is_some());
+        let has_colors = !self.colors.is_empty();
+
+        for key in self.corner_keys.iter() {
+            mesh.positions.push(self.positions[key.position]);
+            if has_tex_coords {
+                mesh.tex_coords.push(key.tex_coords.map_or([0.0, 0.0], |i| self.tex_coords[i]));
+            }
+            if has_colors {
+                let color = self.colors[key.position];
+                mesh.colors.push([color[0], color[1], color[2], 1.0]);
+            }
+        }
+
+        // Normals given by the file, or accumulated from the faces
+        let mut normals = vec![Vector3::new(0.0, 0.0, 0.0); self.corner_keys.len()];
+        for triangle in self.indices.chunks(3) {
+            let a = Vector3::from(mesh.positions[triangle[0] as usize]);
+            let b = Vector3::from(mesh.positions[triangle[1] as usize]);
+            let c = Vector3::from(mesh.positions[triangle[2] as usize]);
+            // Not normalized, so larger faces weigh more
+            let face_normal = (b - a).cross(c - a);
+
+            for &index in triangle.iter() {
+                normals[index as usize] += face_normal;
+            }
+        }
+
+        for (key, normal) in self.corner_keys.iter().zip(normals) {
+            let normal = match key.normal {
+                Some(i) => Vector3::from(self.normals[i]),
+                None => normal
+            };
+            let normal = if normal.magnitude2() > 0.0 { normal.normalize() } else { Vector3::new(0.0, 1.0, 0.0) };
+            mesh.normals.push(normal.into());
+        }
+
+        mesh.indices = self.indices;
+        mesh.submeshes = self.submeshes.into_iter().filter(|submesh| submesh.count > 0).collect();
+        mesh
+    }
+}
+
+// Parse an OBJ model. `base_dir` is where the material libraries are searched.
+pub fn parse_obj<R: BufRead>(reader: R, base_dir: &Path) -> Result<ObjModel, LoadError> {
+    let mut builder = ObjBuilder {
+        positions: Vec::new(),
+        colors: Vec::new(),
+        tex_coords: Vec::new(),
+        normals: Vec::new(),
+        corners: HashMap::new(),
+        corner_keys: Vec::new(),
+        indices: Vec::new(),
+        submeshes: Vec::new(),
+        face_count: 0,
+        object: String::new(),
+        group: String::new(),
+        material: None,
+        smoothing: 0
+    };
+    let mut materials: Vec<ObjMaterial> = Vec::new();
+    let mut warnings = Vec::new();
+    builder.begin_submesh();
+
+    for (line, text) in logical_lines(reader)? {
+        let mut tokens = text.split_whitespace();
+        let keyword = tokens.next().unwrap();
+
+        match keyword {
+            "v" => {
+                let values = parse_floats(tokens, line, 3, 7)?;
+                builder.positions.push([values[0], values[1], values[2]]);
+
+                // Some exporters append a vertex color: v x y z r g b
+                if values.len() >= 6 {
+                    let count = builder.positions.len() - 1;
+                    builder.colors.resize(count, [1.0, 1.0, 1.0]);
+                    builder.colors.push([values[values.len() - 3], values[values.len() - 2],
+                        values[values.len() - 1]]);
+                } else if !builder.colors.is_empty() {
+                    builder.colors.push([1.0, 1.0, 1.0]);
+                }
+            },
+            "vt" => {
+                let values = parse_floats(tokens, line, 1, 3)?;
+                builder.tex_coords.push([values[0], if values.len() > 1 { values[1] } else { 0.0 }]);
+            },
+            "vn" => {
+                let values = parse_floats(tokens, line, 3, 3)?;
+                builder.normals.push([values[0], values[1], values[2]]);
+            },
+            "f" => {
+                builder.add_face(tokens, line)?;
+            },
+            "o" => {
+                builder.object = tokens.collect::<Vec<_>>().join(" ");
+                builder.begin_submesh();
+            },
+            "g" => {
+                builder.group = tokens.collect::<Vec<_>>().join(" ");
+                builder.begin_submesh();
+            },
+            "s" => {
+                builder.smoothing = match tokens.next() {
+                    Some("off") | None => 0,
+                    Some(value) => value.parse::<u32>().map_err(|_| {
+                        LoadError::at_line(line, format!("bad smoothing group '{}'", value))
+                    })?
+                };
+            },
+            "usemtl" => {
+                let name = tokens.collect::<Vec<_>>().join(" ");
+                builder.material = match materials.iter().position(|material| material.name == name) {
+                    Some(index) => Some(index),
+                    None => {
+                        // Keep going with a default material, like most viewers do
+                        materials.push(ObjMaterial::new(&name));
+                        Some(materials.len() - 1)
+                    }
+                };
+                builder.begin_submesh();
+            },
+            "mtllib" => {
+                for file_name in tokens {
+                    // Without its library, the model is still drawn with the default materials
+                    // `usemtl` creates
+                    let library = match load_mtl(base_dir.join(file_name)) {
+                        Err(LoadError::Io(err)) => {
+                            warnings.push(format!("line {}: cannot read the material library '{}': {}", line,
+                                file_name, err));
+                            continue;
+                        },
+                        library => library?
+                    };
+                    for material in library {
+                        // A material used before its library was read only has a placeholder
+                        match materials.iter().position(|m| m.name == material.name) {
+                            Some(index) => materials[index] = material,
+                            None => materials.push(material)
+                        }
+                    }
+                }
+            },
+            // Lines, points, free-form geometry and rendering attributes are not supported
+            _ => ()
+        }
+    }
+
+    let mesh = builder.build();
+    Ok(ObjModel {
+        mesh: mesh,
+        materials: materials,
+        warnings: warnings
+    })
+}
+
+// Parse an MTL material library. `base_dir` is used to resolve the texture paths.
+pub fn parse_mtl<R: BufRead>(reader: R, base_dir: &Path) -> Result<Vec<ObjMaterial>, LoadError> {
+    let mut materials: Vec<ObjMaterial> = Vec::new();
+
+    for (line, text) in logical_lines(reader)? {
+        let mut tokens = text.split_whitespace();
+        let keyword = tokens.next().unwrap();
+
+        if keyword == "newmtl" {
+            let name = tokens.collect::<Vec<_>>().join(" ");
+            if name.is_empty() {
+                return Err(LoadError::at_line(line, "a material needs a name"));
+            }
+            materials.push(ObjMaterial::new(&name));
+            continue;
+        }
+
+        let material = match materials.last_mut() {
+            Some(material) => material,
+            None => return Err(LoadError::at_line(line, format!("'{}' before any newmtl", keyword)))
+        };
+
+        match keyword {
+            "Ka" => material.ambient = parse_color(tokens, line)?,
+            "Kd" => material.diffuse = parse_color(tokens, line)?,
+            "Ks" => material.specular = parse_color(tokens, line)?,
+            "Ns" => material.shininess = parse_floats(tokens, line, 1, 1)?[0],
+            "d" => material.dissolve = parse_floats(tokens, line, 1, 1)?[0],
+            "Tr" => material.dissolve = 1.0 - parse_floats(tokens, line, 1, 1)?[0],
+            "map_Kd" => material.diffuse_map = Some(parse_map(tokens, base_dir, line)?),
+            "map_Ks" => material.specular_map = Some(parse_map(tokens, base_dir, line)?),
+            "map_Bump" | "map_bump" | "bump" | "norm" => {
+                material.normal_map = Some(parse_map(tokens, base_dir, line)?);
+            },
+            // Other properties (illum, Ke, Ni, Tf, other maps...) are ignored
+            _ => ()
+        }
+    }
+
+    Ok(materials)
+}
+
+fn parse_color(tokens: SplitWhitespace, line: usize) -> Result<[f32; 3], LoadError> {
+    let values = parse_floats(tokens, line, 1, 3)?;
+    if values.len() == 1 {
+        Ok([values[0], values[0], values[0]])
+    } else if values.len() == 3 {
+        Ok([values[0], values[1], values[2]])
+    } else {
+        Err(LoadError::at_line(line, "a color needs 1 or 3 components"))
+    }
+}
+
+// The file name is the last token, the options before it (e.g. `-s 1 1 1`) are ignored
+fn parse_map(tokens: SplitWhitespace, base_dir: &Path, line: usize) -> Result<PathBuf, LoadError> {
+    match tokens.last() {
+        Some(file_name) => Ok(base_dir.join(file_name.replace('\\', "/"))),
+        None => Err(LoadError::at_line(line, "a texture map needs a file name"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use error::LoadError;
+    use super::{load_obj, parse_obj, ObjModel};
+
+    const SQUARE: &str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0 0\nvn 0 0 1\n";
+
+    fn parse(text: &str) -> Result<ObjModel, LoadError> {
+        parse_obj(text.as_bytes(), Path::new(""))
+    }
+
+    // The message of a parse error, which must point at `line`
+    fn parse_error(text: &str, line: usize) -> String {
+        match parse(text) {
+            Err(LoadError::Parse(message)) => {
+                assert!(message.starts_with(&format!("line {}:", line)), "{}", message);
+                message
+            },
+            Err(err) => panic!("not a parse error: {}", err),
+            Ok(_) => panic!("{:?} was accepted", text)
+        }
+    }
+
+    #[test]
+    fn faces_are_triangulated() {
+        let model = parse(&format!("{}f 1 2 3 4\nf -4/1/1 -3/1/1 -2/1/1\n", SQUARE)).unwrap();
+        assert_eq!(model.mesh.triangle_count(), 3);
+        assert!(model.mesh.validate().is_ok());
+        assert!(model.materials.is_empty());
+    }
+
+    #[test]
+    fn index_zero_is_rejected() {
+        parse_error(&format!("{}f 0 1 2\n", SQUARE), 7);
+    }
+
+    #[test]
+    fn out_of_range_indices_are_rejected() {
+        parse_error(&format!("{}f 1 2 5\n", SQUARE), 7);
+        parse_error(&format!("{}f 1/2 2/1 3/1\n", SQUARE), 7);
+        parse_error(&format!("{}f 1//2 2//1 3//1\n", SQUARE), 7);
+        // Relative indices count back from the last vertex
+        parse_error(&format!("{}f -1 -2 -5\n", SQUARE), 7);
+    }
+
+    #[test]
+    fn faces_need_three_corners() {
+        parse_error(&format!("{}f 1 2\n", SQUARE), 7);
+        parse_error(&format!("{}f\n", SQUARE), 7);
+    }
+
+    #[test]
+    fn bad_numbers_are_rejected() {
+        parse_error("v 0 0 zero\n", 1);
+        parse_error("v 0 0\n", 1);
+        parse_error("vn 0 0 1 1\n", 1);
+        parse_error(&format!("{}f 1 2 x\n", SQUARE), 7);
+        parse_error(&format!("{}f 1 2 3.5\n", SQUARE), 7);
+    }
+
+    #[test]
+    fn bad_corners_are_rejected() {
+        let message = parse_error(&format!("{}f 1/1/1/1 2/1/1 3/1/1\n", SQUARE), 7);
+        assert!(message.contains("1/1/1/1"), "{}", message);
+        parse_error(&format!("{}f 1/2/3/4 2 3\n", SQUARE), 7);
+        parse_error(&format!("{}f /1 2 3\n", SQUARE), 7);
+    }
+
+    #[test]
+    fn missing_material_library_falls_back_to_default_materials() {
+        let model = parse(&format!("mtllib missing.mtl\n{}usemtl red\nf 1 2 3\n", SQUARE)).unwrap();
+        assert_eq!(model.mesh.triangle_count(), 1);
+        assert_eq!(model.materials.len(), 1);
+        assert_eq!(model.materials[0].name, "red");
+        assert_eq!(model.materials[0].diffuse_map, None);
+        assert_eq!(model.warnings.len(), 1);
+        assert!(model.warnings[0].starts_with("line 1: cannot read the material library 'missing.mtl'"),
+            "{}", model.warnings[0]);
+    }
+
+    #[test]
+    fn bundled_cube_loads() {
+        let model = load_obj("content/cube.obj").unwrap();
+        assert!(model.warnings.is_empty(), "{:?}", model.warnings);
+        let mesh = &model.mesh;
+        assert!(mesh.validate().is_ok());
+        // 6 quads with their own corners
+        assert_eq!(mesh.triangle_count(), 12);
+        assert_eq!(mesh.vertex_count(), 24);
+        assert!(mesh.has_normals() && mesh.has_tex_coords());
+
+        let names: Vec<&str> = mesh.submeshes.iter().map(|submesh| &submesh.name[..]).collect();
+        assert_eq!(names, vec!["cube/sides", "cube/caps"]);
+        assert!(mesh.submeshes.iter().all(|submesh| submesh.material == Some(0)));
+
+        assert_eq!(model.materials.len(), 1);
+        let material = &model.materials[0];
+        assert_eq!(material.name, "textured");
+        assert_eq!(material.shininess, 32.0);
+        assert_eq!(material.diffuse_map, Some(Path::new("content").join("test.png")));
+    }
+}
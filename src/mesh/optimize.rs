@@ -0,0 +1,615 @@
+// Mesh optimizations for the GPU: welding duplicate vertices, removing degenerate triangles and
+// reordering triangles and vertices for the post-transform vertex cache, the pre-transform
+// vertex fetch and overdraw.
+//
+// The index functions work on the plain `u32` triangle lists given to `IndexBuffer::new`, the
+// mesh functions apply them to every submesh of a `Mesh`.
+
+use std::collections::HashMap;
+
+use cgmath::{InnerSpace, Vector3};
+
+use mesh::Mesh;
+
+// Size of the simulated FIFO cache of `acmr`. Real hardware is somewhere around there.
+pub const DEFAULT_CACHE_SIZE: usize = 16;
+
+// Size of the LRU cache modelled by the vertex cache optimization
+const FORSYTH_CACHE_SIZE: usize = 32;
+
+// The average number of vertices transformed per triangle (average cache miss ratio) with a FIFO
+// cache of `cache_size` vertices. 3.0 is the worst, 0.5 is the best possible on big meshes.
+pub fn acmr(indices: &[u32], cache_size: usize) -> f32 {
+    let triangles = indices.len() / 3;
+    if triangles == 0 {
+        return 0.0;
+    }
+
+    let mut cache: Vec<u32> = Vec::with_capacity(cache_size + 1);
+    let mut misses = 0;
+    for &index in indices {
+        if !cache.contains(&index) {
+            misses += 1;
+            cache.push(index);
+            if cache.len() > cache_size {
+                cache.remove(0);
+            }
+        }
+    }
+
+    misses as f32 / triangles as f32
+}
+
+// Drop the triangles using the same vertex twice
+pub fn remove_degenerate_indices(indices: &[u32]) -> Vec<u32> {
+    indices.chunks(3)
+        .filter(|t| t[0] != t[1] && t[1] != t[2] && t[2] != t[0])
+        .flat_map(|t| t.iter().cloned())
+        .collect()
+}
+
+// Drop the triangles using the same vertex twice or without any area
+pub fn remove_degenerate_triangles_by_area(indices: &[u32], positions: &[[f32; 3]]) -> Vec<u32> {
+    remove_degenerate_indices(indices).chunks(3)
+        .filter(|t| {
+            let a = Vector3::from(positions[t[0] as usize]);
+            let b = Vector3::from(positions[t[1] as usize]);
+            let c = Vector3::from(positions[t[2] as usize]);
+            (b - a).cross(c - a).magnitude2() > 0.0
+        })
+        .flat_map(|t| t.iter().cloned())
+        .collect()
+}
+
+fn forsyth_vertex_score(cache_position: Option<usize>, remaining: usize) -> f32 {
+    if remaining == 0 {
+        return -1.0;
+    }
+
+    let cache_score = match cache_position {
+        // The vertices of the last triangle should not be favored too much, or the order ends up
+        // as strips which do not use the cache well
+        Some(position) if position < 3 => 0.75,
+        Some(position) => (1.0 - (position - 3) as f32 / (FORSYTH_CACHE_SIZE - 3) as f32).powf(1.5),
+        None => 0.0
+    };
+
+    // Vertices with few triangles left are finished first so that they leave the cache
+    cache_score + 2.0 * (remaining as f32).powf(-0.5)
+}
+
+// Reorder the triangles for the vertex cache with Tom Forsyth's linear-speed algorithm. The
+// vertices are not touched.
+pub fn optimize_vertex_cache(indices: &[u32], vertex_count: usize) -> Vec<u32> {
+    let triangle_count = indices.len() / 3;
+
+    // The triangles not emitted yet around every vertex
+    let mut adjacency: Vec<Vec<usize>> = vec![Vec::new(); vertex_count];
+    for (t, triangle) in indices.chunks(3).enumerate() {
+        for &vertex in triangle {
+            adjacency[vertex as usize].push(t);
+        }
+    }
+
+    let mut cache_positions: Vec<Option<usize>> = vec![None; vertex_count];
+    let mut scores: Vec<f32> = adjacency.iter().map(|triangles| forsyth_vertex_score(None, triangles.len())).collect();
+    let mut triangle_scores: Vec<f32> = indices.chunks(3)
+        .map(|t| t.iter().map(|&vertex| scores[vertex as usize]).sum())
+        .collect();
+    let mut emitted = vec![false; triangle_count];
+    let mut cache: Vec<u32> = Vec::with_capacity(FORSYTH_CACHE_SIZE + 3);
+    let mut result = Vec::with_capacity(indices.len());
+
+    let mut best = (0..triangle_count).max_by(|&a, &b| triangle_scores[a].partial_cmp(&triangle_scores[b]).unwrap());
+    let mut cursor = 0;
+
+    while let Some(triangle) = best {
+        let corners = &indices[triangle * 3..triangle * 3 + 3];
+        result.extend_from_slice(corners);
+        emitted[triangle] = true;
+
+        for &vertex in corners {
+            adjacency[vertex as usize].retain(|&t| t != triangle);
+        }
+
+        // The vertices of the triangle go to the front of the cache
+        let mut new_cache: Vec<u32> = corners.to_vec();
+        new_cache.extend(cache.iter().filter(|vertex| !corners.contains(vertex)));
+        for &vertex in new_cache.iter().skip(FORSYTH_CACHE_SIZE) {
+            cache_positions[vertex as usize] = None;
+        }
+        let evicted: Vec<u32> = new_cache.iter().skip(FORSYTH_CACHE_SIZE).cloned().collect();
+        new_cache.truncate(FORSYTH_CACHE_SIZE);
+        cache = new_cache;
+
+        // Update the scores around the vertices whose position in the cache changed
+        for (position, &vertex) in cache.iter().enumerate() {
+            cache_positions[vertex as usize] = Some(position);
+        }
+        for &vertex in cache.iter().chain(evicted.iter()) {
+            let vertex = vertex as usize;
+            let score = forsyth_vertex_score(cache_positions[vertex], adjacency[vertex].len());
+            let delta = score - scores[vertex];
+            scores[vertex] = score;
+            for &t in &adjacency[vertex] {
+                triangle_scores[t] += delta;
+            }
+        }
+
+        // The next triangle is the best one around the cache, or any triangle left
+        best = None;
+        let mut best_score = -1.0;
+        for &vertex in &cache {
+            for &t in &adjacency[vertex as usize] {
+                if triangle_scores[t] > best_score {
+                    best_score = triangle_scores[t];
+                    best = Some(t);
+                }
+            }
+        }
+        if best.is_none() {
+            while cursor < triangle_count && emitted[cursor] {
+                cursor += 1;
+            }
+            if cursor < triangle_count {
+                best = Some(cursor);
+            }
+        }
+    }
+
+    result
+}
+
+// The new place of every vertex so that they are fetched in the order the indices use them.
+// Vertices not used by any triangle are moved to the end.
+pub fn vertex_fetch_remap(indices: &[u32], vertex_count: usize) -> Vec<u32> {
+    let mut remap = vec![u32::MAX; vertex_count];
+    let mut next = 0;
+
+    for &index in indices {
+        if remap[index as usize] == u32::MAX {
+            remap[index as usize] = next;
+            next += 1;
+        }
+    }
+    for new_index in remap.iter_mut().filter(|new_index| **new_index == u32::MAX) {
+        *new_index = next;
+        next += 1;
+    }
+
+    remap
+}
+
+// Reorder the triangles (already optimized for the vertex cache) so that the ones facing out of
+// the mesh come first, which lets the depth test reject more of the hidden ones. The triangles
+// are moved by clusters, cut where the cache is cold anyway, so the ACMR barely changes.
+pub fn optimize_overdraw(indices: &[u32], positions: &[[f32; 3]], cache_size: usize) -> Vec<u32> {
+    if indices.is_empty() {
+        return Vec::new();
+    }
+
+    // Cut a cluster at every triangle missing the cache on its three vertices
+    let mut clusters: Vec<(usize, usize)> = Vec::new();
+    let mut cache: Vec<u32> = Vec::new();
+    let mut start = 0;
+    for (t, triangle) in indices.chunks(3).enumerate() {
+        let misses = triangle.iter().filter(|index| !cache.contains(index)).count();
+        if misses == 3 && t > start {
+            clusters.push((start, t));
+            start = t;
+        }
+        for &index in triangle {
+            if !cache.contains(&index) {
+                cache.push(index);
+                if cache.len() > cache_size {
+                    cache.remove(0);
+                }
+            }
+        }
+    }
+    clusters.push((start, indices.len() / 3));
+
+    let vertex = |index: u32| Vector3::from(positions[index as usize]);
+    let mut center = Vector3::new(0.0, 0.0, 0.0);
+    for &index in indices {
+        center += vertex(index);
+    }
+    center /= indices.len() as f32;
+
+    // Sort the clusters by how much they face away from the center
+    let mut sorted: Vec<(f32, usize, usize)> = clusters.into_iter().map(|(start, end)| {
+        let mut normal = Vector3::new(0.0, 0.0, 0.0);
+        let mut centroid = Vector3::new(0.0, 0.0, 0.0);
+        let mut area = 0.0;
+        for triangle in indices[start * 3..end * 3].chunks(3) {
+            let a = vertex(triangle[0]);
+            let b = vertex(triangle[1]);
+            let c = vertex(triangle[2]);
+            let face = (b - a).cross(c - a);
+            let face_area = face.magnitude();
+            normal += face;
+            centroid += (a + b + c) * (face_area / 3.0);
+            area += face_area;
+        }
+
+        let facing = if area > 0.0 && normal.magnitude2() > 0.0 {
+            (centroid / area - center).dot(normal.normalize())
+        } else {
+            0.0
+        };
+        (facing, start, end)
+    }).collect();
+    sorted.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(::std::cmp::Ordering::Equal));
+
+    sorted.iter().flat_map(|&(_, start, end)| indices[start * 3..end * 3].iter().cloned()).collect()
+}
+
+// Rebuild the indices of a mesh part by part, keeping the submeshes in sync
+fn rewrite_parts<F>(mesh: &mut Mesh, mut rewrite: F) where F: FnMut(&[u32]) -> Vec<u32> {
+    let parts = mesh.parts();
+    let mut indices = Vec::with_capacity(mesh.indices.len());
+
+    for (i, part) in parts.iter().enumerate() {
+        let start = indices.len();
+        indices.extend(rewrite(&mesh.indices[part.start..part.start + part.count]));
+        if !mesh.submeshes.is_empty() {
+            mesh.submeshes[i].start = start;
+            mesh.submeshes[i].count = indices.len() - start;
+        }
+    }
+
+    mesh.indices = indices;
+}
+
+// Move the vertex `i` to `remap[i]`, `remap` being a permutation
+fn remap_vertices(mesh: &mut Mesh, remap: &[u32], new_count: usize) {
+    fn apply<T: Copy + Default>(values: &mut Vec<T>, remap: &[u32], new_count: usize) {
+        if values.is_empty() {
+            return;
+        }
+        let mut moved = vec![T::default(); new_count];
+        for (i, &value) in values.iter().enumerate() {
+            moved[remap[i] as usize] = value;
+        }
+        *values = moved;
+    }
+
+    apply(&mut mesh.positions, remap, new_count);
+    apply(&mut mesh.normals, remap, new_count);
+    apply(&mut mesh.tex_coords, remap, new_count);
+    apply(&mut mesh.colors, remap, new_count);
+    apply(&mut mesh.tangents, remap, new_count);
+    for index in mesh.indices.iter_mut() {
+        *index = remap[*index as usize];
+    }
+}
+
+fn attributes_close(mesh: &Mesh, a: usize, b: usize, epsilon: f32) -> bool {
+    fn close(a: &[f32], b: &[f32], epsilon: f32) -> bool {
+        a.iter().zip(b.iter()).all(|(a, b)| (a - b).abs() <= epsilon)
+    }
+
+    close(&mesh.positions[a], &mesh.positions[b], epsilon) &&
+        (!mesh.has_normals() || close(&mesh.normals[a], &mesh.normals[b], epsilon)) &&
+        (!mesh.has_tex_coords() || close(&mesh.tex_coords[a], &mesh.tex_coords[b], epsilon)) &&
+        (!mesh.has_colors() || close(&mesh.colors[a], &mesh.colors[b], epsilon)) &&
+        (!mesh.has_tangents() || close(&mesh.tangents[a], &mesh.tangents[b], epsilon))
+}
+
+// Merge the vertices whose attributes are all within `epsilon` of each other. Returns the number
+// of vertices removed.
+pub fn weld_vertices(mesh: &mut Mesh, epsilon: f32) -> usize {
+    let count = mesh.vertex_count();
+    let cell_size = if epsilon > 0.0 { epsilon * 2.0 } else { 1.0 };
+    let cell = |p: [f32; 3]| {
+        if epsilon > 0.0 {
+            [(p[0] / cell_size).floor() as i64, (p[1] / cell_size).floor() as i64, (p[2] / cell_size).floor() as i64]
+        } else {
+            [p[0].to_bits() as i64, p[1].to_bits() as i64, p[2].to_bits() as i64]
+        }
+    };
+
+    // The kept vertices, by cell
+    let mut grid: HashMap<[i64; 3], Vec<usize>> = HashMap::new();
+    let mut remap = vec![0u32; count];
+    let mut representatives = vec![false; count];
+    let mut kept = 0;
+
+    for vertex in 0..count {
+        let home = cell(mesh.positions[vertex]);
+        let reach = if epsilon > 0.0 { 1 } else { 0 };
+
+        let mut found = None;
+        'search: for dx in -reach..(reach + 1) {
+            for dy in -reach..(reach + 1) {
+                for dz in -reach..(reach + 1) {
+                    if let Some(candidates) = grid.get(&[home[0] + dx, home[1] + dy, home[2] + dz]) {
+                        if let Some(&other) = candidates.iter().find(|&&other| attributes_close(mesh, vertex, other, epsilon)) {
+                            found = Some(other);
+                            break 'search;
+                        }
+                    }
+                }
+            }
+        }
+
+        remap[vertex] = match found {
+            Some(other) => remap[other],
+            None => {
+                grid.entry(home).or_default().push(vertex);
+                representatives[vertex] = true;
+                kept += 1;
+                kept - 1
+            }
+        };
+    }
+
+    let indices: Vec<u32> = mesh.indices.iter().map(|&index| remap[index as usize]).collect();
+    // The kept vertices are numbered in their original order
+    let mut compact = Mesh::new();
+    for vertex in (0..count).filter(|&vertex| representatives[vertex]) {
+        compact.positions.push(mesh.positions[vertex]);
+        if mesh.has_normals() {
+            compact.normals.push(mesh.normals[vertex]);
+        }
+        if mesh.has_tex_coords() {
+            compact.tex_coords.push(mesh.tex_coords[vertex]);
+        }
+        if mesh.has_colors() {
+            compact.colors.push(mesh.colors[vertex]);
+        }
+        if mesh.has_tangents() {
+            compact.tangents.push(mesh.tangents[vertex]);
+        }
+    }
+
+    mesh.positions = compact.positions;
+    mesh.normals = compact.normals;
+    mesh.tex_coords = compact.tex_coords;
+    mesh.colors = compact.colors;
+    mesh.tangents = compact.tangents;
+    mesh.indices = indices;
+
+    count - kept as usize
+}
+
+// Drop the degenerate triangles of every submesh. Returns the number of triangles removed.
+pub fn remove_degenerate_triangles(mesh: &mut Mesh) -> usize {
+    let before = mesh.triangle_count();
+    let positions = mesh.positions.clone();
+    rewrite_parts(mesh, |indices| remove_degenerate_triangles_by_area(indices, &positions));
+    before - mesh.triangle_count()
+}
+
+pub fn optimize_mesh_vertex_cache(mesh: &mut Mesh) {
+    let count = mesh.vertex_count();
+    rewrite_parts(mesh, |indices| optimize_vertex_cache(indices, count));
+}
+
+pub fn optimize_mesh_overdraw(mesh: &mut Mesh, cache_size: usize) {
+    let positions = mesh.positions.clone();
+    rewrite_parts(mesh, |indices| optimize_overdraw(indices, &positions, cache_size));
+}
+
+pub fn optimize_mesh_vertex_fetch(mesh: &mut Mesh) {
+    let count = mesh.vertex_count();
+    let remap = vertex_fetch_remap(&mesh.indices, count);
+    remap_vertices(mesh, &remap, count);
+}
+
+// Drop the vertices used by no triangle, reordering the others for the vertex fetch. Returns the
+// number of vertices removed.
+pub fn remove_unused_vertices(mesh: &mut Mesh) -> usize {
+    let count = mesh.vertex_count();
+    let mut used = vec![false; count];
+    for &index in &mesh.indices {
+        used[index as usize] = true;
+    }
+    let used_count = used.iter().filter(|&&used| used).count();
+
+    // The unused vertices end up last
+    optimize_mesh_vertex_fetch(mesh);
+    mesh.positions.truncate(used_count);
+    mesh.normals.truncate(used_count);
+    mesh.tex_coords.truncate(used_count);
+    mesh.colors.truncate(used_count);
+    mesh.tangents.truncate(used_count);
+
+    count - used_count
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OptimizeReport {
+    pub vertices_before: usize,
+    pub vertices_after: usize,
+    pub triangles_before: usize,
+    pub triangles_after: usize,
+    // With `DEFAULT_CACHE_SIZE`
+    pub acmr_before: f32,
+    pub acmr_after: f32
+}
+
+// Run every optimization, in the order they are meant to be run
+pub fn optimize_mesh(mesh: &mut Mesh, weld_epsilon: f32) -> OptimizeReport {
+    let vertices_before = mesh.vertex_count();
+    let triangles_before = mesh.triangle_count();
+    let acmr_before = acmr(&mesh.indices, DEFAULT_CACHE_SIZE);
+
+    weld_vertices(mesh, weld_epsilon);
+    remove_degenerate_triangles(mesh);
+    optimize_mesh_vertex_cache(mesh);
+    optimize_mesh_overdraw(mesh, DEFAULT_CACHE_SIZE);
+    // Also drops the vertices of the degenerate triangles
+    remove_unused_vertices(mesh);
+
+    OptimizeReport {
+        vertices_before: vertices_before,
+        vertices_after: mesh.vertex_count(),
+        triangles_before: triangles_before,
+        triangles_after: mesh.triangle_count(),
+        acmr_before: acmr_before,
+        acmr_after: acmr(&mesh.indices, DEFAULT_CACHE_SIZE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mesh::Mesh;
+    use super::*;
+
+    // A grid of `size` x `size` quads on the XY plane, `offset` along X
+    fn grid(size: u32, offset: f32) -> Mesh {
+        let mut mesh = Mesh::new();
+        for y in 0..size + 1 {
+            for x in 0..size + 1 {
+                mesh.positions.push([x as f32 + offset, y as f32, 0.0]);
+                mesh.tex_coords.push([x as f32 / size as f32, y as f32 / size as f32]);
+            }
+        }
+        for y in 0..size {
+            for x in 0..size {
+                let corner = y * (size + 1) + x;
+                mesh.indices.extend_from_slice(&[corner, corner + 1, corner + size + 2,
+                    corner, corner + size + 2, corner + size + 1]);
+            }
+        }
+        mesh
+    }
+
+    // The triangles in a deterministic mess
+    fn shuffled(indices: &[u32]) -> Vec<u32> {
+        let count = indices.len() / 3;
+        // 7919 is prime, so this visits every triangle once
+        (0..count).flat_map(|i| {
+            let t = i * 7919 % count;
+            indices[t * 3..t * 3 + 3].to_vec()
+        }).collect()
+    }
+
+    // The triangles turned to start at their smallest index, keeping the winding, and sorted
+    fn triangle_set(indices: &[u32]) -> Vec<[u32; 3]> {
+        let mut triangles: Vec<[u32; 3]> = indices.chunks(3).map(|t| {
+            let first = (0..3).min_by_key(|&k| t[k]).unwrap();
+            [t[first], t[(first + 1) % 3], t[(first + 2) % 3]]
+        }).collect();
+        triangles.sort();
+        triangles
+    }
+
+    #[test]
+    fn acmr_of_known_orders() {
+        // Each triangle of a strip brings one new vertex
+        let strip: Vec<u32> = (0..10u32).flat_map(|i| vec![i, i + 1, i + 2]).collect();
+        assert!((acmr(&strip, 16) - 12.0 / 10.0).abs() < 1e-6);
+        // Without a cache, every corner is a miss
+        assert!((acmr(&strip, 0) - 3.0).abs() < 1e-6);
+        // Two triangles sharing an edge
+        assert!((acmr(&[0, 1, 2, 2, 1, 3], 16) - 2.0).abs() < 1e-6);
+        assert_eq!(acmr(&[], 16), 0.0);
+    }
+
+    #[test]
+    fn welding_merges_close_vertices_but_not_seams() {
+        let mut mesh = Mesh::new();
+        mesh.positions = vec![
+            [0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0],
+            // The same corners, within epsilon, and one just out of it
+            [1.0 + 5e-5, 0.0, 0.0], [0.0, 1.0 - 5e-5, 0.0], [1.0, 1.0, 1e-3],
+            // On the first corner, on the other side of a UV seam
+            [0.0, 0.0, 0.0]
+        ];
+        mesh.tex_coords = vec![[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [1.0, 0.0], [0.0, 1.0], [1.0, 1.0], [1.0, 0.0]];
+        mesh.indices = vec![0, 1, 2, 3, 5, 4, 6, 3, 4];
+
+        assert_eq!(weld_vertices(&mut mesh, 1e-4), 2);
+        assert_eq!(mesh.vertex_count(), 5);
+        assert_eq!(mesh.indices, vec![0, 1, 2, 1, 3, 2, 4, 1, 2]);
+        assert_eq!(mesh.positions[3], [1.0, 1.0, 1e-3]);
+        assert_eq!(mesh.tex_coords[4], [1.0, 0.0]);
+        assert!(mesh.validate().is_ok());
+
+        // Exact welding keeps the vertices only close to each other
+        let mut mesh = grid(2, 0.0);
+        let mut copy = grid(2, 1e-5);
+        assert_eq!(weld_vertices(&mut copy, 0.0), 0);
+        let count = mesh.vertex_count();
+        mesh.append(&grid(2, 0.0), "", None);
+        assert_eq!(weld_vertices(&mut mesh, 0.0), count);
+    }
+
+    #[test]
+    fn vertex_cache_order_is_better_with_the_same_triangles() {
+        let mesh = grid(30, 0.0);
+        let indices = shuffled(&mesh.indices);
+        let optimized = optimize_vertex_cache(&indices, mesh.vertex_count());
+
+        let (before, after) = (acmr(&indices, DEFAULT_CACHE_SIZE), acmr(&optimized, DEFAULT_CACHE_SIZE));
+        assert!(after < before * 0.5, "{} -> {}", before, after);
+        assert!(after < 0.8, "{}", after);
+        assert!(acmr(&optimized, DEFAULT_CACHE_SIZE) <= acmr(&mesh.indices, DEFAULT_CACHE_SIZE));
+        assert_eq!(triangle_set(&optimized), triangle_set(&indices));
+    }
+
+    #[test]
+    fn vertex_fetch_remap_is_a_permutation() {
+        let indices = [5, 2, 7, 2, 0, 5];
+        let remap = vertex_fetch_remap(&indices, 9);
+        // In the order of first use, then the unused ones
+        assert_eq!(&remap[..], &[3, 4, 1, 5, 6, 0, 7, 2, 8]);
+
+        let mesh = grid(8, 0.0);
+        let indices = shuffled(&mesh.indices);
+        let mut remap = vertex_fetch_remap(&indices, mesh.vertex_count());
+        remap.sort();
+        assert_eq!(remap, (0..mesh.vertex_count() as u32).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn submeshes_stay_consistent() {
+        let mut mesh = Mesh::new();
+        let (mut first, second) = (grid(6, 0.0), grid(4, 100.0));
+        first.indices = shuffled(&first.indices);
+        mesh.append(&first, "first", Some(0));
+        mesh.append(&second, "second", Some(1));
+        // A degenerate triangle and a duplicate vertex in the second part
+        mesh.positions.push(mesh.positions[first.vertex_count()]);
+        mesh.tex_coords.push(mesh.tex_coords[first.vertex_count()]);
+        let duplicate = mesh.vertex_count() as u32 - 1;
+        mesh.indices.extend_from_slice(&[duplicate, duplicate, duplicate]);
+        mesh.submeshes[1].count += 3;
+
+        let report = optimize_mesh(&mut mesh, 1e-6);
+        assert_eq!(report.triangles_after, 6 * 6 * 2 + 4 * 4 * 2);
+        assert_eq!(report.vertices_after, 7 * 7 + 5 * 5);
+        assert!(mesh.validate().is_ok());
+
+        // The parts follow each other and keep their own triangles
+        assert_eq!(mesh.submeshes[0].start, 0);
+        assert_eq!(mesh.submeshes[0].count, 6 * 6 * 6);
+        assert_eq!(mesh.submeshes[1].start, mesh.submeshes[0].count);
+        assert_eq!(mesh.submeshes[1].count, 4 * 4 * 6);
+        for (part, submesh) in mesh.submeshes.iter().enumerate() {
+            for &index in &mesh.indices[submesh.start..submesh.start + submesh.count] {
+                assert_eq!(mesh.positions[index as usize][0] >= 100.0, part == 1);
+            }
+        }
+    }
+
+    #[test]
+    fn no_vertex_is_left_without_triangles() {
+        // Welded into a single point, then removed
+        let mut mesh = Mesh::new();
+        mesh.positions = vec![[1.0, 2.0, 3.0], [1.0, 2.0, 3.0], [1.0, 2.0, 3.0]];
+        mesh.indices = vec![0, 1, 2];
+        let report = optimize_mesh(&mut mesh, 1e-6);
+        assert_eq!((report.triangles_after, report.vertices_after), (0, 0));
+        assert_eq!(mesh.vertex_count(), 0);
+
+        let mut mesh = Mesh::new();
+        mesh.positions = vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0]];
+        mesh.normals = vec![[0.0, 1.0, 0.0]; 2];
+        let report = optimize_mesh(&mut mesh, 0.0);
+        assert_eq!((report.vertices_before, report.vertices_after), (2, 0));
+        assert!(mesh.validate().is_ok());
+    }
+}
@@ -0,0 +1,533 @@
+// A reader and a writer for Stanford PLY meshes, in ASCII and in both binary byte orders.
+//
+// Vertices may carry normals, texture coordinates and colors. Faces are polygons, they are
+// triangulated on load. Elements other than `vertex` and `face` are skipped.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use cgmath::Vector3;
+
+use error::LoadError;
+use mesh::Mesh;
+use mesh::polygon;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlyFormat {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ScalarType {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64
+}
+
+impl ScalarType {
+    fn from_name(name: &str) -> Option<ScalarType> {
+        match name {
+            "char" | "int8" => Some(ScalarType::Int8),
+            "uchar" | "uint8" => Some(ScalarType::UInt8),
+            "short" | "int16" => Some(ScalarType::Int16),
+            "ushort" | "uint16" => Some(ScalarType::UInt16),
+            "int" | "int32" => Some(ScalarType::Int32),
+            "uint" | "uint32" => Some(ScalarType::UInt32),
+            "float" | "float32" => Some(ScalarType::Float32),
+            "double" | "float64" => Some(ScalarType::Float64),
+            _ => None
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            ScalarType::Int8 | ScalarType::UInt8 => 1,
+            ScalarType::Int16 | ScalarType::UInt16 => 2,
+            ScalarType::Int32 | ScalarType::UInt32 | ScalarType::Float32 => 4,
+            ScalarType::Float64 => 8
+        }
+    }
+
+    // Colors stored as integers go from 0 to the maximum of their type
+    fn color_scale(self) -> f64 {
+        match self {
+            ScalarType::UInt8 => 255.0,
+            ScalarType::UInt16 => 65535.0,
+            ScalarType::UInt32 => 4294967295.0,
+            ScalarType::Int8 => 127.0,
+            ScalarType::Int16 => 32767.0,
+            ScalarType::Int32 => 2147483647.0,
+            ScalarType::Float32 | ScalarType::Float64 => 1.0
+        }
+    }
+}
+
+struct Property {
+    name: String,
+    value_type: ScalarType,
+    // The type of the length of a list property
+    count_type: Option<ScalarType>
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>
+}
+
+impl Element {
+    // The least one element takes in the body, counted like `Body::remaining`: a token per
+    // property in ASCII, the scalars and the list lengths in binary. Never 0, so that a huge
+    // count of empty elements is not looped over.
+    fn min_size(&self, format: PlyFormat) -> usize {
+        let size = match format {
+            PlyFormat::Ascii => self.properties.len(),
+            _ => self.properties.iter().map(|property| property.count_type.unwrap_or(property.value_type).size()).sum()
+        };
+        size.max(1)
+    }
+}
+
+// Where the values of the body come from
+enum Body {
+    Ascii { tokens: Vec<String>, position: usize },
+    Binary { data: Vec<u8>, position: usize, big_endian: bool }
+}
+
+impl Body {
+    // What is left to read: tokens for ASCII, bytes for binary
+    fn remaining(&self) -> usize {
+        match *self {
+            Body::Ascii { ref tokens, position } => tokens.len().saturating_sub(position),
+            Body::Binary { ref data, position, .. } => data.len().saturating_sub(position)
+        }
+    }
+
+    fn read(&mut self, value_type: ScalarType) -> Result<f64, LoadError> {
+        match *self {
+            Body::Ascii { ref tokens, ref mut position } => {
+                let token = tokens.get(*position).ok_or_else(|| LoadError::parse("unexpected end of file"))?;
+                *position += 1;
+                token.parse::<f64>().map_err(|_| LoadError::parse(format!("'{}' is not a number", token)))
+            },
+            Body::Binary { ref data, ref mut position, big_endian } => {
+                let size = value_type.size();
+                if *position + size > data.len() {
+                    return Err(LoadError::parse("unexpected end of file"));
+                }
+
+                let mut bytes = [0u8; 8];
+                bytes[..size].copy_from_slice(&data[*position..*position + size]);
+                if big_endian {
+                    bytes[..size].reverse();
+                }
+                *position += size;
+
+                Ok(match value_type {
+                    ScalarType::Int8 => bytes[0] as i8 as f64,
+                    ScalarType::UInt8 => bytes[0] as f64,
+                    ScalarType::Int16 => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+                    ScalarType::UInt16 => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+                    ScalarType::Int32 => i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+                    ScalarType::UInt32 => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+                    ScalarType::Float32 => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+                    ScalarType::Float64 => f64::from_le_bytes(bytes)
+                })
+            }
+        }
+    }
+}
+
+pub fn load_ply<P: AsRef<Path>>(path: P) -> Result<Mesh, LoadError> {
+    let file = File::open(path)?;
+    read_ply(BufReader::new(file))
+}
+
+fn read_header<R: BufRead>(reader: &mut R) -> Result<(PlyFormat, Vec<Element>), LoadError> {
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    let mut line = String::new();
+    let mut number = 0;
+
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(LoadError::parse("missing end_header"));
+        }
+        number += 1;
+
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue
+        };
+
+        if number == 1 {
+            if keyword != "ply" {
+                return Err(LoadError::parse("not a PLY file"));
+            }
+            continue;
+        }
+
+        match keyword {
+            "format" => {
+                format = Some(match tokens.next() {
+                    Some("ascii") => PlyFormat::Ascii,
+                    Some("binary_little_endian") => PlyFormat::BinaryLittleEndian,
+                    Some("binary_big_endian") => PlyFormat::BinaryBigEndian,
+                    _ => return Err(LoadError::at_line(number, "unknown format"))
+                });
+            },
+            "element" => {
+                let name = tokens.next().ok_or_else(|| LoadError::at_line(number, "element without name"))?;
+                let count = tokens.next().and_then(|count| count.parse().ok())
+                    .ok_or_else(|| LoadError::at_line(number, "element without count"))?;
+                elements.push(Element {
+                    name: name.to_string(),
+                    count: count,
+                    properties: Vec::new()
+                });
+            },
+            "property" => {
+                let element = elements.last_mut()
+                    .ok_or_else(|| LoadError::at_line(number, "property outside of an element"))?;
+                let type_name = tokens.next().unwrap_or("");
+                let scalar = |name: Option<&str>| name.and_then(ScalarType::from_name)
+                    .ok_or_else(|| LoadError::at_line(number, "unknown property type"));
+
+                let (count_type, value_type) = if type_name == "list" {
+                    (Some(scalar(tokens.next())?), scalar(tokens.next())?)
+                } else {
+                    (None, scalar(Some(type_name))?)
+                };
+
+                element.properties.push(Property {
+                    name: tokens.next().ok_or_else(|| LoadError::at_line(number, "property without name"))?.to_string(),
+                    value_type: value_type,
+                    count_type: count_type
+                });
+            },
+            "end_header" => break,
+            "comment" | "obj_info" => (),
+            _ => return Err(LoadError::at_line(number, format!("unknown header keyword '{}'", keyword)))
+        }
+    }
+
+    let format = format.ok_or_else(|| LoadError::parse("missing format"))?;
+    Ok((format, elements))
+}
+
+// Indices and list lengths are read like any other value, as floats
+fn as_index(value: f64, what: &str) -> Result<usize, LoadError> {
+    if value < 0.0 || value.fract() != 0.0 || value > u32::MAX as f64 {
+        return Err(LoadError::parse(format!("{} {} is not a valid index", what, value)));
+    }
+    Ok(value as usize)
+}
+
+pub fn read_ply<R: BufRead>(mut reader: R) -> Result<Mesh, LoadError> {
+    let (format, elements) = read_header(&mut reader)?;
+
+    let mut body = match format {
+        PlyFormat::Ascii => {
+            let mut text = String::new();
+            reader.read_to_string(&mut text)?;
+            Body::Ascii { tokens: text.split_whitespace().map(|token| token.to_string()).collect(), position: 0 }
+        },
+        _ => {
+            let mut data = Vec::new();
+            reader.read_to_end(&mut data)?;
+            Body::Binary { data: data, position: 0, big_endian: format == PlyFormat::BinaryBigEndian }
+        }
+    };
+
+    let mut mesh = Mesh::new();
+    let mut polygons: Vec<Vec<usize>> = Vec::new();
+
+    for element in &elements {
+        let names: Vec<&str> = element.properties.iter().map(|property| &property.name[..]).collect();
+        let find = |candidates: &[&str]| candidates.iter().filter_map(|c| names.iter().position(|n| n == c)).next();
+
+        let position = [find(&["x"]), find(&["y"]), find(&["z"])];
+        let normal = [find(&["nx"]), find(&["ny"]), find(&["nz"])];
+        let tex_coord = [find(&["u", "s", "texture_u", "texture_s"]), find(&["v", "t", "texture_v", "texture_t"])];
+        let color = [find(&["red", "diffuse_red", "r"]), find(&["green", "diffuse_green", "g"]),
+                     find(&["blue", "diffuse_blue", "b"]), find(&["alpha", "a"])];
+        let face = find(&["vertex_indices", "vertex_index"]);
+
+        let has_normals = normal.iter().all(Option::is_some);
+        let has_tex_coords = tex_coord.iter().all(Option::is_some);
+        let has_colors = color[..3].iter().all(Option::is_some);
+
+        // The counts of the header cannot be trusted with the loop and the allocations
+        if element.count.checked_mul(element.min_size(format)).is_none_or(|size| size > body.remaining()) {
+            return Err(LoadError::parse(format!("{} {} elements do not fit in the file", element.count,
+                element.name)));
+        }
+        if element.name == "vertex" {
+            mesh.positions.reserve(element.count);
+        } else if element.name == "face" {
+            polygons.reserve(element.count);
+        }
+
+        let mut scalars = vec![0.0; element.properties.len()];
+        for _ in 0..element.count {
+            let mut list = Vec::new();
+
+            for (i, property) in element.properties.iter().enumerate() {
+                match property.count_type {
+                    Some(count_type) => {
+                        let count = as_index(body.read(count_type)?, "list length")?;
+                        let values = (0..count).map(|_| body.read(property.value_type)).collect::<Result<Vec<_>, _>>()?;
+                        if Some(i) == face {
+                            list = values;
+                        }
+                    },
+                    None => scalars[i] = body.read(property.value_type)?
+                }
+            }
+
+            if element.name == "vertex" {
+                let value = |index: Option<usize>| index.map_or(0.0, |index| scalars[index] as f32);
+                mesh.positions.push([value(position[0]), value(position[1]), value(position[2])]);
+                if has_normals {
+                    mesh.normals.push([value(normal[0]), value(normal[1]), value(normal[2])]);
+                }
+                if has_tex_coords {
+                    mesh.tex_coords.push([value(tex_coord[0]), value(tex_coord[1])]);
+                }
+                if has_colors {
+                    let channel = |index: Option<usize>| match index {
+                        Some(index) => (scalars[index] / element.properties[index].value_type.color_scale()) as f32,
+                        None => 1.0
+                    };
+                    mesh.colors.push([channel(color[0]), channel(color[1]), channel(color[2]), channel(color[3])]);
+                }
+            } else if element.name == "face" && face.is_some() {
+                polygons.push(list.iter().map(|&index| as_index(index, "face index"))
+                    .collect::<Result<Vec<_>, _>>()?);
+            }
+        }
+    }
+
+    let count = mesh.positions.len();
+    for polygon in polygons {
+        if polygon.iter().any(|&index| index >= count) {
+            return Err(LoadError::parse("face index out of range"));
+        }
+
+        let points: Vec<Vector3<f32>> = polygon.iter().map(|&index| Vector3::from(mesh.positions[index])).collect();
+        for triangle in polygon::triangulate(&points) {
+            mesh.indices.extend(triangle.iter().map(|&k| polygon[k] as u32));
+        }
+    }
+
+    Ok(mesh)
+}
+
+pub fn save_ply<P: AsRef<Path>>(mesh: &Mesh, path: P, format: PlyFormat) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_ply(mesh, &mut writer, format)?;
+    writer.flush()
+}
+
+// Write the triangles of a mesh, with its normals, texture coordinates and colors when it has
+// them. Colors are stored as bytes, the way most tools expect them.
+pub fn write_ply<W: Write>(mesh: &Mesh, writer: &mut W, format: PlyFormat) -> io::Result<()> {
+    let format_name = match format {
+        PlyFormat::Ascii => "ascii",
+        PlyFormat::BinaryLittleEndian => "binary_little_endian",
+        PlyFormat::BinaryBigEndian => "binary_big_endian"
+    };
+
+    writeln!(writer, "ply")?;
+    writeln!(writer, "format {} 1.0", format_name)?;
+    writeln!(writer, "comment written by ogldev")?;
+    writeln!(writer, "element vertex {}", mesh.vertex_count())?;
+    writeln!(writer, "property float x\nproperty float y\nproperty float z")?;
+    if mesh.has_normals() {
+        writeln!(writer, "property float nx\nproperty float ny\nproperty float nz")?;
+    }
+    if mesh.has_tex_coords() {
+        writeln!(writer, "property float s\nproperty float t")?;
+    }
+    if mesh.has_colors() {
+        writeln!(writer, "property uchar red\nproperty uchar green\nproperty uchar blue\nproperty uchar alpha")?;
+    }
+    writeln!(writer, "element face {}", mesh.triangle_count())?;
+    writeln!(writer, "property list uchar int vertex_indices")?;
+    writeln!(writer, "end_header")?;
+
+    let to_byte = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+
+    for i in 0..mesh.vertex_count() {
+        let mut floats = mesh.positions[i].to_vec();
+        if mesh.has_normals() {
+            floats.extend_from_slice(&mesh.normals[i]);
+        }
+        if mesh.has_tex_coords() {
+            floats.extend_from_slice(&mesh.tex_coords[i]);
+        }
+        let bytes: Vec<u8> = if mesh.has_colors() {
+            mesh.colors[i].iter().map(|&value| to_byte(value)).collect()
+        } else {
+            Vec::new()
+        };
+
+        match format {
+            PlyFormat::Ascii => {
+                let mut fields: Vec<String> = floats.iter().map(|value| value.to_string()).collect();
+                fields.extend(bytes.iter().map(|value| value.to_string()));
+                writeln!(writer, "{}", fields.join(" "))?;
+            },
+            PlyFormat::BinaryLittleEndian => {
+                for value in &floats {
+                    writer.write_all(&value.to_le_bytes())?;
+                }
+                writer.write_all(&bytes)?;
+            },
+            PlyFormat::BinaryBigEndian => {
+                for value in &floats {
+                    writer.write_all(&value.to_be_bytes())?;
+                }
+                writer.write_all(&bytes)?;
+            }
+        }
+    }
+
+    for triangle in mesh.indices.chunks(3) {
+        match format {
+            PlyFormat::Ascii => writeln!(writer, "3 {} {} {}", triangle[0], triangle[1], triangle[2])?,
+            PlyFormat::BinaryLittleEndian => {
+                writer.write_all(&[3])?;
+                for &index in triangle {
+                    writer.write_all(&(index as i32).to_le_bytes())?;
+                }
+            },
+            PlyFormat::BinaryBigEndian => {
+                writer.write_all(&[3])?;
+                for &index in triangle {
+                    writer.write_all(&(index as i32).to_be_bytes())?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use error::LoadError;
+    use mesh::Mesh;
+    use super::{read_ply, write_ply, PlyFormat};
+
+    // A quad with every attribute, colors on exact bytes so that they survive
+    fn quad() -> Mesh {
+        let mut mesh = Mesh::new();
+        mesh.positions = vec![[0.0, 0.0, 0.0], [1.5, 0.0, 0.0], [1.5, 2.0, -0.25], [0.0, 2.0, 1e-3]];
+        mesh.normals = vec![[0.0, 0.0, 1.0], [0.0, 0.6, 0.8], [1.0, 0.0, 0.0], [0.0, -1.0, 0.0]];
+        mesh.tex_coords = vec![[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.125, 0.75]];
+        mesh.colors = vec![[1.0, 0.0, 0.0, 1.0], [0.0, 1.0, 0.0, 1.0], [0.0, 0.0, 1.0, 0.0],
+            [51.0 / 255.0, 102.0 / 255.0, 153.0 / 255.0, 204.0 / 255.0]];
+        mesh.indices = vec![0, 1, 2, 0, 2, 3];
+        mesh
+    }
+
+    fn round_trip(mesh: &Mesh, format: PlyFormat) -> Mesh {
+        let mut data = Vec::new();
+        write_ply(mesh, &mut data, format).unwrap();
+        read_ply(&data[..]).unwrap()
+    }
+
+    fn assert_close<T: AsRef<[f32]>>(a: &[T], b: &[T]) {
+        assert_eq!(a.len(), b.len());
+        for (a, b) in a.iter().zip(b.iter()) {
+            for (a, b) in a.as_ref().iter().zip(b.as_ref().iter()) {
+                assert!((a - b).abs() < 1e-6, "{} != {}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn every_format_round_trips() {
+        let mesh = quad();
+        for &format in &[PlyFormat::Ascii, PlyFormat::BinaryLittleEndian, PlyFormat::BinaryBigEndian] {
+            let loaded = round_trip(&mesh, format);
+            assert_eq!(loaded.positions, mesh.positions, "{:?}", format);
+            assert_eq!(loaded.normals, mesh.normals, "{:?}", format);
+            assert_eq!(loaded.tex_coords, mesh.tex_coords, "{:?}", format);
+            assert_close(&loaded.colors, &mesh.colors);
+            assert_eq!(loaded.indices, mesh.indices, "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn missing_attributes_stay_missing() {
+        let mut mesh = quad();
+        mesh.normals.clear();
+        mesh.colors.clear();
+        let loaded = round_trip(&mesh, PlyFormat::BinaryBigEndian);
+        assert!(!loaded.has_normals() && !loaded.has_colors());
+        assert_eq!(loaded.tex_coords, mesh.tex_coords);
+    }
+
+    #[test]
+    fn polygons_are_triangulated() {
+        let text = "ply\nformat ascii 1.0\nelement vertex 4\nproperty float x\nproperty float y\n\
+            property float z\nelement face 1\nproperty list uchar int vertex_indices\nend_header\n\
+            0 0 0\n1 0 0\n1 1 0\n0 1 0\n4 0 1 2 3\n";
+        let mesh = read_ply(text.as_bytes()).unwrap();
+        assert_eq!(mesh.triangle_count(), 2);
+        assert!(mesh.validate().is_ok());
+    }
+
+    #[test]
+    fn bad_face_indices_are_rejected() {
+        let header = "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\nproperty float y\n\
+            property float z\nelement face 1\nproperty list uchar float vertex_indices\nend_header\n\
+            0 0 0\n1 0 0\n1 1 0\n";
+        for face in &["3 0 1 -1", "3 0 1 1.5", "3 0 1 3", "-3 0 1 2", "3 0 1"] {
+            match read_ply(format!("{}{}\n", header, face).as_bytes()) {
+                Err(LoadError::Parse(_)) => (),
+                Err(err) => panic!("{}: {}", face, err),
+                Ok(_) => panic!("{} was accepted", face)
+            }
+        }
+        assert!(read_ply(format!("{}3 0 1 2\n", header).as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn counts_larger_than_the_file_are_rejected() {
+        // Nothing to read per element, it used to loop 4 billion times
+        let header = "ply\nformat ascii 1.0\nelement junk 4000000000\nend_header\n";
+        assert!(read_ply(header.as_bytes()).is_err());
+
+        // Rows of 12 bytes, one short
+        let header = "ply\nformat binary_little_endian 1.0\nelement vertex 2\nproperty float x\n\
+            property float y\nproperty float z\nend_header\n";
+        let mut data = header.as_bytes().to_vec();
+        data.extend_from_slice(&[0; 23]);
+        assert!(read_ply(&data[..]).is_err());
+        data.push(0);
+        assert_eq!(read_ply(&data[..]).unwrap().vertex_count(), 2);
+
+        for &format in &["ascii", "binary_big_endian"] {
+            let text = format!("ply\nformat {} 1.0\nelement vertex 3000000000000000000\nproperty double x\n\
+                element face 1\nproperty list uint uint vertex_indices\nend_header\n0 0 0\n", format);
+            match read_ply(text.as_bytes()) {
+                Err(LoadError::Parse(message)) => assert!(message.contains("do not fit"), "{}", message),
+                Err(err) => panic!("{}", err),
+                Ok(_) => panic!("accepted")
+            }
+        }
+    }
+}
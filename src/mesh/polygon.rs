@@ -0,0 +1,97 @@
+use cgmath::{InnerSpace, Vector2, Vector3};
+
+// Split a planar polygon into triangles by ear clipping, keeping the winding of the polygon.
+// Returns triangles as indices into `points`. Concave polygons are fine, self-intersecting ones
+// fall back to a fan.
+pub fn triangulate(points: &[Vector3<f32>]) -> Vec<[usize; 3]> {
+    let count = points.len();
+    if count < 3 {
+        return Vec::new();
+    } else if count == 3 {
+        return vec![[0, 1, 2]];
+    }
+
+    // Newell's method gives a robust normal even for concave polygons
+    let mut normal = Vector3::new(0.0, 0.0, 0.0);
+    for i in 0..count {
+        let current = points[i];
+        let next = points[(i + 1) % count];
+        normal.x += (current.y - next.y) * (current.z + next.z);
+        normal.y += (current.z - next.z) * (current.x + next.x);
+        normal.z += (current.x - next.x) * (current.y + next.y);
+    }
+    if normal.magnitude2() <= 0.0 {
+        return fan(&(0..count).collect::<Vec<_>>());
+    }
+    let normal = normal.normalize();
+
+    // Project on the plane of the polygon. With `u.cross(v) == normal`, the polygon is
+    // counterclockwise in the (u, v) plane.
+    let helper = if normal.x.abs() < 0.9 { Vector3::new(1.0, 0.0, 0.0) } else { Vector3::new(0.0, 1.0, 0.0) };
+    let u = helper.cross(normal).normalize();
+    let v = normal.cross(u);
+    let projected: Vec<Vector2<f32>> = points.iter().map(|p| Vector2::new(p.dot(u), p.dot(v))).collect();
+
+    let mut remaining: Vec<usize> = (0..count).collect();
+    let mut triangles = Vec::with_capacity(count - 2);
+
+    while remaining.len() > 3 {
+        let length = remaining.len();
+        let mut clipped = false;
+
+        for i in 0..length {
+            let prev = remaining[(i + length - 1) % length];
+            let current = remaining[i];
+            let next = remaining[(i + 1) % length];
+
+            if is_ear(&projected, &remaining, prev, current, next) {
+                triangles.push([prev, current, next]);
+                remaining.remove(i);
+                clipped = true;
+                break;
+            }
+        }
+
+        if !clipped {
+            // Degenerate or self-intersecting, give up on being clever
+            triangles.extend(fan(&remaining));
+            return triangles;
+        }
+    }
+
+    triangles.push([remaining[0], remaining[1], remaining[2]]);
+    triangles
+}
+
+fn fan(indices: &[usize]) -> Vec<[usize; 3]> {
+    (1..(indices.len() - 1)).map(|i| [indices[0], indices[i], indices[i + 1]]).collect()
+}
+
+fn cross(o: Vector2<f32>, a: Vector2<f32>, b: Vector2<f32>) -> f32 {
+    (a.x - o.x) * (b.y - o.y) - (a.y - o.y) * (b.x - o.x)
+}
+
+fn is_ear(points: &[Vector2<f32>], remaining: &[usize], prev: usize, current: usize, next: usize) -> bool {
+    let a = points[prev];
+    let b = points[current];
+    let c = points[next];
+
+    // Reflex or flat corner
+    if cross(a, b, c) <= 0.0 {
+        return false;
+    }
+
+    // No other corner may be inside the ear
+    for &index in remaining.iter() {
+        if index == prev || index == current || index == next {
+            continue;
+        }
+
+        let p = points[index];
+        if cross(a, b, p) >= 0.0 && cross(b, c, p) >= 0.0 && cross(c, a, p) >= 0.0 {
+            return false;
+        }
+    }
+
+    true
+}
@@ -0,0 +1,525 @@
+use std::f32::consts::PI;
+use std::collections::HashMap;
+
+use cgmath::{InnerSpace, Vector2, Vector3};
+
+use mesh::Mesh;
+
+// A point of a profile which is revolved around the Y axis by `lathe()`
+#[derive(Clone, Copy)]
+struct ProfilePoint {
+    radius: f32,
+    y: f32,
+    // The normal in the (radius, y) plane
+    normal: Vector2<f32>
+}
+
+fn profile_point(radius: f32, y: f32, normal_r: f32, normal_y: f32) -> ProfilePoint {
+    ProfilePoint {
+        radius: radius,
+        y: y,
+        normal: Vector2::new(normal_r, normal_y).normalize()
+    }
+}
+
+// Revolve a profile around the Y axis. The profile must go around the shape counterclockwise
+// when seen with the radius to the right and Y up (e.g. from the bottom to the top of a sphere),
+// so that the triangles face outward.
+//
+// The angle starts from +X and goes toward -Z, `u` follows the angle and `v` follows the length
+// of the profile. The tangent (direction of `u`) is always horizontal.
+fn lathe(profile: &[ProfilePoint], segments: u32) -> Mesh {
+    let segments = segments.max(3);
+    let mut mesh = Mesh::new();
+
+    // Accumulate the length of the profile for the texture coordinates
+    let mut lengths = vec![0.0];
+    for pair in profile.windows(2) {
+        let delta = Vector2::new(pair[1].radius - pair[0].radius, pair[1].y - pair[0].y);
+        let last = *lengths.last().unwrap();
+        lengths.push(last + delta.magnitude());
+    }
+    let total_length = lengths.last().unwrap().max(1e-6);
+
+    for (point, length) in profile.iter().zip(lengths.iter()) {
+        for i in 0..(segments + 1) {
+            let u = i as f32 / segments as f32;
+            let (sin, cos) = if i == segments { (0.0, 1.0) } else { (u * 2.0 * PI).sin_cos() };
+
+            mesh.positions.push([point.radius * cos, point.y, -point.radius * sin]);
+            mesh.normals.push([point.normal.x * cos, point.normal.y, -point.normal.x * sin]);
+            mesh.tex_coords.push([u, length / total_length]);
+            mesh.tangents.push([-sin, 0.0, -cos, 1.0]);
+        }
+    }
+
+    let row = segments + 1;
+    for j in 0..(profile.len() as u32 - 1) {
+        for i in 0..segments {
+            let a = j * row + i;
+            let b = a + 1;
+            let c = a + row;
+            let d = c + 1;
+            mesh.indices.extend_from_slice(&[a, b, c, b, d, c]);
+        }
+    }
+
+    remove_degenerate_triangles(&mut mesh);
+    mesh
+}
+
+// Drop the triangles having two corners at the same place (e.g. at the poles of a sphere)
+fn remove_degenerate_triangles(mesh: &mut Mesh) {
+    let mut indices = Vec::with_capacity(mesh.indices.len());
+
+    for triangle in mesh.indices.chunks(3) {
+        let a = mesh.positions[triangle[0] as usize];
+        let b = mesh.positions[triangle[1] as usize];
+        let c = mesh.positions[triangle[2] as usize];
+        if a != b && b != c && c != a {
+            indices.extend_from_slice(triangle);
+        }
+    }
+
+    mesh.indices = indices;
+}
+
+// Append a subdivided square, `size` long along the tangent and the bitangent and split into
+// `subdivisions` quads along each. `tangent.cross(bitangent)` must be the normal of the face.
+fn add_face(mesh: &mut Mesh, center: Vector3<f32>, tangent: Vector3<f32>, bitangent: Vector3<f32>,
+        size: (f32, f32), subdivisions: (u32, u32)) {
+    let (size_u, size_v) = size;
+    let subdivisions_u = subdivisions.0.max(1);
+    let subdivisions_v = subdivisions.1.max(1);
+    let normal = tangent.cross(bitangent).normalize();
+    let base = mesh.positions.len() as u32;
+
+    for j in 0..(subdivisions_v + 1) {
+        let v = j as f32 / subdivisions_v as f32;
+        for i in 0..(subdivisions_u + 1) {
+            let u = i as f32 / subdivisions_u as f32;
+            let position = center + tangent * ((u - 0.5) * size_u) + bitangent * ((v - 0.5) * size_v);
+
+            mesh.positions.push(position.into());
+            mesh.normals.push(normal.into());
+            mesh.tex_coords.push([u, v]);
+            mesh.tangents.push([tangent.x, tangent.y, tangent.z, 1.0]);
+        }
+    }
+
+    let row = subdivisions_u + 1;
+    for j in 0..subdivisions_v {
+        for i in 0..subdivisions_u {
+            let a = base + j * row + i;
+            let b = a + 1;
+            let c = a + row;
+            let d = c + 1;
+            mesh.indices.extend_from_slice(&[a, b, c, b, d, c]);
+        }
+    }
+}
+
+// A flat grid on the XZ plane facing +Y, centered at the origin
+pub fn plane(width: f32, depth: f32, subdivisions_x: u32, subdivisions_z: u32) -> Mesh {
+    let mut mesh = Mesh::new();
+    add_face(&mut mesh, Vector3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0),
+        Vector3::new(0.0, 0.0, -1.0), (width, depth), (subdivisions_x, subdivisions_z));
+    mesh
+}
+
+// A cube centered at the origin. Each face is split into `subdivisions` x `subdivisions` quads
+// and has its own vertices, so the normals stay flat.
+pub fn cube(size: f32, subdivisions: u32) -> Mesh {
+    let half = size / 2.0;
+    let x = Vector3::new(1.0, 0.0, 0.0);
+    let y = Vector3::new(0.0, 1.0, 0.0);
+    let z = Vector3::new(0.0, 0.0, 1.0);
+
+    // (normal, tangent, bitangent)
+    let faces = [
+        (x, -z, y),
+        (-x, z, y),
+        (y, x, -z),
+        (-y, x, z),
+        (z, x, y),
+        (-z, -x, y)
+    ];
+
+    let mut mesh = Mesh::new();
+    for &(normal, tangent, bitangent) in faces.iter() {
+        add_face(&mut mesh, normal * half, tangent, bitangent, (size, size), (subdivisions, subdivisions));
+    }
+    mesh
+}
+
+// A sphere made of `segments` slices around the Y axis and `rings` stacks from pole to pole
+pub fn uv_sphere(radius: f32, segments: u32, rings: u32) -> Mesh {
+    let rings = rings.max(2);
+
+    let mut profile = Vec::new();
+    for j in 0..(rings + 1) {
+        let angle = -PI / 2.0 + PI * j as f32 / rings as f32;
+        let (sin, cos) = angle.sin_cos();
+
+        // Put the poles exactly on the axis, so the triangles there are detected as degenerate
+        let r = if j == 0 || j == rings { 0.0 } else { cos };
+        let y = if j == 0 { -1.0 } else if j == rings { 1.0 } else { sin };
+        profile.push(profile_point(r * radius, y * radius, r, y));
+    }
+
+    lathe(&profile, segments)
+}
+
+// A cylinder along the Y axis, centered at the origin, with caps
+pub fn cylinder(radius: f32, height: f32, segments: u32, stacks: u32) -> Mesh {
+    let stacks = stacks.max(1);
+    let half = height / 2.0;
+
+    let mut profile = vec![
+        profile_point(0.0, -half, 0.0, -1.0),
+        profile_point(radius, -half, 0.0, -1.0)
+    ];
+    for j in 0..(stacks + 1) {
+        let y = -half + height * j as f32 / stacks as f32;
+        profile.push(profile_point(radius, y, 1.0, 0.0));
+    }
+    profile.push(profile_point(radius, half, 0.0, 1.0));
+    profile.push(profile_point(0.0, half, 0.0, 1.0));
+
+    lathe(&profile, segments)
+}
+
+// A cone along the Y axis with the apex at the top, centered at the origin, with a base cap
+pub fn cone(radius: f32, height: f32, segments: u32) -> Mesh {
+    let half = height / 2.0;
+
+    // The normal of the side is perpendicular to the slope
+    let profile = [
+        profile_point(0.0, -half, 0.0, -1.0),
+        profile_point(radius, -half, 0.0, -1.0),
+        profile_point(radius, -half, height, radius),
+        profile_point(0.0, half, height, radius)
+    ];
+
+    lathe(&profile, segments)
+}
+
+// A torus around the Y axis. `sides` is the number of segments of the tube.
+pub fn torus(major_radius: f32, minor_radius: f32, segments: u32, sides: u32) -> Mesh {
+    let sides = sides.max(3);
+
+    let mut profile = Vec::new();
+    for j in 0..(sides + 1) {
+        let (sin, cos) = if j == sides {
+            (0.0, 1.0)
+        } else {
+            (2.0 * PI * j as f32 / sides as f32).sin_cos()
+        };
+        profile.push(profile_point(major_radius + minor_radius * cos, minor_radius * sin, cos, sin));
+    }
+
+    lathe(&profile, segments)
+}
+
+// A cylinder of `height` with two hemispheres of `radius` at both ends. `rings` is the number of
+// stacks of each hemisphere.
+pub fn capsule(radius: f32, height: f32, segments: u32, rings: u32) -> Mesh {
+    let rings = rings.max(1);
+    let half = height / 2.0;
+
+    let mut profile = Vec::new();
+    for &(offset, from, to) in [(-half, 0, rings), (half, rings, 2 * rings)].iter() {
+        for j in from..(to + 1) {
+            let angle = -PI / 2.0 + PI * j as f32 / (2 * rings) as f32;
+            let (sin, cos) = angle.sin_cos();
+
+            let r = if j == 0 || j == 2 * rings { 0.0 } else { cos };
+            let y = if j == 0 { -1.0 } else if j == 2 * rings { 1.0 } else { sin };
+            profile.push(profile_point(r * radius, y * radius + offset, r, y));
+        }
+    }
+
+    lathe(&profile, segments)
+}
+
+// A sphere made by subdividing an icosahedron. The triangles are almost the same size, unlike
+// `uv_sphere()`. The vertices on the texture seam are duplicated.
+pub fn icosphere(radius: f32, subdivisions: u32) -> Mesh {
+    let t = (1.0 + 5.0f32.sqrt()) / 2.0;
+    let mut positions: Vec<Vector3<f32>> = [
+        [-1.0, t, 0.0], [1.0, t, 0.0], [-1.0, -t, 0.0], [1.0, -t, 0.0],
+        [0.0, -1.0, t], [0.0, 1.0, t], [0.0, -1.0, -t], [0.0, 1.0, -t],
+        [t, 0.0, -1.0], [t, 0.0, 1.0], [-t, 0.0, -1.0], [-t, 0.0, 1.0]
+    ].iter().map(|p| Vector3::new(p[0], p[1], p[2]).normalize()).collect();
+
+    let mut triangles: Vec<[u32; 3]> = vec![
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1]
+    ];
+
+    // Make sure every face points outward
+    for triangle in triangles.iter_mut() {
+        let a = positions[triangle[0] as usize];
+        let b = positions[triangle[1] as usize];
+        let c = positions[triangle[2] as usize];
+        if (b - a).cross(c - a).dot(a + b + c) < 0.0 {
+            triangle.swap(1, 2);
+        }
+    }
+
+    for _ in 0..subdivisions {
+        let mut midpoints: HashMap<(u32, u32), u32> = HashMap::new();
+        let mut next = Vec::with_capacity(triangles.len() * 4);
+
+        for triangle in triangles.iter() {
+            let mut middle = [0; 3];
+            for k in 0..3 {
+                let a = triangle[k];
+                let b = triangle[(k + 1) % 3];
+                let key = if a < b { (a, b) } else { (b, a) };
+
+                middle[k] = *midpoints.entry(key).or_insert_with(|| {
+                    let point = (positions[a as usize] + positions[b as usize]).normalize();
+                    positions.push(point);
+                    positions.len() as u32 - 1
+                });
+            }
+
+            next.push([triangle[0], middle[0], middle[2]]);
+            next.push([triangle[1], middle[1], middle[0]]);
+            next.push([triangle[2], middle[2], middle[1]]);
+            next.push([middle[0], middle[1], middle[2]]);
+        }
+
+        triangles = next;
+    }
+
+    let mut mesh = Mesh::new();
+    for p in positions.iter() {
+        let (u, v) = sphere_tex_coords(*p);
+        let angle = u * 2.0 * PI;
+
+        mesh.positions.push((*p * radius).into());
+        mesh.normals.push((*p).into());
+        mesh.tex_coords.push([u, v]);
+        mesh.tangents.push([-angle.sin(), 0.0, -angle.cos(), 1.0]);
+    }
+
+    // Triangles crossing the seam (u jumps from ~1 to ~0) get copies of their vertices with u + 1
+    let mut seam_copies: HashMap<u32, u32> = HashMap::new();
+    for triangle in triangles.iter_mut() {
+        let us: Vec<f32> = triangle.iter().map(|&i| mesh.tex_coords[i as usize][0]).collect();
+        let max_u = us.iter().cloned().fold(0.0, f32::max);
+
+        for k in 0..3 {
+            if max_u - us[k] > 0.5 {
+                let index = triangle[k];
+                triangle[k] = *seam_copies.entry(index).or_insert_with(|| {
+                    let i = index as usize;
+                    let position = mesh.positions[i];
+                    let normal = mesh.normals[i];
+                    let tex_coords = [mesh.tex_coords[i][0] + 1.0, mesh.tex_coords[i][1]];
+                    let tangent = mesh.tangents[i];
+
+                    mesh.positions.push(position);
+                    mesh.normals.push(normal);
+                    mesh.tex_coords.push(tex_coords);
+                    mesh.tangents.push(tangent);
+                    mesh.positions.len() as u32 - 1
+                });
+            }
+        }
+    }
+
+    for triangle in triangles.iter() {
+        mesh.indices.extend_from_slice(triangle);
+    }
+    mesh
+}
+
+// Same mapping as `uv_sphere()`: the angle from +X toward -Z for u, from the bottom for v
+fn sphere_tex_coords(p: Vector3<f32>) -> (f32, f32) {
+    let mut u = (-p.z).atan2(p.x) / (2.0 * PI);
+    if u < 0.0 {
+        u += 1.0;
+    }
+    let v = p.y.clamp(-1.0, 1.0).asin() / PI + 0.5;
+    (u, v)
+}
+
+// The pyramid drawn by the tutorials, with the texture coordinates of tutorial 16. Each face has
+// its own vertices so the normals stay flat.
+pub fn pyramid() -> Mesh {
+    let positions = [
+        Vector3::new(-1.0, -1.0, 0.5773),
+        Vector3::new(0.0, -1.0, -1.15475),
+        Vector3::new(1.0, -1.0, 0.5773),
+        Vector3::new(0.0, 1.0, 0.0)
+    ];
+    let tex_coords = [
+        Vector2::new(0.0, 0.0),
+        Vector2::new(0.5, 0.0),
+        Vector2::new(1.0, 0.0),
+        Vector2::new(0.5, 1.0)
+    ];
+    let faces = [[0, 3, 1], [1, 3, 2], [2, 3, 0], [0, 1, 2]];
+
+    let mut mesh = Mesh::new();
+    for face in faces.iter() {
+        let (p0, p1, p2) = (positions[face[0]], positions[face[1]], positions[face[2]]);
+        let (t0, t1, t2) = (tex_coords[face[0]], tex_coords[face[1]], tex_coords[face[2]]);
+
+        let normal = (p1 - p0).cross(p2 - p0).normalize();
+        let tangent = triangle_tangent(p0, p1, p2, t0, t1, t2, normal);
+
+        for k in 0..3 {
+            mesh.indices.push(mesh.positions.len() as u32);
+            mesh.positions.push(positions[face[k]].into());
+            mesh.normals.push(normal.into());
+            mesh.tex_coords.push(tex_coords[face[k]].into());
+            mesh.tangents.push(tangent);
+        }
+    }
+    mesh
+}
+
+// The tangent of a flat triangle from its texture coordinates
+fn triangle_tangent(p0: Vector3<f32>, p1: Vector3<f32>, p2: Vector3<f32>, t0: Vector2<f32>,
+        t1: Vector2<f32>, t2: Vector2<f32>, normal: Vector3<f32>) -> [f32; 4] {
+    let edge1 = p1 - p0;
+    let edge2 = p2 - p0;
+    let delta1 = t1 - t0;
+    let delta2 = t2 - t0;
+
+    let r = 1.0 / (delta1.x * delta2.y - delta2.x * delta1.y);
+    let tangent = (edge1 * delta2.y - edge2 * delta1.y) * r;
+    let bitangent = (edge2 * delta1.x - edge1 * delta2.x) * r;
+
+    // Gram-Schmidt orthogonalize
+    let tangent = (tangent - normal * normal.dot(tangent)).normalize();
+    let w = if normal.cross(tangent).dot(bitangent) < 0.0 { -1.0 } else { 1.0 };
+
+    [tangent.x, tangent.y, tangent.z, w]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use cgmath::{InnerSpace, Vector3};
+
+    use mesh::Mesh;
+    use super::*;
+
+    // The point inside the shape closest to a point of its surface
+    type Inside = fn(Vector3<f32>) -> Vector3<f32>;
+
+    // Every closed generator, with a point inside each part of the shape for the winding test
+    fn closed_meshes() -> Vec<(&'static str, Mesh, Inside)> {
+        fn origin(_: Vector3<f32>) -> Vector3<f32> {
+            Vector3::new(0.0, 0.0, 0.0)
+        }
+        // The closest point of the circle running inside the tube, of radius 1
+        fn torus_core(p: Vector3<f32>) -> Vector3<f32> {
+            Vector3::new(p.x, 0.0, p.z).normalize()
+        }
+
+        vec![
+            ("cube", cube(2.0, 3), origin),
+            ("uv_sphere", uv_sphere(1.5, 16, 8), origin),
+            ("cylinder", cylinder(1.0, 2.0, 12, 3), origin),
+            ("cone", cone(1.0, 2.0, 12), origin),
+            ("torus", torus(1.0, 0.25, 16, 8), torus_core),
+            ("capsule", capsule(0.5, 1.0, 12, 4), origin),
+            ("icosphere", icosphere(1.0, 2), origin),
+            ("pyramid", pyramid(), origin)
+        ]
+    }
+
+    fn position(mesh: &Mesh, index: u32) -> Vector3<f32> {
+        Vector3::from(mesh.positions[index as usize])
+    }
+
+    // The vertices at the same place get the same index, ignoring the seams
+    fn weld(mesh: &Mesh) -> Vec<u32> {
+        let mut welded = HashMap::new();
+        let key = |p: [f32; 3]| {
+            let q = |x: f32| (x * 1e4).round() as i64;
+            (q(p[0]), q(p[1]), q(p[2]))
+        };
+        let remap: Vec<u32> = mesh.positions.iter().enumerate().map(|(i, &p)| {
+            *welded.entry(key(p)).or_insert(i as u32)
+        }).collect();
+        mesh.indices.iter().map(|&index| remap[index as usize]).collect()
+    }
+
+    fn check_closed(name: &str, mesh: &Mesh) {
+        let indices = weld(mesh);
+        let mut edges: HashMap<(u32, u32), usize> = HashMap::new();
+        for triangle in indices.chunks(3) {
+            for k in 0..3 {
+                *edges.entry((triangle[k], triangle[(k + 1) % 3])).or_insert(0) += 1;
+            }
+        }
+
+        // Two triangles per edge, going through it in opposite directions
+        for (&(a, b), &count) in edges.iter() {
+            assert_eq!(count, 1, "{}: edge {}-{} used {} times the same way", name, a, b, count);
+            assert_eq!(edges.get(&(b, a)), Some(&1), "{}: edge {}-{} is on a hole", name, a, b);
+        }
+    }
+
+    fn check_winding<F: Fn(Vector3<f32>) -> Vector3<f32>>(name: &str, mesh: &Mesh, inside: F) {
+        for triangle in mesh.indices.chunks(3) {
+            let (a, b, c) = (position(mesh, triangle[0]), position(mesh, triangle[1]),
+                position(mesh, triangle[2]));
+            let center = (a + b + c) / 3.0;
+            let outward = (b - a).cross(c - a).dot(center - inside(center));
+            assert!(outward > 0.0, "{}: triangle {:?} faces inward", name, triangle);
+        }
+    }
+
+    fn check_normals(name: &str, mesh: &Mesh) {
+        assert_eq!(mesh.normals.len(), mesh.positions.len(), "{}", name);
+        for normal in mesh.normals.iter() {
+            assert!((Vector3::from(*normal).magnitude() - 1.0).abs() < 1e-5, "{}: {:?}", name, normal);
+        }
+    }
+
+    #[test]
+    fn closed_shapes_are_watertight() {
+        for (name, mesh, _) in closed_meshes() {
+            assert!(mesh.validate().is_ok(), "{}", name);
+            assert!(mesh.triangle_count() > 0, "{}", name);
+            check_closed(name, &mesh);
+        }
+    }
+
+    #[test]
+    fn closed_shapes_face_outward() {
+        for (name, mesh, inside) in closed_meshes() {
+            check_winding(name, &mesh, inside);
+        }
+    }
+
+    #[test]
+    fn normals_are_unit_vectors() {
+        for (name, mesh, _) in closed_meshes() {
+            check_normals(name, &mesh);
+        }
+        check_normals("plane", &plane(2.0, 3.0, 4, 2));
+    }
+
+    #[test]
+    fn plane_faces_up() {
+        let mesh = plane(2.0, 3.0, 4, 2);
+        assert!(mesh.validate().is_ok());
+        assert_eq!(mesh.triangle_count(), 4 * 2 * 2);
+        check_winding("plane", &mesh, |p| p - Vector3::new(0.0, 1.0, 0.0));
+        for normal in mesh.normals.iter() {
+            assert_eq!(*normal, [0.0, 1.0, 0.0]);
+        }
+    }
+}
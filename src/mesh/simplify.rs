@@ -0,0 +1,554 @@
+// Mesh simplification by edge collapses, driven by quadric error metrics (Garland and Heckbert).
+//
+// Each collapse moves a vertex onto one of its neighbours, so the vertices that stay keep their
+// exact attributes. To keep the look of the mesh:
+// - vertices on a UV (or normal) seam, i.e. sharing their position with another vertex, never
+//   move;
+// - vertices on a border only move along the border, and not at all at its corners;
+// - a collapse flipping a triangle is rejected;
+// - so is a collapse joining two vertices which have other neighbours, or an edge in front of
+//   them, in common than the third corners of the triangles along their edge (the link
+//   condition), since it would fold the surface onto itself.
+
+use std::collections::{HashMap, HashSet};
+
+use cgmath::{InnerSpace, Vector3};
+
+use mesh::Mesh;
+use mesh::optimize;
+
+// Border edges are held in place by planes perpendicular to them, this much stronger than the
+// planes of the faces
+const BORDER_WEIGHT: f64 = 10.0;
+
+// The cosine of the largest angle a triangle may turn by in a collapse
+const MAX_NORMAL_TURN_COS: f64 = 0.25;
+
+// The cosine of the sharpest turn of a border at a vertex which may still move (30 degrees)
+const MIN_BORDER_TURN_COS: f64 = 0.866;
+
+// A symmetric 4x4 matrix measuring the sum of the squared distances to a set of planes
+#[derive(Clone, Copy, Debug, Default)]
+struct Quadric {
+    a: [f64; 10]
+}
+
+impl Quadric {
+    // The plane `n.dot(p) + d = 0`, with `n` of unit length
+    fn from_plane(n: Vector3<f64>, d: f64, weight: f64) -> Quadric {
+        Quadric {
+            a: [
+                n.x * n.x * weight, n.x * n.y * weight, n.x * n.z * weight, n.x * d * weight,
+                n.y * n.y * weight, n.y * n.z * weight, n.y * d * weight,
+                n.z * n.z * weight, n.z * d * weight,
+                d * d * weight
+            ]
+        }
+    }
+
+    fn add(&mut self, other: &Quadric) {
+        for (a, b) in self.a.iter_mut().zip(other.a.iter()) {
+            *a += *b;
+        }
+    }
+
+    fn error(&self, p: Vector3<f64>) -> f64 {
+        let a = &self.a;
+        let error = a[0] * p.x * p.x + 2.0 * a[1] * p.x * p.y + 2.0 * a[2] * p.x * p.z + 2.0 * a[3] * p.x +
+            a[4] * p.y * p.y + 2.0 * a[5] * p.y * p.z + 2.0 * a[6] * p.y +
+            a[7] * p.z * p.z + 2.0 * a[8] * p.z +
+            a[9];
+        error.max(0.0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum VertexKind {
+    Interior,
+    Border,
+    Locked
+}
+
+fn vector(p: [f32; 3]) -> Vector3<f64> {
+    Vector3::new(p[0] as f64, p[1] as f64, p[2] as f64)
+}
+
+fn position_key(p: [f32; 3]) -> [u32; 3] {
+    [p[0].to_bits(), p[1].to_bits(), p[2].to_bits()]
+}
+
+fn edge_key(a: usize, b: usize) -> (usize, usize) {
+    if a < b { (a, b) } else { (b, a) }
+}
+
+struct Simplifier<'a> {
+    positions: &'a [[f32; 3]],
+    // The index of the first vertex at the same position, to see through seams
+    groups: Vec<usize>,
+    kinds: Vec<VertexKind>,
+    border_edges: HashSet<(usize, usize)>,
+    quadrics: Vec<Quadric>,
+    triangles: Vec<[u32; 3]>,
+    // Before any collapse, so that the triangles cannot turn a little at a time either
+    normals: Vec<Vector3<f64>>,
+    alive: Vec<bool>,
+    // The triangles around every vertex, some of them may be dead
+    adjacency: Vec<Vec<usize>>
+}
+
+impl<'a> Simplifier<'a> {
+    fn new(positions: &'a [[f32; 3]], triangles: Vec<[u32; 3]>) -> Simplifier<'a> {
+        let count = positions.len();
+
+        let mut first_at: HashMap<[u32; 3], usize> = HashMap::new();
+        let mut used = vec![false; count];
+        for triangle in &triangles {
+            for &index in triangle {
+                used[index as usize] = true;
+            }
+        }
+        let groups: Vec<usize> = (0..count).map(|vertex| {
+            if used[vertex] {
+                *first_at.entry(position_key(positions[vertex])).or_insert(vertex)
+            } else {
+                vertex
+            }
+        }).collect();
+
+        // How many triangles use every edge, seen through the seams
+        let mut edge_uses: HashMap<(usize, usize), usize> = HashMap::new();
+        for triangle in &triangles {
+            for corner in 0..3 {
+                let a = groups[triangle[corner] as usize];
+                let b = groups[triangle[(corner + 1) % 3] as usize];
+                *edge_uses.entry(edge_key(a, b)).or_insert(0) += 1;
+            }
+        }
+
+        let mut kinds = vec![VertexKind::Interior; count];
+        let mut group_sizes: HashMap<usize, usize> = HashMap::new();
+        for vertex in (0..count).filter(|&vertex| used[vertex]) {
+            *group_sizes.entry(groups[vertex]).or_insert(0) += 1;
+        }
+        let mut border_edges = HashSet::new();
+        for (&(a, b), &uses) in &edge_uses {
+            if uses == 1 {
+                border_edges.insert((a, b));
+            }
+        }
+        for vertex in 0..count {
+            kinds[vertex] = if group_sizes.get(&groups[vertex]).is_some_and(|&size| size > 1) {
+                VertexKind::Locked
+            } else {
+                VertexKind::Interior
+            };
+        }
+        for (&(a, b), &uses) in &edge_uses {
+            for &group in &[a, b] {
+                if uses > 2 {
+                    // Non-manifold edges are left alone
+                    kinds[group] = VertexKind::Locked;
+                } else if uses == 1 && kinds[group] == VertexKind::Interior {
+                    kinds[group] = VertexKind::Border;
+                }
+            }
+        }
+
+        // The corners of the borders stay where they are
+        let mut border_neighbours: HashMap<usize, Vec<usize>> = HashMap::new();
+        for &(a, b) in &border_edges {
+            border_neighbours.entry(a).or_default().push(b);
+            border_neighbours.entry(b).or_default().push(a);
+        }
+        for (&group, neighbours) in &border_neighbours {
+            if kinds[group] != VertexKind::Border {
+                continue;
+            }
+            let straight = neighbours.len() == 2 && {
+                let p = vector(positions[group]);
+                let incoming = p - vector(positions[neighbours[0]]);
+                let outgoing = vector(positions[neighbours[1]]) - p;
+                incoming.dot(outgoing) >= incoming.magnitude() * outgoing.magnitude() * MIN_BORDER_TURN_COS
+            };
+            if !straight {
+                kinds[group] = VertexKind::Locked;
+            }
+        }
+
+        let mut quadrics = vec![Quadric::default(); count];
+        let mut adjacency = vec![Vec::new(); count];
+        let mut normals = Vec::with_capacity(triangles.len());
+        for (t, triangle) in triangles.iter().enumerate() {
+            let p0 = vector(positions[triangle[0] as usize]);
+            let p1 = vector(positions[triangle[1] as usize]);
+            let p2 = vector(positions[triangle[2] as usize]);
+            let normal = (p1 - p0).cross(p2 - p0);
+            let length = normal.magnitude();
+            normals.push(normal);
+
+            if length > 0.0 {
+                let n = normal / length;
+                let face = Quadric::from_plane(n, -n.dot(p0), length * 0.5);
+
+                for corner in 0..3 {
+                    let a = triangle[corner] as usize;
+                    let b = triangle[(corner + 1) % 3] as usize;
+                    quadrics[a].add(&face);
+
+                    if border_edges.contains(&edge_key(groups[a], groups[b])) {
+                        let pa = vector(positions[a]);
+                        let edge = vector(positions[b]) - pa;
+                        let perpendicular = edge.cross(n);
+                        if perpendicular.magnitude2() > 0.0 {
+                            let perpendicular = perpendicular.normalize();
+                            let border = Quadric::from_plane(perpendicular, -perpendicular.dot(pa),
+                                edge.magnitude2() * BORDER_WEIGHT);
+                            quadrics[a].add(&border);
+                            quadrics[b].add(&border);
+                        }
+                    }
+                }
+            }
+
+            for &index in triangle {
+                adjacency[index as usize].push(t);
+            }
+        }
+
+        Simplifier {
+            positions: positions,
+            groups: groups,
+            kinds: kinds,
+            border_edges: border_edges,
+            alive: vec![true; triangles.len()],
+            triangles: triangles,
+            normals: normals,
+            quadrics: quadrics,
+            adjacency: adjacency
+        }
+    }
+
+    fn can_collapse(&self, from: usize, to: usize) -> bool {
+        match self.kinds[from] {
+            VertexKind::Interior => true,
+            VertexKind::Border => self.kinds[to] != VertexKind::Interior &&
+                self.border_edges.contains(&edge_key(self.groups[from], self.groups[to])),
+            VertexKind::Locked => false
+        }
+    }
+
+    fn cost(&self, from: usize, to: usize) -> f64 {
+        let mut quadric = self.quadrics[from];
+        quadric.add(&self.quadrics[to]);
+        quadric.error(vector(self.positions[to]))
+    }
+
+    // Moving `from` onto `to` must not flip, turn too much from their original normal or flatten
+    // the triangles kept around `from`
+    fn flips(&self, from: usize, to: usize) -> bool {
+        let target = vector(self.positions[to]);
+
+        for &t in &self.adjacency[from] {
+            let triangle = self.triangles[t];
+            if !self.alive[t] || triangle.contains(&(to as u32)) {
+                continue;
+            }
+
+            let corners: Vec<Vector3<f64>> = triangle.iter().map(|&index| vector(self.positions[index as usize])).collect();
+            let moved: Vec<Vector3<f64>> = triangle.iter().zip(corners.iter())
+                .map(|(&index, &corner)| if index as usize == from { target } else { corner })
+                .collect();
+
+            let before = (corners[1] - corners[0]).cross(corners[2] - corners[0]);
+            let after = (moved[1] - moved[0]).cross(moved[2] - moved[0]);
+            let original = self.normals[t];
+            let lengths = after.magnitude() * original.magnitude();
+            if after.dot(original) <= lengths * MAX_NORMAL_TURN_COS ||
+                after.magnitude2() <= before.magnitude2() * 1e-6 {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    // The groups of the vertices sharing a live triangle with `vertex`, and of the edges in front
+    // of it in these triangles
+    fn link(&self, vertex: usize) -> (HashSet<usize>, HashSet<(usize, usize)>) {
+        let group = self.groups[vertex];
+        let mut neighbours = HashSet::new();
+        let mut edges = HashSet::new();
+        for &t in &self.adjacency[vertex] {
+            if !self.alive[t] {
+                continue;
+            }
+            let others: Vec<usize> = self.triangles[t].iter()
+                .map(|&index| self.groups[index as usize])
+                .filter(|&other| other != group)
+                .collect();
+            neighbours.extend(others.iter().cloned());
+            if others.len() == 2 {
+                edges.insert(edge_key(others[0], others[1]));
+            }
+        }
+        (neighbours, edges)
+    }
+
+    // Whether `from` and `to` have a neighbour or an edge in front of them in common, other than
+    // the third corners of the triangles along their edge
+    fn breaks_link(&self, from: usize, to: usize) -> bool {
+        let mut corners = HashSet::new();
+        for &t in &self.adjacency[from] {
+            let triangle = self.triangles[t];
+            if self.alive[t] && triangle.contains(&(to as u32)) {
+                corners.extend(triangle.iter().map(|&index| self.groups[index as usize]));
+            }
+        }
+        let (from_neighbours, from_edges) = self.link(from);
+        let (to_neighbours, to_edges) = self.link(to);
+        from_neighbours.intersection(&to_neighbours).any(|group| !corners.contains(group)) ||
+            from_edges.intersection(&to_edges).next().is_some()
+    }
+
+    fn collapse(&mut self, from: usize, to: usize) -> usize {
+        let mut removed = 0;
+        let around = ::std::mem::take(&mut self.adjacency[from]);
+
+        for t in around {
+            if !self.alive[t] {
+                continue;
+            }
+
+            if self.triangles[t].contains(&(to as u32)) {
+                self.alive[t] = false;
+                removed += 1;
+            } else {
+                for index in self.triangles[t].iter_mut() {
+                    if *index as usize == from {
+                        *index = to as u32;
+                    }
+                }
+                self.adjacency[to].push(t);
+            }
+        }
+
+        let quadric = self.quadrics[from];
+        self.quadrics[to].add(&quadric);
+        removed
+    }
+
+    fn run(&mut self, target_triangles: usize) {
+        let mut count = self.alive.iter().filter(|&&alive| alive).count();
+
+        while count > target_triangles {
+            // Every possible collapse, cheapest first
+            let mut candidates: Vec<(f64, usize, usize)> = Vec::new();
+            for (t, triangle) in self.triangles.iter().enumerate() {
+                if !self.alive[t] {
+                    continue;
+                }
+                for corner in 0..3 {
+                    let a = triangle[corner] as usize;
+                    let b = triangle[(corner + 1) % 3] as usize;
+                    for &(from, to) in &[(a, b), (b, a)] {
+                        if self.can_collapse(from, to) {
+                            candidates.push((self.cost(from, to), from, to));
+                        }
+                    }
+                }
+            }
+            candidates.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(::std::cmp::Ordering::Equal));
+
+            // Collapse as many as possible in one pass, but only once around every vertex since
+            // the costs around a collapse are not valid anymore
+            let mut touched = vec![false; self.positions.len()];
+            let mut collapsed = 0;
+            let pass_limit = (count - target_triangles).div_ceil(2).max(1);
+
+            for (_, from, to) in candidates {
+                if count <= target_triangles || collapsed >= pass_limit {
+                    break;
+                }
+                if touched[from] || touched[to] || self.flips(from, to) || self.breaks_link(from, to) {
+                    continue;
+                }
+
+                for &t in &self.adjacency[from] {
+                    for &index in &self.triangles[t] {
+                        touched[index as usize] = true;
+                    }
+                }
+                touched[to] = true;
+
+                count -= self.collapse(from, to);
+                collapsed += 1;
+            }
+
+            if collapsed == 0 {
+                break;
+            }
+        }
+    }
+}
+
+// Simplify a mesh down to about `target_triangles` triangles, or less if that is not possible
+// without breaking its seams and borders. The submeshes are simplified together but their
+// triangles stay in their own submesh.
+pub fn simplify(mesh: &Mesh, target_triangles: usize) -> Mesh {
+    let parts = mesh.parts();
+
+    // Triangles using a vertex twice would only get in the way
+    let mut triangles = Vec::with_capacity(mesh.triangle_count());
+    let mut part_of = Vec::with_capacity(mesh.triangle_count());
+    for (p, part) in parts.iter().enumerate() {
+        for triangle in mesh.indices[part.start..part.start + part.count].chunks(3) {
+            if triangle[0] != triangle[1] && triangle[1] != triangle[2] && triangle[2] != triangle[0] {
+                triangles.push([triangle[0], triangle[1], triangle[2]]);
+                part_of.push(p);
+            }
+        }
+    }
+
+    let mut simplifier = Simplifier::new(&mesh.positions, triangles);
+    simplifier.run(target_triangles);
+
+    let mut result = mesh.clone();
+    result.indices.clear();
+    for (p, _) in parts.iter().enumerate() {
+        let start = result.indices.len();
+        for (t, triangle) in simplifier.triangles.iter().enumerate() {
+            if simplifier.alive[t] && part_of[t] == p {
+                result.indices.extend_from_slice(triangle);
+            }
+        }
+        if !result.submeshes.is_empty() {
+            result.submeshes[p].start = start;
+            result.submeshes[p].count = result.indices.len() - start;
+        }
+    }
+
+    optimize::remove_unused_vertices(&mut result);
+    result
+}
+
+// Simplify a mesh to a fraction of its triangles
+pub fn simplify_ratio(mesh: &Mesh, ratio: f32) -> Mesh {
+    let target = (mesh.triangle_count() as f32 * ratio.clamp(0.0, 1.0)).round() as usize;
+    simplify(mesh, target)
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Vector3};
+
+    use mesh::Mesh;
+    use mesh::primitives;
+    use super::*;
+
+    // The triangles by the positions of their corners, each starting at its smallest corner
+    fn triangles_by_position(mesh: &Mesh) -> Vec<[[u32; 3]; 3]> {
+        let mut triangles: Vec<[[u32; 3]; 3]> = mesh.indices.chunks(3).map(|t| {
+            let corners: Vec<[u32; 3]> = t.iter().map(|&index| position_key(mesh.positions[index as usize])).collect();
+            let first = (0..3).min_by_key(|&k| corners[k]).unwrap();
+            [corners[first], corners[(first + 1) % 3], corners[(first + 2) % 3]]
+        }).collect();
+        triangles.sort();
+        triangles
+    }
+
+    fn normals(mesh: &Mesh) -> Vec<(Vector3<f64>, Vector3<f64>)> {
+        mesh.indices.chunks(3).map(|t| {
+            let p: Vec<Vector3<f64>> = t.iter().map(|&index| vector(mesh.positions[index as usize])).collect();
+            ((p[1] - p[0]).cross(p[2] - p[0]), (p[0] + p[1] + p[2]) / 3.0)
+        }).collect()
+    }
+
+    fn count_at(mesh: &Mesh, position: [f32; 3]) -> usize {
+        mesh.positions.iter().filter(|&&p| p == position).count()
+    }
+
+    #[test]
+    fn target_is_reached() {
+        let sphere = primitives::icosphere(1.0, 3);
+        for &target in &[640, 320, 80] {
+            let simplified = simplify(&sphere, target);
+            assert!(simplified.validate().is_ok());
+            assert!(simplified.triangle_count() <= target && simplified.triangle_count() * 2 > target,
+                "{} triangles for {}", simplified.triangle_count(), target);
+        }
+    }
+
+    #[test]
+    fn triangles_do_not_flip() {
+        let sphere = primitives::icosphere(1.0, 3);
+        let triangles: Vec<[u32; 3]> = sphere.indices.chunks(3).map(|t| [t[0], t[1], t[2]]).collect();
+        for &target in &[640, 320, 80] {
+            let mut simplifier = Simplifier::new(&sphere.positions, triangles.clone());
+            simplifier.run(target);
+
+            for (t, triangle) in simplifier.triangles.iter().enumerate().filter(|&(t, _)| simplifier.alive[t]) {
+                let p: Vec<Vector3<f64>> = triangle.iter()
+                    .map(|&index| vector(sphere.positions[index as usize]))
+                    .collect();
+                let normal = (p[1] - p[0]).cross(p[2] - p[0]);
+                assert!(normal.dot(simplifier.normals[t]) > 0.0, "{:?} was {:?}", normal, simplifier.normals[t]);
+            }
+        }
+    }
+
+    #[test]
+    fn seams_stay_in_place() {
+        let sphere = primitives::uv_sphere(1.0, 16, 8);
+        let simplified = simplify_ratio(&sphere, 0.5);
+        assert!(simplified.triangle_count() < sphere.triangle_count());
+
+        for &position in &sphere.positions {
+            if count_at(&sphere, position) > 1 {
+                assert!(count_at(&simplified, position) > 0, "{:?}", position);
+            }
+        }
+        for (normal, center) in normals(&simplified) {
+            assert!(normal.dot(center) > 0.0, "{:?} at {:?}", normal, center);
+        }
+    }
+
+    #[test]
+    fn border_corners_stay_in_place() {
+        let plane = primitives::plane(2.0, 2.0, 8, 8);
+        let simplified = simplify_ratio(&plane, 0.25);
+        assert!(simplified.triangle_count() < plane.triangle_count());
+
+        let corners: Vec<[f32; 3]> = plane.positions.iter().cloned()
+            .filter(|p| p[0].abs() == 1.0 && p[2].abs() == 1.0)
+            .collect();
+        assert_eq!(corners.len(), 4);
+        for &corner in &corners {
+            assert_eq!(count_at(&simplified, corner), 1, "{:?}", corner);
+        }
+        // The border moves along itself only, so the plane still covers the same square
+        for &p in &simplified.positions {
+            assert!(p[0].abs() <= 1.0 && p[1] == 0.0 && p[2].abs() <= 1.0, "{:?}", p);
+        }
+        for (normal, _) in normals(&simplified) {
+            assert!(normal.y > 0.0, "{:?}", normal);
+        }
+    }
+
+    #[test]
+    fn link_condition_is_kept() {
+        // A tetrahedron cannot lose an edge without folding onto itself
+        let mut tetrahedron = Mesh::new();
+        tetrahedron.positions = vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+        tetrahedron.indices = vec![0, 2, 1, 0, 1, 3, 0, 3, 2, 1, 2, 3];
+        let simplified = simplify(&tetrahedron, 0);
+        assert_eq!(triangles_by_position(&simplified), triangles_by_position(&tetrahedron));
+    }
+
+    #[test]
+    fn full_ratio_is_the_identity() {
+        let sphere = primitives::uv_sphere(1.0, 16, 8);
+        let simplified = simplify_ratio(&sphere, 1.0);
+        assert_eq!(simplified.triangle_count(), sphere.triangle_count());
+        assert_eq!(triangles_by_position(&simplified), triangles_by_position(&sphere));
+    }
+}
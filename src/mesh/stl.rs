@@ -0,0 +1,243 @@
+// A reader and a writer for STL meshes, in ASCII and binary.
+//
+// STL stores independent triangles with one normal per facet, so a loaded mesh has three
+// vertices per triangle and flat normals.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+
+use cgmath::{InnerSpace, Vector3};
+
+use error::LoadError;
+use mesh::Mesh;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StlFormat {
+    Ascii,
+    Binary
+}
+
+pub fn load_stl<P: AsRef<Path>>(path: P) -> Result<Mesh, LoadError> {
+    let mut data = Vec::new();
+    File::open(path)?.read_to_end(&mut data)?;
+    parse_stl(&data)
+}
+
+pub fn read_stl<R: Read>(mut reader: R) -> Result<Mesh, LoadError> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    parse_stl(&data)
+}
+
+// Binary files may also start with "solid", so the size announced by a binary header is checked
+// first.
+fn parse_stl(data: &[u8]) -> Result<Mesh, LoadError> {
+    if data.len() >= 84 {
+        let count = u32::from_le_bytes([data[80], data[81], data[82], data[83]]) as usize;
+        if count.checked_mul(50).and_then(|size| size.checked_add(84)) == Some(data.len()) {
+            return parse_binary(data, count);
+        }
+    }
+
+    if data.starts_with(b"solid") {
+        parse_ascii(&String::from_utf8_lossy(data))
+    } else {
+        Err(LoadError::parse("not an STL file"))
+    }
+}
+
+// Use the normal of the file, unless it is missing (all zeros)
+fn push_facet(mesh: &mut Mesh, normal: [f32; 3], vertices: &[[f32; 3]]) {
+    let normal = if normal == [0.0, 0.0, 0.0] {
+        facet_normal(vertices[0], vertices[1], vertices[2])
+    } else {
+        normal
+    };
+
+    for vertex in vertices {
+        mesh.indices.push(mesh.positions.len() as u32);
+        mesh.positions.push(*vertex);
+        mesh.normals.push(normal);
+    }
+}
+
+fn facet_normal(a: [f32; 3], b: [f32; 3], c: [f32; 3]) -> [f32; 3] {
+    let a = Vector3::from(a);
+    let normal = (Vector3::from(b) - a).cross(Vector3::from(c) - a);
+    if normal.magnitude2() > 0.0 {
+        normal.normalize().into()
+    } else {
+        [0.0, 0.0, 0.0]
+    }
+}
+
+fn parse_binary(data: &[u8], count: usize) -> Result<Mesh, LoadError> {
+    let mut mesh = Mesh::new();
+    let read_vector = |offset: usize| {
+        let value = |i: usize| {
+            let start = offset + i * 4;
+            f32::from_le_bytes([data[start], data[start + 1], data[start + 2], data[start + 3]])
+        };
+        [value(0), value(1), value(2)]
+    };
+
+    for i in 0..count {
+        // Normal, three vertices and a 16 bits attribute which is ignored
+        let offset = 84 + i * 50;
+        let vertices = [read_vector(offset + 12), read_vector(offset + 24), read_vector(offset + 36)];
+        push_facet(&mut mesh, read_vector(offset), &vertices);
+    }
+
+    Ok(mesh)
+}
+
+fn parse_ascii(text: &str) -> Result<Mesh, LoadError> {
+    let mut mesh = Mesh::new();
+    let mut normal = [0.0, 0.0, 0.0];
+    let mut vertices = Vec::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let number = number + 1;
+        let mut tokens = line.split_whitespace();
+        let vector = |tokens: &mut dyn Iterator<Item = &str>| -> Result<[f32; 3], LoadError> {
+            let mut values = [0.0; 3];
+            for value in values.iter_mut() {
+                let token = tokens.next().ok_or_else(|| LoadError::at_line(number, "expected 3 numbers"))?;
+                *value = token.parse().map_err(|_| LoadError::at_line(number, format!("'{}' is not a number", token)))?;
+            }
+            Ok(values)
+        };
+
+        match tokens.next() {
+            Some("facet") => {
+                if tokens.next() != Some("normal") {
+                    return Err(LoadError::at_line(number, "expected 'facet normal'"));
+                }
+                normal = vector(&mut tokens)?;
+                vertices.clear();
+            },
+            Some("vertex") => vertices.push(vector(&mut tokens)?),
+            Some("endfacet") => {
+                if vertices.len() != 3 {
+                    return Err(LoadError::at_line(number, format!("facet with {} vertices", vertices.len())));
+                }
+                push_facet(&mut mesh, normal, &vertices);
+            },
+            // solid, outer loop, endloop, endsolid
+            _ => ()
+        }
+    }
+
+    Ok(mesh)
+}
+
+pub fn save_stl<P: AsRef<Path>>(mesh: &Mesh, path: P, format: StlFormat) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_stl(mesh, &mut writer, format)?;
+    writer.flush()
+}
+
+// Write the triangles of a mesh. The facet normals are computed from the positions, the vertex
+// normals of the mesh are not used.
+pub fn write_stl<W: Write>(mesh: &Mesh, writer: &mut W, format: StlFormat) -> io::Result<()> {
+    let triangles = mesh.indices.chunks(3).map(|triangle| {
+        let a = mesh.positions[triangle[0] as usize];
+        let b = mesh.positions[triangle[1] as usize];
+        let c = mesh.positions[triangle[2] as usize];
+        (facet_normal(a, b, c), [a, b, c])
+    });
+
+    match format {
+        StlFormat::Ascii => {
+            writeln!(writer, "solid ogldev")?;
+            for (normal, vertices) in triangles {
+                writeln!(writer, "  facet normal {} {} {}", normal[0], normal[1], normal[2])?;
+                writeln!(writer, "    outer loop")?;
+                for vertex in &vertices {
+                    writeln!(writer, "      vertex {} {} {}", vertex[0], vertex[1], vertex[2])?;
+                }
+                writeln!(writer, "    endloop")?;
+                writeln!(writer, "  endfacet")?;
+            }
+            writeln!(writer, "endsolid ogldev")?;
+        },
+        StlFormat::Binary => {
+            // The header must not start with "solid"
+            let mut header = [0u8; 80];
+            header[..14].copy_from_slice(b"binary ogldev ");
+            writer.write_all(&header)?;
+            writer.write_all(&(mesh.triangle_count() as u32).to_le_bytes())?;
+
+            for (normal, vertices) in triangles {
+                for value in normal.iter().chain(vertices.iter().flat_map(|vertex| vertex.iter())) {
+                    writer.write_all(&value.to_le_bytes())?;
+                }
+                writer.write_all(&[0, 0])?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use mesh::{primitives, Mesh};
+    use super::{facet_normal, read_stl, write_stl, StlFormat};
+
+    fn round_trip(mesh: &Mesh, format: StlFormat) -> Mesh {
+        let mut data = Vec::new();
+        write_stl(mesh, &mut data, format).unwrap();
+        read_stl(&data[..]).unwrap()
+    }
+
+    // The same triangles, each with three vertices of its own and the facet normal
+    fn check(mesh: &Mesh, loaded: &Mesh) {
+        assert_eq!(loaded.triangle_count(), mesh.triangle_count());
+        assert_eq!(loaded.vertex_count(), mesh.triangle_count() * 3);
+        for (triangle, loaded_triangle) in mesh.indices.chunks(3).zip(loaded.indices.chunks(3)) {
+            let corners: Vec<[f32; 3]> = triangle.iter().map(|&i| mesh.positions[i as usize]).collect();
+            let loaded_corners: Vec<[f32; 3]> = loaded_triangle.iter().map(|&i| loaded.positions[i as usize])
+                .collect();
+            assert_eq!(loaded_corners, corners);
+
+            let normal = facet_normal(corners[0], corners[1], corners[2]);
+            for &i in loaded_triangle {
+                assert_eq!(loaded.normals[i as usize], normal);
+            }
+        }
+    }
+
+    #[test]
+    fn ascii_round_trips() {
+        let mesh = primitives::icosphere(1.0, 1);
+        check(&mesh, &round_trip(&mesh, StlFormat::Ascii));
+    }
+
+    #[test]
+    fn binary_round_trips() {
+        let mesh = primitives::icosphere(1.0, 1);
+        let mut data = Vec::new();
+        write_stl(&mesh, &mut data, StlFormat::Binary).unwrap();
+        assert_eq!(data.len(), 84 + 50 * mesh.triangle_count());
+        assert!(!data.starts_with(b"solid"));
+        check(&mesh, &round_trip(&mesh, StlFormat::Binary));
+    }
+
+    #[test]
+    fn missing_normals_are_computed() {
+        let text = "solid test\nfacet normal 0 0 0\nouter loop\nvertex 0 0 0\nvertex 1 0 0\n\
+            vertex 0 1 0\nendloop\nendfacet\nendsolid test\n";
+        let mesh = read_stl(text.as_bytes()).unwrap();
+        assert_eq!(mesh.normals, vec![[0.0, 0.0, 1.0]; 3]);
+    }
+
+    #[test]
+    fn malformed_facets_are_rejected() {
+        let text = "solid test\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nvertex 1 0 0\nendloop\n\
+            endfacet\nendsolid test\n";
+        assert!(read_stl(text.as_bytes()).is_err());
+        assert!(read_stl(&b"not an stl"[..]).is_err());
+    }
+}
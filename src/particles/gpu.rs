@@ -0,0 +1,335 @@
+// The particles simulated on the GPU. The slots live in two vertex buffers: each update draws one
+// as points through a program which writes the next state of every slot into the other with
+// transform feedback, then the two swap. Nothing is rasterized.
+//
+// Transform feedback writes the outputs named like the fields of `Particle`, which are also the
+// names of the inputs of the vertex shader, so a geometry shader passes the new state along under
+// these names.
+
+use cgmath::{InnerSpace, Vector3};
+use glium::{DrawParameters, Program, Surface, VertexBuffer};
+use glium::backend::Facade;
+use glium::framebuffer::SimpleFrameBuffer;
+use glium::index::{NoIndices, PrimitiveType};
+use glium::program::{ProgramCreationInput, TransformFeedbackMode};
+use glium::texture::Texture2d;
+use glium::vertex::{self, TransformFeedbackSession};
+
+use billboard::{self, BILLBOARD_FRAGMENT_SHADER, BILLBOARD_GEOMETRY_SHADER, BillboardMode, BillboardRenderer};
+use camera::Camera;
+use particles::{Emitter, Particle, ParticleError, ParticleSettings};
+use pipeline::Pipeline;
+use texture::SampledTexture;
+
+// The same rules as `CpuParticles::update`, with a hash of the slot and its generation for the
+// random values
+const UPDATE_VERTEX_SHADER: &str = r#"
+    #version 330
+
+    in vec3 position;
+    in vec3 velocity;
+    in float age;
+    in float lifetime;
+    in float generation;
+
+    out vec3 position0;
+    out vec3 velocity0;
+    out float age0;
+    out float lifetime0;
+    out float generation0;
+
+    uniform float gDeltaTime;
+    uniform float gCycle;
+    uniform bool gEmitting;
+    uniform uint gSeed;
+
+    uniform vec3 gEmitterPos;
+    // 0 for a point, 1 for a sphere, 2 for a cone
+    uniform int gEmitterType;
+    uniform float gSphereRadius;
+    uniform vec3 gConeDirection;
+    uniform float gConeCos;
+
+    uniform vec2 gLifetime;
+    uniform vec2 gSpeed;
+    uniform vec3 gGravity;
+
+    uint state;
+
+    uint Hash(uint x) {
+        x ^= x >> 16;
+        x *= 0x7feb352dU;
+        x ^= x >> 15;
+        x *= 0x846ca68bU;
+        x ^= x >> 16;
+        return x;
+    }
+
+    float Random() {
+        state = Hash(state);
+        return float(state >> 8) / 16777216.0;
+    }
+
+    vec3 RandomUnitVector() {
+        float z = Random() * 2.0 - 1.0;
+        float angle = Random() * 6.28318530718;
+        float r = sqrt(max(1.0 - z * z, 0.0));
+        return vec3(r * cos(angle), r * sin(angle), z);
+    }
+
+    void Sample(out vec3 offset, out vec3 direction) {
+        offset = vec3(0.0);
+        if (gEmitterType == 0) {
+            direction = RandomUnitVector();
+        } else if (gEmitterType == 1) {
+            direction = RandomUnitVector();
+            offset = direction * gSphereRadius * pow(Random(), 1.0 / 3.0);
+        } else {
+            vec3 axis = normalize(gConeDirection);
+            float cosTheta = 1.0 - Random() * (1.0 - gConeCos);
+            float sinTheta = sqrt(max(1.0 - cosTheta * cosTheta, 0.0));
+            float around = Random() * 6.28318530718;
+            vec3 other = abs(axis.x) < 0.9 ? vec3(1.0, 0.0, 0.0) : vec3(0.0, 1.0, 0.0);
+            vec3 u = normalize(cross(axis, other));
+            vec3 v = cross(axis, u);
+            direction = axis * cosTheta + (u * cos(around) + v * sin(around)) * sinTheta;
+        }
+    }
+
+    void main() {
+        position0 = position;
+        velocity0 = velocity;
+        age0 = age + gDeltaTime;
+        lifetime0 = lifetime;
+        generation0 = generation;
+
+        if (age0 >= gCycle) {
+            age0 = mod(age0, gCycle);
+            generation0 += 1.0;
+            if (gEmitting) {
+                state = gSeed ^ Hash(uint(gl_VertexID) ^ Hash(uint(generation0)));
+                vec3 offset;
+                vec3 direction;
+                Sample(offset, direction);
+                float speed = mix(gSpeed.x, gSpeed.y, Random());
+                lifetime0 = mix(gLifetime.x, gLifetime.y, Random());
+
+                // Born `age0` seconds ago, somewhere in the last step
+                velocity0 = direction * speed + gGravity * age0;
+                position0 = gEmitterPos + offset + direction * speed * age0 + gGravity * (0.5 * age0 * age0);
+            } else {
+                lifetime0 = 0.0;
+            }
+        } else {
+            velocity0 += gGravity * gDeltaTime;
+            position0 += velocity0 * gDeltaTime;
+        }
+    }
+"#;
+
+const UPDATE_GEOMETRY_SHADER: &str = r#"
+    #version 330
+
+    layout (points) in;
+    layout (points, max_vertices = 1) out;
+
+    in vec3 position0[];
+    in vec3 velocity0[];
+    in float age0[];
+    in float lifetime0[];
+    in float generation0[];
+
+    out vec3 position;
+    out vec3 velocity;
+    out float age;
+    out float lifetime;
+    out float generation;
+
+    void main() {
+        position = position0[0];
+        velocity = velocity0[0];
+        age = age0[0];
+        lifetime = lifetime0[0];
+        generation = generation0[0];
+        EmitVertex();
+        EndPrimitive();
+    }
+"#;
+
+const UPDATE_FRAGMENT_SHADER: &str = r#"
+    #version 330
+
+    void main() {
+    }
+"#;
+
+// Feeds the billboard shaders, the dead particles get no size
+const RENDER_VERTEX_SHADER: &str = r#"
+    #version 330
+
+    in vec3 position;
+    in float age;
+    in float lifetime;
+
+    uniform vec4 gStartColor;
+    uniform vec4 gEndColor;
+    uniform float gStartSize;
+    uniform float gEndSize;
+
+    out vec2 size0;
+    out vec4 color0;
+
+    void main() {
+        gl_Position = vec4(position, 1.0);
+        if (age < lifetime) {
+            float t = clamp(age / lifetime, 0.0, 1.0);
+            size0 = vec2(mix(gStartSize, gEndSize, t));
+            color0 = mix(gStartColor, gEndColor, t);
+        } else {
+            size0 = vec2(0.0);
+            color0 = vec4(0.0);
+        }
+    }
+"#;
+
+pub struct GpuParticles {
+    settings: ParticleSettings,
+    seed: u32,
+    emitting: bool,
+    // The current state is in `buffers[current]`
+    buffers: [VertexBuffer<Particle>; 2],
+    current: usize,
+    update_program: Program,
+    render_program: Program,
+    // The updates need a framebuffer, even if they draw nothing
+    target: Texture2d
+}
+
+impl GpuParticles {
+    pub fn is_supported<F: Facade>(facade: &F) -> bool {
+        vertex::is_transform_feedback_supported(facade)
+    }
+
+    pub fn new<F: Facade>(facade: &F, settings: ParticleSettings, seed: u32) -> Result<GpuParticles, ParticleError> {
+        if !GpuParticles::is_supported(facade) {
+            return Err(ParticleError::TransformFeedback);
+        }
+
+        let varyings = ["position", "velocity", "age", "lifetime", "generation"].iter()
+            .map(|name| name.to_string())
+            .collect();
+        let update_program = Program::new(facade, ProgramCreationInput::SourceCode {
+            vertex_shader: UPDATE_VERTEX_SHADER,
+            tessellation_control_shader: None,
+            tessellation_evaluation_shader: None,
+            geometry_shader: Some(UPDATE_GEOMETRY_SHADER),
+            fragment_shader: UPDATE_FRAGMENT_SHADER,
+            transform_feedback_varyings: Some((varyings, TransformFeedbackMode::Interleaved)),
+            outputs_srgb: false,
+            uses_point_size: false
+        })?;
+
+        let slots = Particle::slots(&settings);
+        Ok(GpuParticles {
+            settings: settings,
+            seed: seed,
+            emitting: true,
+            buffers: [VertexBuffer::dynamic(facade, &slots)?, VertexBuffer::dynamic(facade, &slots)?],
+            current: 0,
+            update_program: update_program,
+            render_program: Program::from_source(facade, RENDER_VERTEX_SHADER, BILLBOARD_FRAGMENT_SHADER,
+                Some(BILLBOARD_GEOMETRY_SHADER))?,
+            target: Texture2d::empty(facade, 1, 1)?
+        })
+    }
+
+    pub fn settings(&self) -> &ParticleSettings {
+        &self.settings
+    }
+
+    // The current state of the slots
+    pub fn buffer(&self) -> &VertexBuffer<Particle> {
+        &self.buffers[self.current]
+    }
+
+    pub fn set_position(&mut self, position: Vector3<f32>) {
+        self.settings.position = position;
+    }
+
+    pub fn set_emitting(&mut self, emitting: bool) {
+        self.emitting = emitting;
+    }
+
+    pub fn update<F: Facade>(&mut self, facade: &F, dt: f32) -> Result<(), ParticleError> {
+        let settings = &self.settings;
+        let (emitter_type, radius, cone_direction, cone_cos) = match settings.emitter {
+            Emitter::Point => (0, 0.0, Vector3::new(0.0, 1.0, 0.0), 1.0),
+            Emitter::Sphere { radius } => (1, radius, Vector3::new(0.0, 1.0, 0.0), 1.0),
+            Emitter::Cone { direction, angle } => (2, 0.0, direction.normalize(), angle.to_radians().cos())
+        };
+        let emitter_pos: [f32; 3] = settings.position.into();
+        let cone_direction: [f32; 3] = cone_direction.into();
+        let gravity: [f32; 3] = settings.gravity.into();
+        let uniforms = uniform! {
+            gDeltaTime: dt,
+            gCycle: settings.cycle(),
+            gEmitting: self.emitting,
+            gSeed: self.seed,
+            gEmitterPos: emitter_pos,
+            gEmitterType: emitter_type,
+            gSphereRadius: radius,
+            gConeDirection: cone_direction,
+            gConeCos: cone_cos,
+            gLifetime: [settings.min_lifetime, settings.max_lifetime],
+            gSpeed: [settings.min_speed, settings.max_speed],
+            gGravity: gravity
+        };
+
+        {
+            let (first, second) = self.buffers.split_at_mut(1);
+            let (source, destination) = if self.current == 0 {
+                (&first[0], &mut second[0])
+            } else {
+                (&second[0], &mut first[0])
+            };
+
+            let session = TransformFeedbackSession::new(facade, &self.update_program, destination)
+                .map_err(|_| ParticleError::TransformFeedback)?;
+            let params = DrawParameters {
+                draw_primitives: false,
+                transform_feedback: Some(&session),
+                .. Default::default()
+            };
+
+            let mut framebuffer = SimpleFrameBuffer::new(facade, &self.target)?;
+            framebuffer.draw(source, NoIndices(PrimitiveType::Points), &self.update_program, &uniforms, &params)?;
+        }
+
+        self.current = 1 - self.current;
+        Ok(())
+    }
+
+    // Draw the living particles in the order of their slots, as spherical billboards
+    pub fn draw<S: Surface>(&self, surface: &mut S, renderer: &BillboardRenderer, pipeline: &mut Pipeline,
+            camera: &Camera, texture: SampledTexture<'_>) -> Result<(), ParticleError> {
+        let (right, up) = billboard::billboard_axes(BillboardMode::Spherical, camera);
+        let view_projection: [[f32; 4]; 4] = (pipeline.get_project_trans() * pipeline.get_view_trans()).into();
+        let right: [f32; 3] = right.into();
+        let up: [f32; 3] = up.into();
+        let uniforms = uniform! {
+            gVP: view_projection,
+            gRight: right,
+            gUp: up,
+            gTexture: texture,
+            gAlphaCutoff: renderer.alpha_cutoff,
+            gStartColor: self.settings.start_color,
+            gEndColor: self.settings.end_color,
+            gStartSize: self.settings.start_size,
+            gEndSize: self.settings.end_size
+        };
+
+        surface.draw(self.buffer(), NoIndices(PrimitiveType::Points), &self.render_program, &uniforms,
+            &renderer.draw_parameters())?;
+        Ok(())
+    }
+}
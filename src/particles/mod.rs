@@ -0,0 +1,565 @@
+// Particle systems: fire, smoke, sparks... Each particle is born at an emitter, falls with the
+// gravity, changes its color and size as it ages and dies at the end of its lifetime. They are
+// drawn as billboards.
+//
+// A system has a fixed number of slots, `rate` x the longest lifetime. Slot i gives birth to a
+// particle at i / rate seconds and then once per cycle of `capacity / rate` seconds, so particles
+// are emitted at a steady rate without counting the living ones.
+//
+// The same rules run on the CPU, with `CpuParticles`, or on the GPU, with `GpuParticles` and
+// transform feedback. The CPU path is deterministic for a given seed and needs no context, the
+// GPU path keeps the particles in video memory. `ParticleSystem` hides which one is used.
+
+use std::f32::consts::PI;
+
+use cgmath::{InnerSpace, Vector3};
+use glium::{DrawError, ProgramCreationError, Surface};
+use glium::backend::Facade;
+use glium::framebuffer::ValidationError;
+use glium::texture::TextureCreationError;
+use glium::vertex::BufferCreationError;
+
+use billboard::{Billboard, BillboardBatch, BillboardError, BillboardMode, BillboardRenderer};
+use camera::Camera;
+use pipeline::Pipeline;
+use texture::SampledTexture;
+
+pub use self::gpu::GpuParticles;
+
+mod gpu;
+
+#[derive(Debug)]
+pub enum ParticleError {
+    Buffer(BufferCreationError),
+    Program(ProgramCreationError),
+    Texture(TextureCreationError),
+    Framebuffer(ValidationError),
+    // Transform feedback is not supported, or the program does not write the particles
+    TransformFeedback,
+    Draw(DrawError),
+    Billboard(BillboardError)
+}
+
+impl From<BufferCreationError> for ParticleError {
+    fn from(err: BufferCreationError) -> ParticleError {
+        ParticleError::Buffer(err)
+    }
+}
+
+impl From<ProgramCreationError> for ParticleError {
+    fn from(err: ProgramCreationError) -> ParticleError {
+        ParticleError::Program(err)
+    }
+}
+
+impl From<TextureCreationError> for ParticleError {
+    fn from(err: TextureCreationError) -> ParticleError {
+        ParticleError::Texture(err)
+    }
+}
+
+impl From<ValidationError> for ParticleError {
+    fn from(err: ValidationError) -> ParticleError {
+        ParticleError::Framebuffer(err)
+    }
+}
+
+impl From<DrawError> for ParticleError {
+    fn from(err: DrawError) -> ParticleError {
+        ParticleError::Draw(err)
+    }
+}
+
+impl From<BillboardError> for ParticleError {
+    fn from(err: BillboardError) -> ParticleError {
+        ParticleError::Billboard(err)
+    }
+}
+
+// Where the particles are born and where they go
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Emitter {
+    // From one point, in every direction
+    Point,
+    // From anywhere inside a sphere, away from its center
+    Sphere {
+        radius: f32
+    },
+    // From one point, in the directions at most `angle` degrees away from `direction`
+    Cone {
+        direction: Vector3<f32>,
+        angle: f32
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ParticleSettings {
+    pub emitter: Emitter,
+    pub position: Vector3<f32>,
+    // Particles per second
+    pub rate: f32,
+    // In seconds, each particle gets a lifetime between the two
+    pub min_lifetime: f32,
+    pub max_lifetime: f32,
+    // In units per second
+    pub min_speed: f32,
+    pub max_speed: f32,
+    // An acceleration, in units per second squared
+    pub gravity: Vector3<f32>,
+    // From the birth to the death of a particle, the color and the size go from start to end
+    pub start_color: [f32; 4],
+    pub end_color: [f32; 4],
+    pub start_size: f32,
+    pub end_size: f32
+}
+
+impl Default for ParticleSettings {
+    fn default() -> ParticleSettings {
+        ParticleSettings {
+            emitter: Emitter::Point,
+            position: Vector3::new(0.0, 0.0, 0.0),
+            rate: 100.0,
+            min_lifetime: 1.0,
+            max_lifetime: 2.0,
+            min_speed: 1.0,
+            max_speed: 2.0,
+            gravity: Vector3::new(0.0, -9.81, 0.0),
+            start_color: [1.0, 1.0, 1.0, 1.0],
+            end_color: [1.0, 1.0, 1.0, 0.0],
+            start_size: 0.2,
+            end_size: 0.2
+        }
+    }
+}
+
+impl ParticleSettings {
+    // The number of slots, enough for every particle to live its whole lifetime
+    pub fn capacity(&self) -> usize {
+        (self.rate * self.max_lifetime.max(self.min_lifetime)).ceil().max(1.0) as usize
+    }
+
+    // How long a slot waits between two births
+    pub fn cycle(&self) -> f32 {
+        self.capacity() as f32 / self.rate
+    }
+
+    // The color and the size of a particle at `t`, from 0 at its birth to 1 at its death
+    pub fn color_at(&self, t: f32) -> [f32; 4] {
+        let t = t.clamp(0.0, 1.0);
+        let mut color = [0.0; 4];
+        for (i, channel) in color.iter_mut().enumerate() {
+            *channel = self.start_color[i] + (self.end_color[i] - self.start_color[i]) * t;
+        }
+        color
+    }
+
+    pub fn size_at(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        self.start_size + (self.end_size - self.start_size) * t
+    }
+}
+
+// A slot, also the vertex of the transform feedback
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Particle {
+    pub position: [f32; 3],
+    pub velocity: [f32; 3],
+    // The time since the last birth of the slot
+    pub age: f32,
+    // 0 until the first birth, and when the emitter is off
+    pub lifetime: f32,
+    // The number of births of the slot, which seeds the random values of the next one
+    pub generation: f32
+}
+
+implement_vertex!(Particle, position, velocity, age, lifetime, generation);
+
+impl Particle {
+    // The slots of `settings`, each waiting for its first birth
+    pub fn slots(settings: &ParticleSettings) -> Vec<Particle> {
+        let cycle = settings.cycle();
+        (0..settings.capacity()).map(|i| Particle {
+            position: settings.position.into(),
+            velocity: [0.0; 3],
+            age: cycle - i as f32 / settings.rate,
+            lifetime: 0.0,
+            generation: 0.0
+        }).collect()
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.age < self.lifetime
+    }
+
+    // From 0 at the birth to 1 at the death
+    pub fn life_fraction(&self) -> f32 {
+        if self.lifetime > 0.0 { self.age / self.lifetime } else { 1.0 }
+    }
+
+    pub fn billboard(&self, settings: &ParticleSettings) -> Billboard {
+        let t = self.life_fraction();
+        let size = settings.size_at(t);
+        Billboard {
+            position: self.position,
+            size: [size, size],
+            color: settings.color_at(t)
+        }
+    }
+}
+
+// A xorshift generator, small and the same everywhere
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Random {
+    state: u32
+}
+
+impl Random {
+    pub fn new(seed: u32) -> Random {
+        // Xorshift never leaves 0
+        Random { state: if seed == 0 { 0x9e37_79b9 } else { seed } }
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        x
+    }
+
+    // In [0, 1)
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1 << 24) as f32
+    }
+
+    pub fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+
+    // Uniformly distributed on the unit sphere
+    pub fn unit_vector(&mut self) -> Vector3<f32> {
+        let z = self.next_f32() * 2.0 - 1.0;
+        let angle = self.next_f32() * 2.0 * PI;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        Vector3::new(r * angle.cos(), r * angle.sin(), z)
+    }
+}
+
+// Two directions perpendicular to `axis` and to each other
+fn perpendiculars(axis: Vector3<f32>) -> (Vector3<f32>, Vector3<f32>) {
+    let other = if axis.x.abs() < 0.9 { Vector3::new(1.0, 0.0, 0.0) } else { Vector3::new(0.0, 1.0, 0.0) };
+    let u = axis.cross(other).normalize();
+    (u, axis.cross(u))
+}
+
+impl Emitter {
+    // The offset from the position of the emitter and the direction of a new particle
+    pub fn sample(&self, random: &mut Random) -> (Vector3<f32>, Vector3<f32>) {
+        match *self {
+            Emitter::Point => (Vector3::new(0.0, 0.0, 0.0), random.unit_vector()),
+            Emitter::Sphere { radius } => {
+                let direction = random.unit_vector();
+                (direction * (radius * random.next_f32().cbrt()), direction)
+            },
+            Emitter::Cone { direction, angle } => {
+                // Uniformly distributed on the cap of the sphere inside the cone
+                let axis = direction.normalize();
+                let cos = 1.0 - random.next_f32() * (1.0 - angle.to_radians().cos());
+                let sin = (1.0 - cos * cos).max(0.0).sqrt();
+                let around = random.next_f32() * 2.0 * PI;
+                let (u, v) = perpendiculars(axis);
+                (Vector3::new(0.0, 0.0, 0.0), axis * cos + (u * around.cos() + v * around.sin()) * sin)
+            }
+        }
+    }
+}
+
+// The simulation on the CPU
+pub struct CpuParticles {
+    settings: ParticleSettings,
+    particles: Vec<Particle>,
+    random: Random,
+    emitting: bool
+}
+
+impl CpuParticles {
+    pub fn new(settings: ParticleSettings, seed: u32) -> CpuParticles {
+        CpuParticles {
+            particles: Particle::slots(&settings),
+            settings: settings,
+            random: Random::new(seed),
+            emitting: true
+        }
+    }
+
+    pub fn settings(&self) -> &ParticleSettings {
+        &self.settings
+    }
+
+    // Every slot, alive or not
+    pub fn particles(&self) -> &[Particle] {
+        &self.particles
+    }
+
+    pub fn alive_count(&self) -> usize {
+        self.particles.iter().filter(|particle| particle.is_alive()).count()
+    }
+
+    pub fn set_position(&mut self, position: Vector3<f32>) {
+        self.settings.position = position;
+    }
+
+    // The particles already born keep living
+    pub fn set_emitting(&mut self, emitting: bool) {
+        self.emitting = emitting;
+    }
+
+    // Move the simulation `dt` seconds forward. The slots are updated in order, so that the
+    // random values only depend on the seed and the steps.
+    pub fn update(&mut self, dt: f32) {
+        let settings = self.settings;
+        let cycle = settings.cycle();
+        let gravity = settings.gravity;
+
+        for particle in &mut self.particles {
+            let mut velocity = Vector3::from(particle.velocity);
+            let mut position = Vector3::from(particle.position);
+            particle.age += dt;
+
+            if particle.age >= cycle {
+                particle.age %= cycle;
+                particle.generation += 1.0;
+                if self.emitting {
+                    let (offset, direction) = settings.emitter.sample(&mut self.random);
+                    let speed = self.random.range(settings.min_speed, settings.max_speed);
+                    particle.lifetime = self.random.range(settings.min_lifetime, settings.max_lifetime);
+
+                    // Born `age` seconds ago, somewhere in the last step
+                    let age = particle.age;
+                    velocity = direction * speed + gravity * age;
+                    position = settings.position + offset + direction * (speed * age) + gravity * (0.5 * age * age);
+                } else {
+                    particle.lifetime = 0.0;
+                }
+            } else {
+                velocity += gravity * dt;
+                position += velocity * dt;
+            }
+
+            particle.velocity = velocity.into();
+            particle.position = position.into();
+        }
+    }
+
+    // Replace the billboards of `batch` by the living particles
+    pub fn fill_batch(&self, batch: &mut BillboardBatch) {
+        batch.clear();
+        for particle in self.particles.iter().filter(|particle| particle.is_alive()) {
+            batch.push(particle.billboard(&self.settings));
+        }
+    }
+}
+
+enum Backend {
+    // Boxed, the vertex buffers and programs are large
+    Cpu(CpuParticles, Box<BillboardBatch>),
+    Gpu(Box<GpuParticles>)
+}
+
+// A particle system simulated on the GPU when transform feedback is available, on the CPU
+// otherwise. The CPU particles are sorted for blending, the GPU ones are not, which only
+// matters with alpha blending.
+pub struct ParticleSystem {
+    backend: Backend
+}
+
+impl ParticleSystem {
+    pub fn new<F: Facade>(facade: &F, settings: ParticleSettings, seed: u32) -> Result<ParticleSystem, ParticleError> {
+        if GpuParticles::is_supported(facade) {
+            ParticleSystem::gpu(facade, settings, seed)
+        } else {
+            Ok(ParticleSystem::cpu(settings, seed))
+        }
+    }
+
+    pub fn cpu(settings: ParticleSettings, seed: u32) -> ParticleSystem {
+        ParticleSystem {
+            backend: Backend::Cpu(CpuParticles::new(settings, seed), Box::new(BillboardBatch::new()))
+        }
+    }
+
+    pub fn gpu<F: Facade>(facade: &F, settings: ParticleSettings, seed: u32) -> Result<ParticleSystem, ParticleError> {
+        Ok(ParticleSystem {
+            backend: Backend::Gpu(Box::new(GpuParticles::new(facade, settings, seed)?))
+        })
+    }
+
+    pub fn is_gpu(&self) -> bool {
+        match self.backend {
+            Backend::Cpu(..) => false,
+            Backend::Gpu(_) => true
+        }
+    }
+
+    pub fn settings(&self) -> &ParticleSettings {
+        match self.backend {
+            Backend::Cpu(ref particles, _) => particles.settings(),
+            Backend::Gpu(ref particles) => particles.settings()
+        }
+    }
+
+    pub fn set_position(&mut self, position: Vector3<f32>) {
+        match self.backend {
+            Backend::Cpu(ref mut particles, _) => particles.set_position(position),
+            Backend::Gpu(ref mut particles) => particles.set_position(position)
+        }
+    }
+
+    pub fn set_emitting(&mut self, emitting: bool) {
+        match self.backend {
+            Backend::Cpu(ref mut particles, _) => particles.set_emitting(emitting),
+            Backend::Gpu(ref mut particles) => particles.set_emitting(emitting)
+        }
+    }
+
+    pub fn update<F: Facade>(&mut self, facade: &F, dt: f32) -> Result<(), ParticleError> {
+        match self.backend {
+            Backend::Cpu(ref mut particles, _) => {
+                particles.update(dt);
+                Ok(())
+            },
+            Backend::Gpu(ref mut particles) => particles.update(facade, dt)
+        }
+    }
+
+    // Draw the living particles as spherical billboards, with the blending of `renderer`
+    pub fn draw<F: Facade, S: Surface>(&mut self, facade: &F, surface: &mut S, renderer: &BillboardRenderer,
+            pipeline: &mut Pipeline, camera: &Camera, texture: SampledTexture<'_>) -> Result<(), ParticleError> {
+        match self.backend {
+            Backend::Cpu(ref particles, ref mut batch) => {
+                particles.fill_batch(batch);
+                batch.draw(facade, surface, renderer, pipeline, BillboardMode::Spherical, camera, texture)?;
+            },
+            Backend::Gpu(ref particles) => {
+                particles.draw(surface, renderer, pipeline, camera, texture)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Vector3};
+
+    use super::*;
+
+    const STEP: f32 = 1.0 / 60.0;
+
+    fn run(particles: &mut CpuParticles, seconds: f32) {
+        for _ in 0..(seconds / STEP).round() as usize {
+            particles.update(STEP);
+        }
+    }
+
+    #[test]
+    fn same_seed_same_particles() {
+        let settings = ParticleSettings {
+            emitter: Emitter::Sphere { radius: 0.5 },
+            .. ParticleSettings::default()
+        };
+        let (mut first, mut second, mut other) =
+            (CpuParticles::new(settings, 7), CpuParticles::new(settings, 7), CpuParticles::new(settings, 8));
+        run(&mut first, 3.0);
+        run(&mut second, 3.0);
+        run(&mut other, 3.0);
+        assert_eq!(first.particles(), second.particles());
+        assert!(first.particles() != other.particles());
+    }
+
+    #[test]
+    fn alive_count_reaches_rate_times_lifetime() {
+        let settings = ParticleSettings::default();
+        let mut particles = CpuParticles::new(settings, 1);
+        run(&mut particles, 5.0);
+
+        // Averaged over a few seconds: 100 particles per second living 1.5 seconds on average
+        let mut total = 0;
+        let steps = 180;
+        for _ in 0..steps {
+            particles.update(STEP);
+            total += particles.alive_count();
+        }
+        let average = total as f32 / steps as f32;
+        assert!((average - 150.0).abs() < 10.0, "{} alive", average);
+        assert!(particles.alive_count() <= settings.capacity());
+    }
+
+    #[test]
+    fn stopping_drains_the_particles() {
+        let mut particles = CpuParticles::new(ParticleSettings::default(), 3);
+        run(&mut particles, 3.0);
+        assert!(particles.alive_count() > 0);
+
+        particles.set_emitting(false);
+        run(&mut particles, 1.0);
+        assert!(particles.alive_count() > 0, "the living particles keep living");
+        run(&mut particles, 1.1);
+        assert_eq!(particles.alive_count(), 0);
+
+        // And they come back
+        particles.set_emitting(true);
+        run(&mut particles, 1.0);
+        assert!(particles.alive_count() > 0);
+    }
+
+    #[test]
+    fn cones_stay_inside_their_angle() {
+        let direction = Vector3::new(1.0, 2.0, -0.5);
+        for &angle in &[0.0, 10.0, 45.0, 90.0, 170.0] {
+            let emitter = Emitter::Cone { direction: direction, angle: angle };
+            let mut random = Random::new(11);
+            let mut widest = 0.0f32;
+            for _ in 0..5000 {
+                let (offset, sample) = emitter.sample(&mut random);
+                assert_eq!(offset, Vector3::new(0.0, 0.0, 0.0));
+                assert!((sample.magnitude() - 1.0).abs() < 1e-4);
+                let degrees = sample.dot(direction.normalize()).clamp(-1.0, 1.0).acos().to_degrees();
+                assert!(degrees <= angle + 0.1, "{} degrees in a {} degrees cone", degrees, angle);
+                widest = widest.max(degrees);
+            }
+            // The samples reach the edge
+            assert!(widest > angle * 0.95, "at most {} degrees in a {} degrees cone", widest, angle);
+        }
+    }
+
+    #[test]
+    fn cone_particles_fly_inside_the_cone() {
+        let settings = ParticleSettings {
+            emitter: Emitter::Cone { direction: Vector3::new(0.0, 1.0, 0.0), angle: 20.0 },
+            gravity: Vector3::new(0.0, 0.0, 0.0),
+            .. ParticleSettings::default()
+        };
+        let mut particles = CpuParticles::new(settings, 5);
+        run(&mut particles, 3.0);
+        for particle in particles.particles().iter().filter(|particle| particle.is_alive()) {
+            let velocity = Vector3::from(particle.velocity).normalize();
+            assert!(velocity.y >= 20f32.to_radians().cos() - 1e-4, "{:?}", velocity);
+        }
+    }
+
+    #[test]
+    fn colors_and_sizes_are_clamped() {
+        let settings = ParticleSettings {
+            start_size: 1.0,
+            end_size: 3.0,
+            .. ParticleSettings::default()
+        };
+        assert_eq!(settings.size_at(0.5), 2.0);
+        assert_eq!(settings.size_at(-1.0), 1.0);
+        assert_eq!(settings.size_at(2.0), 3.0);
+        assert_eq!(settings.color_at(0.5), [1.0, 1.0, 1.0, 0.5]);
+        assert_eq!(settings.color_at(7.0), settings.end_color);
+    }
+}
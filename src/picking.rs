@@ -0,0 +1,244 @@
+// Picking: which object, and which triangle of it, is under a pixel. The objects are rendered
+// into an integer texture holding their index and the index of their triangles instead of
+// colors, then the texel under the mouse is read back.
+//
+// Unlike casting a ray against the meshes, this works for anything the GPU draws, and the answer
+// is exactly what is seen.
+
+use cgmath::Matrix4;
+use glium::{Depth, DepthTest, DrawError, DrawParameters, Program, ProgramCreationError, Rect, Surface};
+use glium::backend::Facade;
+use glium::framebuffer::{DepthRenderBuffer, SimpleFrameBuffer, ValidationError};
+use glium::texture::{DepthFormat, MipmapsOption, TextureCreationError, UncompressedUintFormat, UnsignedTexture2d};
+
+use mesh::GpuMesh;
+
+const VERTEX_SHADER: &str = r#"
+    #version 330
+
+    in vec3 position;
+
+    uniform mat4 gWVP;
+
+    void main() {
+        gl_Position = gWVP * vec4(position, 1.0);
+    }
+"#;
+
+// The triangles are numbered in the order of the index buffer
+const FRAGMENT_SHADER: &str = r#"
+    #version 330
+
+    uniform uint gObjectId;
+
+    out uvec2 id;
+
+    void main() {
+        id = uvec2(gObjectId, uint(gl_PrimitiveID));
+    }
+"#;
+
+#[derive(Debug)]
+pub enum PickingError {
+    Texture(TextureCreationError),
+    Program(ProgramCreationError),
+    Framebuffer(ValidationError),
+    Draw(DrawError),
+    // More objects than the IDs can number
+    TooManyObjects
+}
+
+impl From<TextureCreationError> for PickingError {
+    fn from(err: TextureCreationError) -> PickingError {
+        PickingError::Texture(err)
+    }
+}
+
+impl From<ProgramCreationError> for PickingError {
+    fn from(err: ProgramCreationError) -> PickingError {
+        PickingError::Program(err)
+    }
+}
+
+impl From<ValidationError> for PickingError {
+    fn from(err: ValidationError) -> PickingError {
+        PickingError::Framebuffer(err)
+    }
+}
+
+impl From<DrawError> for PickingError {
+    fn from(err: DrawError) -> PickingError {
+        PickingError::Draw(err)
+    }
+}
+
+// What is under a pixel: the index of the object in the slice given to `PickingTexture::render`
+// and the index of the triangle in its index buffer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PickId {
+    pub object: usize,
+    pub primitive: u32
+}
+
+impl PickId {
+    // The texel written for this triangle. The objects are numbered from 1, 0 means nothing.
+    pub fn encode(&self) -> Result<(u32, u32), PickingError> {
+        Ok((encode_object(self.object)?, self.primitive))
+    }
+
+    // None for a texel where nothing was drawn
+    pub fn decode(texel: (u32, u32)) -> Option<PickId> {
+        if texel.0 == 0 {
+            return None;
+        }
+
+        Some(PickId {
+            object: (texel.0 - 1) as usize,
+            primitive: texel.1
+        })
+    }
+}
+
+// The value of `gObjectId` for the object at `index`
+pub fn encode_object(index: usize) -> Result<u32, PickingError> {
+    if index >= u32::MAX as usize {
+        return Err(PickingError::TooManyObjects);
+    }
+    Ok(index as u32 + 1)
+}
+
+// The texel under the window coordinates of the mouse, which start at the top left corner, or
+// None outside of the texture
+pub fn window_to_texel(x: i32, y: i32, width: u32, height: u32) -> Option<(u32, u32)> {
+    if x < 0 || y < 0 || x as u32 >= width || y as u32 >= height {
+        return None;
+    }
+    Some((x as u32, height - 1 - y as u32))
+}
+
+// The IDs of the last rendering, as large as the window
+pub struct PickingTexture {
+    texture: UnsignedTexture2d,
+    depth: DepthRenderBuffer,
+    program: Program
+}
+
+impl PickingTexture {
+    pub fn new<F: Facade>(facade: &F, width: u32, height: u32) -> Result<PickingTexture, PickingError> {
+        Ok(PickingTexture {
+            texture: UnsignedTexture2d::empty_with_format(facade, UncompressedUintFormat::U32U32,
+                MipmapsOption::NoMipmap, width, height)?,
+            // A render buffer can only fail for an unsupported format
+            depth: DepthRenderBuffer::new(facade, DepthFormat::I24, width, height)
+                .map_err(|_| TextureCreationError::FormatNotSupported)?,
+            program: Program::from_source(facade, VERTEX_SHADER, FRAGMENT_SHADER, None)?
+        })
+    }
+
+    pub fn texture(&self) -> &UnsignedTexture2d {
+        &self.texture
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.texture.dimensions()
+    }
+
+    // Render the IDs of the objects, each with its world matrix, seen through `view_projection`.
+    // The objects which are culled when they are drawn should be culled here too, with `params`.
+    pub fn render<F: Facade>(&mut self, facade: &F, view_projection: Matrix4<f32>,
+            objects: &[(&GpuMesh, Matrix4<f32>)], params: &DrawParameters) -> Result<(), PickingError> {
+        self.texture.main_level().first_layer().into_image(None).unwrap().raw_clear_buffer([0u32; 4]);
+
+        let mut framebuffer = SimpleFrameBuffer::with_depth_buffer(facade, &self.texture, &self.depth)?;
+        framebuffer.clear_depth(1.0);
+
+        let params = DrawParameters {
+            depth: Depth {
+                test: DepthTest::IfLess,
+                write: true,
+                .. Default::default()
+            },
+            .. params.clone()
+        };
+
+        for (index, &(mesh, world)) in objects.iter().enumerate() {
+            let wvp: [[f32; 4]; 4] = (view_projection * world).into();
+            let uniforms = uniform! {
+                gWVP: wvp,
+                gObjectId: encode_object(index)?
+            };
+            mesh.draw(&mut framebuffer, &self.program, &uniforms, &params)?;
+        }
+        Ok(())
+    }
+
+    // What is under the window coordinates of the mouse, e.g. the last ones given to
+    // `Camera::on_mouse`
+    pub fn pick(&self, x: i32, y: i32) -> Option<PickId> {
+        let (width, height) = self.dimensions();
+        let (x, y) = window_to_texel(x, y, width, height)?;
+
+        let rect = Rect {
+            left: x,
+            bottom: y,
+            width: 1,
+            height: 1
+        };
+        let image = self.texture.main_level().first_layer().into_image(None).unwrap();
+        let texels: Vec<Vec<(u32, u32)>> = image.raw_read(&rect);
+        texels.first().and_then(|row| row.first()).and_then(|&texel| PickId::decode(texel))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_round_trip() {
+        for &object in &[0, 1, 2, 1000, u32::MAX as usize - 1] {
+            for &primitive in &[0, 1, 12345, u32::MAX] {
+                let id = PickId { object: object, primitive: primitive };
+                let texel = id.encode().unwrap();
+                assert!(texel.0 != 0, "{:?}", id);
+                assert_eq!(PickId::decode(texel), Some(id));
+            }
+        }
+        assert_eq!(PickId { object: 0, primitive: 7 }.encode().unwrap(), (1, 7));
+    }
+
+    #[test]
+    fn nothing_is_object_zero() {
+        for &primitive in &[0, 5, u32::MAX] {
+            assert_eq!(PickId::decode((0, primitive)), None);
+        }
+    }
+
+    #[test]
+    fn too_many_objects() {
+        assert_eq!(encode_object(u32::MAX as usize - 1).unwrap(), u32::MAX);
+        assert!(matches!(encode_object(u32::MAX as usize), Err(PickingError::TooManyObjects)));
+        let id = PickId { object: usize::MAX, primitive: 0 };
+        assert!(matches!(id.encode(), Err(PickingError::TooManyObjects)));
+    }
+
+    #[test]
+    fn window_corners_flip_to_texels() {
+        let (width, height) = (640, 480);
+        assert_eq!(window_to_texel(0, 0, width, height), Some((0, 479)));
+        assert_eq!(window_to_texel(639, 0, width, height), Some((639, 479)));
+        assert_eq!(window_to_texel(0, 479, width, height), Some((0, 0)));
+        assert_eq!(window_to_texel(639, 479, width, height), Some((639, 0)));
+        assert_eq!(window_to_texel(100, 200, width, height), Some((100, 279)));
+        assert_eq!(window_to_texel(0, 0, 1, 1), Some((0, 0)));
+    }
+
+    #[test]
+    fn outside_the_window_is_nothing() {
+        let (width, height) = (640, 480);
+        for &(x, y) in &[(-1, 0), (0, -1), (640, 0), (0, 480), (640, 480), (-1, -1), (i32::MIN, 10), (10, i32::MAX)] {
+            assert_eq!(window_to_texel(x, y, width, height), None, "{:?}", (x, y));
+        }
+        assert_eq!(window_to_texel(0, 0, 0, 0), None);
+    }
+}
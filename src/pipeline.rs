@@ -1,7 +1,7 @@
 
-use cgmath::{Vector3, Matrix, Matrix4};
+use cgmath::{Vector3, Matrix, Matrix3, Matrix4};
 use graphical_math;
-use graphical_math::PersProjInfo;
+use graphical_math::{PersProjInfo, OrthoProjInfo};
 
 fn default_matrix() -> Matrix4<f32> {
     Matrix4::new(
@@ -12,12 +12,18 @@ fn default_matrix() -> Matrix4<f32> {
     ).transpose()
 }
 
+#[derive(Clone, Copy)]
+enum Projection {
+    Perspective(PersProjInfo),
+    Orthographic(OrthoProjInfo)
+}
+
 pub struct Pipeline {
     scale: Vector3<f32>,
     world_pos: Vector3<f32>,
     rotate_info: Vector3<f32>,
 
-    pers_proj_info: PersProjInfo,
+    projection: Projection,
     camera_pos: Vector3<f32>,
     camera_target: Vector3<f32>,
     camera_up: Vector3<f32>,
@@ -35,7 +41,7 @@ impl Pipeline {
             scale: Vector3::new(1.0, 1.0, 1.0),
             world_pos: Vector3::new(0.0, 0.0, 0.0),
             rotate_info: Vector3::new(0.0, 0.0, 0.0),
-            pers_proj_info: PersProjInfo::default(),
+            projection: Projection::Perspective(PersProjInfo::default()),
             camera_pos: Vector3::new(0.0, 0.0, 0.0),
             camera_target: Vector3::new(0.0, 0.0, 1.0),
             camera_up: Vector3::new(0.0, 1.0, 0.0),
@@ -66,13 +72,25 @@ impl Pipeline {
     }
 
     pub fn set_perspective_proj(&mut self, fov: f32, width: f32, height: f32, z_near: f32, z_far: f32) {
-        self.pers_proj_info = PersProjInfo {
+        self.projection = Projection::Perspective(PersProjInfo {
             fov: fov,
             width: width,
             height: height,
             z_near: z_near,
             z_far: z_far
-        };
+        });
+    }
+
+    pub fn set_orthographic_proj(&mut self, left: f32, right: f32, bottom: f32, top: f32,
+            z_near: f32, z_far: f32) {
+        self.projection = Projection::Orthographic(OrthoProjInfo {
+            left: left,
+            right: right,
+            bottom: bottom,
+            top: top,
+            z_near: z_near,
+            z_far: z_far
+        });
     }
 
     pub fn set_camera(&mut self, pos: Vector3<f32>, target: Vector3<f32>, up: Vector3<f32>) {
@@ -90,6 +108,16 @@ impl Pipeline {
         self.w_transformation
     }
 
+    // For the normals, which the world matrix only keeps perpendicular to the surfaces when its
+    // scaling is uniform
+    pub fn get_normal_trans(&mut self) -> Matrix3<f32> {
+        graphical_math::normal_matrix(self.get_world_trans())
+    }
+
+    pub fn get_camera_pos(&self) -> Vector3<f32> {
+        self.camera_pos
+    }
+
     pub fn get_view_trans(&mut self) -> Matrix4<f32> {
         let camera_translation_trans = graphical_math::init_translation_transform(
             -self.camera_pos.x, -self.camera_pos.y, -self.camera_pos.z);
@@ -101,16 +129,18 @@ impl Pipeline {
     }
 
     pub fn get_project_trans(&mut self) -> Matrix4<f32> {
-        self.p_transformation = graphical_math::init_pers_proj_transform(self.pers_proj_info);
+        self.p_transformation = match self.projection {
+            Projection::Perspective(info) => graphical_math::init_pers_proj_transform(info),
+            Projection::Orthographic(info) => graphical_math::init_ortho_proj_transform(info)
+        };
         self.p_transformation
     }
 
     pub fn get_wp_trans(&mut self) -> Matrix4<f32> {
         self.get_world_trans();
+        self.get_project_trans();
 
-        let pers_proj_trans = graphical_math::init_pers_proj_transform(self.pers_proj_info);
-
-        self.wp_transformation = pers_proj_trans * self.w_transformation;
+        self.wp_transformation = self.p_transformation * self.w_transformation;
         self.wp_transformation
     }
 
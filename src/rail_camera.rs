@@ -0,0 +1,147 @@
+use cgmath::{InnerSpace, Quaternion, Rotation, Vector3};
+
+use spline::{ArcLengthTable, Spline};
+
+const ARC_LENGTH_SAMPLES: usize = 512;
+
+// How a rail camera decides where to look while it moves along the rail
+pub enum RailOrientation {
+    // Always look at a fixed point
+    LookAtPoint(Vector3<f32>),
+    // Look at a point which moves along its own spline, in sync with the camera
+    LookAtPath(Box<dyn Spline<Vector3<f32>>>),
+    // Interpolate between orientation keys. The identity orientation looks toward +Z with +Y up,
+    // same as the default `Camera`.
+    Keys(Box<dyn Spline<Quaternion<f32>>>)
+}
+
+// A camera which follows a spline at a constant speed. Use `get_pos`, `get_target` and `get_up`
+// with `Pipeline::set_camera`, same as `Camera`.
+pub struct RailCamera {
+    path: Box<dyn Spline<Vector3<f32>>>,
+    arc_length: ArcLengthTable,
+    orientation: RailOrientation,
+
+    speed: f32,
+    distance: f32,
+    looping: bool,
+
+    pos: Vector3<f32>,
+    target: Vector3<f32>,
+    up: Vector3<f32>
+}
+
+impl RailCamera {
+    pub fn new(path: Box<dyn Spline<Vector3<f32>>>, orientation: RailOrientation) -> RailCamera {
+        let arc_length = ArcLengthTable::new(&*path, ARC_LENGTH_SAMPLES);
+
+        let mut camera = RailCamera {
+            path: path,
+            arc_length: arc_length,
+            orientation: orientation,
+            speed: 1.0,
+            distance: 0.0,
+            looping: false,
+            pos: Vector3::new(0.0, 0.0, 0.0),
+            target: Vector3::new(0.0, 0.0, 1.0),
+            up: Vector3::new(0.0, 1.0, 0.0)
+        };
+
+        camera.update();
+        camera
+    }
+
+    // Distance travelled per call of `on_render`, or per second with `advance`
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed;
+    }
+
+    // Start over from the beginning after reaching the end of the rail
+    pub fn set_looping(&mut self, looping: bool) {
+        self.looping = looping;
+    }
+
+    pub fn get_length(&self) -> f32 {
+        self.arc_length.length()
+    }
+
+    pub fn get_distance(&self) -> f32 {
+        self.distance
+    }
+
+    pub fn is_finished(&self) -> bool {
+        !self.looping && self.distance >= self.arc_length.length()
+    }
+
+    pub fn get_pos(&self) -> Vector3<f32> {
+        self.pos
+    }
+
+    pub fn get_target(&self) -> Vector3<f32> {
+        self.target
+    }
+
+    pub fn get_up(&self) -> Vector3<f32> {
+        self.up
+    }
+
+    // Jump to a given distance along the rail
+    pub fn seek(&mut self, distance: f32) {
+        let length = self.arc_length.length();
+
+        self.distance = if self.looping && length > 0.0 {
+            let wrapped = distance % length;
+            if wrapped < 0.0 { wrapped + length } else { wrapped }
+        } else {
+            distance.max(0.0).min(length)
+        };
+
+        self.update();
+    }
+
+    pub fn advance(&mut self, delta_time: f32) {
+        let distance = self.distance + self.speed * delta_time;
+        self.seek(distance);
+    }
+
+    pub fn on_render(&mut self) {
+        self.advance(1.0);
+    }
+
+    fn update(&mut self) {
+        let length = self.arc_length.length();
+        let fraction = if length > 0.0 { self.distance / length } else { 0.0 };
+        let t = self.arc_length.param_at_fraction(fraction);
+
+        self.pos = self.path.evaluate(t);
+
+        let (target, up) = match self.orientation {
+            RailOrientation::LookAtPoint(point) => look_at(point - self.pos),
+            RailOrientation::LookAtPath(ref path) => look_at(path.evaluate(fraction) - self.pos),
+            RailOrientation::Keys(ref keys) => {
+                let rotation = keys.evaluate(fraction);
+                (rotation.rotate_vector(Vector3::new(0.0, 0.0, 1.0)).normalize(),
+                    rotation.rotate_vector(Vector3::new(0.0, 1.0, 0.0)).normalize())
+            }
+        };
+
+        // Keep the last orientation if the camera sits on the point it looks at
+        if is_finite(target) && is_finite(up) {
+            self.target = target;
+            self.up = up;
+        }
+    }
+}
+
+// Build a target and an up vector the same way `Camera` does, keeping the horizon level
+fn look_at(direction: Vector3<f32>) -> (Vector3<f32>, Vector3<f32>) {
+    let v_axis = Vector3::new(0.0, 1.0, 0.0);
+    let target = direction.normalize();
+    let h_axis = v_axis.cross(target).normalize();
+    let up = target.cross(h_axis).normalize();
+    (target, up)
+}
+
+fn is_finite(v: Vector3<f32>) -> bool {
+    v.x.is_finite() && v.y.is_finite() && v.z.is_finite()
+}
@@ -0,0 +1,368 @@
+// Cascaded shadow maps: the view frustum of the camera is cut into slices along its depth, and
+// each slice gets its own map of the directional light. The near slices are small, so their
+// texels are small where the details are seen.
+//
+// A slice is fitted with its bounding sphere rather than its bounding box. The sphere does not
+// change when the camera turns, and its center is snapped to the texels of the map, so the
+// shadows stand still instead of shimmering when the camera moves.
+
+use cgmath::{InnerSpace, Matrix4, Vector3};
+use glium::Surface;
+use glium::backend::Facade;
+use glium::framebuffer::SimpleFrameBuffer;
+use glium::texture::{DepthFormat, DepthTexture2dArray, MipmapsOption};
+use glium::uniforms::{UniformValue, Uniforms};
+
+use camera::Camera;
+use graphical_math::{self, OrthoProjInfo, PersProjInfo};
+use lighting::LightUniforms;
+use mesh::GpuMesh;
+use shadow::{DepthPass, ShadowError, ShadowSettings, shadow_sampler};
+use shadow::frustum;
+
+pub const MAX_CASCADES: usize = 4;
+
+// `CalcCascadedShadowFactor` works like `CalcShadowFactor`, with the map of the slice holding the
+// point. Past the last slice, nothing is in the shadow.
+pub fn cascade_glsl() -> String {
+    format!(r#"
+    #define MAX_CASCADES {}
+
+    struct CascadeInfo {{
+        mat4 LightVP[MAX_CASCADES];
+        float SplitDistances[MAX_CASCADES];
+        int Count;
+        vec3 CameraPos;
+        vec3 CameraDirection;
+        float Bias;
+        float SlopeBias;
+        int PcfRadius;
+        sampler2DArray Map;
+    }};
+
+    uniform CascadeInfo gCascades;
+
+    // The slice holding a point, or Count past the last one
+    int CascadeIndex(vec3 worldPos) {{
+        float depth = dot(worldPos - gCascades.CameraPos, gCascades.CameraDirection);
+        for (int i = 0; i < gCascades.Count; i++) {{
+            if (depth <= gCascades.SplitDistances[i]) {{
+                return i;
+            }}
+        }}
+        return gCascades.Count;
+    }}
+
+    float CalcCascadedShadowFactor(vec3 worldPos, float cosTheta) {{
+        int cascade = CascadeIndex(worldPos);
+        if (cascade >= gCascades.Count) {{
+            return 1.0;
+        }}
+
+        vec4 lightSpacePos = gCascades.LightVP[cascade] * vec4(worldPos, 1.0);
+        vec3 coords = lightSpacePos.xyz / lightSpacePos.w * 0.5 + 0.5;
+        if (coords.z > 1.0) {{
+            return 1.0;
+        }}
+
+        cosTheta = clamp(cosTheta, 0.05, 1.0);
+        float bias = gCascades.Bias + gCascades.SlopeBias * sqrt(1.0 - cosTheta * cosTheta) / cosTheta;
+
+        vec2 texelSize = 1.0 / vec2(textureSize(gCascades.Map, 0).xy);
+        float lit = 0.0;
+        for (int y = -gCascades.PcfRadius; y <= gCascades.PcfRadius; y++) {{
+            for (int x = -gCascades.PcfRadius; x <= gCascades.PcfRadius; x++) {{
+                vec2 offset = vec2(x, y) * texelSize;
+                float depth = texture(gCascades.Map, vec3(coords.xy + offset, float(cascade))).r;
+                lit += coords.z - bias > depth ? 0.0 : 1.0;
+            }}
+        }}
+
+        float size = float(2 * gCascades.PcfRadius + 1);
+        return lit / (size * size);
+    }}
+"#, MAX_CASCADES)
+}
+
+// The distances from the camera where the slices end, `count` + 1 of them from the near plane to
+// `far`, with at least one slice. `lambda` blends between even slices (0) and slices growing with
+// the distance (1), which keep the same texel size on the screen from near to far.
+pub fn split_distances(near: f32, far: f32, count: usize, lambda: f32) -> Vec<f32> {
+    let count = count.max(1);
+    let lambda = lambda.clamp(0.0, 1.0);
+    (0..count + 1).map(|i| {
+        let t = i as f32 / count as f32;
+        let logarithmic = near * (far / near).powf(t);
+        let uniform = near + (far - near) * t;
+        lambda * logarithmic + (1.0 - lambda) * uniform
+    }).collect()
+}
+
+// The orthographic projection of a light looking along `direction` around a bounding sphere,
+// for a map of `map_size` texels. The center is snapped to the texels of the map, and the radius
+// is rounded up, so small moves of the sphere move the projection by whole texels.
+pub fn fit_sphere(direction: Vector3<f32>, center: Vector3<f32>, radius: f32, map_size: u32, caster_margin: f32)
+        -> OrthoProjInfo {
+    let view = graphical_math::init_camera_transform(direction, frustum::light_up(direction));
+    let center = (view * center.extend(1.0)).truncate();
+    let radius = (radius * 16.0).ceil() / 16.0;
+
+    let texel = 2.0 * radius / map_size as f32;
+    let x = (center.x / texel).floor() * texel;
+    let y = (center.y / texel).floor() * texel;
+
+    OrthoProjInfo {
+        left: x - radius,
+        right: x + radius,
+        bottom: y - radius,
+        top: y + radius,
+        z_near: center.z - radius - caster_margin,
+        z_far: center.z + radius
+    }
+}
+
+// The sphere around the corners of a slice: centered on their average, so that it only depends on
+// the shape of the slice and not on where the camera looks
+pub fn slice_sphere(corners: &[Vector3<f32>; 8]) -> (Vector3<f32>, f32) {
+    let center = corners.iter().fold(Vector3::new(0.0, 0.0, 0.0), |sum, &corner| sum + corner) / 8.0;
+    let radius = corners.iter().map(|&corner| (corner - center).magnitude()).fold(0.0, f32::max);
+    (center, radius)
+}
+
+// The projections of the light for each slice between `splits`, see `split_distances()`
+pub fn fit_cascades(direction: Vector3<f32>, camera: &Camera, proj: &PersProjInfo, splits: &[f32], map_size: u32,
+        caster_margin: f32) -> Vec<OrthoProjInfo> {
+    splits.windows(2).map(|slice| {
+        let corners = frustum::frustum_corners(camera.get_pos(), camera.get_target(), camera.get_up(), proj,
+            slice[0], slice[1]);
+        let (center, radius) = slice_sphere(&corners);
+        fit_sphere(direction, center, radius, map_size, caster_margin)
+    }).collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CascadeSettings {
+    // At most `MAX_CASCADES`, and at most the count the map was made with
+    pub count: usize,
+    // See `split_distances()`
+    pub lambda: f32,
+    // The shadows end there, or at the far plane if it is closer
+    pub shadow_distance: f32,
+    // How far toward the light the casters can be
+    pub caster_margin: f32
+}
+
+impl Default for CascadeSettings {
+    fn default() -> CascadeSettings {
+        CascadeSettings {
+            count: 4,
+            lambda: 0.75,
+            shadow_distance: 200.0,
+            caster_margin: 50.0
+        }
+    }
+}
+
+// The maps of the slices, in the layers of a texture array
+pub struct CascadedShadowMap {
+    texture: DepthTexture2dArray,
+    pass: DepthPass,
+    pub settings: ShadowSettings,
+    pub cascades: CascadeSettings,
+    // Where each slice ends, and the view and projection of its light
+    splits: Vec<f32>,
+    view_projections: Vec<Matrix4<f32>>,
+    camera_pos: Vector3<f32>,
+    camera_direction: Vector3<f32>
+}
+
+impl CascadedShadowMap {
+    pub fn new<F: Facade>(facade: &F, settings: ShadowSettings, cascades: CascadeSettings)
+            -> Result<CascadedShadowMap, ShadowError> {
+        let cascades = CascadeSettings {
+            count: cascades.count.clamp(1, MAX_CASCADES),
+            .. cascades
+        };
+        let size = settings.size;
+
+        Ok(CascadedShadowMap {
+            texture: DepthTexture2dArray::empty_with_format(facade, DepthFormat::F32, MipmapsOption::NoMipmap,
+                size, size, cascades.count as u32)?,
+            pass: DepthPass::new(facade)?,
+            settings: settings,
+            cascades: cascades,
+            splits: Vec::new(),
+            view_projections: Vec::new(),
+            camera_pos: Vector3::new(0.0, 0.0, 0.0),
+            camera_direction: Vector3::new(0.0, 0.0, 1.0)
+        })
+    }
+
+    pub fn texture(&self) -> &DepthTexture2dArray {
+        &self.texture
+    }
+
+    // The number of slices the texture has room for
+    pub fn max_cascades(&self) -> usize {
+        self.texture.get_array_size().unwrap_or(1) as usize
+    }
+
+    // The distances where the slices end, from the last rendering
+    pub fn split_distances(&self) -> &[f32] {
+        &self.splits
+    }
+
+    pub fn view_projections(&self) -> &[Matrix4<f32>] {
+        &self.view_projections
+    }
+
+    // Render the casters, each with its world matrix, into every slice seen by `camera` through
+    // `proj`
+    pub fn render<F: Facade>(&mut self, facade: &F, direction: Vector3<f32>, camera: &Camera, proj: &PersProjInfo,
+            casters: &[(&GpuMesh, Matrix4<f32>)]) -> Result<(), ShadowError> {
+        let far = self.cascades.shadow_distance.min(proj.z_far);
+        let count = self.cascades.count.clamp(1, self.max_cascades());
+        self.splits = split_distances(proj.z_near, far, count, self.cascades.lambda);
+        self.view_projections = fit_cascades(direction, camera, proj, &self.splits, self.settings.size,
+                self.cascades.caster_margin).into_iter()
+            .map(|ortho| {
+                let mut pipeline = frustum::directional_light_pipeline(direction, ortho);
+                pipeline.get_project_trans() * pipeline.get_view_trans()
+            })
+            .collect();
+        self.camera_pos = camera.get_pos();
+        self.camera_direction = camera.get_target().normalize();
+
+        for (layer, &view_projection) in self.view_projections.iter().enumerate() {
+            let image = self.texture.main_level().layer(layer as u32).unwrap();
+            let mut framebuffer = SimpleFrameBuffer::depth_only(facade, image)?;
+            framebuffer.clear_depth(1.0);
+            self.pass.draw(&mut framebuffer, view_projection, casters)?;
+        }
+        Ok(())
+    }
+
+    // The values of `gCascades`
+    pub fn uniform_values(&self) -> Vec<(String, UniformValue<'_>)> {
+        let mut values = Vec::new();
+        for (i, view_projection) in self.view_projections.iter().enumerate() {
+            values.push((format!("LightVP[{}]", i), UniformValue::Mat4((*view_projection).into())));
+            values.push((format!("SplitDistances[{}]", i), UniformValue::Float(self.splits[i + 1])));
+        }
+        values.push(("Count".to_string(), UniformValue::SignedInt(self.view_projections.len() as i32)));
+        values.push(("CameraPos".to_string(), UniformValue::Vec3(self.camera_pos.into())));
+        values.push(("CameraDirection".to_string(), UniformValue::Vec3(self.camera_direction.into())));
+        values.push(("Bias".to_string(), UniformValue::Float(self.settings.bias)));
+        values.push(("SlopeBias".to_string(), UniformValue::Float(self.settings.slope_bias)));
+        values.push(("PcfRadius".to_string(), UniformValue::SignedInt(self.settings.pcf_radius.max(0))));
+        values.push(("Map".to_string(), UniformValue::DepthTexture2dArray(&self.texture, Some(shadow_sampler()))));
+        values
+    }
+}
+
+impl<'a, U: Uniforms> LightUniforms<'a, U> {
+    // Fill `gCascades`, as `cascade_glsl()` declares it
+    pub fn with_cascaded_shadow_map(mut self, shadow_map: &'a CascadedShadowMap) -> LightUniforms<'a, U> {
+        for (field, value) in shadow_map.uniform_values() {
+            self = self.with_value(&format!("gCascades.{}", field), value);
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Vector3};
+
+    use camera::Camera;
+    use graphical_math::PersProjInfo;
+    use shadow::frustum;
+    use super::*;
+
+    fn assert_close(values: &[f32], expected: &[f32]) {
+        assert_eq!(values.len(), expected.len());
+        for (value, expected) in values.iter().zip(expected) {
+            assert!((value - expected).abs() <= expected.abs() * 1e-5, "{:?} instead of {:?}", values, expected);
+        }
+    }
+
+    #[test]
+    fn even_and_logarithmic_splits() {
+        assert_close(&split_distances(1.0, 101.0, 4, 0.0), &[1.0, 26.0, 51.0, 76.0, 101.0]);
+        assert_close(&split_distances(1.0, 10000.0, 4, 1.0), &[1.0, 10.0, 100.0, 1000.0, 10000.0]);
+        // Out of range, clamped
+        assert_close(&split_distances(1.0, 10000.0, 4, 3.0), &[1.0, 10.0, 100.0, 1000.0, 10000.0]);
+    }
+
+    #[test]
+    fn splits_go_from_near_to_far() {
+        for &lambda in &[0.0, 0.25, 0.5, 0.75, 1.0] {
+            for count in 1..MAX_CASCADES + 1 {
+                let splits = split_distances(0.1, 200.0, count, lambda);
+                assert_eq!(splits.len(), count + 1);
+                assert!((splits[0] - 0.1).abs() < 1e-6 && (splits[count] - 200.0).abs() < 1e-3, "{:?}", splits);
+                assert!(splits.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", splits);
+            }
+        }
+    }
+
+    #[test]
+    fn no_cascade_is_one_cascade() {
+        assert_close(&split_distances(1.0, 50.0, 0, 0.5), &[1.0, 50.0]);
+    }
+
+    #[test]
+    fn spheres_move_by_whole_texels() {
+        let direction = Vector3::new(0.3, -1.0, 0.4);
+        let (radius, map_size) = (10.0, 1024);
+        let first = fit_sphere(direction, Vector3::new(1.0, 2.0, 3.0), radius, map_size, 0.0);
+        let texel = (first.right - first.left) / map_size as f32;
+
+        // Sideways from the light, in steps of a fraction of a texel
+        let side = direction.cross(Vector3::new(0.0, 0.0, 1.0)).normalize();
+        for step in 1..50 {
+            let center = Vector3::new(1.0, 2.0, 3.0) + side * (texel * step as f32 / 7.0);
+            let ortho = fit_sphere(direction, center, radius, map_size, 0.0);
+            assert_eq!(ortho.right - ortho.left, first.right - first.left);
+            for &(moved, start) in &[(ortho.left, first.left), (ortho.bottom, first.bottom)] {
+                let texels = (moved - start) / texel;
+                assert!((texels - texels.round()).abs() < 1e-2, "moved by {} texels", texels);
+            }
+        }
+
+        // The radius is rounded up, so that it does not jitter either
+        for &smaller in &[radius - 0.01, radius - 0.05] {
+            let ortho = fit_sphere(direction, Vector3::new(1.0, 2.0, 3.0), smaller, map_size, 0.0);
+            assert_eq!(ortho.right - ortho.left, first.right - first.left);
+        }
+    }
+
+    #[test]
+    fn cascades_hold_their_slices() {
+        let camera = Camera::new(1280, 720, Vector3::new(5.0, 8.0, -2.0), Vector3::new(0.4, -0.3, 1.0),
+            Vector3::new(0.0, 1.0, 0.0));
+        let proj = PersProjInfo {
+            fov: 60.0,
+            width: 1280.0,
+            height: 720.0,
+            z_near: 0.5,
+            z_far: 300.0
+        };
+        let direction = Vector3::new(-0.5, -1.0, 0.2);
+        let splits = split_distances(proj.z_near, 150.0, 4, 0.75);
+
+        let cascades = fit_cascades(direction, &camera, &proj, &splits, 2048, 0.0);
+        assert_eq!(cascades.len(), 4);
+        for (slice, &ortho) in splits.windows(2).zip(&cascades) {
+            let mut pipeline = frustum::directional_light_pipeline(direction, ortho);
+            let view_projection = pipeline.get_project_trans() * pipeline.get_view_trans();
+            let corners = frustum::frustum_corners(camera.get_pos(), camera.get_target(), camera.get_up(), &proj,
+                slice[0], slice[1]);
+            for &corner in &corners {
+                let p = view_projection * corner.extend(1.0);
+                let p = p.truncate() / p.w;
+                assert!(p.x.abs() <= 1.0 && p.y.abs() <= 1.0 && p.z.abs() <= 1.0 + 1e-5, "{:?} in {:?}", p, slice);
+            }
+        }
+    }
+}
@@ -0,0 +1,254 @@
+// Shadows of point lights, which shine in every direction: the scene is rendered six times from
+// the light, once per face of a cube map, with 90 degrees projections.
+//
+// The faces store the distance from the light rather than the depth, so the shaders compare
+// distances in world units whatever face the point falls in.
+
+use cgmath::{Matrix4, Vector3};
+use glium::{Depth, DepthTest, DrawParameters, Program, Surface};
+use glium::backend::Facade;
+use glium::framebuffer::{DepthRenderBuffer, SimpleFrameBuffer};
+use glium::texture::{Cubemap, DepthFormat, MipmapsOption, TextureCreationError, UncompressedFloatFormat};
+use glium::uniforms::{UniformValue, Uniforms};
+
+use graphical_math::{self, PersProjInfo};
+use lighting::LightUniforms;
+use mesh::GpuMesh;
+use shadow::{ShadowError, shadow_sampler};
+use texture::cubemap::CUBE_FACES;
+
+const DISTANCE_VERTEX_SHADER: &str = r#"
+    #version 330
+
+    in vec3 position;
+
+    uniform mat4 gLightWVP;
+    uniform mat4 gWorld;
+
+    out vec3 worldPos0;
+
+    void main() {
+        gl_Position = gLightWVP * vec4(position, 1.0);
+        worldPos0 = (gWorld * vec4(position, 1.0)).xyz;
+    }
+"#;
+
+const DISTANCE_FRAGMENT_SHADER: &str = r#"
+    #version 330
+
+    in vec3 worldPos0;
+
+    out float distance;
+
+    uniform vec3 gLightWorldPos;
+
+    void main() {
+        distance = length(worldPos0 - gLightWorldPos);
+    }
+"#;
+
+// `CalcPointShadowFactor` gives 1 where a point is lit by the light at `gPointShadow.LightPos`
+// and 0 where it is in the shadow. With some softness, 27 directions around the point are
+// compared and the edges fade.
+pub const CUBE_SHADOW_GLSL: &str = r#"
+    struct PointShadowInfo {
+        vec3 LightPos;
+        float Bias;
+        float SlopeBias;
+        float Softness;
+        samplerCube Map;
+    };
+
+    uniform PointShadowInfo gPointShadow;
+
+    float CalcPointShadowFactor(vec3 worldPos, float cosTheta) {
+        vec3 toPixel = worldPos - gPointShadow.LightPos;
+        float distance = length(toPixel);
+
+        cosTheta = clamp(cosTheta, 0.05, 1.0);
+        float bias = gPointShadow.Bias + gPointShadow.SlopeBias * sqrt(1.0 - cosTheta * cosTheta) / cosTheta;
+
+        if (gPointShadow.Softness <= 0.0) {
+            return distance - bias > texture(gPointShadow.Map, toPixel).r ? 0.0 : 1.0;
+        }
+
+        float lit = 0.0;
+        for (int x = -1; x <= 1; x++) {
+            for (int y = -1; y <= 1; y++) {
+                for (int z = -1; z <= 1; z++) {
+                    vec3 offset = vec3(x, y, z) * gPointShadow.Softness * distance;
+                    lit += distance - bias > texture(gPointShadow.Map, toPixel + offset).r ? 0.0 : 1.0;
+                }
+            }
+        }
+        return lit / 27.0;
+    }
+"#;
+
+// The view of the face `face` of a cube map rendered from `position`, in the order of
+// `CUBE_FACES`
+pub fn cube_face_view(face: usize, position: Vector3<f32>) -> Matrix4<f32> {
+    graphical_math::init_cube_face_transform(face) *
+        graphical_math::init_translation_transform(-position.x, -position.y, -position.z)
+}
+
+// The projection of every face: 90 degrees wide and high
+pub fn cube_face_projection(z_near: f32, z_far: f32) -> PersProjInfo {
+    PersProjInfo {
+        fov: 90.0,
+        width: 1.0,
+        height: 1.0,
+        z_near: z_near,
+        z_far: z_far
+    }
+}
+
+// The view and projection of the six faces
+pub fn cube_face_view_projections(position: Vector3<f32>, z_near: f32, z_far: f32) -> [Matrix4<f32>; 6] {
+    let projection = graphical_math::init_pers_proj_transform(cube_face_projection(z_near, z_far));
+    let face = |face| projection * cube_face_view(face, position);
+    [face(0), face(1), face(2), face(3), face(4), face(5)]
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CubeShadowSettings {
+    // The width and height of each face, in texels
+    pub size: u32,
+    // In world units, subtracted from the distance of a point before the comparison
+    pub bias: f32,
+    pub slope_bias: f32,
+    // In world units at a distance of 1 from the light, 0 gives hard edges
+    pub softness: f32,
+    pub z_near: f32,
+    // Nothing farther than this from the light casts a shadow
+    pub z_far: f32
+}
+
+impl Default for CubeShadowSettings {
+    fn default() -> CubeShadowSettings {
+        CubeShadowSettings {
+            size: 1024,
+            bias: 0.05,
+            slope_bias: 0.05,
+            softness: 0.0,
+            z_near: 0.1,
+            z_far: 100.0
+        }
+    }
+}
+
+pub struct CubeShadowMap {
+    texture: Cubemap,
+    depth: DepthRenderBuffer,
+    program: Program,
+    pub settings: CubeShadowSettings,
+    light_pos: Vector3<f32>
+}
+
+impl CubeShadowMap {
+    pub fn new<F: Facade>(facade: &F, settings: CubeShadowSettings) -> Result<CubeShadowMap, ShadowError> {
+        let size = settings.size;
+        Ok(CubeShadowMap {
+            texture: Cubemap::empty_with_format(facade, UncompressedFloatFormat::F32, MipmapsOption::NoMipmap, size)?,
+            // A render buffer can only fail for an unsupported format
+            depth: DepthRenderBuffer::new(facade, DepthFormat::F32, size, size)
+                .map_err(|_| TextureCreationError::FormatNotSupported)?,
+            program: Program::from_source(facade, DISTANCE_VERTEX_SHADER, DISTANCE_FRAGMENT_SHADER, None)?,
+            settings: settings,
+            light_pos: Vector3::new(0.0, 0.0, 0.0)
+        })
+    }
+
+    pub fn texture(&self) -> &Cubemap {
+        &self.texture
+    }
+
+    // Render the distances of the casters, each with its world matrix, from a light at `position`
+    pub fn render<F: Facade>(&mut self, facade: &F, position: Vector3<f32>, casters: &[(&GpuMesh, Matrix4<f32>)])
+            -> Result<(), ShadowError> {
+        self.light_pos = position;
+        let view_projections = cube_face_view_projections(position, self.settings.z_near, self.settings.z_far);
+        let light_pos: [f32; 3] = position.into();
+
+        // The faces are mirrored, so no culling
+        let params = DrawParameters {
+            depth: Depth {
+                test: DepthTest::IfLess,
+                write: true,
+                .. Default::default()
+            },
+            .. Default::default()
+        };
+
+        for (face, view_projection) in view_projections.iter().enumerate() {
+            let image = self.texture.main_level().image(CUBE_FACES[face]);
+            let mut framebuffer = SimpleFrameBuffer::with_depth_buffer(facade, image, &self.depth)?;
+            // Where nothing is drawn, nothing casts a shadow
+            framebuffer.clear_color_and_depth((f32::MAX, 0.0, 0.0, 0.0), 1.0);
+
+            for &(mesh, world) in casters {
+                let light_wvp: [[f32; 4]; 4] = (view_projection * world).into();
+                let world: [[f32; 4]; 4] = world.into();
+                let uniforms = uniform! {
+                    gLightWVP: light_wvp,
+                    gWorld: world,
+                    gLightWorldPos: light_pos
+                };
+                mesh.draw(&mut framebuffer, &self.program, &uniforms, &params)?;
+            }
+        }
+        Ok(())
+    }
+
+    // The values of `gPointShadow`
+    pub fn uniform_values(&self) -> Vec<(&'static str, UniformValue<'_>)> {
+        vec![
+            ("LightPos", UniformValue::Vec3(self.light_pos.into())),
+            ("Bias", UniformValue::Float(self.settings.bias)),
+            ("SlopeBias", UniformValue::Float(self.settings.slope_bias)),
+            ("Softness", UniformValue::Float(self.settings.softness)),
+            ("Map", UniformValue::Cubemap(&self.texture, Some(shadow_sampler())))
+        ]
+    }
+}
+
+impl<'a, U: Uniforms> LightUniforms<'a, U> {
+    // Fill `gPointShadow`, as `CUBE_SHADOW_GLSL` declares it
+    pub fn with_cube_shadow_map(self, shadow_map: &'a CubeShadowMap) -> LightUniforms<'a, U> {
+        self.with_struct("gPointShadow", shadow_map.uniform_values())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Vector3;
+
+    use texture::cubemap::face_direction;
+    use super::*;
+
+    // Where a point lands on a face, in normalized device coordinates
+    fn project(view_projection: Matrix4<f32>, point: Vector3<f32>) -> Vector3<f32> {
+        let p = view_projection * point.extend(1.0);
+        p.truncate() / p.w
+    }
+
+    #[test]
+    fn faces_match_the_cube_map_layout() {
+        let position = Vector3::new(3.0, -1.0, 2.0);
+        let view_projections = cube_face_view_projections(position, 0.1, 50.0);
+
+        for (face, &view_projection) in view_projections.iter().enumerate() {
+            let center = project(view_projection, position + face_direction(face, 0.5, 0.5) * 10.0);
+            assert!(center.x.abs() < 1e-5 && center.y.abs() < 1e-5, "face {}: {:?}", face, center);
+
+            // The top of the face image is the first row of the framebuffer, at the bottom
+            for &(s, t) in &[(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0), (0.25, 0.75)] {
+                let p = project(view_projection, position + face_direction(face, s, t) * 10.0);
+                let expected = (2.0 * s - 1.0, 2.0 * t - 1.0);
+                assert!((p.x - expected.0).abs() < 1e-5 && (p.y - expected.1).abs() < 1e-5,
+                    "face {} at ({}, {}): {:?}", face, s, t, p);
+                assert!(p.z.abs() < 1.0);
+            }
+        }
+    }
+}
@@ -0,0 +1,178 @@
+// The pipelines of the lights for the shadow maps, and the fitting of a directional light to the
+// part of the scene the camera sees. All of this is plain math, it needs no GPU.
+
+use cgmath::{InnerSpace, Vector3};
+
+use camera::Camera;
+use graphical_math::{self, OrthoProjInfo, PersProjInfo};
+use lighting::SpotLight;
+use pipeline::Pipeline;
+
+// The up vector of a camera looking along `direction`, anything not parallel to it
+pub fn light_up(direction: Vector3<f32>) -> Vector3<f32> {
+    if direction.normalize().y.abs() > 0.99 {
+        Vector3::new(0.0, 0.0, 1.0)
+    } else {
+        Vector3::new(0.0, 1.0, 0.0)
+    }
+}
+
+// The corners of the part of a perspective frustum between the distances `near` and `far`, in
+// world space: the near ones first, then the far ones, each from the bottom-left corner,
+// counterclockwise as seen by the camera
+pub fn frustum_corners(pos: Vector3<f32>, target: Vector3<f32>, up: Vector3<f32>, proj: &PersProjInfo,
+        near: f32, far: f32) -> [Vector3<f32>; 8] {
+    // Same basis as `init_camera_transform`
+    let n = target.normalize();
+    let u = up.normalize().cross(n).normalize();
+    let v = n.cross(u);
+
+    let tan_half_fov = (proj.fov / 2.0).to_radians().tan();
+    let ar = proj.width / proj.height;
+
+    let corner = |distance: f32, x: f32, y: f32| {
+        pos + n * distance + u * (x * distance * tan_half_fov * ar) + v * (y * distance * tan_half_fov)
+    };
+    [
+        corner(near, -1.0, -1.0), corner(near, 1.0, -1.0), corner(near, 1.0, 1.0), corner(near, -1.0, 1.0),
+        corner(far, -1.0, -1.0), corner(far, 1.0, -1.0), corner(far, 1.0, 1.0), corner(far, -1.0, 1.0)
+    ]
+}
+
+// The corners of the frustum of a camera, up to `far`
+pub fn camera_frustum_corners(camera: &Camera, proj: &PersProjInfo, far: f32) -> [Vector3<f32>; 8] {
+    frustum_corners(camera.get_pos(), camera.get_target(), camera.get_up(), proj, proj.z_near, far)
+}
+
+// The smallest orthographic projection of a light looking along `direction` from the origin which
+// holds all the points. The near plane moves `caster_margin` toward the light, so that the
+// objects between the light and the points still cast their shadows on them.
+pub fn fit_ortho(direction: Vector3<f32>, points: &[Vector3<f32>], caster_margin: f32) -> OrthoProjInfo {
+    let view = graphical_math::init_camera_transform(direction, light_up(direction));
+
+    let mut min = Vector3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY);
+    let mut max = -min;
+    for &point in points {
+        let p = (view * point.extend(1.0)).truncate();
+        min = Vector3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z));
+        max = Vector3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z));
+    }
+
+    OrthoProjInfo {
+        left: min.x,
+        right: max.x,
+        bottom: min.y,
+        top: max.y,
+        z_near: min.z - caster_margin,
+        z_far: max.z
+    }
+}
+
+// The pipeline of a directional light, with a projection from `fit_ortho()`
+pub fn directional_light_pipeline(direction: Vector3<f32>, ortho: OrthoProjInfo) -> Pipeline {
+    let mut pipeline = Pipeline::new();
+    pipeline.set_camera(Vector3::new(0.0, 0.0, 0.0), direction, light_up(direction));
+    pipeline.set_orthographic_proj(ortho.left, ortho.right, ortho.bottom, ortho.top, ortho.z_near, ortho.z_far);
+    pipeline
+}
+
+// The pipeline of a directional light covering what the camera sees up to `shadow_distance`.
+// Keeping the distance short keeps the texels of the map small.
+pub fn fit_directional_light(direction: Vector3<f32>, camera: &Camera, proj: &PersProjInfo,
+        shadow_distance: f32, caster_margin: f32) -> Pipeline {
+    let corners = camera_frustum_corners(camera, proj, shadow_distance.min(proj.z_far));
+    directional_light_pipeline(direction, fit_ortho(direction, &corners, caster_margin))
+}
+
+// The pipeline of a spot light: a square perspective projection as wide as its cone
+pub fn spot_light_pipeline(light: &SpotLight, z_near: f32, z_far: f32) -> Pipeline {
+    let mut pipeline = Pipeline::new();
+    pipeline.set_camera(light.base.position, light.direction, light_up(light.direction));
+    pipeline.set_perspective_proj(2.0 * light.cutoff, 1.0, 1.0, z_near, z_far);
+    pipeline
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Vector3};
+
+    use camera::Camera;
+    use graphical_math::PersProjInfo;
+    use super::*;
+
+    fn proj() -> PersProjInfo {
+        PersProjInfo {
+            fov: 60.0,
+            width: 1280.0,
+            height: 720.0,
+            z_near: 0.5,
+            z_far: 500.0
+        }
+    }
+
+    fn cameras() -> Vec<Camera> {
+        vec![
+            Camera::new(1280, 720, Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 1.0),
+                Vector3::new(0.0, 1.0, 0.0)),
+            Camera::new(1280, 720, Vector3::new(10.0, 5.0, -3.0), Vector3::new(-1.0, -0.5, 0.3),
+                Vector3::new(0.0, 1.0, 0.0)),
+            Camera::new(1280, 720, Vector3::new(-4.0, 20.0, 7.0), Vector3::new(0.2, -1.0, 0.1),
+                Vector3::new(0.0, 1.0, 0.0))
+        ]
+    }
+
+    fn directions() -> Vec<Vector3<f32>> {
+        // Straight down uses another up vector
+        vec![Vector3::new(0.0, -1.0, 0.0), Vector3::new(1.0, -1.0, 0.5), Vector3::new(-0.3, -0.2, -1.0)]
+    }
+
+    fn in_clip_space(point: Vector3<f32>, tolerance: f32) -> bool {
+        point.x.abs() <= 1.0 + tolerance && point.y.abs() <= 1.0 + tolerance && point.z.abs() <= 1.0 + tolerance
+    }
+
+    #[test]
+    fn fitted_light_holds_the_camera_frustum() {
+        for camera in &cameras() {
+            let corners = camera_frustum_corners(camera, &proj(), 40.0);
+            for &direction in &directions() {
+                let mut pipeline = fit_directional_light(direction, camera, &proj(), 40.0, 0.0);
+                let view_projection = pipeline.get_project_trans() * pipeline.get_view_trans();
+
+                let mut extent = Vector3::new(0.0f32, 0.0, 0.0);
+                for &corner in &corners {
+                    let p = view_projection * corner.extend(1.0);
+                    let p = p.truncate() / p.w;
+                    assert!(in_clip_space(p, 1e-4), "{:?} along {:?}", p, direction);
+                    extent = Vector3::new(extent.x.max(p.x.abs()), extent.y.max(p.y.abs()), extent.z.max(p.z.abs()));
+                }
+                // And nothing more: the frustum touches every side
+                assert!((extent - Vector3::new(1.0, 1.0, 1.0)).magnitude() < 1e-4, "{:?}", extent);
+            }
+        }
+    }
+
+    #[test]
+    fn caster_margin_moves_only_the_near_plane() {
+        let camera = &cameras()[1];
+        let corners = camera_frustum_corners(camera, &proj(), 40.0);
+        for &direction in &directions() {
+            let tight = fit_ortho(direction, &corners, 0.0);
+            let wide = fit_ortho(direction, &corners, 25.0);
+            assert_eq!((wide.left, wide.right, wide.bottom, wide.top, wide.z_far),
+                (tight.left, tight.right, tight.bottom, tight.top, tight.z_far));
+            assert!((tight.z_near - wide.z_near - 25.0).abs() < 1e-4);
+
+            // A caster between the light and the frustum is only kept with the margin
+            let caster = corners.iter()
+                .min_by(|a, b| a.dot(direction).partial_cmp(&b.dot(direction)).unwrap())
+                .unwrap() - direction.normalize() * 20.0;
+            let clip = |ortho: OrthoProjInfo| {
+                let mut pipeline = directional_light_pipeline(direction, ortho);
+                let p = pipeline.get_project_trans() * pipeline.get_view_trans() * caster.extend(1.0);
+                p.truncate() / p.w
+            };
+            assert!(!in_clip_space(clip(tight), 1e-4));
+            assert!(in_clip_space(clip(wide), 1e-4));
+        }
+    }
+}
@@ -0,0 +1,240 @@
+// Shadow maps: the depth of the scene seen from a light, rendered before the main pass. A point is
+// in the shadow when it is farther from the light than the depth stored where it projects.
+//
+// The light is seen through its own `Pipeline`, see `frustum` for the ones of the directional and
+// spot lights. The main pass includes `SHADOW_GLSL` and transforms its world positions by
+// `gShadow.LightVP` to find them in the map.
+
+use cgmath::Matrix4;
+use glium::{Depth, DepthTest, DrawError, DrawParameters, Program, ProgramCreationError, Surface};
+use glium::backend::Facade;
+use glium::framebuffer::{SimpleFrameBuffer, ValidationError};
+use glium::texture::{DepthFormat, DepthTexture2d, MipmapsOption, TextureCreationError};
+use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter, SamplerBehavior, SamplerWrapFunction,
+    UniformValue, Uniforms};
+
+use lighting::LightUniforms;
+use mesh::GpuMesh;
+use pipeline::Pipeline;
+
+pub mod frustum;
+pub mod cascade;
+pub mod cube;
+
+const DEPTH_VERTEX_SHADER: &str = r#"
+    #version 330
+
+    in vec3 position;
+
+    uniform mat4 gLightWVP;
+
+    void main() {
+        gl_Position = gLightWVP * vec4(position, 1.0);
+    }
+"#;
+
+// Only the depth is written
+const DEPTH_FRAGMENT_SHADER: &str = r#"
+    #version 330
+
+    void main() {
+    }
+"#;
+
+// `CalcShadowFactor` gives 1 where a point is lit and 0 where it is in the shadow, with the
+// fractions in between on the edges blurred by the PCF kernel. `cosTheta` is the cosine of the
+// angle between the normal and the direction to the light, steep surfaces need more bias.
+pub const SHADOW_GLSL: &str = r#"
+    struct ShadowInfo {
+        mat4 LightVP;
+        float Bias;
+        float SlopeBias;
+        int PcfRadius;
+        sampler2D Map;
+    };
+
+    uniform ShadowInfo gShadow;
+
+    float CalcShadowFactor(vec3 worldPos, float cosTheta) {
+        vec4 lightSpacePos = gShadow.LightVP * vec4(worldPos, 1.0);
+        vec3 coords = lightSpacePos.xyz / lightSpacePos.w * 0.5 + 0.5;
+
+        // Outside of the map, nothing casts a shadow
+        if (coords.z > 1.0 || any(lessThan(coords.xy, vec2(0.0))) || any(greaterThan(coords.xy, vec2(1.0)))) {
+            return 1.0;
+        }
+
+        cosTheta = clamp(cosTheta, 0.05, 1.0);
+        float bias = gShadow.Bias + gShadow.SlopeBias * sqrt(1.0 - cosTheta * cosTheta) / cosTheta;
+
+        vec2 texelSize = 1.0 / vec2(textureSize(gShadow.Map, 0));
+        float lit = 0.0;
+        for (int y = -gShadow.PcfRadius; y <= gShadow.PcfRadius; y++) {
+            for (int x = -gShadow.PcfRadius; x <= gShadow.PcfRadius; x++) {
+                float depth = texture(gShadow.Map, coords.xy + vec2(x, y) * texelSize).r;
+                lit += coords.z - bias > depth ? 0.0 : 1.0;
+            }
+        }
+
+        float size = float(2 * gShadow.PcfRadius + 1);
+        return lit / (size * size);
+    }
+"#;
+
+#[derive(Debug)]
+pub enum ShadowError {
+    Texture(TextureCreationError),
+    Program(ProgramCreationError),
+    Framebuffer(ValidationError),
+    Draw(DrawError)
+}
+
+impl From<TextureCreationError> for ShadowError {
+    fn from(err: TextureCreationError) -> ShadowError {
+        ShadowError::Texture(err)
+    }
+}
+
+impl From<ProgramCreationError> for ShadowError {
+    fn from(err: ProgramCreationError) -> ShadowError {
+        ShadowError::Program(err)
+    }
+}
+
+impl From<ValidationError> for ShadowError {
+    fn from(err: ValidationError) -> ShadowError {
+        ShadowError::Framebuffer(err)
+    }
+}
+
+impl From<DrawError> for ShadowError {
+    fn from(err: DrawError) -> ShadowError {
+        ShadowError::Draw(err)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShadowSettings {
+    // The width and height of the map, in texels
+    pub size: u32,
+    // Subtracted from the depth of a point before the comparison, against shadow acne
+    pub bias: f32,
+    // Added to the bias as the surface turns away from the light
+    pub slope_bias: f32,
+    // The PCF kernel has (2 * radius + 1)^2 samples, 0 gives hard edges
+    pub pcf_radius: i32
+}
+
+impl Default for ShadowSettings {
+    fn default() -> ShadowSettings {
+        ShadowSettings {
+            size: 2048,
+            bias: 0.0005,
+            slope_bias: 0.001,
+            pcf_radius: 1
+        }
+    }
+}
+
+// The depth program and the draw parameters of the passes from the lights
+pub struct DepthPass {
+    program: Program
+}
+
+impl DepthPass {
+    pub fn new<F: Facade>(facade: &F) -> Result<DepthPass, ShadowError> {
+        Ok(DepthPass {
+            program: Program::from_source(facade, DEPTH_VERTEX_SHADER, DEPTH_FRAGMENT_SHADER, None)?
+        })
+    }
+
+    // Draw the casters, each with its world matrix, seen through `view_projection`. Both faces
+    // are drawn so that open meshes cast shadows too.
+    pub fn draw<S: Surface>(&self, surface: &mut S, view_projection: Matrix4<f32>,
+            casters: &[(&GpuMesh, Matrix4<f32>)]) -> Result<(), DrawError> {
+        let params = DrawParameters {
+            depth: Depth {
+                test: DepthTest::IfLess,
+                write: true,
+                .. Default::default()
+            },
+            .. Default::default()
+        };
+
+        for &(mesh, world) in casters {
+            let light_wvp: [[f32; 4]; 4] = (view_projection * world).into();
+            mesh.draw(surface, &self.program, &uniform! { gLightWVP: light_wvp }, &params)?;
+        }
+        Ok(())
+    }
+}
+
+// The sampling of the maps: their texels are compared one by one, so no filtering, and no
+// repetition at the edges
+pub fn shadow_sampler() -> SamplerBehavior {
+    SamplerBehavior {
+        wrap_function: (SamplerWrapFunction::Clamp, SamplerWrapFunction::Clamp, SamplerWrapFunction::Clamp),
+        minify_filter: MinifySamplerFilter::Nearest,
+        magnify_filter: MagnifySamplerFilter::Nearest,
+        .. Default::default()
+    }
+}
+
+// The shadow map of one light
+pub struct ShadowMap {
+    texture: DepthTexture2d,
+    pass: DepthPass,
+    pub settings: ShadowSettings,
+    light_view_projection: Matrix4<f32>
+}
+
+impl ShadowMap {
+    pub fn new<F: Facade>(facade: &F, settings: ShadowSettings) -> Result<ShadowMap, ShadowError> {
+        let size = settings.size;
+        Ok(ShadowMap {
+            texture: DepthTexture2d::empty_with_format(facade, DepthFormat::F32, MipmapsOption::NoMipmap, size, size)?,
+            pass: DepthPass::new(facade)?,
+            settings: settings,
+            light_view_projection: Matrix4::from_scale(1.0)
+        })
+    }
+
+    pub fn texture(&self) -> &DepthTexture2d {
+        &self.texture
+    }
+
+    // The view and projection of the light the last time the map was rendered
+    pub fn light_view_projection(&self) -> Matrix4<f32> {
+        self.light_view_projection
+    }
+
+    // Render the casters, each with its world matrix, from the camera and the projection of
+    // `light`
+    pub fn render<F: Facade>(&mut self, facade: &F, light: &mut Pipeline, casters: &[(&GpuMesh, Matrix4<f32>)])
+            -> Result<(), ShadowError> {
+        self.light_view_projection = light.get_project_trans() * light.get_view_trans();
+
+        let mut framebuffer = SimpleFrameBuffer::depth_only(facade, &self.texture)?;
+        framebuffer.clear_depth(1.0);
+        self.pass.draw(&mut framebuffer, self.light_view_projection, casters)?;
+        Ok(())
+    }
+
+    // The values of `gShadow`
+    pub fn uniform_values(&self) -> Vec<(&'static str, UniformValue<'_>)> {
+        vec![
+            ("LightVP", UniformValue::Mat4(self.light_view_projection.into())),
+            ("Bias", UniformValue::Float(self.settings.bias)),
+            ("SlopeBias", UniformValue::Float(self.settings.slope_bias)),
+            ("PcfRadius", UniformValue::SignedInt(self.settings.pcf_radius.max(0))),
+            ("Map", UniformValue::DepthTexture2d(&self.texture, Some(shadow_sampler())))
+        ]
+    }
+}
+
+impl<'a, U: Uniforms> LightUniforms<'a, U> {
+    // Fill `gShadow`, as `SHADOW_GLSL` declares it
+    pub fn with_shadow_map(self, shadow_map: &'a ShadowMap) -> LightUniforms<'a, U> {
+        self.with_struct("gShadow", shadow_map.uniform_values())
+    }
+}
@@ -0,0 +1,107 @@
+use cgmath::{Matrix4, Vector4};
+use glium::{Depth, DepthTest, DrawError, DrawParameters, Program, ProgramCreationError, Surface};
+use glium::backend::Facade;
+
+use mesh::{GpuMesh, UploadError};
+use mesh::primitives;
+use pipeline::Pipeline;
+use texture::SampledTexture;
+
+const VERTEX_SHADER: &str = r#"
+    #version 330
+
+    in vec3 position;
+
+    uniform mat4 gVP;
+
+    out vec3 direction;
+
+    void main() {
+        // z = w puts the sky at the far plane, behind everything else
+        gl_Position = (gVP * vec4(position, 1.0)).xyww;
+        direction = position;
+    }
+"#;
+
+const FRAGMENT_SHADER: &str = r#"
+    #version 330
+
+    in vec3 direction;
+
+    out vec4 fragColor;
+
+    uniform samplerCube gCubemap;
+    uniform float gExposure;
+
+    void main() {
+        fragColor = vec4(texture(gCubemap, direction).rgb * gExposure, 1.0);
+    }
+"#;
+
+#[derive(Debug)]
+pub enum SkyboxError {
+    Upload(UploadError),
+    Program(ProgramCreationError)
+}
+
+impl From<UploadError> for SkyboxError {
+    fn from(err: UploadError) -> SkyboxError {
+        SkyboxError::Upload(err)
+    }
+}
+
+impl From<ProgramCreationError> for SkyboxError {
+    fn from(err: ProgramCreationError) -> SkyboxError {
+        SkyboxError::Program(err)
+    }
+}
+
+// A cube map drawn around the camera. The cube follows the camera, so the sky never gets closer,
+// and it is drawn at the maximum depth, so it only fills the pixels nothing else covers. Drawing
+// it after the opaque objects saves shading the hidden pixels.
+pub struct Skybox {
+    cube: GpuMesh,
+    program: Program,
+    // Scales the colors, for HDR cube maps
+    pub exposure: f32
+}
+
+impl Skybox {
+    pub fn new<F: Facade>(facade: &F) -> Result<Skybox, SkyboxError> {
+        Ok(Skybox {
+            cube: primitives::cube(2.0, 1).upload(facade)?,
+            program: Program::from_source(facade, VERTEX_SHADER, FRAGMENT_SHADER, None)?,
+            exposure: 1.0
+        })
+    }
+
+    // The view of the pipeline without its translation, then its projection
+    pub fn view_projection(pipeline: &mut Pipeline) -> Matrix4<f32> {
+        let mut view = pipeline.get_view_trans();
+        view.w = Vector4::new(0.0, 0.0, 0.0, 1.0);
+        pipeline.get_project_trans() * view
+    }
+
+    // Draw with the camera and the projection of `pipeline`. The depth test must accept the far
+    // plane, i.e. the depth buffer is cleared to 1.
+    pub fn draw<S: Surface>(&self, surface: &mut S, pipeline: &mut Pipeline, cubemap: SampledTexture<'_>)
+            -> Result<(), DrawError> {
+        let view_projection: [[f32; 4]; 4] = Skybox::view_projection(pipeline).into();
+        let uniforms = uniform! {
+            gVP: view_projection,
+            gCubemap: cubemap,
+            gExposure: self.exposure
+        };
+
+        // Seen from the inside, so no culling, and the sky never hides what is drawn after it
+        let params = DrawParameters {
+            depth: Depth {
+                test: DepthTest::IfLessOrEqual,
+                write: false,
+                .. Default::default()
+            },
+            .. Default::default()
+        };
+        self.cube.draw(surface, &self.program, &uniforms, &params)
+    }
+}
@@ -0,0 +1,299 @@
+use cgmath::{InnerSpace, Quaternion, Vector3};
+
+// A value that can be placed on a spline. Both spline types below are evaluated by repeated
+// blending (De Casteljau / Barry-Goldman), so positions use a linear interpolation and
+// orientations use a spherical one.
+pub trait SplinePoint: Copy {
+    fn blend(a: Self, b: Self, t: f32) -> Self;
+}
+
+impl SplinePoint for Vector3<f32> {
+    fn blend(a: Vector3<f32>, b: Vector3<f32>, t: f32) -> Vector3<f32> {
+        a + (b - a) * t
+    }
+}
+
+impl SplinePoint for Quaternion<f32> {
+    fn blend(a: Quaternion<f32>, b: Quaternion<f32>, t: f32) -> Quaternion<f32> {
+        // Always take the shortest arc
+        let b = if a.dot(b) < 0.0 { -b } else { b };
+        a.slerp(b, t).normalize()
+    }
+}
+
+pub trait Spline<T> {
+    // Evaluate the curve at `t`, which runs from 0.0 (the first point) to 1.0 (the last point)
+    fn evaluate(&self, t: f32) -> T;
+}
+
+// Split a global parameter into (segment index, local parameter)
+fn locate_segment(t: f32, segment_count: usize) -> (usize, f32) {
+    let t = t.clamp(0.0, 1.0) * segment_count as f32;
+    let index = (t.floor() as usize).min(segment_count - 1);
+    (index, t - index as f32)
+}
+
+// A uniform Catmull-Rom spline passing through every point. The end points are duplicated so
+// that the curve starts and stops exactly at the first and the last point.
+pub struct CatmullRom<T> {
+    points: Vec<T>
+}
+
+impl<T: SplinePoint> CatmullRom<T> {
+    pub fn new(points: Vec<T>) -> CatmullRom<T> {
+        assert!(points.len() >= 2, "a Catmull-Rom spline needs at least 2 points");
+        CatmullRom { points: points }
+    }
+
+    pub fn points(&self) -> &[T] {
+        &self.points
+    }
+
+    pub fn segment_count(&self) -> usize {
+        self.points.len() - 1
+    }
+
+    fn point(&self, index: isize) -> T {
+        let last = self.points.len() as isize - 1;
+        self.points[index.max(0).min(last) as usize]
+    }
+
+    pub fn evaluate_segment(&self, segment: usize, t: f32) -> T {
+        let i = segment as isize;
+        let p0 = self.point(i - 1);
+        let p1 = self.point(i);
+        let p2 = self.point(i + 1);
+        let p3 = self.point(i + 2);
+
+        // Barry and Goldman's pyramidal formulation, which only needs blending
+        let a1 = T::blend(p0, p1, t + 1.0);
+        let a2 = T::blend(p1, p2, t);
+        let a3 = T::blend(p2, p3, t - 1.0);
+        let b1 = T::blend(a1, a2, (t + 1.0) / 2.0);
+        let b2 = T::blend(a2, a3, t / 2.0);
+        T::blend(b1, b2, t)
+    }
+}
+
+impl<T: SplinePoint> Spline<T> for CatmullRom<T> {
+    fn evaluate(&self, t: f32) -> T {
+        let (segment, local_t) = locate_segment(t, self.segment_count());
+        self.evaluate_segment(segment, local_t)
+    }
+}
+
+// A chain of cubic Bézier segments. The points are laid out as
+// [p0, c0, c1, p1, c2, c3, p2, ...], so there must be 3n + 1 of them.
+pub struct CubicBezier<T> {
+    points: Vec<T>
+}
+
+impl<T: SplinePoint> CubicBezier<T> {
+    pub fn new(points: Vec<T>) -> CubicBezier<T> {
+        assert!(points.len() >= 4 && (points.len() - 1).is_multiple_of(3),
+            "a cubic Bézier spline needs 3n + 1 control points");
+        CubicBezier { points: points }
+    }
+
+    pub fn points(&self) -> &[T] {
+        &self.points
+    }
+
+    pub fn segment_count(&self) -> usize {
+        (self.points.len() - 1) / 3
+    }
+
+    pub fn evaluate_segment(&self, segment: usize, t: f32) -> T {
+        let p = &self.points[segment * 3..segment * 3 + 4];
+
+        // De Casteljau's algorithm
+        let a1 = T::blend(p[0], p[1], t);
+        let a2 = T::blend(p[1], p[2], t);
+        let a3 = T::blend(p[2], p[3], t);
+        let b1 = T::blend(a1, a2, t);
+        let b2 = T::blend(a2, a3, t);
+        T::blend(b1, b2, t)
+    }
+}
+
+impl<T: SplinePoint> Spline<T> for CubicBezier<T> {
+    fn evaluate(&self, t: f32) -> T {
+        let (segment, local_t) = locate_segment(t, self.segment_count());
+        self.evaluate_segment(segment, local_t)
+    }
+}
+
+// Maps distances along a position spline back to the curve parameter, so that the curve can be
+// traversed at a constant speed.
+pub struct ArcLengthTable {
+    params: Vec<f32>,
+    distances: Vec<f32>
+}
+
+impl ArcLengthTable {
+    pub fn new<S: Spline<Vector3<f32>> + ?Sized>(spline: &S, samples: usize) -> ArcLengthTable {
+        let samples = samples.max(1);
+        let mut params = Vec::with_capacity(samples + 1);
+        let mut distances = Vec::with_capacity(samples + 1);
+
+        let mut last = spline.evaluate(0.0);
+        let mut distance = 0.0;
+        params.push(0.0);
+        distances.push(0.0);
+
+        for i in 1..(samples + 1) {
+            let t = i as f32 / samples as f32;
+            let current = spline.evaluate(t);
+            distance += (current - last).magnitude();
+            last = current;
+
+            params.push(t);
+            distances.push(distance);
+        }
+
+        ArcLengthTable {
+            params: params,
+            distances: distances
+        }
+    }
+
+    pub fn length(&self) -> f32 {
+        *self.distances.last().unwrap()
+    }
+
+    // Find the curve parameter which is `distance` away from the start of the curve
+    pub fn param_at_distance(&self, distance: f32) -> f32 {
+        if distance <= 0.0 {
+            return 0.0;
+        } else if distance >= self.length() {
+            return 1.0;
+        }
+
+        // Binary search for the first sample beyond the distance
+        let mut low = 0;
+        let mut high = self.distances.len() - 1;
+        while low + 1 < high {
+            let middle = (low + high) / 2;
+            if self.distances[middle] < distance {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+
+        let span = self.distances[high] - self.distances[low];
+        if span <= 0.0 {
+            return self.params[low];
+        }
+
+        let ratio = (distance - self.distances[low]) / span;
+        self.params[low] + (self.params[high] - self.params[low]) * ratio
+    }
+
+    // Same as `param_at_distance`, but `fraction` is in [0.0, 1.0] of the total length
+    pub fn param_at_fraction(&self, fraction: f32) -> f32 {
+        self.param_at_distance(fraction * self.length())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Vector3};
+
+    use super::{locate_segment, ArcLengthTable, CatmullRom, CubicBezier, Spline};
+
+    fn close(a: Vector3<f32>, b: Vector3<f32>) -> bool {
+        (a - b).magnitude() < 1e-5
+    }
+
+    fn points() -> Vec<Vector3<f32>> {
+        vec![
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(1.0, 2.0, 0.0),
+            Vector3::new(3.0, 2.0, 1.0),
+            Vector3::new(4.0, 0.0, 3.0)
+        ]
+    }
+
+    #[test]
+    fn catmull_rom_passes_through_its_points() {
+        let points = points();
+        let spline = CatmullRom::new(points.clone());
+        assert!(close(spline.evaluate(0.0), points[0]));
+        assert!(close(spline.evaluate(1.0), points[3]));
+        assert!(close(spline.evaluate(1.0 / 3.0), points[1]));
+        assert!(close(spline.evaluate(2.0 / 3.0), points[2]));
+        for segment in 0..spline.segment_count() {
+            assert!(close(spline.evaluate_segment(segment, 0.0), points[segment]));
+            assert!(close(spline.evaluate_segment(segment, 1.0), points[segment + 1]));
+        }
+    }
+
+    #[test]
+    fn bezier_interpolates_its_end_points_only() {
+        let mut points = points();
+        points.extend_from_slice(&[Vector3::new(5.0, -2.0, 3.0), Vector3::new(6.0, -2.0, 2.0),
+            Vector3::new(7.0, 0.0, 0.0)]);
+        let spline = CubicBezier::new(points.clone());
+        assert_eq!(spline.segment_count(), 2);
+        assert!(close(spline.evaluate(0.0), points[0]));
+        assert!(close(spline.evaluate(0.5), points[3]));
+        assert!(close(spline.evaluate(1.0), points[6]));
+        // The control points pull the curve without being on it
+        assert!(!close(spline.evaluate(1.0 / 6.0), points[1]));
+    }
+
+    #[test]
+    #[should_panic]
+    fn bezier_rejects_incomplete_segments() {
+        let mut points = points();
+        points.push(Vector3::new(0.0, 0.0, 0.0));
+        CubicBezier::new(points);
+    }
+
+    #[test]
+    fn segments_are_located_at_the_ends_and_knots() {
+        assert_eq!(locate_segment(0.0, 4), (0, 0.0));
+        // The last point belongs to the last segment, not to a fifth one
+        assert_eq!(locate_segment(1.0, 4), (3, 1.0));
+        assert_eq!(locate_segment(0.5, 4), (2, 0.0));
+        assert_eq!(locate_segment(0.25, 4), (1, 0.0));
+        // Out of range parameters are clamped
+        assert_eq!(locate_segment(-1.0, 4), (0, 0.0));
+        assert_eq!(locate_segment(2.0, 4), (3, 1.0));
+    }
+
+    #[test]
+    fn arc_length_maps_distances_monotonically() {
+        let spline = CatmullRom::new(points());
+        let table = ArcLengthTable::new(&spline, 256);
+        assert!(table.length() > 0.0);
+        assert_eq!(table.param_at_distance(0.0), 0.0);
+        assert_eq!(table.param_at_distance(table.length()), 1.0);
+
+        let mut last = 0.0;
+        for i in 1..101 {
+            let t = table.param_at_fraction(i as f32 / 100.0);
+            assert!(t > last, "{} is not after {}", t, last);
+            last = t;
+        }
+    }
+
+    #[test]
+    fn arc_length_samples_at_a_constant_speed() {
+        // Control points bunched at the start, so that the parameter alone runs at an uneven speed
+        let spline = CubicBezier::new(vec![Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.1, 0.0, 0.0),
+            Vector3::new(0.2, 0.0, 0.0), Vector3::new(10.0, 0.0, 0.0)]);
+        let table = ArcLengthTable::new(&spline, 1024);
+        assert!((table.length() - 10.0).abs() < 1e-3);
+
+        let steps = 20;
+        let step = table.length() / steps as f32;
+        let mut last = spline.evaluate(0.0);
+        for i in 1..(steps + 1) {
+            let current = spline.evaluate(table.param_at_distance(i as f32 * step));
+            assert!(((current - last).magnitude() - step).abs() < 0.01);
+            last = current;
+        }
+    }
+}
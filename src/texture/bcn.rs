@@ -0,0 +1,684 @@
+// Decoders for the BC1 to BC7 block formats (S3TC, RGTC and BPTC in OpenGL), used when the GPU
+// cannot sample them directly.
+//
+// Every block covers 4x4 pixels, stored row by row in the output arrays. BC4 and BC5 decode to
+// (r, 0, 0, 255) and (r, g, 0, 255) like the GPU samples them. BC6H decodes to linear floats.
+
+const BC7_WEIGHTS_2: [u32; 4] = [0, 21, 43, 64];
+const BC7_WEIGHTS_3: [u32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+const BC7_WEIGHTS_4: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+// The subset of each pixel in the 64 partitions of 2 subsets, one bit per pixel
+const PARTITIONS_2: [u16; 64] = [
+    0xCCCC, 0x8888, 0xEEEE, 0xECC8, 0xC880, 0xFEEC, 0xFEC8, 0xEC80,
+    0xC800, 0xFFEC, 0xFE80, 0xE800, 0xFFE8, 0xFF00, 0xFFF0, 0xF000,
+    0xF710, 0x008E, 0x7100, 0x08CE, 0x008C, 0x7310, 0x3100, 0x8CCE,
+    0x088C, 0x3110, 0x6666, 0x366C, 0x17E8, 0x0FF0, 0x718E, 0x399C,
+    0xAAAA, 0xF0F0, 0x5A5A, 0x33CC, 0x3C3C, 0x55AA, 0x9696, 0xA55A,
+    0x73CE, 0x13C8, 0x324C, 0x3BDC, 0x6996, 0xC33C, 0x9966, 0x0660,
+    0x0272, 0x04E4, 0x4E40, 0x2720, 0xC936, 0x936C, 0x39C6, 0x639C,
+    0x9336, 0x9CC6, 0x817E, 0xE718, 0xCCF0, 0x0FCC, 0x7744, 0xEE22
+];
+
+// The subset of each pixel in the 64 partitions of 3 subsets, two bits per pixel
+const PARTITIONS_3: [u32; 64] = [
+    0xAA68_5050, 0x6A5A_5040, 0x5A5A_4200, 0x5450_A0A8, 0xA5A5_0000, 0xA0A0_5050, 0x5555_A0A0, 0x5A5A_5050,
+    0xAA55_0000, 0xAA55_5500, 0xAAAA_5500, 0x9090_9090, 0x9494_9494, 0xA4A4_A4A4, 0xA9A5_9450, 0x2A0A_4250,
+    0xA594_5040, 0x0A42_5054, 0xA5A5_A500, 0x55A0_A0A0, 0xA8A8_5454, 0x6A6A_4040, 0xA4A4_5000, 0x1A1A_0500,
+    0x0050_A4A4, 0xAAA5_9090, 0x1469_6914, 0x6969_1400, 0xA085_85A0, 0xAA82_1414, 0x50A4_A450, 0x6A5A_0200,
+    0xA9A5_8000, 0x5090_A0A8, 0xA8A0_9050, 0x2424_2424, 0x00AA_5500, 0x2492_4924, 0x2449_9224, 0x50A5_0A50,
+    0x500A_A550, 0xAAAA_4444, 0x6666_0000, 0xA5A0_A5A0, 0x50A0_50A0, 0x6928_6928, 0x44AA_AA44, 0x6666_6600,
+    0xAA44_4444, 0x54A8_54A8, 0x9580_9580, 0x9696_9600, 0xA854_54A8, 0x8095_9580, 0xAA14_1414, 0x9696_0000,
+    0xAAAA_1414, 0xA050_50A0, 0xA0A5_A5A0, 0x9600_0000, 0x4080_4080, 0xA9A8_A9A8, 0xAAAA_AA44, 0x2A4A_5254
+];
+
+// The anchor pixel of the second subset of the partitions of 2 subsets. The anchor of the first
+// subset is always pixel 0.
+const ANCHORS_2: [u8; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15,
+    15, 15, 15, 15, 15, 15, 15, 15,
+    15, 2, 8, 2, 2, 8, 8, 15,
+    2, 8, 2, 2, 8, 8, 2, 2,
+    15, 15, 6, 8, 2, 8, 15, 15,
+    2, 8, 2, 2, 2, 15, 15, 6,
+    6, 2, 6, 8, 15, 15, 2, 2,
+    15, 15, 15, 15, 15, 2, 2, 15
+];
+
+// The anchors of the second and third subsets of the partitions of 3 subsets
+const ANCHORS_3_SECOND: [u8; 64] = [
+    3, 3, 15, 15, 8, 3, 15, 15,
+    8, 8, 6, 6, 6, 5, 3, 3,
+    3, 3, 8, 15, 3, 3, 6, 10,
+    5, 8, 8, 6, 8, 5, 15, 15,
+    8, 15, 3, 5, 6, 10, 8, 15,
+    15, 3, 15, 5, 15, 15, 15, 15,
+    3, 15, 5, 5, 5, 8, 5, 10,
+    5, 10, 8, 13, 15, 12, 3, 3
+];
+
+const ANCHORS_3_THIRD: [u8; 64] = [
+    15, 8, 8, 3, 15, 15, 3, 8,
+    15, 15, 15, 15, 15, 15, 15, 8,
+    15, 8, 15, 3, 15, 8, 15, 8,
+    3, 15, 6, 10, 15, 15, 10, 8,
+    15, 3, 15, 10, 10, 8, 9, 10,
+    6, 15, 8, 15, 3, 6, 6, 8,
+    15, 3, 15, 15, 15, 15, 15, 15,
+    15, 15, 15, 15, 3, 15, 15, 8
+];
+
+// Reads the bits of a 128 bits block from the least significant one
+struct Bits {
+    value: u128,
+    position: u32
+}
+
+impl Bits {
+    fn new(block: &[u8]) -> Bits {
+        let mut bytes = [0u8; 16];
+        bytes.copy_from_slice(&block[..16]);
+        Bits {
+            value: u128::from_le_bytes(bytes),
+            position: 0
+        }
+    }
+
+    fn read(&mut self, count: u32) -> u32 {
+        let value = ((self.value >> self.position) & ((1u128 << count) - 1)) as u32;
+        self.position += count;
+        value
+    }
+}
+
+fn subset(partition: usize, subsets: usize, pixel: usize) -> usize {
+    match subsets {
+        1 => 0,
+        2 => (PARTITIONS_2[partition] >> pixel) as usize & 1,
+        _ => (PARTITIONS_3[partition] >> (pixel * 2)) as usize & 3
+    }
+}
+
+fn is_anchor(partition: usize, subsets: usize, pixel: usize) -> bool {
+    pixel == 0 || match subsets {
+        1 => false,
+        2 => pixel == ANCHORS_2[partition] as usize,
+        _ => pixel == ANCHORS_3_SECOND[partition] as usize || pixel == ANCHORS_3_THIRD[partition] as usize
+    }
+}
+
+fn rgb565(color: u16) -> [u32; 3] {
+    let r = u32::from(color >> 11) & 31;
+    let g = u32::from(color >> 5) & 63;
+    let b = u32::from(color) & 31;
+    [(r << 3) | (r >> 2), (g << 2) | (g >> 4), (b << 3) | (b >> 2)]
+}
+
+// The color part of BC1, BC2 and BC3. Only BC1 has the 3 colors mode with transparent black.
+fn decode_color_block(block: &[u8], allow_transparency: bool, out: &mut [[u8; 4]; 16]) {
+    let color0 = u16::from_le_bytes([block[0], block[1]]);
+    let color1 = u16::from_le_bytes([block[2], block[3]]);
+    let (c0, c1) = (rgb565(color0), rgb565(color1));
+
+    let mut palette = [[0u8; 4]; 4];
+    for channel in 0..3 {
+        palette[0][channel] = c0[channel] as u8;
+        palette[1][channel] = c1[channel] as u8;
+        if color0 > color1 || !allow_transparency {
+            palette[2][channel] = ((2 * c0[channel] + c1[channel]) / 3) as u8;
+            palette[3][channel] = ((c0[channel] + 2 * c1[channel]) / 3) as u8;
+        } else {
+            palette[2][channel] = ((c0[channel] + c1[channel]) / 2) as u8;
+        }
+    }
+    palette[0][3] = 255;
+    palette[1][3] = 255;
+    palette[2][3] = 255;
+    palette[3][3] = if color0 > color1 || !allow_transparency { 255 } else { 0 };
+
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    for (pixel, color) in out.iter_mut().enumerate() {
+        *color = palette[(indices >> (pixel * 2)) as usize & 3];
+    }
+}
+
+// The 8 bytes single channel block of BC3, BC4 and BC5
+fn decode_channel_block(block: &[u8], out: &mut [u8; 16]) {
+    let (a0, a1) = (u32::from(block[0]), u32::from(block[1]));
+    let mut palette = [a0, a1, 0, 0, 0, 0, 0, 255];
+    if a0 > a1 {
+        for i in 1..7 {
+            palette[i + 1] = ((7 - i as u32) * a0 + i as u32 * a1) / 7;
+        }
+    } else {
+        for i in 1..5 {
+            palette[i + 1] = ((5 - i as u32) * a0 + i as u32 * a1) / 5;
+        }
+    }
+
+    let mut bytes = [0u8; 8];
+    bytes[..6].copy_from_slice(&block[2..8]);
+    let indices = u64::from_le_bytes(bytes);
+    for (pixel, value) in out.iter_mut().enumerate() {
+        *value = palette[(indices >> (pixel * 3)) as usize & 7] as u8;
+    }
+}
+
+pub fn decode_bc1(block: &[u8], out: &mut [[u8; 4]; 16]) {
+    decode_color_block(block, true, out);
+}
+
+// Explicit 4 bits alpha
+pub fn decode_bc2(block: &[u8], out: &mut [[u8; 4]; 16]) {
+    decode_color_block(&block[8..], false, out);
+    for (pixel, color) in out.iter_mut().enumerate() {
+        let alpha = (block[pixel / 2] >> ((pixel % 2) * 4)) & 15;
+        color[3] = alpha * 17;
+    }
+}
+
+// Interpolated alpha
+pub fn decode_bc3(block: &[u8], out: &mut [[u8; 4]; 16]) {
+    decode_color_block(&block[8..], false, out);
+    let mut alpha = [0u8; 16];
+    decode_channel_block(block, &mut alpha);
+    for (color, &alpha) in out.iter_mut().zip(alpha.iter()) {
+        color[3] = alpha;
+    }
+}
+
+pub fn decode_bc4(block: &[u8], out: &mut [[u8; 4]; 16]) {
+    let mut red = [0u8; 16];
+    decode_channel_block(block, &mut red);
+    for (color, &red) in out.iter_mut().zip(red.iter()) {
+        *color = [red, 0, 0, 255];
+    }
+}
+
+pub fn decode_bc5(block: &[u8], out: &mut [[u8; 4]; 16]) {
+    let (mut red, mut green) = ([0u8; 16], [0u8; 16]);
+    decode_channel_block(block, &mut red);
+    decode_channel_block(&block[8..], &mut green);
+    for (pixel, color) in out.iter_mut().enumerate() {
+        *color = [red[pixel], green[pixel], 0, 255];
+    }
+}
+
+struct Bc7Mode {
+    subsets: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,
+    endpoint_p_bits: bool,
+    shared_p_bits: bool,
+    index_bits: u32,
+    // 0 when the alpha uses the same indices as the color
+    secondary_index_bits: u32
+}
+
+const BC7_MODES: [Bc7Mode; 8] = [
+    Bc7Mode { subsets: 3, partition_bits: 4, rotation_bits: 0, index_selection_bits: 0, color_bits: 4,
+        alpha_bits: 0, endpoint_p_bits: true, shared_p_bits: false, index_bits: 3, secondary_index_bits: 0 },
+    Bc7Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 6,
+        alpha_bits: 0, endpoint_p_bits: false, shared_p_bits: true, index_bits: 3, secondary_index_bits: 0 },
+    Bc7Mode { subsets: 3, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 5,
+        alpha_bits: 0, endpoint_p_bits: false, shared_p_bits: false, index_bits: 2, secondary_index_bits: 0 },
+    Bc7Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 7,
+        alpha_bits: 0, endpoint_p_bits: true, shared_p_bits: false, index_bits: 2, secondary_index_bits: 0 },
+    Bc7Mode { subsets: 1, partition_bits: 0, rotation_bits: 2, index_selection_bits: 1, color_bits: 5,
+        alpha_bits: 6, endpoint_p_bits: false, shared_p_bits: false, index_bits: 2, secondary_index_bits: 3 },
+    Bc7Mode { subsets: 1, partition_bits: 0, rotation_bits: 2, index_selection_bits: 0, color_bits: 7,
+        alpha_bits: 8, endpoint_p_bits: false, shared_p_bits: false, index_bits: 2, secondary_index_bits: 2 },
+    Bc7Mode { subsets: 1, partition_bits: 0, rotation_bits: 0, index_selection_bits: 0, color_bits: 7,
+        alpha_bits: 7, endpoint_p_bits: true, shared_p_bits: false, index_bits: 4, secondary_index_bits: 0 },
+    Bc7Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 5,
+        alpha_bits: 5, endpoint_p_bits: true, shared_p_bits: false, index_bits: 2, secondary_index_bits: 0 }
+];
+
+fn bc7_weights(bits: u32) -> &'static [u32] {
+    match bits {
+        2 => &BC7_WEIGHTS_2,
+        3 => &BC7_WEIGHTS_3,
+        _ => &BC7_WEIGHTS_4
+    }
+}
+
+fn bc7_interpolate(e0: u32, e1: u32, weight: u32) -> u8 {
+    (((64 - weight) * e0 + weight * e1 + 32) >> 6) as u8
+}
+
+// Reserved modes decode to transparent black, as the GPU does
+pub fn decode_bc7(block: &[u8], out: &mut [[u8; 4]; 16]) {
+    let mut bits = Bits::new(block);
+    let mode_index = match (0..8).find(|_| bits.read(1) == 1) {
+        Some(mode) => mode,
+        None => {
+            *out = [[0; 4]; 16];
+            return;
+        }
+    };
+    let mode = &BC7_MODES[mode_index];
+
+    let partition = bits.read(mode.partition_bits) as usize;
+    let rotation = bits.read(mode.rotation_bits);
+    let index_selection = bits.read(mode.index_selection_bits);
+
+    // endpoints[subset * 2 + end][channel], channels in RGBA order
+    let endpoint_count = mode.subsets * 2;
+    let mut endpoints = [[0u32; 4]; 6];
+    for channel in 0..3 {
+        for endpoint in endpoints.iter_mut().take(endpoint_count) {
+            endpoint[channel] = bits.read(mode.color_bits);
+        }
+    }
+    for endpoint in endpoints.iter_mut().take(endpoint_count) {
+        endpoint[3] = if mode.alpha_bits > 0 { bits.read(mode.alpha_bits) } else { 255 };
+    }
+
+    let mut color_bits = mode.color_bits;
+    let mut alpha_bits = mode.alpha_bits;
+    if mode.endpoint_p_bits || mode.shared_p_bits {
+        let mut p_bits = [0u32; 6];
+        if mode.endpoint_p_bits {
+            for p_bit in p_bits.iter_mut().take(endpoint_count) {
+                *p_bit = bits.read(1);
+            }
+        } else {
+            for subset in 0..mode.subsets {
+                let p_bit = bits.read(1);
+                p_bits[subset * 2] = p_bit;
+                p_bits[subset * 2 + 1] = p_bit;
+            }
+        }
+        for (endpoint, &p_bit) in endpoints.iter_mut().zip(p_bits.iter()).take(endpoint_count) {
+            for value in endpoint.iter_mut().take(3) {
+                *value = (*value << 1) | p_bit;
+            }
+            if mode.alpha_bits > 0 {
+                endpoint[3] = (endpoint[3] << 1) | p_bit;
+            }
+        }
+        color_bits += 1;
+        if alpha_bits > 0 {
+            alpha_bits += 1;
+        }
+    }
+
+    // Back to 8 bits by replicating the high bits
+    for endpoint in endpoints.iter_mut().take(endpoint_count) {
+        for value in endpoint.iter_mut().take(3) {
+            let shifted = *value << (8 - color_bits);
+            *value = shifted | (shifted >> color_bits);
+        }
+        if alpha_bits > 0 {
+            let value = endpoint[3] << (8 - alpha_bits);
+            endpoint[3] = value | (value >> alpha_bits);
+        }
+    }
+
+    let mut indices = [0u32; 16];
+    for (pixel, index) in indices.iter_mut().enumerate() {
+        let anchor = is_anchor(partition, mode.subsets, pixel);
+        *index = bits.read(if anchor { mode.index_bits - 1 } else { mode.index_bits });
+    }
+    let mut secondary_indices = [0u32; 16];
+    if mode.secondary_index_bits > 0 {
+        for (pixel, index) in secondary_indices.iter_mut().enumerate() {
+            *index = bits.read(if pixel == 0 { mode.secondary_index_bits - 1 } else { mode.secondary_index_bits });
+        }
+    }
+
+    for (pixel, color) in out.iter_mut().enumerate() {
+        let subset = subset(partition, mode.subsets, pixel);
+        let (e0, e1) = (endpoints[subset * 2], endpoints[subset * 2 + 1]);
+
+        let (color_weight, alpha_weight) = if mode.secondary_index_bits == 0 {
+            let weight = bc7_weights(mode.index_bits)[indices[pixel] as usize];
+            (weight, weight)
+        } else {
+            let primary = bc7_weights(mode.index_bits)[indices[pixel] as usize];
+            let secondary = bc7_weights(mode.secondary_index_bits)[secondary_indices[pixel] as usize];
+            if index_selection == 0 { (primary, secondary) } else { (secondary, primary) }
+        };
+
+        for channel in 0..3 {
+            color[channel] = bc7_interpolate(e0[channel], e1[channel], color_weight);
+        }
+        color[3] = bc7_interpolate(e0[3], e1[3], alpha_weight);
+
+        match rotation {
+            1 => color.swap(0, 3),
+            2 => color.swap(1, 3),
+            3 => color.swap(2, 3),
+            _ => ()
+        }
+    }
+}
+
+// The fields of the BC6H endpoints: red, green and blue of the four endpoints
+const R0: usize = 0;
+const G0: usize = 1;
+const B0: usize = 2;
+const R1: usize = 3;
+const G1: usize = 4;
+const B1: usize = 5;
+const R2: usize = 6;
+const G2: usize = 7;
+const B2: usize = 8;
+const R3: usize = 9;
+const G3: usize = 10;
+const B3: usize = 11;
+
+struct Bc6hMode {
+    // The 2 or 5 bits value which selects the mode
+    value: u32,
+    subsets: usize,
+    transformed: bool,
+    endpoint_bits: u32,
+    delta_bits: [u32; 3],
+    // The bits of the endpoints in the order they are stored: (field, a, b) is the bits b to a of
+    // the field from the lowest one, or the bits b down to a when a < b.
+    layout: &'static [(usize, u32, u32)]
+}
+
+const BC6H_MODES: [Bc6hMode; 14] = [
+    Bc6hMode { value: 0, subsets: 2, transformed: true, endpoint_bits: 10, delta_bits: [5, 5, 5], layout: &[
+        (G2, 4, 4), (B2, 4, 4), (B3, 4, 4), (R0, 9, 0), (G0, 9, 0), (B0, 9, 0), (R1, 4, 0), (G3, 4, 4),
+        (G2, 3, 0), (G1, 4, 0), (B3, 0, 0), (G3, 3, 0), (B1, 4, 0), (B3, 1, 1), (B2, 3, 0), (R2, 4, 0),
+        (B3, 2, 2), (R3, 4, 0), (B3, 3, 3)] },
+    Bc6hMode { value: 1, subsets: 2, transformed: true, endpoint_bits: 7, delta_bits: [6, 6, 6], layout: &[
+        (G2, 5, 5), (G3, 4, 4), (G3, 5, 5), (R0, 6, 0), (B3, 0, 0), (B3, 1, 1), (B2, 4, 4), (G0, 6, 0),
+        (B2, 5, 5), (B3, 2, 2), (G2, 4, 4), (B0, 6, 0), (B3, 3, 3), (B3, 5, 5), (B3, 4, 4), (R1, 5, 0),
+        (G2, 3, 0), (G1, 5, 0), (G3, 3, 0), (B1, 5, 0), (B2, 3, 0), (R2, 5, 0), (R3, 5, 0)] },
+    Bc6hMode { value: 2, subsets: 2, transformed: true, endpoint_bits: 11, delta_bits: [5, 4, 4], layout: &[
+        (R0, 9, 0), (G0, 9, 0), (B0, 9, 0), (R1, 4, 0), (R0, 10, 10), (G2, 3, 0), (G1, 3, 0), (G0, 10, 10),
+        (B3, 0, 0), (G3, 3, 0), (B1, 3, 0), (B0, 10, 10), (B3, 1, 1), (B2, 3, 0), (R2, 4, 0), (B3, 2, 2),
+        (R3, 4, 0), (B3, 3, 3)] },
+    Bc6hMode { value: 6, subsets: 2, transformed: true, endpoint_bits: 11, delta_bits: [4, 5, 4], layout: &[
+        (R0, 9, 0), (G0, 9, 0), (B0, 9, 0), (R1, 3, 0), (R0, 10, 10), (G3, 4, 4), (G2, 3, 0), (G1, 4, 0),
+        (G0, 10, 10), (G3, 3, 0), (B1, 3, 0), (B0, 10, 10), (B3, 1, 1), (B2, 3, 0), (R2, 3, 0), (B3, 0, 0),
+        (B3, 2, 2), (R3, 3, 0), (G2, 4, 4), (B3, 3, 3)] },
+    Bc6hMode { value: 10, subsets: 2, transformed: true, endpoint_bits: 11, delta_bits: [4, 4, 5], layout: &[
+        (R0, 9, 0), (G0, 9, 0), (B0, 9, 0), (R1, 3, 0), (R0, 10, 10), (B2, 4, 4), (G2, 3, 0), (G1, 3, 0),
+        (G0, 10, 10), (B3, 0, 0), (G3, 3, 0), (B1, 4, 0), (B0, 10, 10), (B2, 3, 0), (R2, 3, 0), (B3, 1, 1),
+        (B3, 2, 2), (R3, 3, 0), (B3, 4, 4), (B3, 3, 3)] },
+    Bc6hMode { value: 14, subsets: 2, transformed: true, endpoint_bits: 9, delta_bits: [5, 5, 5], layout: &[
+        (R0, 8, 0), (B2, 4, 4), (G0, 8, 0), (G2, 4, 4), (B0, 8, 0), (B3, 4, 4), (R1, 4, 0), (G3, 4, 4),
+        (G2, 3, 0), (G1, 4, 0), (B3, 0, 0), (G3, 3, 0), (B1, 4, 0), (B3, 1, 1), (B2, 3, 0), (R2, 4, 0),
+        (B3, 2, 2), (R3, 4, 0), (B3, 3, 3)] },
+    Bc6hMode { value: 18, subsets: 2, transformed: true, endpoint_bits: 8, delta_bits: [6, 5, 5], layout: &[
+        (R0, 7, 0), (G3, 4, 4), (B2, 4, 4), (G0, 7, 0), (B3, 2, 2), (G2, 4, 4), (B0, 7, 0), (B3, 3, 3),
+        (B3, 4, 4), (R1, 5, 0), (G2, 3, 0), (G1, 4, 0), (B3, 0, 0), (G3, 3, 0), (B1, 4, 0), (B3, 1, 1),
+        (B2, 3, 0), (R2, 5, 0), (R3, 5, 0)] },
+    Bc6hMode { value: 22, subsets: 2, transformed: true, endpoint_bits: 8, delta_bits: [5, 6, 5], layout: &[
+        (R0, 7, 0), (B3, 0, 0), (B2, 4, 4), (G0, 7, 0), (G2, 5, 5), (G2, 4, 4), (B0, 7, 0), (G3, 5, 5),
+        (B3, 4, 4), (R1, 4, 0), (G3, 4, 4), (G2, 3, 0), (G1, 5, 0), (G3, 3, 0), (B1, 4, 0), (B3, 1, 1),
+        (B2, 3, 0), (R2, 4, 0), (B3, 2, 2), (R3, 4, 0), (B3, 3, 3)] },
+    Bc6hMode { value: 26, subsets: 2, transformed: true, endpoint_bits: 8, delta_bits: [5, 5, 6], layout: &[
+        (R0, 7, 0), (B3, 1, 1), (B2, 4, 4), (G0, 7, 0), (B2, 5, 5), (G2, 4, 4), (B0, 7, 0), (B3, 5, 5),
+        (B3, 4, 4), (R1, 4, 0), (G3, 4, 4), (G2, 3, 0), (G1, 4, 0), (B3, 0, 0), (G3, 3, 0), (B1, 5, 0),
+        (B2, 3, 0), (R2, 4, 0), (B3, 2, 2), (R3, 4, 0), (B3, 3, 3)] },
+    Bc6hMode { value: 30, subsets: 2, transformed: false, endpoint_bits: 6, delta_bits: [6, 6, 6], layout: &[
+        (R0, 5, 0), (G3, 4, 4), (B3, 0, 0), (B3, 1, 1), (B2, 4, 4), (G0, 5, 0), (G2, 5, 5), (B2, 5, 5),
+        (B3, 2, 2), (G2, 4, 4), (B0, 5, 0), (G3, 5, 5), (B3, 3, 3), (B3, 5, 5), (B3, 4, 4), (R1, 5, 0),
+        (G2, 3, 0), (G1, 5, 0), (G3, 3, 0), (B1, 5, 0), (B2, 3, 0), (R2, 5, 0), (R3, 5, 0)] },
+    Bc6hMode { value: 3, subsets: 1, transformed: false, endpoint_bits: 10, delta_bits: [10, 10, 10], layout: &[
+        (R0, 9, 0), (G0, 9, 0), (B0, 9, 0), (R1, 9, 0), (G1, 9, 0), (B1, 9, 0)] },
+    Bc6hMode { value: 7, subsets: 1, transformed: true, endpoint_bits: 11, delta_bits: [9, 9, 9], layout: &[
+        (R0, 9, 0), (G0, 9, 0), (B0, 9, 0), (R1, 8, 0), (R0, 10, 10), (G1, 8, 0), (G0, 10, 10), (B1, 8, 0),
+        (B0, 10, 10)] },
+    Bc6hMode { value: 11, subsets: 1, transformed: true, endpoint_bits: 12, delta_bits: [8, 8, 8], layout: &[
+        (R0, 9, 0), (G0, 9, 0), (B0, 9, 0), (R1, 7, 0), (R0, 10, 11), (G1, 7, 0), (G0, 10, 11), (B1, 7, 0),
+        (B0, 10, 11)] },
+    Bc6hMode { value: 15, subsets: 1, transformed: true, endpoint_bits: 16, delta_bits: [4, 4, 4], layout: &[
+        (R0, 9, 0), (G0, 9, 0), (B0, 9, 0), (R1, 3, 0), (R0, 10, 15), (G1, 3, 0), (G0, 10, 15), (B1, 3, 0),
+        (B0, 10, 15)] }
+];
+
+fn sign_extend(value: i32, bits: u32) -> i32 {
+    let shift = 32 - bits;
+    (value << shift) >> shift
+}
+
+fn bc6h_unquantize(value: i32, bits: u32, signed: bool) -> i32 {
+    if !signed {
+        if bits >= 15 || value == 0 {
+            value
+        } else if value == (1 << bits) - 1 {
+            0xFFFF
+        } else {
+            ((value << 16) + 0x8000) >> bits
+        }
+    } else {
+        if bits >= 16 || value == 0 {
+            return value;
+        }
+        let magnitude = value.abs();
+        let unquantized = if magnitude >= (1 << (bits - 1)) - 1 {
+            0x7FFF
+        } else {
+            ((magnitude << 15) + 0x4000) >> (bits - 1)
+        };
+        if value < 0 { -unquantized } else { unquantized }
+    }
+}
+
+// The half float bits of an interpolated value
+fn bc6h_finish(value: i32, signed: bool) -> u16 {
+    if !signed {
+        ((value * 31) >> 6) as u16
+    } else if value < 0 {
+        0x8000 | (((-value) * 31) >> 5) as u16
+    } else {
+        ((value * 31) >> 5) as u16
+    }
+}
+
+pub fn half_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = i32::from((half >> 10) & 31);
+    let mantissa = f32::from(half & 1023);
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        31 => if mantissa == 0.0 { sign * f32::INFINITY } else { f32::NAN },
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15)
+    }
+}
+
+// Reserved modes decode to black
+pub fn decode_bc6h(block: &[u8], signed: bool, out: &mut [[f32; 3]; 16]) {
+    let mut bits = Bits::new(block);
+    let mut value = bits.read(2);
+    if value >= 2 {
+        value |= bits.read(3) << 2;
+    }
+    let mode = match BC6H_MODES.iter().find(|mode| mode.value == value) {
+        Some(mode) => mode,
+        None => {
+            *out = [[0.0; 3]; 16];
+            return;
+        }
+    };
+
+    let mut fields = [0i32; 12];
+    for &(field, a, b) in mode.layout {
+        if a >= b {
+            for bit in b..=a {
+                fields[field] |= (bits.read(1) as i32) << bit;
+            }
+        } else {
+            for bit in (a..=b).rev() {
+                fields[field] |= (bits.read(1) as i32) << bit;
+            }
+        }
+    }
+
+    let partition = if mode.subsets == 2 { bits.read(5) as usize } else { 0 };
+    let endpoint_count = mode.subsets * 2;
+    let mut endpoints = [[0i32; 3]; 4];
+    for (endpoint, values) in endpoints.iter_mut().enumerate().take(endpoint_count) {
+        values.copy_from_slice(&fields[endpoint * 3..endpoint * 3 + 3]);
+    }
+
+    // The other endpoints may be deltas from the first one
+    let mask = (1i32 << mode.endpoint_bits) - 1;
+    for channel in 0..3 {
+        if signed {
+            endpoints[0][channel] = sign_extend(endpoints[0][channel], mode.endpoint_bits);
+        }
+        for endpoint in endpoints.iter_mut().take(endpoint_count).skip(1) {
+            if mode.transformed {
+                let delta = sign_extend(endpoint[channel], mode.delta_bits[channel]);
+                endpoint[channel] = (fields[channel] + delta) & mask;
+            }
+            if signed {
+                endpoint[channel] = sign_extend(endpoint[channel], mode.endpoint_bits);
+            }
+        }
+    }
+
+    for endpoint in endpoints.iter_mut().take(endpoint_count) {
+        for value in endpoint.iter_mut() {
+            *value = bc6h_unquantize(*value, mode.endpoint_bits, signed);
+        }
+    }
+
+    let index_bits = if mode.subsets == 2 { 3 } else { 4 };
+    let weights = bc7_weights(index_bits);
+    for (pixel, color) in out.iter_mut().enumerate() {
+        let anchor = is_anchor(partition, mode.subsets, pixel);
+        let weight = weights[bits.read(if anchor { index_bits - 1 } else { index_bits }) as usize] as i32;
+        let subset = subset(partition, mode.subsets, pixel);
+        let (e0, e1) = (endpoints[subset * 2], endpoints[subset * 2 + 1]);
+
+        for channel in 0..3 {
+            let value = ((64 - weight) * e0[channel] + weight * e1[channel] + 32) >> 6;
+            color[channel] = half_to_f32(bc6h_finish(value, signed));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Pixel i uses index i % 8, for the 3 bits indices of the channel blocks
+    const RAMP_INDICES: [u8; 6] = [0x88, 0xC6, 0xFA, 0x88, 0xC6, 0xFA];
+
+    fn decode(decoder: fn(&[u8], &mut [[u8; 4]; 16]), block: &[u8]) -> [[u8; 4]; 16] {
+        let mut out = [[0u8; 4]; 16];
+        decoder(block, &mut out);
+        out
+    }
+
+    #[test]
+    fn bc1_four_colors() {
+        // Red and blue, pixel i uses index i % 4
+        let out = decode(decode_bc1, &[0x00, 0xF8, 0x1F, 0x00, 0xE4, 0xE4, 0xE4, 0xE4]);
+        let row = [[255, 0, 0, 255], [0, 0, 255, 255], [170, 0, 85, 255], [85, 0, 170, 255]];
+        for (pixel, color) in out.iter().enumerate() {
+            assert_eq!(*color, row[pixel % 4]);
+        }
+    }
+
+    #[test]
+    fn bc1_three_colors_and_transparency() {
+        // Blue before red selects the mode with transparent black
+        let out = decode(decode_bc1, &[0x1F, 0x00, 0x00, 0xF8, 0xE4, 0xE4, 0xE4, 0xE4]);
+        let row = [[0, 0, 255, 255], [255, 0, 0, 255], [127, 0, 127, 255], [0, 0, 0, 0]];
+        for (pixel, color) in out.iter().enumerate() {
+            assert_eq!(*color, row[pixel % 4]);
+        }
+    }
+
+    #[test]
+    fn bc2_explicit_alpha() {
+        // Pixel i has the alpha i, and the colors always have 4 entries
+        let mut block = [0x10, 0x32, 0x54, 0x76, 0x98, 0xBA, 0xDC, 0xFE, 0x1F, 0x00, 0x00, 0xF8, 0, 0, 0, 0];
+        block[12..].copy_from_slice(&[0xFF; 4]);
+        let out = decode(decode_bc2, &block);
+        for (pixel, color) in out.iter().enumerate() {
+            assert_eq!(*color, [170, 0, 85, pixel as u8 * 17]);
+        }
+    }
+
+    #[test]
+    fn bc3_interpolated_alpha() {
+        let mut block = [0u8; 16];
+        block[..2].copy_from_slice(&[255, 0]);
+        block[2..8].copy_from_slice(&RAMP_INDICES);
+        block[8..12].copy_from_slice(&[0xE0, 0x07, 0xE0, 0x07]);
+        let out = decode(decode_bc3, &block);
+        let alphas = [255, 0, 218, 182, 145, 109, 72, 36];
+        for (pixel, color) in out.iter().enumerate() {
+            assert_eq!(*color, [0, 255, 0, alphas[pixel % 8]]);
+        }
+    }
+
+    #[test]
+    fn bc4_and_bc5_channels() {
+        // With the first value lower, 6 values are interpolated, then come 0 and 255
+        let mut block = [0u8; 16];
+        block[..2].copy_from_slice(&[0, 255]);
+        block[2..8].copy_from_slice(&RAMP_INDICES);
+        block[8..10].copy_from_slice(&[255, 0]);
+        block[10..].copy_from_slice(&RAMP_INDICES);
+
+        let reds = [0, 255, 51, 102, 153, 204, 0, 255];
+        let greens = [255, 0, 218, 182, 145, 109, 72, 36];
+        let out = decode(decode_bc4, &block);
+        for (pixel, color) in out.iter().enumerate() {
+            assert_eq!(*color, [reds[pixel % 8], 0, 0, 255]);
+        }
+        let out = decode(decode_bc5, &block);
+        for (pixel, color) in out.iter().enumerate() {
+            assert_eq!(*color, [reds[pixel % 8], greens[pixel % 8], 0, 255]);
+        }
+    }
+
+    // Mode 6: endpoints (255, 1, 1, 255) and (0, 0, 254, 254), pixel i uses index i
+    const BC7_MODE_6: [u8; 16] = [
+        0xC0, 0x3F, 0x00, 0x00, 0x00, 0xFC, 0xFF, 0xFF, 0x10, 0x32, 0x54, 0x76, 0x98, 0xBA, 0xDC, 0xFE
+    ];
+
+    #[test]
+    fn bc7_mode_6() {
+        let out = decode(decode_bc7, &BC7_MODE_6);
+        assert_eq!(out[0], [255, 1, 1, 255]);
+        assert_eq!(out[7], [135, 1, 120, 255]);
+        assert_eq!(out[8], [120, 0, 135, 254]);
+        assert_eq!(out[15], [0, 0, 254, 254]);
+    }
+
+    #[test]
+    fn bc7_reserved_mode() {
+        let out = decode(decode_bc7, &[0u8; 16]);
+        assert_eq!(out, [[0; 4]; 16]);
+    }
+
+    #[test]
+    fn bc6h_unsigned() {
+        // Mode 11: 10 bits endpoints, 495 is 1.0. Red, and green for the last pixel.
+        let block = [
+            0xE3, 0x3D, 0x00, 0x00, 0x00, 0xE0, 0x3D, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xF0
+        ];
+        let mut out = [[0.0f32; 3]; 16];
+        decode_bc6h(&block, false, &mut out);
+        for color in &out[..15] {
+            assert_eq!(*color, [1.0, 0.0, 0.0]);
+        }
+        assert_eq!(out[15], [0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn half_floats() {
+        assert_eq!(half_to_f32(0x3C00), 1.0);
+        assert_eq!(half_to_f32(0xC000), -2.0);
+        assert_eq!(half_to_f32(0x7BFF), 65504.0);
+        assert_eq!(half_to_f32(0x0001), 2f32.powi(-24));
+        assert_eq!(half_to_f32(0x7C00), f32::INFINITY);
+        assert!(half_to_f32(0x7E00).is_nan());
+    }
+}
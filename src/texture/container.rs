@@ -0,0 +1,426 @@
+// Texture data as DDS and KTX files store it: already in its final (often compressed) format,
+// with its whole mip chain and possibly several layers or the six faces of a cube map.
+//
+// The rows are kept in the order of the file and uploaded as they are, since compressed blocks
+// cannot be flipped. These files usually start with the top row, so unlike the images loaded by
+// `TextureManager::load`, v = 0 is at the top.
+
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use glium::backend::Facade;
+use glium::texture::{ClientFormat, CompressedFormat, CompressedMipmapsOption, CompressedSrgbFormat,
+    CompressedSrgbTexture2d, CompressedTexture2d, MipmapsOption, RawImage2d, SrgbTexture2d,
+    SrgbTexture2dArray, Texture2d, Texture2dArray, UncompressedFloatFormat};
+use glium::Rect;
+
+use error::LoadError;
+use texture::{ColorSpace, ManagedTexture};
+use texture::{bcn, cubemap, dds, etc2, ktx};
+
+// Enough for 2^31 x 2^31 pixels
+const MAX_LEVELS: u32 = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PixelFormat {
+    // Uncompressed, 4 bytes per pixel
+    Rgba8,
+    Bc1,
+    Bc2,
+    Bc3,
+    Bc4,
+    Bc5,
+    Bc6hUnsigned,
+    Bc6hSigned,
+    Bc7,
+    Etc2Rgb,
+    // One bit of alpha
+    Etc2RgbA1,
+    Etc2Rgba
+}
+
+impl PixelFormat {
+    // Bytes per block of 4x4 pixels, or per pixel for `Rgba8`
+    pub fn block_size(&self) -> usize {
+        match *self {
+            PixelFormat::Rgba8 => 4,
+            PixelFormat::Bc1 | PixelFormat::Bc4 | PixelFormat::Etc2Rgb | PixelFormat::Etc2RgbA1 => 8,
+            _ => 16
+        }
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        *self != PixelFormat::Rgba8
+    }
+
+    // Formats which hold colors may be stored in sRGB
+    pub fn supports_srgb(&self) -> bool {
+        !matches!(*self, PixelFormat::Bc4 | PixelFormat::Bc5 | PixelFormat::Bc6hUnsigned | PixelFormat::Bc6hSigned)
+    }
+
+    // The color space a file declares by choosing between a format and its sRGB twin
+    pub fn declared_color_space(&self, srgb: bool) -> Option<ColorSpace> {
+        if !self.supports_srgb() {
+            None
+        } else if srgb {
+            Some(ColorSpace::Srgb)
+        } else {
+            Some(ColorSpace::Linear)
+        }
+    }
+
+    pub fn is_hdr(&self) -> bool {
+        *self == PixelFormat::Bc6hUnsigned || *self == PixelFormat::Bc6hSigned
+    }
+
+    // The size of an image of this format, an error when it does not fit in memory
+    pub fn image_size(&self, width: u32, height: u32) -> Result<usize, LoadError> {
+        let (columns, rows) = if self.is_compressed() {
+            (width.div_ceil(4).max(1), height.div_ceil(4).max(1))
+        } else {
+            (width, height)
+        };
+        (columns as usize).checked_mul(rows as usize)
+            .and_then(|units| units.checked_mul(self.block_size()))
+            .ok_or_else(|| LoadError::parse(format!("a {}x{} image is too large", width, height)))
+    }
+
+    fn compressed_format(&self) -> Option<CompressedFormat> {
+        match *self {
+            PixelFormat::Bc1 => Some(CompressedFormat::S3tcDxt1Alpha),
+            PixelFormat::Bc2 => Some(CompressedFormat::S3tcDxt3Alpha),
+            PixelFormat::Bc3 => Some(CompressedFormat::S3tcDxt5Alpha),
+            PixelFormat::Bc4 => Some(CompressedFormat::RgtcFormatU),
+            PixelFormat::Bc5 => Some(CompressedFormat::RgtcFormatUU),
+            PixelFormat::Bc6hUnsigned => Some(CompressedFormat::BptcUnsignedFloat3),
+            PixelFormat::Bc6hSigned => Some(CompressedFormat::BptcSignedFloat3),
+            PixelFormat::Bc7 => Some(CompressedFormat::BptcUnorm4),
+            _ => None
+        }
+    }
+
+    fn compressed_srgb_format(&self) -> Option<CompressedSrgbFormat> {
+        match *self {
+            PixelFormat::Bc1 => Some(CompressedSrgbFormat::S3tcDxt1Alpha),
+            PixelFormat::Bc2 => Some(CompressedSrgbFormat::S3tcDxt3Alpha),
+            PixelFormat::Bc3 => Some(CompressedSrgbFormat::S3tcDxt5Alpha),
+            PixelFormat::Bc7 => Some(CompressedSrgbFormat::Bptc),
+            _ => None
+        }
+    }
+}
+
+// Decoded pixels, row by row
+#[derive(Clone, Debug, PartialEq)]
+pub enum DecodedPixels {
+    Rgba8(Vec<u8>),
+    // BC6H, in linear space
+    RgbF32(Vec<f32>)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextureImage {
+    pub format: PixelFormat,
+    // `None` when the file does not tell
+    pub color_space: Option<ColorSpace>,
+    pub width: u32,
+    pub height: u32,
+    // 1 for a plain texture
+    pub layers: u32,
+    // 6 for a cube map (+X, -X, +Y, -Y, +Z, -Z), 1 otherwise
+    pub faces: u32,
+    pub levels: u32,
+    // Every image, see `image()` for the order
+    pub images: Vec<Vec<u8>>
+}
+
+impl TextureImage {
+    // Read a DDS, KTX or KTX2 file, recognized by its content
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<TextureImage, LoadError> {
+        let mut data = Vec::new();
+        File::open(path)?.read_to_end(&mut data)?;
+        TextureImage::from_bytes(&data)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<TextureImage, LoadError> {
+        if dds::is_dds(data) {
+            dds::parse_dds(data)
+        } else if ktx::is_ktx(data) {
+            ktx::parse_ktx(data)
+        } else {
+            Err(LoadError::parse("neither a DDS nor a KTX file"))
+        }
+    }
+
+    pub fn is_cubemap(&self) -> bool {
+        self.faces == 6
+    }
+
+    pub fn level_dimensions(&self, level: u32) -> (u32, u32) {
+        ((self.width >> level).max(1), (self.height >> level).max(1))
+    }
+
+    pub fn image(&self, level: u32, layer: u32, face: u32) -> &[u8] {
+        &self.images[((level * self.layers + layer) * self.faces + face) as usize]
+    }
+
+    // Check the dimensions and counts read from a header before trusting them
+    pub fn check_counts(width: u32, height: u32, levels: u32, layers: u32, faces: u32, file_size: usize)
+            -> Result<(), LoadError> {
+        let max_size = 1u32 << (MAX_LEVELS - 1);
+        if width > max_size || height > max_size {
+            return Err(LoadError::parse(format!("{}x{} pixels", width, height)));
+        }
+        if levels > MAX_LEVELS {
+            return Err(LoadError::parse(format!("{} mip levels", levels)));
+        }
+        // Every image takes at least a byte
+        let images = (layers as usize).checked_mul(faces as usize)
+            .and_then(|images| images.checked_mul(levels as usize));
+        if images.is_none_or(|images| images > file_size) {
+            return Err(LoadError::parse(format!("{} layers do not fit in the file", layers)));
+        }
+        Ok(())
+    }
+
+    // Check the sizes of the images, for the parsers
+    pub fn validate(&self) -> Result<(), LoadError> {
+        if self.width == 0 || self.height == 0 || self.layers == 0 || self.levels == 0 {
+            return Err(LoadError::parse("empty texture"));
+        }
+        if self.faces != 1 && self.faces != 6 {
+            return Err(LoadError::parse(format!("{} faces", self.faces)));
+        }
+        if self.is_cubemap() && self.width != self.height {
+            return Err(LoadError::parse(format!("{}x{} cube map faces are not square", self.width, self.height)));
+        }
+        if self.images.len() != (self.levels * self.layers * self.faces) as usize {
+            return Err(LoadError::parse(format!("{} images for {} levels, {} layers and {} faces",
+                self.images.len(), self.levels, self.layers, self.faces)));
+        }
+        for level in 0..self.levels {
+            let (width, height) = self.level_dimensions(level);
+            let size = self.format.image_size(width, height)?;
+            if self.images.iter().skip((level * self.layers * self.faces) as usize)
+                    .take((self.layers * self.faces) as usize).any(|image| image.len() != size) {
+                return Err(LoadError::parse(format!("level {} is not {} bytes", level, size)));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn decode(&self, level: u32, layer: u32, face: u32) -> DecodedPixels {
+        let (width, height) = self.level_dimensions(level);
+        decode_image(self.format, self.image(level, layer, face), width, height)
+    }
+
+    // Upload a plain 2D texture, a texture array or a cube map. The color space of the file wins
+    // over `color_space`, which is used for the files which do not tell. The formats glium or the
+    // GPU do not know are decoded first.
+    pub fn upload<F: Facade>(&self, facade: &F, color_space: ColorSpace)
+            -> Result<ManagedTexture, LoadError> {
+        let srgb = self.format.supports_srgb() && self.color_space.unwrap_or(color_space) == ColorSpace::Srgb;
+
+        if self.is_cubemap() {
+            let color_space = if srgb { ColorSpace::Srgb } else { ColorSpace::Linear };
+            return cubemap::upload_cubemap_image(facade, self, color_space);
+        }
+
+        if self.layers > 1 {
+            return self.upload_array(facade, srgb);
+        }
+
+        // glium only uploads compressed images made of whole blocks, the smallest levels may be
+        // dropped
+        let levels = (0..self.levels)
+            .take_while(|&level| {
+                let (width, height) = self.level_dimensions(level);
+                width % 4 == 0 && height % 4 == 0
+            })
+            .count() as u32;
+        if levels > 0 {
+            if srgb {
+                if let Some(format) = self.format.compressed_srgb_format().filter(|format| format.is_supported(facade)) {
+                    let texture = CompressedSrgbTexture2d::with_compressed_data(facade, self.image(0, 0, 0),
+                        self.width, self.height, format, CompressedMipmapsOption::EmptyMipmapsMax(levels - 1))?;
+                    for level in 1..levels {
+                        let (width, height) = self.level_dimensions(level);
+                        texture.mipmap(level).unwrap().write_compressed_data(rect(width, height),
+                            self.image(level, 0, 0), width, height, format)
+                            .map_err(|_| LoadError::parse(format!("cannot upload level {}", level)))?;
+                    }
+                    return Ok(ManagedTexture::CompressedSrgb(texture));
+                }
+            } else if let Some(format) = self.format.compressed_format().filter(|format| format.is_supported(facade)) {
+                let texture = CompressedTexture2d::with_compressed_data(facade, self.image(0, 0, 0),
+                    self.width, self.height, format, CompressedMipmapsOption::EmptyMipmapsMax(levels - 1))?;
+                for level in 1..levels {
+                    let (width, height) = self.level_dimensions(level);
+                    texture.mipmap(level).unwrap().write_compressed_data(rect(width, height),
+                        self.image(level, 0, 0), width, height, format)
+                        .map_err(|_| LoadError::parse(format!("cannot upload level {}", level)))?;
+                }
+                return Ok(ManagedTexture::Compressed(texture));
+            }
+        }
+
+        // Decoded, with the mip chain of the file
+        let mipmaps = MipmapsOption::EmptyMipmapsMax(self.levels - 1);
+        if self.format.is_hdr() {
+            let texture = Texture2d::with_format(facade, self.decoded_raw(0, 0), UncompressedFloatFormat::F16F16F16,
+                mipmaps)?;
+            for level in 1..self.levels {
+                let (width, height) = self.level_dimensions(level);
+                texture.mipmap(level).unwrap().write(rect(width, height), self.decoded_raw(level, 0));
+            }
+            Ok(ManagedTexture::Linear(texture))
+        } else if srgb {
+            let texture = SrgbTexture2d::with_mipmaps(facade, self.decoded_raw(0, 0), mipmaps)?;
+            for level in 1..self.levels {
+                let (width, height) = self.level_dimensions(level);
+                texture.mipmap(level).unwrap().write(rect(width, height), self.decoded_raw(level, 0));
+            }
+            Ok(ManagedTexture::Srgb(texture))
+        } else {
+            let texture = Texture2d::with_mipmaps(facade, self.decoded_raw(0, 0), mipmaps)?;
+            for level in 1..self.levels {
+                let (width, height) = self.level_dimensions(level);
+                texture.mipmap(level).unwrap().write(rect(width, height), self.decoded_raw(level, 0));
+            }
+            Ok(ManagedTexture::Linear(texture))
+        }
+    }
+
+    // glium can neither upload compressed arrays with their mipmaps nor write the levels of an
+    // array, so arrays are decoded and their mipmaps generated
+    fn upload_array<F: Facade>(&self, facade: &F, srgb: bool) -> Result<ManagedTexture, LoadError> {
+        let layers = || (0..self.layers).map(|layer| self.decoded_raw(0, layer)).collect::<Vec<_>>();
+        let mipmaps = MipmapsOption::AutoGeneratedMipmaps;
+
+        if self.format.is_hdr() {
+            Ok(ManagedTexture::LinearArray(Texture2dArray::with_format(facade, layers(),
+                UncompressedFloatFormat::F16F16F16, mipmaps)?))
+        } else if srgb {
+            Ok(ManagedTexture::SrgbArray(SrgbTexture2dArray::with_mipmaps(facade, layers(), mipmaps)?))
+        } else {
+            Ok(ManagedTexture::LinearArray(Texture2dArray::with_mipmaps(facade, layers(), mipmaps)?))
+        }
+    }
+
+    // The decoded pixels of a face, given to glium as they are
+    pub fn decoded_raw(&self, level: u32, layer_or_face: u32) -> RawImage2d<'static, u8> {
+        let (width, height) = self.level_dimensions(level);
+        let (layer, face) = if self.is_cubemap() { (0, layer_or_face) } else { (layer_or_face, 0) };
+        match self.decode(level, layer, face) {
+            DecodedPixels::Rgba8(pixels) => RawImage2d {
+                data: Cow::Owned(pixels),
+                width: width,
+                height: height,
+                format: ClientFormat::U8U8U8U8
+            },
+            // Passed as bytes so that every format has the same image type
+            DecodedPixels::RgbF32(pixels) => RawImage2d {
+                data: Cow::Owned(pixels.iter().flat_map(|value| value.to_bits().to_ne_bytes().to_vec()).collect()),
+                width: width,
+                height: height,
+                format: ClientFormat::F32F32F32
+            }
+        }
+    }
+}
+
+fn rect(width: u32, height: u32) -> Rect {
+    Rect {
+        left: 0,
+        bottom: 0,
+        width: width,
+        height: height
+    }
+}
+
+// Decode one image of any format, cropping the blocks which go past its edges
+pub fn decode_image(format: PixelFormat, data: &[u8], width: u32, height: u32) -> DecodedPixels {
+    let (width, height) = (width as usize, height as usize);
+    if format == PixelFormat::Rgba8 {
+        return DecodedPixels::Rgba8(data[..width * height * 4].to_vec());
+    }
+
+    let blocks_x = width.div_ceil(4).max(1);
+    let block_size = format.block_size();
+    let blocks = data.chunks(block_size).enumerate().take(blocks_x * height.div_ceil(4).max(1));
+
+    if format.is_hdr() {
+        let mut pixels = vec![0.0f32; width * height * 3];
+        let mut out = [[0.0f32; 3]; 16];
+        for (index, block) in blocks {
+            bcn::decode_bc6h(block, format == PixelFormat::Bc6hSigned, &mut out);
+            for (i, color) in out.iter().enumerate() {
+                let (x, y) = ((index % blocks_x) * 4 + i % 4, (index / blocks_x) * 4 + i / 4);
+                if x < width && y < height {
+                    pixels[(y * width + x) * 3..(y * width + x) * 3 + 3].copy_from_slice(color);
+                }
+            }
+        }
+        return DecodedPixels::RgbF32(pixels);
+    }
+
+    let decode: fn(&[u8], &mut [[u8; 4]; 16]) = match format {
+        PixelFormat::Bc1 => bcn::decode_bc1,
+        PixelFormat::Bc2 => bcn::decode_bc2,
+        PixelFormat::Bc3 => bcn::decode_bc3,
+        PixelFormat::Bc4 => bcn::decode_bc4,
+        PixelFormat::Bc5 => bcn::decode_bc5,
+        PixelFormat::Bc7 => bcn::decode_bc7,
+        PixelFormat::Etc2Rgb => etc2::decode_etc2_rgb,
+        PixelFormat::Etc2RgbA1 => etc2::decode_etc2_rgb_a1,
+        _ => etc2::decode_etc2_rgba
+    };
+
+    let mut pixels = vec![0u8; width * height * 4];
+    let mut out = [[0u8; 4]; 16];
+    for (index, block) in blocks {
+        decode(block, &mut out);
+        for (i, color) in out.iter().enumerate() {
+            let (x, y) = ((index % blocks_x) * 4 + i % 4, (index / blocks_x) * 4 + i / 4);
+            if x < width && y < height {
+                pixels[(y * width + x) * 4..(y * width + x) * 4 + 4].copy_from_slice(color);
+            }
+        }
+    }
+    DecodedPixels::Rgba8(pixels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image_sizes() {
+        assert_eq!(PixelFormat::Rgba8.image_size(3, 5).unwrap(), 60);
+        // Whole blocks, at least one
+        assert_eq!(PixelFormat::Bc1.image_size(5, 4).unwrap(), 16);
+        assert_eq!(PixelFormat::Bc7.image_size(1, 1).unwrap(), 16);
+        assert_eq!(PixelFormat::Etc2Rgba.image_size(8, 8).unwrap(), 64);
+    }
+
+    #[test]
+    fn huge_images_overflow() {
+        assert!(PixelFormat::Rgba8.image_size(u32::MAX, u32::MAX).is_err());
+        assert!(PixelFormat::Bc1.image_size(u32::MAX, u32::MAX).is_ok());
+    }
+
+    #[test]
+    fn header_counts() {
+        let max_size = 1 << (MAX_LEVELS - 1);
+        assert!(TextureImage::check_counts(max_size, max_size, MAX_LEVELS, 1, 1, 1000).is_ok());
+        assert!(TextureImage::check_counts(max_size + 1, 1, 1, 1, 1, 1000).is_err());
+        assert!(TextureImage::check_counts(1, max_size + 1, 1, 1, 1, 1000).is_err());
+        assert!(TextureImage::check_counts(1, 1, MAX_LEVELS + 1, 1, 1, 1000).is_err());
+        // Every image takes a byte, and the count must not wrap around
+        assert!(TextureImage::check_counts(1, 1, 2, 3, 6, 36).is_ok());
+        assert!(TextureImage::check_counts(1, 1, 2, 3, 6, 35).is_err());
+        assert!(TextureImage::check_counts(1, 1, MAX_LEVELS, u32::MAX, u32::MAX, usize::MAX).is_err());
+    }
+}
@@ -0,0 +1,306 @@
+// Cube maps from six images or from one equirectangular panorama.
+//
+// glium cannot create a cube map with data, so every face is uploaded as a 2D texture first and
+// blitted into its face of the cube map. The faces are kept in the order of their files, top row
+// first, which is what OpenGL expects for cube maps.
+//
+// With the left-handed world of the tutorials (x right, y up, z forward), the +Z face is in front
+// of the default camera and the images are upright.
+
+use std::borrow::Cow;
+use std::f32::consts::PI;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use cgmath::{InnerSpace, Vector3};
+use glium::{BlitTarget, Surface};
+use glium::backend::Facade;
+use glium::framebuffer::{SimpleFrameBuffer, ToColorAttachment};
+use glium::texture::{ClientFormat, CubeLayer, Cubemap, MipmapsOption, PixelValue, RawImage2d, SrgbCubemap,
+    SrgbFormat, SrgbTexture2d, Texture2d, TextureCreationError, UncompressedFloatFormat};
+use glium::uniforms::MagnifySamplerFilter;
+use image::{self, RgbaImage};
+use image::hdr::HDRDecoder;
+
+use error::LoadError;
+use texture::{ColorSpace, DecodedPixels, ManagedTexture, TextureImage};
+
+// The faces in the order of OpenGL, which is also the order of the files given to the loaders
+pub const CUBE_FACES: [CubeLayer; 6] = [
+    CubeLayer::PositiveX,
+    CubeLayer::NegativeX,
+    CubeLayer::PositiveY,
+    CubeLayer::NegativeY,
+    CubeLayer::PositiveZ,
+    CubeLayer::NegativeZ
+];
+
+// Linear cube maps are stored in half floats, so that HDR panoramas keep their range
+const LINEAR_FORMAT: UncompressedFloatFormat = UncompressedFloatFormat::F16F16F16F16;
+
+// The direction a point of a face looks at. `s` and `t` go from 0 to 1, from the left and from
+// the top of the face image.
+pub fn face_direction(face: usize, s: f32, t: f32) -> Vector3<f32> {
+    let (sc, tc) = (2.0 * s - 1.0, 2.0 * t - 1.0);
+    let direction = match face {
+        0 => Vector3::new(1.0, -tc, -sc),
+        1 => Vector3::new(-1.0, -tc, sc),
+        2 => Vector3::new(sc, 1.0, tc),
+        3 => Vector3::new(sc, -1.0, -tc),
+        4 => Vector3::new(sc, -tc, 1.0),
+        _ => Vector3::new(-sc, -tc, -1.0)
+    };
+    direction.normalize()
+}
+
+// Where a direction falls on an equirectangular panorama, from 0 to 1 from its left and top
+// edges. The center of the panorama is in front (+Z).
+pub fn equirectangular_coords(direction: Vector3<f32>) -> (f32, f32) {
+    let direction = direction.normalize();
+    let longitude = direction.x.atan2(direction.z);
+    let latitude = direction.y.clamp(-1.0, 1.0).asin();
+    (0.5 + longitude / (2.0 * PI), 0.5 - latitude / PI)
+}
+
+// Resample a panorama of RGB floats into six faces of `size` x `size` RGB floats, bilinearly
+pub fn equirectangular_to_faces(pixels: &[f32], width: u32, height: u32, size: u32)
+        -> Result<Vec<Vec<f32>>, LoadError> {
+    let (width, height) = (width as usize, height as usize);
+    if width == 0 || height == 0 || pixels.len() < width * height * 3 {
+        return Err(LoadError::parse(format!("{} floats for a panorama of {}x{} pixels", pixels.len(),
+            width, height)));
+    }
+    let texel = |x: usize, y: usize| &pixels[(y * width + x) * 3..(y * width + x) * 3 + 3];
+
+    Ok((0..6).map(|face| {
+        let mut face_pixels = Vec::with_capacity(size as usize * size as usize * 3);
+        for y in 0..size {
+            for x in 0..size {
+                let direction = face_direction(face, (x as f32 + 0.5) / size as f32, (y as f32 + 0.5) / size as f32);
+                let (u, v) = equirectangular_coords(direction);
+
+                // Wrapping around horizontally, clamped at the poles
+                let fx = u * width as f32 - 0.5;
+                let fy = (v * height as f32 - 0.5).max(0.0).min(height as f32 - 1.0);
+                let (x0, y0) = (fx.floor(), fy.floor());
+                let (tx, ty) = (fx - x0, fy - y0);
+                let x0 = (x0 as isize).rem_euclid(width as isize) as usize;
+                let x1 = (x0 + 1) % width;
+                let y0 = y0 as usize;
+                let y1 = (y0 + 1).min(height - 1);
+
+                for channel in 0..3 {
+                    let top = texel(x0, y0)[channel] * (1.0 - tx) + texel(x1, y0)[channel] * tx;
+                    let bottom = texel(x0, y1)[channel] * (1.0 - tx) + texel(x1, y1)[channel] * tx;
+                    face_pixels.push(top * (1.0 - ty) + bottom * ty);
+                }
+            }
+        }
+        face_pixels
+    }).collect())
+}
+
+// The linear value of an 8 bits sRGB value
+fn srgb_to_linear(value: u8) -> f32 {
+    let value = f32::from(value) / 255.0;
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// Read a panorama as linear RGB floats. Radiance HDR files are already linear, the other formats
+// are taken as sRGB.
+pub fn load_panorama<P: AsRef<Path>>(path: P) -> Result<(Vec<f32>, u32, u32), LoadError> {
+    let path = path.as_ref();
+    let is_hdr = path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("hdr"));
+
+    if is_hdr {
+        let decoder = HDRDecoder::new(BufReader::new(File::open(path)?))?;
+        let metadata = decoder.metadata();
+        let pixels = decoder.read_image_hdr()?.iter().flat_map(|pixel| pixel.data.to_vec()).collect();
+        Ok((pixels, metadata.width, metadata.height))
+    } else {
+        let image = image::open(path)?.to_rgba();
+        let (width, height) = image.dimensions();
+        let pixels = image.pixels().flat_map(|pixel| pixel.data[..3].iter().map(|&value| srgb_to_linear(value))).collect();
+        Ok((pixels, width, height))
+    }
+}
+
+// Blit a texture into a face of a cube map
+fn blit_face<'a, F: Facade, S: ToColorAttachment<'a>, T: ToColorAttachment<'a>>(facade: &F, source: S, target: T,
+        size: u32) -> Result<(), LoadError> {
+    // Both are fresh textures of a renderable format, so this only fails without framebuffer
+    // objects
+    let unsupported = |_| LoadError::Texture(TextureCreationError::FormatNotSupported);
+    let source = SimpleFrameBuffer::new(facade, source).map_err(unsupported)?;
+    let target = SimpleFrameBuffer::new(facade, target).map_err(unsupported)?;
+
+    source.blit_whole_color_to(&target, &BlitTarget {
+        left: 0,
+        bottom: 0,
+        width: size as i32,
+        height: size as i32
+    }, MagnifySamplerFilter::Nearest);
+    Ok(())
+}
+
+// Upload six square faces of the same size, in the order of `CUBE_FACES`. The cube map has no
+// mipmaps, see `SamplerSettings::cubemap()`.
+pub fn upload_cubemap<'a, F: Facade, T: PixelValue + Clone + 'a>(facade: &F, faces: Vec<RawImage2d<'a, T>>,
+        color_space: ColorSpace) -> Result<ManagedTexture, LoadError> {
+    if faces.len() != 6 {
+        return Err(LoadError::parse(format!("a cube map has 6 faces, not {}", faces.len())));
+    }
+    let size = faces[0].width;
+    if faces.iter().any(|face| face.width != size || face.height != size) {
+        return Err(LoadError::parse("the faces of a cube map must be squares of the same size"));
+    }
+
+    // The faces have the format of the cube map, blits cannot convert between normalized and
+    // float formats
+    match color_space {
+        ColorSpace::Srgb => {
+            let cubemap = SrgbCubemap::empty_with_format(facade, SrgbFormat::U8U8U8U8, MipmapsOption::NoMipmap, size)?;
+            for (face, image) in faces.into_iter().enumerate() {
+                let texture = SrgbTexture2d::with_format(facade, image, SrgbFormat::U8U8U8U8, MipmapsOption::NoMipmap)?;
+                blit_face(facade, &texture, cubemap.main_level().image(CUBE_FACES[face]), size)?;
+            }
+            Ok(ManagedTexture::SrgbCubemap(cubemap))
+        },
+        ColorSpace::Linear => {
+            let cubemap = Cubemap::empty_with_format(facade, LINEAR_FORMAT, MipmapsOption::NoMipmap, size)?;
+            for (face, image) in faces.into_iter().enumerate() {
+                let texture = Texture2d::with_format(facade, image, LINEAR_FORMAT, MipmapsOption::NoMipmap)?;
+                blit_face(facade, &texture, cubemap.main_level().image(CUBE_FACES[face]), size)?;
+            }
+            Ok(ManagedTexture::LinearCubemap(cubemap))
+        }
+    }
+}
+
+pub fn rgba_face(image: RgbaImage) -> RawImage2d<'static, u8> {
+    let (width, height) = image.dimensions();
+    RawImage2d {
+        data: Cow::Owned(image.into_raw()),
+        width: width,
+        height: height,
+        format: ClientFormat::U8U8U8U8
+    }
+}
+
+pub fn rgb_float_face(pixels: Vec<f32>, size: u32) -> RawImage2d<'static, f32> {
+    RawImage2d {
+        data: Cow::Owned(pixels),
+        width: size,
+        height: size,
+        format: ClientFormat::F32F32F32
+    }
+}
+
+// The base level of the six faces of a DDS or KTX cube map, decoded
+pub fn upload_cubemap_image<F: Facade>(facade: &F, image: &TextureImage, color_space: ColorSpace)
+        -> Result<ManagedTexture, LoadError> {
+    let decoded: Vec<DecodedPixels> = (0..6).map(|face| image.decode(0, 0, face)).collect();
+    let size = image.width;
+
+    if image.format.is_hdr() {
+        let faces = decoded.into_iter().map(|pixels| match pixels {
+            DecodedPixels::RgbF32(pixels) => rgb_float_face(pixels, size),
+            DecodedPixels::Rgba8(_) => unreachable!()
+        }).collect();
+        upload_cubemap(facade, faces, ColorSpace::Linear)
+    } else {
+        let faces = decoded.into_iter().map(|pixels| match pixels {
+            DecodedPixels::Rgba8(pixels) => RawImage2d {
+                data: Cow::Owned(pixels),
+                width: size,
+                height: size,
+                format: ClientFormat::U8U8U8U8
+            },
+            DecodedPixels::RgbF32(_) => unreachable!()
+        }).collect();
+        upload_cubemap(facade, faces, color_space)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Vector3};
+
+    use super::*;
+
+    fn assert_close(a: Vector3<f32>, b: Vector3<f32>) {
+        assert!((a - b).magnitude() < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn faces_look_along_their_axis() {
+        let axes = [
+            Vector3::new(1.0, 0.0, 0.0),
+            Vector3::new(-1.0, 0.0, 0.0),
+            Vector3::new(0.0, 1.0, 0.0),
+            Vector3::new(0.0, -1.0, 0.0),
+            Vector3::new(0.0, 0.0, 1.0),
+            Vector3::new(0.0, 0.0, -1.0)
+        ];
+        for (face, &axis) in axes.iter().enumerate() {
+            assert_close(face_direction(face, 0.5, 0.5), axis);
+        }
+
+        // The side faces are upright, the +Z one has +X on its right
+        assert_close(face_direction(4, 0.5, 0.0), Vector3::new(0.0, 1.0, 1.0).normalize());
+        assert_close(face_direction(4, 1.0, 0.5), Vector3::new(1.0, 0.0, 1.0).normalize());
+        assert_close(face_direction(0, 0.5, 1.0), Vector3::new(1.0, -1.0, 0.0).normalize());
+    }
+
+    #[test]
+    fn panorama_coords() {
+        let coords = |x: f32, y: f32, z: f32| equirectangular_coords(Vector3::new(x, y, z));
+        let assert_coords = |(u, v): (f32, f32), expected: (f32, f32)| {
+            assert!((u - expected.0).abs() < 1e-6 && (v - expected.1).abs() < 1e-6, "{:?} != {:?}", (u, v), expected);
+        };
+        assert_coords(coords(0.0, 0.0, 1.0), (0.5, 0.5));
+        assert_coords(coords(1.0, 0.0, 0.0), (0.75, 0.5));
+        assert_coords(coords(-1.0, 0.0, 0.0), (0.25, 0.5));
+        // Behind is on the left and right edges
+        let (u, v) = coords(0.0, 0.0, -1.0);
+        assert_coords((u.min(1.0 - u), v), (0.0, 0.5));
+        assert_coords(coords(0.0, 1.0, 0.0), (0.5, 0.0));
+        assert_coords(coords(0.0, -2.0, 0.0), (0.5, 1.0));
+    }
+
+    #[test]
+    fn panoramas_are_resampled() {
+        // The top half is red and the bottom half blue
+        let (width, height) = (8, 4);
+        let mut pixels = Vec::new();
+        for y in 0..height {
+            for _ in 0..width {
+                pixels.extend_from_slice(if y < height / 2 { &[1.0, 0.0, 0.0] } else { &[0.0, 0.0, 1.0] });
+            }
+        }
+
+        let faces = equirectangular_to_faces(&pixels, width, height, 4).unwrap();
+        assert_eq!(faces.len(), 6);
+        for face in &faces {
+            assert_eq!(face.len(), 4 * 4 * 3);
+        }
+        assert!(faces[2].chunks(3).all(|pixel| pixel == [1.0, 0.0, 0.0]), "{:?}", faces[2]);
+        assert!(faces[3].chunks(3).all(|pixel| pixel == [0.0, 0.0, 1.0]), "{:?}", faces[3]);
+        // The top row of a side face is red, the bottom row blue
+        assert_eq!(&faces[4][..3], &[1.0, 0.0, 0.0]);
+        assert_eq!(&faces[4][faces[4].len() - 3..], &[0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn empty_panoramas_are_rejected() {
+        assert!(equirectangular_to_faces(&[], 0, 4, 4).is_err());
+        assert!(equirectangular_to_faces(&[], 8, 0, 4).is_err());
+        assert!(equirectangular_to_faces(&[0.0; 3], 8, 4, 4).is_err());
+        assert_eq!(equirectangular_to_faces(&[0.5; 3], 1, 1, 2).unwrap(), vec![vec![0.5; 12]; 6]);
+    }
+}
@@ -0,0 +1,285 @@
+// DirectDraw Surface files, with the legacy header (FourCC codes) or the DX10 one (DXGI formats).
+// Every array layer or cube face is stored with its whole mip chain before the next one.
+
+use error::LoadError;
+use texture::container::{PixelFormat, TextureImage};
+
+const MAGIC: &[u8] = b"DDS ";
+const HEADER_SIZE: usize = 128;
+const DX10_HEADER_SIZE: usize = 20;
+
+const DDPF_ALPHAPIXELS: u32 = 0x1;
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+const DDSCAPS2_CUBEMAP: u32 = 0x200;
+const DDSCAPS2_VOLUME: u32 = 0x20_0000;
+const RESOURCE_MISC_TEXTURECUBE: u32 = 0x4;
+const RESOURCE_DIMENSION_TEXTURE2D: u32 = 3;
+
+pub fn is_dds(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0u8; 4];
+    bytes.copy_from_slice(&data[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+// How the bytes of an uncompressed pixel are turned into RGBA
+#[derive(Clone, Copy)]
+enum Swizzle {
+    None,
+    Bgra,
+    // The byte of each channel, none for an opaque alpha
+    Masks([usize; 3], Option<usize>)
+}
+
+fn fourcc_format(fourcc: &[u8]) -> Option<PixelFormat> {
+    match fourcc {
+        b"DXT1" => Some(PixelFormat::Bc1),
+        b"DXT2" | b"DXT3" => Some(PixelFormat::Bc2),
+        b"DXT4" | b"DXT5" => Some(PixelFormat::Bc3),
+        b"ATI1" | b"BC4U" => Some(PixelFormat::Bc4),
+        b"ATI2" | b"BC5U" => Some(PixelFormat::Bc5),
+        _ => None
+    }
+}
+
+// The format, whether it is sRGB and how to swizzle it
+fn dxgi_format(format: u32) -> Option<(PixelFormat, bool, Swizzle)> {
+    let compressed = |format| Some((format, false, Swizzle::None));
+    let srgb = |format| Some((format, true, Swizzle::None));
+
+    match format {
+        28 => Some((PixelFormat::Rgba8, false, Swizzle::None)),
+        29 => srgb(PixelFormat::Rgba8),
+        87 => Some((PixelFormat::Rgba8, false, Swizzle::Bgra)),
+        91 => Some((PixelFormat::Rgba8, true, Swizzle::Bgra)),
+        70 | 71 => compressed(PixelFormat::Bc1),
+        72 => srgb(PixelFormat::Bc1),
+        73 | 74 => compressed(PixelFormat::Bc2),
+        75 => srgb(PixelFormat::Bc2),
+        76 | 77 => compressed(PixelFormat::Bc3),
+        78 => srgb(PixelFormat::Bc3),
+        79 | 80 => compressed(PixelFormat::Bc4),
+        82 | 83 => compressed(PixelFormat::Bc5),
+        95 => compressed(PixelFormat::Bc6hUnsigned),
+        96 => compressed(PixelFormat::Bc6hSigned),
+        97 | 98 => compressed(PixelFormat::Bc7),
+        99 => srgb(PixelFormat::Bc7),
+        _ => None
+    }
+}
+
+// The byte a mask of a 32 bits pixel selects
+fn mask_byte(mask: u32) -> Option<usize> {
+    match mask {
+        0x0000_00FF => Some(0),
+        0x0000_FF00 => Some(1),
+        0x00FF_0000 => Some(2),
+        0xFF00_0000 => Some(3),
+        _ => None
+    }
+}
+
+pub fn parse_dds(data: &[u8]) -> Result<TextureImage, LoadError> {
+    if !is_dds(data) {
+        return Err(LoadError::parse("not a DDS file"));
+    }
+    if data.len() < HEADER_SIZE || read_u32(data, 4) != 124 {
+        return Err(LoadError::parse("truncated DDS header"));
+    }
+
+    let height = read_u32(data, 12);
+    let width = read_u32(data, 16);
+    let levels = read_u32(data, 28).max(1);
+    let pixel_flags = read_u32(data, 80);
+    let fourcc = &data[84..88];
+    let caps2 = read_u32(data, 112);
+    if caps2 & DDSCAPS2_VOLUME != 0 {
+        return Err(LoadError::parse("volume textures are not supported"));
+    }
+
+    let dx10 = pixel_flags & DDPF_FOURCC != 0 && fourcc == b"DX10";
+    let (format, color_space, swizzle, layers, faces, mut offset) = if dx10 {
+        if data.len() < HEADER_SIZE + DX10_HEADER_SIZE {
+            return Err(LoadError::parse("truncated DX10 header"));
+        }
+        let dxgi = read_u32(data, HEADER_SIZE);
+        let (format, srgb, swizzle) = dxgi_format(dxgi)
+            .ok_or_else(|| LoadError::parse(format!("unsupported DXGI format {}", dxgi)))?;
+        if read_u32(data, HEADER_SIZE + 4) != RESOURCE_DIMENSION_TEXTURE2D {
+            return Err(LoadError::parse("only 2D textures are supported"));
+        }
+        let faces = if read_u32(data, HEADER_SIZE + 8) & RESOURCE_MISC_TEXTURECUBE != 0 { 6 } else { 1 };
+        let layers = read_u32(data, HEADER_SIZE + 12).max(1);
+        (format, format.declared_color_space(srgb), swizzle, layers, faces, HEADER_SIZE + DX10_HEADER_SIZE)
+    } else {
+        let faces = if caps2 & DDSCAPS2_CUBEMAP != 0 { 6 } else { 1 };
+        let (format, swizzle) = if pixel_flags & DDPF_FOURCC != 0 {
+            let format = fourcc_format(fourcc).ok_or_else(|| {
+                LoadError::parse(format!("unsupported FourCC '{}'", String::from_utf8_lossy(fourcc)))
+            })?;
+            (format, Swizzle::None)
+        } else if pixel_flags & DDPF_RGB != 0 && read_u32(data, 88) == 32 {
+            let masks = [92, 96, 100].map(|offset| mask_byte(read_u32(data, offset)));
+            let alpha = if pixel_flags & DDPF_ALPHAPIXELS != 0 { mask_byte(read_u32(data, 104)) } else { None };
+            match masks {
+                [Some(r), Some(g), Some(b)] => (PixelFormat::Rgba8, Swizzle::Masks([r, g, b], alpha)),
+                _ => return Err(LoadError::parse("unsupported RGB masks"))
+            }
+        } else {
+            return Err(LoadError::parse("unsupported pixel format"));
+        };
+        (format, None, swizzle, 1, faces, HEADER_SIZE)
+    };
+
+    TextureImage::check_counts(width, height, levels, layers, faces, data.len())?;
+
+    // Read in the order of the file, stored in the order of `TextureImage`
+    let mut images = vec![Vec::new(); (levels * layers * faces) as usize];
+    for layer in 0..layers {
+        for face in 0..faces {
+            for level in 0..levels {
+                let size = format.image_size((width >> level).max(1), (height >> level).max(1))?;
+                let image = offset.checked_add(size).and_then(|end| data.get(offset..end))
+                    .ok_or_else(|| LoadError::parse(format!("level {} of layer {} is truncated", level, layer)))?;
+                images[((level * layers + layer) * faces + face) as usize] = swizzled(image, swizzle);
+                offset += size;
+            }
+        }
+    }
+
+    let image = TextureImage {
+        format: format,
+        color_space: color_space,
+        width: width,
+        height: height,
+        layers: layers,
+        faces: faces,
+        levels: levels,
+        images: images
+    };
+    image.validate()?;
+    Ok(image)
+}
+
+fn swizzled(image: &[u8], swizzle: Swizzle) -> Vec<u8> {
+    match swizzle {
+        Swizzle::None => image.to_vec(),
+        Swizzle::Bgra => image.chunks(4).flat_map(|pixel| vec![pixel[2], pixel[1], pixel[0], pixel[3]]).collect(),
+        Swizzle::Masks([r, g, b], alpha) => image.chunks(4)
+            .flat_map(|pixel| vec![pixel[r], pixel[g], pixel[b], alpha.map_or(255, |a| pixel[a])])
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use texture::ColorSpace;
+    use texture::container::{DecodedPixels, PixelFormat, TextureImage};
+    use super::*;
+
+    fn fixture(name: &str) -> Vec<u8> {
+        fs::read(format!("content/textures/{}", name)).unwrap()
+    }
+
+    fn pixel(pixels: &DecodedPixels, width: u32, x: u32, y: u32) -> [u8; 4] {
+        match *pixels {
+            DecodedPixels::Rgba8(ref pixels) => {
+                let start = ((y * width + x) * 4) as usize;
+                [pixels[start], pixels[start + 1], pixels[start + 2], pixels[start + 3]]
+            },
+            _ => panic!("not an 8 bits image")
+        }
+    }
+
+    #[test]
+    fn legacy_mipmaps() {
+        let image = parse_dds(&fixture("bc1_mips.dds")).unwrap();
+        assert_eq!((image.format, image.color_space), (PixelFormat::Bc1, None));
+        assert_eq!((image.width, image.height, image.levels, image.layers, image.faces), (8, 8, 4, 1, 1));
+
+        let base = image.decode(0, 0, 0);
+        assert_eq!(pixel(&base, 8, 0, 0), [255, 0, 0, 255]);
+        assert_eq!(pixel(&base, 8, 7, 0), [0, 255, 0, 255]);
+        assert_eq!(pixel(&base, 8, 0, 7), [0, 0, 255, 255]);
+        assert_eq!(pixel(&base, 8, 7, 7), [255, 255, 255, 255]);
+        assert_eq!(image.decode(1, 0, 0), DecodedPixels::Rgba8([0, 255, 0, 255].repeat(16)));
+        assert_eq!(image.decode(2, 0, 0), DecodedPixels::Rgba8([0, 0, 255, 255].repeat(4)));
+        assert_eq!(image.decode(3, 0, 0), DecodedPixels::Rgba8(vec![255; 4]));
+    }
+
+    #[test]
+    fn dx10_srgb() {
+        let image = TextureImage::open("content/textures/bc7_srgb.dds").unwrap();
+        assert_eq!((image.format, image.color_space), (PixelFormat::Bc7, Some(ColorSpace::Srgb)));
+        assert_eq!((image.width, image.height, image.levels), (4, 4, 1));
+        let pixels = image.decode(0, 0, 0);
+        assert_eq!(pixel(&pixels, 4, 0, 0), [255, 1, 1, 255]);
+        assert_eq!(pixel(&pixels, 4, 3, 3), [0, 0, 254, 254]);
+    }
+
+    #[test]
+    fn legacy_cubemap() {
+        let image = parse_dds(&fixture("cube.dds")).unwrap();
+        assert!(image.is_cubemap());
+        assert_eq!((image.format, image.width, image.height, image.layers), (PixelFormat::Rgba8, 2, 2, 1));
+        // Stored as BGRA
+        let faces = [[255, 0, 0], [0, 255, 255], [0, 255, 0], [255, 0, 255], [0, 0, 255], [255, 255, 0]];
+        for (face, color) in faces.iter().enumerate() {
+            let expected = [color[0], color[1], color[2], 128].repeat(4);
+            assert_eq!(image.image(0, 0, face as u32), &expected[..]);
+        }
+    }
+
+    #[test]
+    fn non_square_cubemaps_are_rejected() {
+        // Faces of 2x1 pixels, with the data they need
+        let mut data = fixture("cube.dds");
+        data[12..16].copy_from_slice(&1u32.to_le_bytes());
+        data.truncate(HEADER_SIZE + 6 * 8);
+        assert!(parse_dds(&data).is_err());
+    }
+
+    #[test]
+    fn dx10_array() {
+        let image = parse_dds(&fixture("bc1_array.dds")).unwrap();
+        assert_eq!((image.format, image.color_space), (PixelFormat::Bc1, Some(ColorSpace::Linear)));
+        assert_eq!((image.layers, image.faces, image.levels), (3, 1, 1));
+        for (layer, color) in [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]].iter().enumerate() {
+            assert_eq!(image.decode(0, layer as u32, 0), DecodedPixels::Rgba8(color.repeat(16)));
+        }
+    }
+
+    #[test]
+    fn truncated_files_are_rejected() {
+        for name in &["bc1_mips.dds", "bc7_srgb.dds", "cube.dds", "bc1_array.dds"] {
+            let data = fixture(name);
+            assert!(parse_dds(&data[..data.len() - 1]).is_err(), "{}", name);
+            assert!(parse_dds(&data[..HEADER_SIZE - 1]).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn huge_headers_are_rejected() {
+        // Wider than 2^31 pixels
+        let mut data = fixture("bc1_mips.dds");
+        data[16..20].copy_from_slice(&0x8000_0001u32.to_le_bytes());
+        assert!(parse_dds(&data).is_err());
+
+        // As wide as possible, the size of the image would overflow on 32 bits machines and is
+        // out of the file on the others
+        let mut data = fixture("cube.dds");
+        data[12..20].copy_from_slice(&[0, 0, 0, 0x80, 0, 0, 0, 0x80]);
+        assert!(parse_dds(&data).is_err());
+
+        // More layers than bytes
+        let mut data = fixture("bc1_array.dds");
+        data[HEADER_SIZE + 12..HEADER_SIZE + 16].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(parse_dds(&data).is_err());
+    }
+}
@@ -0,0 +1,309 @@
+// Decoders for the ETC2 block formats, which OpenGL ES 3 and OpenGL 4.3 sample directly but glium
+// cannot upload. ETC1 blocks are valid ETC2 blocks.
+//
+// Blocks are 64 bits big-endian words covering 4x4 pixels. The pixels of a block are numbered by
+// column (x * 4 + y), the output arrays are row by row like the BCn decoders.
+
+const MODIFIERS: [[i32; 2]; 8] = [[2, 8], [5, 17], [9, 29], [13, 42], [18, 60], [24, 80], [33, 106], [47, 183]];
+
+const DISTANCES: [i32; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
+
+const ALPHA_MODIFIERS: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14],
+    [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12],
+    [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11],
+    [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10],
+    [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9],
+    [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9],
+    [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9],
+    [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8],
+    [-3, -5, -7, -9, 2, 4, 6, 8]
+];
+
+fn word(block: &[u8]) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&block[..8]);
+    u64::from_be_bytes(bytes)
+}
+
+fn bits(word: u64, high: u32, low: u32) -> i32 {
+    ((word >> low) & ((1 << (high - low + 1)) - 1)) as i32
+}
+
+fn extend4(value: i32) -> i32 {
+    (value << 4) | value
+}
+
+fn extend5(value: i32) -> i32 {
+    (value << 3) | (value >> 2)
+}
+
+fn clamp(value: i32) -> u8 {
+    value.clamp(0, 255) as u8
+}
+
+// The output index of the pixel with the given position in the block word
+fn pixel(j: usize) -> usize {
+    (j % 4) * 4 + j / 4
+}
+
+fn selector(word: u64, j: usize) -> usize {
+    let msb = (word >> (j + 16)) & 1;
+    let lsb = (word >> j) & 1;
+    (msb * 2 + lsb) as usize
+}
+
+// `punchthrough` is for ETC2 RGB8A1: the differential bit becomes an opacity bit
+fn decode_color(block: &[u8], punchthrough: bool, out: &mut [[u8; 4]; 16]) {
+    let word = word(block);
+    let differential = punchthrough || bits(word, 33, 33) == 1;
+    let opaque = !punchthrough || bits(word, 33, 33) == 1;
+
+    if differential {
+        let (r, g, b) = (bits(word, 63, 59), bits(word, 55, 51), bits(word, 47, 43));
+        let (dr, dg, db) = (bits(word, 58, 56), bits(word, 50, 48), bits(word, 42, 40));
+        let delta = |value: i32| if value >= 4 { value - 8 } else { value };
+
+        if !(0..32).contains(&(r + delta(dr))) {
+            return decode_t_mode(word, opaque, out);
+        }
+        if !(0..32).contains(&(g + delta(dg))) {
+            return decode_h_mode(word, opaque, out);
+        }
+        if !(0..32).contains(&(b + delta(db))) {
+            return decode_planar(word, out);
+        }
+
+        let bases = [
+            [extend5(r), extend5(g), extend5(b)],
+            [extend5(r + delta(dr)), extend5(g + delta(dg)), extend5(b + delta(db))]
+        ];
+        decode_sub_blocks(word, bases, opaque, out);
+    } else {
+        let bases = [
+            [extend4(bits(word, 63, 60)), extend4(bits(word, 55, 52)), extend4(bits(word, 47, 44))],
+            [extend4(bits(word, 59, 56)), extend4(bits(word, 51, 48)), extend4(bits(word, 43, 40))]
+        ];
+        decode_sub_blocks(word, bases, true, out);
+    }
+}
+
+// The ETC1 modes: two halves with a base color and a table of brightness modifiers each
+fn decode_sub_blocks(word: u64, bases: [[i32; 3]; 2], opaque: bool, out: &mut [[u8; 4]; 16]) {
+    let tables = [bits(word, 39, 37) as usize, bits(word, 36, 34) as usize];
+    let flip = bits(word, 32, 32) == 1;
+
+    for j in 0..16 {
+        let (x, y) = (j / 4, j % 4);
+        let half = if flip { (y >= 2) as usize } else { (x >= 2) as usize };
+        let [small, large] = MODIFIERS[tables[half]];
+        let modifier = match selector(word, j) {
+            0 => if opaque { small } else { 0 },
+            1 => large,
+            2 => {
+                if !opaque {
+                    out[pixel(j)] = [0, 0, 0, 0];
+                    continue;
+                }
+                -small
+            },
+            _ => -large
+        };
+
+        let base = bases[half];
+        out[pixel(j)] = [clamp(base[0] + modifier), clamp(base[1] + modifier), clamp(base[2] + modifier), 255];
+    }
+}
+
+fn paint(word: u64, colors: [[i32; 3]; 4], opaque: bool, out: &mut [[u8; 4]; 16]) {
+    for j in 0..16 {
+        let index = selector(word, j);
+        out[pixel(j)] = if !opaque && index == 2 {
+            [0, 0, 0, 0]
+        } else {
+            let color = colors[index];
+            [clamp(color[0]), clamp(color[1]), clamp(color[2]), 255]
+        };
+    }
+}
+
+fn shift(color: [i32; 3], distance: i32) -> [i32; 3] {
+    [color[0] + distance, color[1] + distance, color[2] + distance]
+}
+
+fn decode_t_mode(word: u64, opaque: bool, out: &mut [[u8; 4]; 16]) {
+    let c1 = [
+        extend4((bits(word, 60, 59) << 2) | bits(word, 57, 56)),
+        extend4(bits(word, 55, 52)),
+        extend4(bits(word, 51, 48))
+    ];
+    let c2 = [extend4(bits(word, 47, 44)), extend4(bits(word, 43, 40)), extend4(bits(word, 39, 36))];
+    let distance = DISTANCES[((bits(word, 35, 34) << 1) | bits(word, 32, 32)) as usize];
+
+    paint(word, [c1, shift(c2, distance), c2, shift(c2, -distance)], opaque, out);
+}
+
+fn decode_h_mode(word: u64, opaque: bool, out: &mut [[u8; 4]; 16]) {
+    let c1 = [
+        extend4(bits(word, 62, 59)),
+        extend4((bits(word, 58, 56) << 1) | bits(word, 52, 52)),
+        extend4((bits(word, 51, 51) << 3) | bits(word, 49, 47))
+    ];
+    let c2 = [extend4(bits(word, 46, 43)), extend4(bits(word, 42, 39)), extend4(bits(word, 38, 35))];
+
+    // The order of the colors gives the last bit of the distance index
+    let value = |color: [i32; 3]| (color[0] << 16) | (color[1] << 8) | color[2];
+    let order = (value(c1) >= value(c2)) as i32;
+    let distance = DISTANCES[((bits(word, 34, 34) << 2) | (bits(word, 32, 32) << 1) | order) as usize];
+
+    paint(word, [shift(c1, distance), shift(c1, -distance), shift(c2, distance), shift(c2, -distance)],
+        opaque, out);
+}
+
+// A gradient given by the colors at the origin, at (4, 0) and at (0, 4)
+fn decode_planar(word: u64, out: &mut [[u8; 4]; 16]) {
+    let extend6 = |value: i32| (value << 2) | (value >> 4);
+    let extend7 = |value: i32| (value << 1) | (value >> 6);
+
+    let origin = [
+        extend6(bits(word, 62, 57)),
+        extend7((bits(word, 56, 56) << 6) | bits(word, 54, 49)),
+        extend6((bits(word, 48, 48) << 5) | (bits(word, 44, 43) << 3) | bits(word, 41, 39))
+    ];
+    let horizontal = [
+        extend6((bits(word, 38, 34) << 1) | bits(word, 32, 32)),
+        extend7(bits(word, 31, 25)),
+        extend6(bits(word, 24, 19))
+    ];
+    let vertical = [extend6(bits(word, 18, 13)), extend7(bits(word, 12, 6)), extend6(bits(word, 5, 0))];
+
+    for y in 0..4 {
+        for x in 0..4 {
+            let channel = |c: usize| {
+                clamp((x * (horizontal[c] - origin[c]) + y * (vertical[c] - origin[c]) + 4 * origin[c] + 2) >> 2)
+            };
+            out[(y * 4 + x) as usize] = [channel(0), channel(1), channel(2), 255];
+        }
+    }
+}
+
+pub fn decode_etc2_rgb(block: &[u8], out: &mut [[u8; 4]; 16]) {
+    decode_color(block, false, out);
+}
+
+pub fn decode_etc2_rgb_a1(block: &[u8], out: &mut [[u8; 4]; 16]) {
+    decode_color(block, true, out);
+}
+
+// An EAC alpha block followed by an ETC2 color block
+pub fn decode_etc2_rgba(block: &[u8], out: &mut [[u8; 4]; 16]) {
+    decode_color(&block[8..], false, out);
+
+    let word = word(block);
+    let base = bits(word, 63, 56);
+    let multiplier = bits(word, 55, 52);
+    let table = ALPHA_MODIFIERS[bits(word, 51, 48) as usize];
+    for j in 0..16 {
+        let index = ((word >> (45 - 3 * j)) & 7) as usize;
+        out[pixel(j)][3] = clamp(base + table[index] * multiplier);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Bases (132, 132, 132) on both halves, tables 0 and 7, selector 0 but -large for (0, 1)
+    const DIFFERENTIAL: [u8; 8] = [0x80, 0x80, 0x80, 0x1E, 0x00, 0x02, 0x00, 0x02];
+
+    fn gray(value: u8) -> [u8; 4] {
+        [value, value, value, 255]
+    }
+
+    fn decode(decoder: fn(&[u8], &mut [[u8; 4]; 16]), block: &[u8]) -> [[u8; 4]; 16] {
+        let mut out = [[0u8; 4]; 16];
+        decoder(block, &mut out);
+        out
+    }
+
+    #[test]
+    fn differential_mode() {
+        let out = decode(decode_etc2_rgb, &DIFFERENTIAL);
+        for (pixel, color) in out.iter().enumerate() {
+            let expected = match pixel {
+                4 => gray(124),
+                _ if pixel % 4 < 2 => gray(134),
+                _ => gray(179)
+            };
+            assert_eq!(*color, expected, "pixel {}", pixel);
+        }
+    }
+
+    #[test]
+    fn individual_mode() {
+        // Bases 0x88 and 0xFF, flipped so that the halves are the top and the bottom, table 1
+        let out = decode(decode_etc2_rgb, &[0x8F, 0x8F, 0x8F, 0x25, 0x00, 0x00, 0x00, 0x00]);
+        for (pixel, color) in out.iter().enumerate() {
+            assert_eq!(*color, if pixel < 8 { gray(141) } else { gray(255) }, "pixel {}", pixel);
+        }
+    }
+
+    #[test]
+    fn t_mode() {
+        // Black, then (136, 136, 136) with the distance 3, down the first column
+        let out = decode(decode_etc2_rgb, &[0x04, 0x00, 0x88, 0x82, 0x00, 0x0C, 0x00, 0x0A]);
+        assert_eq!(out[0], gray(0));
+        assert_eq!(out[4], gray(139));
+        assert_eq!(out[8], gray(136));
+        assert_eq!(out[12], gray(133));
+        assert_eq!(out[1], gray(0));
+    }
+
+    #[test]
+    fn planar_mode() {
+        // Red goes from 0 at the origin to 255 at (4, 0)
+        let out = decode(decode_etc2_rgb, &[0x00, 0x00, 0x04, 0x7F, 0x00, 0x00, 0x00, 0x00]);
+        for (pixel, color) in out.iter().enumerate() {
+            assert_eq!(*color, [[0, 64, 128, 191][pixel % 4], 0, 0, 255]);
+        }
+    }
+
+    #[test]
+    fn punchthrough_alpha() {
+        // The differential block without the opaque bit: no small modifier, selector 2 at (0, 2)
+        // is transparent
+        let out = decode(decode_etc2_rgb_a1, &[0x80, 0x80, 0x80, 0x1C, 0x00, 0x06, 0x00, 0x02]);
+        for (pixel, color) in out.iter().enumerate() {
+            let expected = match pixel {
+                4 => gray(124),
+                8 => [0, 0, 0, 0],
+                _ => gray(132)
+            };
+            assert_eq!(*color, expected, "pixel {}", pixel);
+        }
+    }
+
+    #[test]
+    fn eac_alpha() {
+        // Base 128, multiplier 1, table 13: 9 at (0, 0), -10 at (1, 1) and 0 elsewhere
+        let mut block = [0x80, 0x1D, 0xF2, 0x48, 0xE4, 0x92, 0x49, 0x24, 0, 0, 0, 0, 0, 0, 0, 0];
+        block[8..].copy_from_slice(&DIFFERENTIAL);
+        let out = decode(decode_etc2_rgba, &block);
+        let colors = decode(decode_etc2_rgb, &DIFFERENTIAL);
+        for (pixel, (color, rgb)) in out.iter().zip(colors.iter()).enumerate() {
+            let alpha = match pixel {
+                0 => 137,
+                5 => 118,
+                _ => 128
+            };
+            assert_eq!(*color, [rgb[0], rgb[1], rgb[2], alpha], "pixel {}", pixel);
+        }
+    }
+}
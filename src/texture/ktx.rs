@@ -0,0 +1,295 @@
+// Khronos texture files, version 1 (OpenGL enums) and version 2 (Vulkan formats). Both store the
+// images level by level, with every layer and face of a level together. Both versions name the
+// sRGB formats explicitly. Supercompressed KTX2 files (Basis, Zstandard) are not supported.
+
+use error::LoadError;
+use texture::container::{PixelFormat, TextureImage};
+
+const KTX1_IDENTIFIER: &[u8] = &[0xAB, 0x4B, 0x54, 0x58, 0x20, 0x31, 0x31, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+const KTX2_IDENTIFIER: &[u8] = &[0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+const KTX1_HEADER_SIZE: usize = 64;
+const KTX2_HEADER_SIZE: usize = 80;
+const ENDIANNESS: u32 = 0x0403_0201;
+
+pub fn is_ktx(data: &[u8]) -> bool {
+    data.starts_with(KTX1_IDENTIFIER) || data.starts_with(KTX2_IDENTIFIER)
+}
+
+pub fn parse_ktx(data: &[u8]) -> Result<TextureImage, LoadError> {
+    if data.starts_with(KTX1_IDENTIFIER) {
+        parse_ktx1(data)
+    } else if data.starts_with(KTX2_IDENTIFIER) {
+        parse_ktx2(data)
+    } else {
+        Err(LoadError::parse("not a KTX file"))
+    }
+}
+
+// The format of a glInternalFormat and whether it is sRGB
+fn gl_format(format: u32) -> Option<(PixelFormat, bool)> {
+    match format {
+        // GL_RGBA, GL_RGBA8
+        0x1908 | 0x8058 => Some((PixelFormat::Rgba8, false)),
+        0x8C43 => Some((PixelFormat::Rgba8, true)),
+        0x83F0 | 0x83F1 => Some((PixelFormat::Bc1, false)),
+        0x8C4C | 0x8C4D => Some((PixelFormat::Bc1, true)),
+        0x83F2 => Some((PixelFormat::Bc2, false)),
+        0x8C4E => Some((PixelFormat::Bc2, true)),
+        0x83F3 => Some((PixelFormat::Bc3, false)),
+        0x8C4F => Some((PixelFormat::Bc3, true)),
+        0x8DBB => Some((PixelFormat::Bc4, false)),
+        0x8DBD => Some((PixelFormat::Bc5, false)),
+        0x8E8C => Some((PixelFormat::Bc7, false)),
+        0x8E8D => Some((PixelFormat::Bc7, true)),
+        0x8E8E => Some((PixelFormat::Bc6hSigned, false)),
+        0x8E8F => Some((PixelFormat::Bc6hUnsigned, false)),
+        // ETC1 is a subset of ETC2
+        0x8D64 | 0x9274 => Some((PixelFormat::Etc2Rgb, false)),
+        0x9275 => Some((PixelFormat::Etc2Rgb, true)),
+        0x9276 => Some((PixelFormat::Etc2RgbA1, false)),
+        0x9277 => Some((PixelFormat::Etc2RgbA1, true)),
+        0x9278 => Some((PixelFormat::Etc2Rgba, false)),
+        0x9279 => Some((PixelFormat::Etc2Rgba, true)),
+        _ => None
+    }
+}
+
+fn vk_format(format: u32) -> Option<(PixelFormat, bool)> {
+    match format {
+        37 => Some((PixelFormat::Rgba8, false)),
+        43 => Some((PixelFormat::Rgba8, true)),
+        131 | 133 => Some((PixelFormat::Bc1, false)),
+        132 | 134 => Some((PixelFormat::Bc1, true)),
+        135 => Some((PixelFormat::Bc2, false)),
+        136 => Some((PixelFormat::Bc2, true)),
+        137 => Some((PixelFormat::Bc3, false)),
+        138 => Some((PixelFormat::Bc3, true)),
+        139 => Some((PixelFormat::Bc4, false)),
+        141 => Some((PixelFormat::Bc5, false)),
+        143 => Some((PixelFormat::Bc6hUnsigned, false)),
+        144 => Some((PixelFormat::Bc6hSigned, false)),
+        145 => Some((PixelFormat::Bc7, false)),
+        146 => Some((PixelFormat::Bc7, true)),
+        147 => Some((PixelFormat::Etc2Rgb, false)),
+        148 => Some((PixelFormat::Etc2Rgb, true)),
+        149 => Some((PixelFormat::Etc2RgbA1, false)),
+        150 => Some((PixelFormat::Etc2RgbA1, true)),
+        151 => Some((PixelFormat::Etc2Rgba, false)),
+        152 => Some((PixelFormat::Etc2Rgba, true)),
+        _ => None
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize, big_endian: bool) -> u32 {
+    let mut bytes = [0u8; 4];
+    bytes.copy_from_slice(&data[offset..offset + 4]);
+    if big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) }
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&data[offset..offset + 8]);
+    u64::from_le_bytes(bytes)
+}
+
+// Split the images of a level, stored one after the other
+fn split_level(image: &mut TextureImage, level: u32, data: &[u8], mut offset: usize, padding: usize)
+        -> Result<usize, LoadError> {
+    let (width, height) = image.level_dimensions(level);
+    let size = image.format.image_size(width, height)?;
+    for _ in 0..image.layers * image.faces {
+        let bytes = offset.checked_add(size).and_then(|end| data.get(offset..end))
+            .ok_or_else(|| LoadError::parse(format!("level {} is truncated", level)))?;
+        image.images.push(bytes.to_vec());
+        offset = (offset + size).next_multiple_of(padding);
+    }
+    Ok(offset)
+}
+
+fn parse_ktx1(data: &[u8]) -> Result<TextureImage, LoadError> {
+    if data.len() < KTX1_HEADER_SIZE {
+        return Err(LoadError::parse("truncated KTX header"));
+    }
+    // Written in the byte order of the machine which made the file
+    let big_endian = match read_u32(data, 12, false) {
+        ENDIANNESS => false,
+        0x0102_0304 => true,
+        _ => return Err(LoadError::parse("invalid KTX endianness"))
+    };
+    let read = |offset| read_u32(data, offset, big_endian);
+
+    let internal_format = read(28);
+    let (format, srgb) = gl_format(internal_format)
+        .ok_or_else(|| LoadError::parse(format!("unsupported internal format 0x{:X}", internal_format)))?;
+    if format == PixelFormat::Rgba8 && read(16) != 0x1401 {
+        return Err(LoadError::parse("only GL_UNSIGNED_BYTE pixels are supported"));
+    }
+    if read(44) > 1 {
+        return Err(LoadError::parse("3D textures are not supported"));
+    }
+
+    let mut image = TextureImage {
+        format: format,
+        color_space: format.declared_color_space(srgb),
+        width: read(36),
+        height: read(40).max(1),
+        layers: read(48).max(1),
+        faces: read(52),
+        // 0 when only the base level is stored
+        levels: read(56).max(1),
+        images: Vec::new()
+    };
+
+    TextureImage::check_counts(image.width, image.height, image.levels, image.layers, image.faces, data.len())?;
+
+    // The key/value data is skipped
+    let mut offset = KTX1_HEADER_SIZE.checked_add(read(60) as usize)
+        .ok_or_else(|| LoadError::parse("truncated KTX key/value data"))?;
+    for level in 0..image.levels {
+        if data.len().saturating_sub(4) < offset {
+            return Err(LoadError::parse(format!("level {} is truncated", level)));
+        }
+        offset = split_level(&mut image, level, data, offset + 4, 4)?;
+    }
+
+    image.validate()?;
+    Ok(image)
+}
+
+fn parse_ktx2(data: &[u8]) -> Result<TextureImage, LoadError> {
+    if data.len() < KTX2_HEADER_SIZE {
+        return Err(LoadError::parse("truncated KTX2 header"));
+    }
+    let read = |offset| read_u32(data, offset, false);
+
+    let vk = read(12);
+    let (format, srgb) = vk_format(vk).ok_or_else(|| LoadError::parse(format!("unsupported Vulkan format {}", vk)))?;
+    if read(28) > 1 {
+        return Err(LoadError::parse("3D textures are not supported"));
+    }
+    if read(44) != 0 {
+        return Err(LoadError::parse("supercompressed KTX2 files are not supported"));
+    }
+
+    let mut image = TextureImage {
+        format: format,
+        color_space: format.declared_color_space(srgb),
+        width: read(20),
+        height: read(24).max(1),
+        layers: read(32).max(1),
+        faces: read(36),
+        levels: read(40).max(1),
+        images: Vec::new()
+    };
+
+    TextureImage::check_counts(image.width, image.height, image.levels, image.layers, image.faces, data.len())?;
+
+    let index_end = KTX2_HEADER_SIZE + image.levels as usize * 24;
+    if data.len() < index_end {
+        return Err(LoadError::parse("truncated KTX2 level index"));
+    }
+    for level in 0..image.levels {
+        let entry = KTX2_HEADER_SIZE + level as usize * 24;
+        let (offset, length) = (read_u64(data, entry) as usize, read_u64(data, entry + 8) as usize);
+        let level_data = data.get(offset..offset.saturating_add(length))
+            .ok_or_else(|| LoadError::parse(format!("level {} is out of the file", level)))?;
+        split_level(&mut image, level, level_data, 0, 1)?;
+    }
+
+    image.validate()?;
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use texture::ColorSpace;
+    use texture::container::{DecodedPixels, PixelFormat};
+    use super::*;
+
+    fn fixture(name: &str) -> Vec<u8> {
+        fs::read(format!("content/textures/{}", name)).unwrap()
+    }
+
+    #[test]
+    fn ktx1_etc2() {
+        let image = parse_ktx(&fixture("etc2.ktx")).unwrap();
+        assert_eq!((image.format, image.color_space), (PixelFormat::Etc2Rgb, Some(ColorSpace::Linear)));
+        assert_eq!((image.width, image.height, image.levels, image.layers, image.faces), (4, 4, 2, 1, 1));
+
+        // A red gradient, then a 2x2 corner of a gray block
+        let mut gradient = Vec::new();
+        for _ in 0..4 {
+            for &red in &[0, 64, 128, 191] {
+                gradient.extend_from_slice(&[red, 0, 0, 255]);
+            }
+        }
+        assert_eq!(image.decode(0, 0, 0), DecodedPixels::Rgba8(gradient));
+        let gray = |value| [value, value, value, 255];
+        let corner = [gray(134), gray(134), gray(124), gray(134)].concat();
+        assert_eq!(image.decode(1, 0, 0), DecodedPixels::Rgba8(corner));
+    }
+
+    #[test]
+    fn ktx1_big_endian() {
+        // The same file written by a big-endian machine: every 32 bits value of the header and
+        // every image size is swapped
+        let data = fixture("etc2.ktx");
+        let mut swapped = data.clone();
+        let swap = |data: &mut Vec<u8>, offset: usize| data[offset..offset + 4].reverse();
+        for offset in (12..KTX1_HEADER_SIZE).step_by(4) {
+            swap(&mut swapped, offset);
+        }
+        let key_values = read_u32(&data, 60, false) as usize;
+        swap(&mut swapped, KTX1_HEADER_SIZE);
+        swap(&mut swapped, KTX1_HEADER_SIZE + key_values);
+        swap(&mut swapped, KTX1_HEADER_SIZE + key_values + 12);
+        assert_eq!(parse_ktx(&swapped).unwrap(), parse_ktx(&data).unwrap());
+    }
+
+    #[test]
+    fn ktx2_array() {
+        let image = parse_ktx(&fixture("rgba_array.ktx2")).unwrap();
+        assert_eq!((image.format, image.color_space), (PixelFormat::Rgba8, Some(ColorSpace::Srgb)));
+        assert_eq!((image.width, image.height, image.levels, image.layers, image.faces), (2, 2, 2, 2, 1));
+
+        // The level index points at the levels, stored from the smallest
+        assert_eq!(image.image(0, 0, 0), &[255, 0, 0, 255].repeat(4)[..]);
+        assert_eq!(image.image(0, 1, 0), &[0, 0, 255, 255].repeat(4)[..]);
+        assert_eq!(image.image(1, 0, 0), &[0, 255, 0, 255]);
+        assert_eq!(image.image(1, 1, 0), &[255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn truncated_files_are_rejected() {
+        for name in &["etc2.ktx", "rgba_array.ktx2"] {
+            let data = fixture(name);
+            assert!(parse_ktx(&data[..data.len() - 1]).is_err(), "{}", name);
+            assert!(parse_ktx(&data[..KTX1_HEADER_SIZE - 1]).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn huge_headers_are_rejected() {
+        // Key/value data past the end of the file
+        let mut data = fixture("etc2.ktx");
+        data[60..64].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(parse_ktx(&data).is_err());
+
+        // Taller than 2^31 pixels
+        let mut data = fixture("etc2.ktx");
+        data[40..44].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(parse_ktx(&data).is_err());
+
+        // A level at the very end of the address space
+        let mut data = fixture("rgba_array.ktx2");
+        data[KTX2_HEADER_SIZE..KTX2_HEADER_SIZE + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(parse_ktx(&data).is_err());
+
+        // More faces than bytes
+        let mut data = fixture("rgba_array.ktx2");
+        data[36..40].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(parse_ktx(&data).is_err());
+    }
+}
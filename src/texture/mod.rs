@@ -0,0 +1,507 @@
+// Textures shared between meshes and materials. The `TextureManager` owns every texture and
+// hands out `TextureHandle`s; loading the same file twice gives the same handle.
+//
+// Color textures (albedo, emissive) are stored in sRGB so that the GPU turns them back into
+// linear values when sampling. Data textures (normals, roughness...) must stay linear, otherwise
+// their values would be bent by the sRGB curve.
+//
+// DDS and KTX files are uploaded as they are stored, compressed when the GPU can sample their
+// format and decoded on the CPU otherwise.
+//
+// Cube maps come from six images, one panorama or a DDS or KTX cube map. They have no mipmaps and
+// are sampled with `SamplerSettings::cubemap()`.
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use glium::backend::Facade;
+use glium::texture::{CompressedSrgbTexture2d, CompressedTexture2d, Cubemap, MipmapsOption, RawImage2d,
+    SrgbCubemap, SrgbTexture2d, SrgbTexture2dArray, Texture2d, Texture2dArray};
+use glium::uniforms::{AsUniformValue, MagnifySamplerFilter, MinifySamplerFilter, SamplerBehavior,
+    SamplerWrapFunction, UniformValue};
+use image::{self, RgbaImage};
+
+use asset::AssetMaterial;
+use error::LoadError;
+use gltf::GltfSampler;
+
+pub use self::container::{DecodedPixels, PixelFormat, TextureImage, decode_image};
+
+pub mod bcn;
+pub mod cubemap;
+pub mod dds;
+pub mod etc2;
+pub mod ktx;
+mod container;
+
+// The extensions `TextureManager::load` reads as DDS or KTX files
+pub const CONTAINER_EXTENSIONS: &[&str] = &["dds", "ktx", "ktx2"];
+
+const GL_NEAREST: u32 = 0x2600;
+const GL_LINEAR: u32 = 0x2601;
+const GL_NEAREST_MIPMAP_NEAREST: u32 = 0x2700;
+const GL_LINEAR_MIPMAP_NEAREST: u32 = 0x2701;
+const GL_NEAREST_MIPMAP_LINEAR: u32 = 0x2702;
+const GL_CLAMP_TO_EDGE: u32 = 0x812F;
+const GL_MIRRORED_REPEAT: u32 = 0x8370;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ColorSpace {
+    Srgb,
+    Linear
+}
+
+// How a texture is sampled, kept as plain data so that it can come from a material or a file
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SamplerSettings {
+    pub wrap_s: SamplerWrapFunction,
+    pub wrap_t: SamplerWrapFunction,
+    pub min_filter: MinifySamplerFilter,
+    pub mag_filter: MagnifySamplerFilter,
+    // 1 disables anisotropic filtering
+    pub anisotropy: u16
+}
+
+impl Default for SamplerSettings {
+    // Repeating and trilinear, the usual settings for the textures of a model
+    fn default() -> SamplerSettings {
+        SamplerSettings {
+            wrap_s: SamplerWrapFunction::Repeat,
+            wrap_t: SamplerWrapFunction::Repeat,
+            min_filter: MinifySamplerFilter::LinearMipmapLinear,
+            mag_filter: MagnifySamplerFilter::Linear,
+            anisotropy: 1
+        }
+    }
+}
+
+impl SamplerSettings {
+    // Sharp pixels, e.g. for pixel art or lookup tables
+    pub fn nearest() -> SamplerSettings {
+        SamplerSettings {
+            min_filter: MinifySamplerFilter::Nearest,
+            mag_filter: MagnifySamplerFilter::Nearest,
+            ..SamplerSettings::default()
+        }
+    }
+
+    // No wrapping, e.g. for screen-sized textures or decals
+    pub fn clamped() -> SamplerSettings {
+        SamplerSettings {
+            wrap_s: SamplerWrapFunction::Clamp,
+            wrap_t: SamplerWrapFunction::Clamp,
+            ..SamplerSettings::default()
+        }
+    }
+
+    // The raw OpenGL enums of a glTF sampler, missing filters fall back to the defaults
+    pub fn from_gltf(sampler: &GltfSampler) -> SamplerSettings {
+        let wrap = |mode| match mode {
+            GL_CLAMP_TO_EDGE => SamplerWrapFunction::Clamp,
+            GL_MIRRORED_REPEAT => SamplerWrapFunction::Mirror,
+            _ => SamplerWrapFunction::Repeat
+        };
+        let defaults = SamplerSettings::default();
+
+        SamplerSettings {
+            wrap_s: wrap(sampler.wrap_s),
+            wrap_t: wrap(sampler.wrap_t),
+            min_filter: match sampler.min_filter {
+                Some(GL_NEAREST) => MinifySamplerFilter::Nearest,
+                Some(GL_LINEAR) => MinifySamplerFilter::Linear,
+                Some(GL_NEAREST_MIPMAP_NEAREST) => MinifySamplerFilter::NearestMipmapNearest,
+                Some(GL_LINEAR_MIPMAP_NEAREST) => MinifySamplerFilter::LinearMipmapNearest,
+                Some(GL_NEAREST_MIPMAP_LINEAR) => MinifySamplerFilter::NearestMipmapLinear,
+                _ => defaults.min_filter
+            },
+            mag_filter: match sampler.mag_filter {
+                Some(GL_NEAREST) => MagnifySamplerFilter::Nearest,
+                _ => defaults.mag_filter
+            },
+            ..defaults
+        }
+    }
+
+    // Clamped and without mipmaps, for cube maps
+    pub fn cubemap() -> SamplerSettings {
+        SamplerSettings {
+            min_filter: MinifySamplerFilter::Linear,
+            ..SamplerSettings::clamped()
+        }
+    }
+
+    pub fn with_anisotropy(self, anisotropy: u16) -> SamplerSettings {
+        SamplerSettings {
+            anisotropy: anisotropy.max(1),
+            ..self
+        }
+    }
+
+    pub fn behavior(&self) -> SamplerBehavior {
+        SamplerBehavior {
+            wrap_function: (self.wrap_s, self.wrap_t, self.wrap_t),
+            minify_filter: self.min_filter,
+            magnify_filter: self.mag_filter,
+            max_anisotropy: self.anisotropy
+        }
+    }
+}
+
+// A texture of the manager. Handles are only meaningful for the manager which created them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TextureHandle(usize);
+
+pub enum ManagedTexture {
+    Srgb(SrgbTexture2d),
+    Linear(Texture2d),
+    CompressedSrgb(CompressedSrgbTexture2d),
+    Compressed(CompressedTexture2d),
+    SrgbArray(SrgbTexture2dArray),
+    LinearArray(Texture2dArray),
+    SrgbCubemap(SrgbCubemap),
+    LinearCubemap(Cubemap)
+}
+
+impl ManagedTexture {
+    pub fn dimensions(&self) -> (u32, u32) {
+        match *self {
+            ManagedTexture::Srgb(ref texture) => texture.dimensions(),
+            ManagedTexture::Linear(ref texture) => texture.dimensions(),
+            ManagedTexture::CompressedSrgb(ref texture) => texture.dimensions(),
+            ManagedTexture::Compressed(ref texture) => texture.dimensions(),
+            ManagedTexture::SrgbArray(ref texture) => (texture.width(), texture.height()),
+            ManagedTexture::LinearArray(ref texture) => (texture.width(), texture.height()),
+            ManagedTexture::SrgbCubemap(ref texture) => (texture.dimensions(), texture.dimensions()),
+            ManagedTexture::LinearCubemap(ref texture) => (texture.dimensions(), texture.dimensions())
+        }
+    }
+
+    pub fn is_cubemap(&self) -> bool {
+        matches!(*self, ManagedTexture::SrgbCubemap(_) | ManagedTexture::LinearCubemap(_))
+    }
+
+    pub fn color_space(&self) -> ColorSpace {
+        match *self {
+            ManagedTexture::Srgb(_) | ManagedTexture::CompressedSrgb(_) | ManagedTexture::SrgbArray(_)
+                | ManagedTexture::SrgbCubemap(_) => ColorSpace::Srgb,
+            _ => ColorSpace::Linear
+        }
+    }
+}
+
+// A texture with its sampler settings, to put in `uniform!` like a glium `Sampler`
+#[derive(Clone, Copy)]
+pub struct SampledTexture<'a> {
+    texture: &'a ManagedTexture,
+    behavior: SamplerBehavior
+}
+
+impl<'a> SampledTexture<'a> {
+    // The uniform value for as long as the texture is borrowed, not only as long as `self`
+    pub fn uniform_value(self) -> UniformValue<'a> {
+        match *self.texture {
+            ManagedTexture::Srgb(ref texture) => UniformValue::SrgbTexture2d(texture, Some(self.behavior)),
+            ManagedTexture::Linear(ref texture) => UniformValue::Texture2d(texture, Some(self.behavior)),
+            ManagedTexture::CompressedSrgb(ref texture) => {
+                UniformValue::CompressedSrgbTexture2d(texture, Some(self.behavior))
+            },
+            ManagedTexture::Compressed(ref texture) => UniformValue::CompressedTexture2d(texture, Some(self.behavior)),
+            ManagedTexture::SrgbArray(ref texture) => UniformValue::SrgbTexture2dArray(texture, Some(self.behavior)),
+            ManagedTexture::LinearArray(ref texture) => UniformValue::Texture2dArray(texture, Some(self.behavior)),
+            ManagedTexture::SrgbCubemap(ref texture) => UniformValue::SrgbCubemap(texture, Some(self.behavior)),
+            ManagedTexture::LinearCubemap(ref texture) => UniformValue::Cubemap(texture, Some(self.behavior))
+        }
+    }
+}
+
+impl<'a> AsUniformValue for SampledTexture<'a> {
+    fn as_uniform_value(&self) -> UniformValue<'_> {
+        self.uniform_value()
+    }
+}
+
+struct Entry {
+    texture: ManagedTexture,
+    sampler: SamplerSettings,
+    path: Option<PathBuf>
+}
+
+// The textures of a material, as `TextureManager::load_material` loads them
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MaterialTextures {
+    pub base_color: Option<TextureHandle>,
+    pub specular: Option<TextureHandle>,
+    pub normal: Option<TextureHandle>,
+    pub metallic_roughness: Option<TextureHandle>
+}
+
+// The files a texture was loaded from and how, with canonical paths
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum TextureKey {
+    Image(PathBuf, ColorSpace),
+    Cubemap([PathBuf; 6], ColorSpace),
+    // With the size of the faces
+    Panorama(PathBuf, u32)
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[derive(Default)]
+pub struct TextureManager {
+    entries: Vec<Entry>,
+    by_path: HashMap<TextureKey, TextureHandle>
+}
+
+impl TextureManager {
+    pub fn new() -> TextureManager {
+        TextureManager::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Load an image file with mipmaps and the default sampler settings. A file already loaded in
+    // the same color space is not loaded again. DDS and KTX files keep their own mipmaps, and
+    // their own color space when they tell it.
+    pub fn load<F: Facade, P: AsRef<Path>>(&mut self, facade: &F, path: P, color_space: ColorSpace)
+            -> Result<TextureHandle, LoadError> {
+        let path = path.as_ref();
+        let key = TextureKey::Image(canonical(path), color_space);
+        if let Some(&handle) = self.by_path.get(&key) {
+            return Ok(handle);
+        }
+
+        let extension = path.extension().and_then(OsStr::to_str).unwrap_or("").to_lowercase();
+        let handle = if CONTAINER_EXTENSIONS.contains(&extension.as_str()) {
+            let texture = TextureImage::open(path)?.upload(facade, color_space)?;
+            self.insert(texture)
+        } else {
+            let image = image::open(path)?.to_rgba();
+            self.insert_image(facade, image, color_space)?
+        };
+        self.entries[handle.0].path = Some(path.to_path_buf());
+        self.by_path.insert(key, handle);
+        Ok(handle)
+    }
+
+    // Add an image which does not come from a file, e.g. one embedded in a glTF file. It is
+    // flipped like the loaded files, so that (0, 0) is the bottom left corner as OpenGL wants.
+    pub fn insert_image<F: Facade>(&mut self, facade: &F, image: RgbaImage, color_space: ColorSpace)
+            -> Result<TextureHandle, LoadError> {
+        let image_dim = image.dimensions();
+        let raw = RawImage2d::from_raw_rgba_reversed(image.into_raw(), image_dim);
+        let mipmaps = MipmapsOption::AutoGeneratedMipmaps;
+        let texture = match color_space {
+            ColorSpace::Srgb => ManagedTexture::Srgb(SrgbTexture2d::with_mipmaps(facade, raw, mipmaps)?),
+            ColorSpace::Linear => ManagedTexture::Linear(Texture2d::with_mipmaps(facade, raw, mipmaps)?)
+        };
+        Ok(self.insert(texture))
+    }
+
+    // Six images in the order of `cubemap::CUBE_FACES`: +X, -X, +Y, -Y, +Z, -Z. The same six
+    // files in the same color space are not loaded again.
+    pub fn load_cubemap<F: Facade, P: AsRef<Path>>(&mut self, facade: &F, faces: &[P; 6], color_space: ColorSpace)
+            -> Result<TextureHandle, LoadError> {
+        let paths = [0, 1, 2, 3, 4, 5].map(|face| canonical(faces[face].as_ref()));
+        let key = TextureKey::Cubemap(paths, color_space);
+        if let Some(&handle) = self.by_path.get(&key) {
+            return Ok(handle);
+        }
+
+        let mut images = Vec::with_capacity(6);
+        for path in faces {
+            images.push(cubemap::rgba_face(image::open(path)?.to_rgba()));
+        }
+        let texture = cubemap::upload_cubemap(facade, images, color_space)?;
+        let handle = self.insert(texture);
+        self.by_path.insert(key, handle);
+        Ok(handle)
+    }
+
+    // An equirectangular panorama, HDR or not, resampled into a linear cube map with faces of
+    // `size` texels. 0 picks a quarter of the panorama width. A panorama already loaded with the
+    // same size of faces is not loaded again.
+    pub fn load_panorama<F: Facade, P: AsRef<Path>>(&mut self, facade: &F, path: P, size: u32)
+            -> Result<TextureHandle, LoadError> {
+        let path = path.as_ref();
+        let requested = TextureKey::Panorama(canonical(path), size);
+        if let Some(&handle) = self.by_path.get(&requested) {
+            return Ok(handle);
+        }
+
+        let (pixels, width, height) = cubemap::load_panorama(path)?;
+        let size = if size == 0 { (width / 4).max(1) } else { size };
+        let key = TextureKey::Panorama(canonical(path), size);
+        if let Some(&handle) = self.by_path.get(&key) {
+            self.by_path.insert(requested, handle);
+            return Ok(handle);
+        }
+
+        let faces = cubemap::equirectangular_to_faces(&pixels, width, height, size)?.into_iter()
+            .map(|face| cubemap::rgb_float_face(face, size))
+            .collect();
+        let texture = cubemap::upload_cubemap(facade, faces, ColorSpace::Linear)?;
+
+        let handle = self.insert(texture);
+        self.entries[handle.0].path = Some(path.to_path_buf());
+        self.by_path.insert(requested, handle);
+        self.by_path.insert(key, handle);
+        Ok(handle)
+    }
+
+    // Take ownership of a texture created elsewhere
+    pub fn insert(&mut self, texture: ManagedTexture) -> TextureHandle {
+        let sampler = if texture.is_cubemap() { SamplerSettings::cubemap() } else { SamplerSettings::default() };
+        self.entries.push(Entry {
+            texture: texture,
+            sampler: sampler,
+            path: None
+        });
+        TextureHandle(self.entries.len() - 1)
+    }
+
+    // Color maps in sRGB, the others in linear space. Missing maps give no handle.
+    pub fn load_material<F: Facade>(&mut self, facade: &F, material: &AssetMaterial)
+            -> Result<MaterialTextures, LoadError> {
+        let mut load = |&(path, color_space): &(&Option<PathBuf>, ColorSpace)| match *path {
+            Some(ref path) => self.load(facade, path, color_space).map(Some),
+            None => Ok(None)
+        };
+
+        let maps = material_maps(material);
+        Ok(MaterialTextures {
+            base_color: load(&maps[0])?,
+            specular: load(&maps[1])?,
+            normal: load(&maps[2])?,
+            metallic_roughness: load(&maps[3])?
+        })
+    }
+
+    pub fn find<P: AsRef<Path>>(&self, path: P, color_space: ColorSpace) -> Option<TextureHandle> {
+        self.by_path.get(&TextureKey::Image(canonical(path.as_ref()), color_space)).cloned()
+    }
+
+    pub fn get(&self, handle: TextureHandle) -> &ManagedTexture {
+        &self.entries[handle.0].texture
+    }
+
+    // The file a texture was loaded from
+    pub fn path(&self, handle: TextureHandle) -> Option<&Path> {
+        self.entries[handle.0].path.as_deref()
+    }
+
+    pub fn sampler(&self, handle: TextureHandle) -> SamplerSettings {
+        self.entries[handle.0].sampler
+    }
+
+    pub fn set_sampler(&mut self, handle: TextureHandle, sampler: SamplerSettings) {
+        self.entries[handle.0].sampler = sampler;
+    }
+
+    // The texture with its own sampler settings
+    pub fn sampled(&self, handle: TextureHandle) -> SampledTexture<'_> {
+        self.sampled_with(handle, self.sampler(handle))
+    }
+
+    pub fn sampled_with(&self, handle: TextureHandle, sampler: SamplerSettings) -> SampledTexture<'_> {
+        SampledTexture {
+            texture: self.get(handle),
+            behavior: sampler.behavior()
+        }
+    }
+}
+
+// The maps of a material in the order of `MaterialTextures`, with the color space each one is
+// loaded in
+fn material_maps(material: &AssetMaterial) -> [(&Option<PathBuf>, ColorSpace); 4] {
+    [
+        (&material.base_color_map, ColorSpace::Srgb),
+        (&material.specular_map, ColorSpace::Srgb),
+        (&material.normal_map, ColorSpace::Linear),
+        (&material.metallic_roughness_map, ColorSpace::Linear)
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter, SamplerWrapFunction};
+
+    use asset::AssetMaterial;
+    use gltf::GltfSampler;
+    use super::*;
+
+    const GL_LINEAR_MIPMAP_LINEAR: u32 = 0x2703;
+    const GL_REPEAT: u32 = 0x2901;
+
+    fn gltf_sampler(mag_filter: Option<u32>, min_filter: Option<u32>, wrap_s: u32, wrap_t: u32) -> GltfSampler {
+        GltfSampler {
+            mag_filter: mag_filter,
+            min_filter: min_filter,
+            wrap_s: wrap_s,
+            wrap_t: wrap_t
+        }
+    }
+
+    #[test]
+    fn gltf_samplers() {
+        // The glTF defaults
+        assert_eq!(SamplerSettings::from_gltf(&gltf_sampler(None, None, GL_REPEAT, GL_REPEAT)),
+            SamplerSettings::default());
+
+        let settings = SamplerSettings::from_gltf(&gltf_sampler(Some(GL_NEAREST), Some(GL_NEAREST_MIPMAP_LINEAR),
+            GL_CLAMP_TO_EDGE, GL_MIRRORED_REPEAT));
+        assert_eq!(settings.wrap_s, SamplerWrapFunction::Clamp);
+        assert_eq!(settings.wrap_t, SamplerWrapFunction::Mirror);
+        assert_eq!(settings.min_filter, MinifySamplerFilter::NearestMipmapLinear);
+        assert_eq!(settings.mag_filter, MagnifySamplerFilter::Nearest);
+        assert_eq!(settings.anisotropy, 1);
+
+        let filters = [
+            (GL_NEAREST, MinifySamplerFilter::Nearest),
+            (GL_LINEAR, MinifySamplerFilter::Linear),
+            (GL_NEAREST_MIPMAP_NEAREST, MinifySamplerFilter::NearestMipmapNearest),
+            (GL_LINEAR_MIPMAP_NEAREST, MinifySamplerFilter::LinearMipmapNearest),
+            (GL_NEAREST_MIPMAP_LINEAR, MinifySamplerFilter::NearestMipmapLinear),
+            (GL_LINEAR_MIPMAP_LINEAR, MinifySamplerFilter::LinearMipmapLinear)
+        ];
+        for &(gl, filter) in &filters {
+            let settings = SamplerSettings::from_gltf(&gltf_sampler(Some(GL_LINEAR), Some(gl), GL_REPEAT, GL_REPEAT));
+            assert_eq!(settings.min_filter, filter, "{:x}", gl);
+            assert_eq!(settings.mag_filter, MagnifySamplerFilter::Linear);
+        }
+
+        // Unknown values fall back to the defaults instead of failing
+        let settings = SamplerSettings::from_gltf(&gltf_sampler(Some(0), Some(0), 0, 0));
+        assert_eq!(settings, SamplerSettings::default());
+    }
+
+    #[test]
+    fn color_maps_are_srgb_and_data_maps_linear() {
+        let mut material = AssetMaterial::new("material");
+        material.base_color_map = Some(PathBuf::from("albedo.png"));
+        material.specular_map = Some(PathBuf::from("specular.png"));
+        material.normal_map = Some(PathBuf::from("normal.png"));
+        material.metallic_roughness_map = Some(PathBuf::from("metallic_roughness.png"));
+
+        let maps: Vec<(Option<PathBuf>, ColorSpace)> = material_maps(&material).iter()
+            .map(|&(path, color_space)| (path.clone(), color_space))
+            .collect();
+        assert_eq!(maps, vec![
+            (Some(PathBuf::from("albedo.png")), ColorSpace::Srgb),
+            (Some(PathBuf::from("specular.png")), ColorSpace::Srgb),
+            (Some(PathBuf::from("normal.png")), ColorSpace::Linear),
+            (Some(PathBuf::from("metallic_roughness.png")), ColorSpace::Linear)
+        ]);
+    }
+}
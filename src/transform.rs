@@ -0,0 +1,146 @@
+use cgmath::{InnerSpace, Matrix3, Matrix4, One, Quaternion, Vector3};
+
+use pipeline::Pipeline;
+
+// A translation, a rotation and a scale, applied in the reverse order. This is the same
+// decomposition `Pipeline` uses for its world transformation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>
+}
+
+impl Transform {
+    pub fn identity() -> Transform {
+        Transform {
+            translation: Vector3::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            scale: Vector3::new(1.0, 1.0, 1.0)
+        }
+    }
+
+    pub fn to_matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation) *
+            Matrix4::from(self.rotation) *
+            Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+
+    // Split an affine matrix back into its parts. Shearing (which only comes from non-uniform
+    // scales in a hierarchy) cannot be represented and is lost.
+    pub fn from_matrix(m: Matrix4<f32>) -> Transform {
+        let translation = Vector3::new(m.w.x, m.w.y, m.w.z);
+        let mut x = Vector3::new(m.x.x, m.x.y, m.x.z);
+        let y = Vector3::new(m.y.x, m.y.y, m.y.z);
+        let z = Vector3::new(m.z.x, m.z.y, m.z.z);
+
+        let mut scale = Vector3::new(x.magnitude(), y.magnitude(), z.magnitude());
+
+        // A mirrored matrix cannot be a rotation, put the mirror in the scale
+        if x.dot(y.cross(z)) < 0.0 {
+            scale.x = -scale.x;
+            x = -x;
+        }
+
+        let rotation = if scale.x != 0.0 && scale.y != 0.0 && scale.z != 0.0 {
+            let basis = Matrix3::from_cols(x / scale.x.abs(), y / scale.y, z / scale.z);
+            Quaternion::from(basis).normalize()
+        } else {
+            Quaternion::one()
+        };
+
+        Transform {
+            translation: translation,
+            rotation: rotation,
+            scale: scale
+        }
+    }
+
+    // The angles in degrees to give to `Pipeline::rotate()` for the same rotation.
+    // `init_rotate_transform()` builds Rz * Ry * Rx, whose Ry turns the other way around.
+    pub fn rotation_degrees(&self) -> Vector3<f32> {
+        let m = Matrix3::from(self.rotation);
+        let sin_y = m.x.z.clamp(-1.0, 1.0);
+        // More precise than the arc sine close to the poles
+        let y = sin_y.atan2(m.x.x.hypot(m.x.y));
+
+        let (x, z) = if sin_y.abs() < 0.9999 {
+            (m.y.z.atan2(m.z.z), m.x.y.atan2(m.x.x))
+        } else {
+            // Gimbal lock, put everything on the Z axis
+            (0.0, (-m.y.x).atan2(m.y.y))
+        };
+
+        Vector3::new(x.to_degrees(), y.to_degrees(), z.to_degrees())
+    }
+
+    pub fn apply_to(&self, pipeline: &mut Pipeline) {
+        let rotation = self.rotation_degrees();
+        pipeline.scale(self.scale.x, self.scale.y, self.scale.z);
+        pipeline.rotate(rotation.x, rotation.y, rotation.z);
+        pipeline.world_pos(self.translation.x, self.translation.y, self.translation.z);
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Transform {
+        Transform::identity()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Deg, Matrix4, Quaternion, Rotation3, Vector3};
+
+    use graphical_math;
+    use pipeline::Pipeline;
+    use super::*;
+
+    fn close(a: Matrix4<f32>, b: Matrix4<f32>) -> bool {
+        let (a, b): ([[f32; 4]; 4], [[f32; 4]; 4]) = (a.into(), b.into());
+        a.iter().zip(b.iter()).all(|(a, b)| a.iter().zip(b.iter()).all(|(a, b)| (a - b).abs() < 1e-4))
+    }
+
+    fn rotations() -> Vec<Quaternion<f32>> {
+        let mut rotations = vec![
+            Quaternion::one(),
+            Quaternion::from_angle_x(Deg(30.0)),
+            Quaternion::from_angle_y(Deg(-75.0)),
+            Quaternion::from_angle_z(Deg(120.0)),
+            // Gimbal lock, both ways
+            Quaternion::from_angle_y(Deg(90.0)),
+            Quaternion::from_angle_y(Deg(-90.0)) * Quaternion::from_angle_x(Deg(40.0)),
+            Quaternion::from_angle_z(Deg(25.0)) * Quaternion::from_angle_y(Deg(90.0))
+        ];
+        for i in 0..20 {
+            let angle = i as f32 * 37.0;
+            let axis = Vector3::new(angle.sin(), (angle * 1.3).cos(), 0.5).normalize();
+            rotations.push(Quaternion::from_axis_angle(axis, Deg(angle)));
+        }
+        rotations
+    }
+
+    #[test]
+    fn rotation_degrees_rebuild_the_rotation() {
+        for rotation in rotations() {
+            let transform = Transform { rotation: rotation, .. Transform::identity() };
+            let degrees = transform.rotation_degrees();
+            let rebuilt = graphical_math::init_rotate_transform(degrees.x, degrees.y, degrees.z);
+            assert!(close(rebuilt, Matrix4::from(rotation)), "{:?} gave {:?}", rotation, degrees);
+        }
+    }
+
+    #[test]
+    fn pipelines_get_the_same_world_matrix() {
+        for rotation in rotations() {
+            let transform = Transform {
+                translation: Vector3::new(1.0, -2.0, 3.5),
+                rotation: rotation,
+                scale: Vector3::new(2.0, 0.5, 3.0)
+            };
+            let mut pipeline = Pipeline::new();
+            transform.apply_to(&mut pipeline);
+            assert!(close(pipeline.get_world_trans(), transform.to_matrix()), "{:?}", transform);
+        }
+    }
+}
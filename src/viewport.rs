@@ -0,0 +1,389 @@
+use glium::{DrawParameters, Rect, Surface};
+use glium::glutin::VirtualKeyCode;
+
+use camera::Camera;
+use pipeline::Pipeline;
+
+// A part of the window in fractions of the window size. The origin is at the top-left corner,
+// same as the mouse coordinates.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Region {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32
+}
+
+impl Region {
+    pub fn full() -> Region {
+        Region { x: 0.0, y: 0.0, width: 1.0, height: 1.0 }
+    }
+
+    // Split the window into `columns` x `rows` cells, row by row from the top-left cell
+    pub fn grid(columns: u32, rows: u32) -> Vec<Region> {
+        let width = 1.0 / columns as f32;
+        let height = 1.0 / rows as f32;
+
+        let mut regions = Vec::new();
+        for row in 0..rows {
+            for column in 0..columns {
+                regions.push(Region {
+                    x: column as f32 * width,
+                    y: row as f32 * height,
+                    width: width,
+                    height: height
+                });
+            }
+        }
+        regions
+    }
+
+    // Side-by-side split screen
+    pub fn split_horizontally(count: u32) -> Vec<Region> {
+        Region::grid(count, 1)
+    }
+
+    // Top-to-bottom split screen
+    pub fn split_vertically(count: u32) -> Vec<Region> {
+        Region::grid(1, count)
+    }
+
+    // The full window plus a small inset at the top-right corner. `size` and `margin` are in
+    // fractions of the window size.
+    pub fn picture_in_picture(size: f32, margin: f32) -> (Region, Region) {
+        let inset = Region {
+            x: 1.0 - size - margin,
+            y: margin,
+            width: size,
+            height: size
+        };
+        (Region::full(), inset)
+    }
+
+    // Convert to a glium rectangle, whose origin is at the bottom-left corner
+    fn to_rect(self, window_width: u32, window_height: u32) -> Rect {
+        let left = (self.x * window_width as f32).round() as u32;
+        let top = (self.y * window_height as f32).round() as u32;
+        let right = ((self.x + self.width) * window_width as f32).round() as u32;
+        let bottom = ((self.y + self.height) * window_height as f32).round() as u32;
+
+        Rect {
+            left: left,
+            bottom: window_height.saturating_sub(bottom),
+            width: right.saturating_sub(left).max(1),
+            height: bottom.saturating_sub(top).max(1)
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum ViewProjection {
+    Perspective { fov: f32, z_near: f32, z_far: f32 },
+    // `height` is the visible height in world units, the width follows the aspect ratio
+    Orthographic { height: f32, z_near: f32, z_far: f32 }
+}
+
+pub struct Viewport {
+    region: Region,
+    rect: Rect,
+    interactive: bool,
+
+    pub camera: Camera,
+    pub projection: ViewProjection
+}
+
+impl Viewport {
+    pub fn get_rect(&self) -> Rect {
+        self.rect
+    }
+
+    pub fn get_region(&self) -> Region {
+        self.region
+    }
+
+    pub fn get_aspect_ratio(&self) -> f32 {
+        self.rect.width as f32 / self.rect.height as f32
+    }
+
+    // Non-interactive viewports ignore the mouse and the keyboard (e.g. fixed top/front/side views)
+    pub fn set_interactive(&mut self, interactive: bool) {
+        self.interactive = interactive;
+    }
+
+    pub fn is_interactive(&self) -> bool {
+        self.interactive
+    }
+
+    // Whether a point in window coordinates (origin at the top-left corner) is in this viewport
+    pub fn contains(&self, x: i32, y: i32, window_height: u32) -> bool {
+        let (local_x, local_y) = self.to_local(x, y, window_height);
+        local_x >= 0 && local_y >= 0 &&
+            local_x < self.rect.width as i32 && local_y < self.rect.height as i32
+    }
+
+    // Convert window coordinates into coordinates relative to the top-left corner of this viewport
+    pub fn to_local(&self, x: i32, y: i32, window_height: u32) -> (i32, i32) {
+        let top = window_height as i32 - (self.rect.bottom + self.rect.height) as i32;
+        (x - self.rect.left as i32, y - top)
+    }
+
+    // Create a pipeline with the camera and the projection of this viewport
+    pub fn pipeline(&self) -> Pipeline {
+        let mut pipeline = Pipeline::new();
+        pipeline.set_camera(self.camera.get_pos(), self.camera.get_target(), self.camera.get_up());
+
+        let width = self.rect.width as f32;
+        let height = self.rect.height as f32;
+        match self.projection {
+            ViewProjection::Perspective { fov, z_near, z_far } => {
+                pipeline.set_perspective_proj(fov, width, height, z_near, z_far);
+            },
+            ViewProjection::Orthographic { height: view_height, z_near, z_far } => {
+                let half_height = view_height / 2.0;
+                let half_width = half_height * width / height;
+                pipeline.set_orthographic_proj(-half_width, half_width,
+                    -half_height, half_height, z_near, z_far);
+            }
+        }
+
+        pipeline
+    }
+
+    // Copy the draw parameters, limiting the drawing to this viewport
+    pub fn draw_parameters<'a>(&self, params: &DrawParameters<'a>) -> DrawParameters<'a> {
+        DrawParameters {
+            viewport: Some(self.rect),
+            scissor: Some(self.rect),
+            .. params.clone()
+        }
+    }
+
+    pub fn clear<S: Surface>(&self, surface: &mut S, color: (f32, f32, f32, f32), depth: f32) {
+        surface.clear(Some(&self.rect), Some(color), false, Some(depth), None);
+    }
+}
+
+// A set of viewports sharing one window. The mouse events go to the viewport under the cursor,
+// and the keyboard events go to the last viewport which received the mouse.
+pub struct ViewportLayout {
+    window_width: u32,
+    window_height: u32,
+    viewports: Vec<Viewport>,
+    active: Option<usize>
+}
+
+impl ViewportLayout {
+    pub fn new(window_width: u32, window_height: u32) -> ViewportLayout {
+        ViewportLayout {
+            window_width: window_width,
+            window_height: window_height,
+            viewports: Vec::new(),
+            active: None
+        }
+    }
+
+    // Add a viewport and return its index. The camera is resized to fit the viewport.
+    pub fn add(&mut self, region: Region, mut camera: Camera, projection: ViewProjection) -> usize {
+        let rect = region.to_rect(self.window_width, self.window_height);
+        camera.set_window_size(rect.width, rect.height);
+
+        self.viewports.push(Viewport {
+            region: region,
+            rect: rect,
+            interactive: true,
+            camera: camera,
+            projection: projection
+        });
+
+        if self.active.is_none() {
+            self.active = Some(0);
+        }
+
+        self.viewports.len() - 1
+    }
+
+    pub fn get(&self, index: usize) -> &Viewport {
+        &self.viewports[index]
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> &mut Viewport {
+        &mut self.viewports[index]
+    }
+
+    pub fn viewports(&self) -> &[Viewport] {
+        &self.viewports
+    }
+
+    pub fn len(&self) -> usize {
+        self.viewports.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.viewports.is_empty()
+    }
+
+    pub fn get_active(&self) -> Option<usize> {
+        self.active
+    }
+
+    // Find the viewport under a point in window coordinates. Later viewports are on top of the
+    // earlier ones (e.g. the inset of a picture-in-picture layout).
+    pub fn viewport_at(&self, x: i32, y: i32) -> Option<usize> {
+        let window_height = self.window_height;
+        self.viewports.iter().rposition(|viewport| viewport.contains(x, y, window_height))
+    }
+
+    pub fn set_window_size(&mut self, window_width: u32, window_height: u32) {
+        self.window_width = window_width;
+        self.window_height = window_height;
+
+        for viewport in self.viewports.iter_mut() {
+            viewport.rect = viewport.region.to_rect(window_width, window_height);
+            viewport.camera.set_window_size(viewport.rect.width, viewport.rect.height);
+        }
+    }
+
+    pub fn on_key_board(&mut self, key: VirtualKeyCode) -> bool {
+        match self.active {
+            Some(index) if self.viewports[index].interactive => {
+                self.viewports[index].camera.on_key_board(key)
+            },
+            _ => false
+        }
+    }
+
+    pub fn on_mouse(&mut self, x: i32, y: i32) {
+        let index = match self.viewport_at(x, y) {
+            Some(index) => index,
+            None => return
+        };
+
+        let window_height = self.window_height;
+        let changed = self.active != Some(index);
+        self.active = Some(index);
+
+        let viewport = &mut self.viewports[index];
+        let (local_x, local_y) = viewport.to_local(x, y, window_height);
+
+        if changed {
+            // The cursor just entered this viewport
+            viewport.camera.set_mouse_pos(local_x, local_y);
+        }
+
+        if viewport.interactive {
+            viewport.camera.on_mouse(local_x, local_y);
+        }
+    }
+
+    pub fn on_render(&mut self) {
+        if let Some(index) = self.active {
+            if self.viewports[index].interactive {
+                self.viewports[index].camera.on_render();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glium::Rect;
+
+    use camera::Camera;
+    use super::*;
+
+    fn rect(left: u32, bottom: u32, width: u32, height: u32) -> Rect {
+        Rect { left: left, bottom: bottom, width: width, height: height }
+    }
+
+    fn perspective() -> ViewProjection {
+        ViewProjection::Perspective { fov: 60.0, z_near: 1.0, z_far: 100.0 }
+    }
+
+    #[test]
+    fn regions_flip_to_the_bottom_left_origin() {
+        assert_eq!(Region::full().to_rect(800, 600), rect(0, 0, 800, 600));
+
+        let cells = Region::grid(2, 2);
+        assert_eq!(cells[0].to_rect(800, 600), rect(0, 300, 400, 300));
+        assert_eq!(cells[1].to_rect(800, 600), rect(400, 300, 400, 300));
+        assert_eq!(cells[2].to_rect(800, 600), rect(0, 0, 400, 300));
+        assert_eq!(cells[3].to_rect(800, 600), rect(400, 0, 400, 300));
+
+        let (_, inset) = Region::picture_in_picture(0.25, 0.05);
+        assert_eq!(inset.to_rect(800, 600), rect(560, 420, 200, 150));
+    }
+
+    #[test]
+    fn rounded_cells_share_their_edges() {
+        let cells: Vec<Rect> = Region::split_horizontally(3).iter().map(|cell| cell.to_rect(100, 10)).collect();
+        assert_eq!(cells, vec![rect(0, 0, 33, 10), rect(33, 0, 34, 10), rect(67, 0, 33, 10)]);
+
+        let rows: Vec<Rect> = Region::split_vertically(3).iter().map(|row| row.to_rect(10, 100)).collect();
+        assert_eq!(rows, vec![rect(0, 67, 10, 33), rect(0, 33, 10, 34), rect(0, 0, 10, 33)]);
+    }
+
+    #[test]
+    fn rects_are_at_least_one_pixel() {
+        let sliver = Region { x: 0.5, y: 0.5, width: 0.001, height: 0.0 };
+        assert_eq!(sliver.to_rect(100, 100), rect(50, 50, 1, 1));
+        assert_eq!(Region::full().to_rect(0, 0), rect(0, 0, 1, 1));
+    }
+
+    #[test]
+    fn local_coordinates_start_at_the_top_left_corner() {
+        let mut layout = ViewportLayout::new(800, 600);
+        let (_, inset) = Region::picture_in_picture(0.25, 0.05);
+        let index = layout.add(inset, Camera::default(800, 600), perspective());
+        let viewport = layout.get(index);
+
+        // 560..760 by 30..180 in window coordinates
+        assert_eq!(viewport.to_local(560, 30, 600), (0, 0));
+        assert_eq!(viewport.to_local(759, 179, 600), (199, 149));
+        assert_eq!(viewport.to_local(0, 0, 600), (-560, -30));
+
+        assert!(viewport.contains(560, 30, 600));
+        assert!(viewport.contains(759, 179, 600));
+        assert!(!viewport.contains(559, 30, 600));
+        assert!(!viewport.contains(560, 29, 600));
+        assert!(!viewport.contains(760, 100, 600));
+        assert!(!viewport.contains(600, 180, 600));
+    }
+
+    #[test]
+    fn the_top_most_viewport_wins() {
+        let mut layout = ViewportLayout::new(800, 600);
+        let (full, inset) = Region::picture_in_picture(0.25, 0.05);
+        layout.add(full, Camera::default(800, 600), perspective());
+        layout.add(inset, Camera::default(800, 600), perspective());
+
+        assert_eq!(layout.viewport_at(600, 100), Some(1));
+        assert_eq!(layout.viewport_at(100, 500), Some(0));
+        assert_eq!(layout.viewport_at(559, 100), Some(0));
+        assert_eq!(layout.viewport_at(-1, 100), None);
+        assert_eq!(layout.viewport_at(800, 100), None);
+        assert_eq!(layout.viewport_at(100, 600), None);
+
+        // The mouse makes the viewport under it active
+        layout.on_mouse(600, 100);
+        assert_eq!(layout.get_active(), Some(1));
+        layout.on_mouse(100, 500);
+        assert_eq!(layout.get_active(), Some(0));
+    }
+
+    #[test]
+    fn aspect_ratios_follow_the_window() {
+        let mut layout = ViewportLayout::new(800, 600);
+        for region in Region::split_horizontally(2) {
+            layout.add(region, Camera::default(800, 600), perspective());
+        }
+        for viewport in layout.viewports() {
+            assert!((viewport.get_aspect_ratio() - 400.0 / 600.0).abs() < 1e-6);
+        }
+
+        layout.set_window_size(1000, 250);
+        assert_eq!(layout.get(1).get_rect(), rect(500, 0, 500, 250));
+        for viewport in layout.viewports() {
+            assert!((viewport.get_aspect_ratio() - 2.0).abs() < 1e-6);
+        }
+    }
+}